@@ -0,0 +1,344 @@
+// Library Backup & Portable Export Module
+// Backups are a single zip archive (database snapshot, config and optionally the image
+// cache). Exports are versioned JSON that can be merged into another library.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::database::{self, Database, PortableImportCounts, PortableMediaRow, StreamingHistoryItem};
+
+/// Bump when the archive layout changes in a way older versions can't read
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+/// Bump when the JSON export shape changes
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const DATABASE_ENTRY: &str = "media_library.db";
+const CONFIG_ENTRY: &str = "media_config.json";
+const IMAGE_CACHE_PREFIX: &str = "image_cache/";
+
+/// Describes the contents of a backup archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    pub app_version: String,
+    pub created_at: String,
+    pub includes_images: bool,
+}
+
+/// Versioned, machine-independent dump of the library and watch state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryExport {
+    pub format_version: u32,
+    pub exported_at: String,
+    pub app_version: String,
+    pub media: Vec<PortableMediaRow>,
+    pub streaming_history: Vec<StreamingHistoryItem>,
+}
+
+/// Prefix substitution applied to local file paths on import (e.g. `D:\Media` -> `/mnt/media`)
+#[derive(Debug, Clone, Deserialize)]
+pub struct PathRewrite {
+    pub from: String,
+    pub to: String,
+}
+
+/// Summary of a portable import
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportSummary {
+    pub media: PortableImportCounts,
+    pub history_imported: usize,
+}
+
+/// Temporary file in the app data dir, removed on drop
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(name: &str) -> Self {
+        let path = database::get_app_data_dir().join(format!(
+            "{}_{}.tmp",
+            name,
+            chrono::Utc::now().timestamp_millis()
+        ));
+        TempFile(path)
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+fn zip_options() -> FileOptions {
+    FileOptions::default().compression_method(CompressionMethod::Deflated)
+}
+
+/// Write a backup archive to `dest`. The database is copied with SQLite's online
+/// backup API so the snapshot is consistent even while the app keeps writing.
+pub fn create_backup(db: &Database, dest: &Path, include_images: bool) -> Result<BackupManifest, String> {
+    let snapshot = TempFile::new("backup_snapshot");
+    db.backup_to(&snapshot.0)
+        .map_err(|e| format!("Failed to snapshot database: {}", e))?;
+
+    let manifest = BackupManifest {
+        format_version: BACKUP_FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        includes_images: include_images,
+    };
+
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create backup folder: {}", e))?;
+    }
+    let file = fs::File::create(dest).map_err(|e| format!("Failed to create backup file: {}", e))?;
+    let mut zip = ZipWriter::new(file);
+
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    add_bytes(&mut zip, MANIFEST_ENTRY, &manifest_json)?;
+    add_file(&mut zip, DATABASE_ENTRY, &snapshot.0)?;
+
+    let config_path = PathBuf::from(database::get_config_path());
+    if config_path.exists() {
+        add_file(&mut zip, CONFIG_ENTRY, &config_path)?;
+    }
+
+    if include_images {
+        let image_dir = PathBuf::from(database::get_image_cache_dir());
+        if image_dir.exists() {
+            for entry in walkdir::WalkDir::new(&image_dir).into_iter().filter_map(|e| e.ok()) {
+                if !entry.file_type().is_file() {
+                    continue;
+                }
+                let relative = match entry.path().strip_prefix(&image_dir) {
+                    Ok(r) => r.to_string_lossy().replace('\\', "/"),
+                    Err(_) => continue,
+                };
+                add_file(&mut zip, &format!("{}{}", IMAGE_CACHE_PREFIX, relative), entry.path())?;
+            }
+        }
+    }
+
+    zip.finish().map_err(|e| format!("Failed to finalize backup: {}", e))?;
+    println!("[BACKUP] Created backup at {:?} (images: {})", dest, include_images);
+    Ok(manifest)
+}
+
+fn add_bytes(zip: &mut ZipWriter<fs::File>, name: &str, bytes: &[u8]) -> Result<(), String> {
+    zip.start_file(name, zip_options())
+        .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+    zip.write_all(bytes).map_err(|e| format!("Failed to write {}: {}", name, e))
+}
+
+fn add_file(zip: &mut ZipWriter<fs::File>, name: &str, path: &Path) -> Result<(), String> {
+    zip.start_file(name, zip_options())
+        .map_err(|e| format!("Failed to write {}: {}", name, e))?;
+    let mut file = fs::File::open(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    std::io::copy(&mut file, zip).map_err(|e| format!("Failed to write {}: {}", name, e))?;
+    Ok(())
+}
+
+fn read_manifest(archive: &mut ZipArchive<fs::File>) -> Result<BackupManifest, String> {
+    let mut entry = archive
+        .by_name(MANIFEST_ENTRY)
+        .map_err(|_| "Backup archive has no manifest".to_string())?;
+    let mut contents = String::new();
+    entry.read_to_string(&mut contents).map_err(|e| e.to_string())?;
+    let manifest: BackupManifest =
+        serde_json::from_str(&contents).map_err(|e| format!("Invalid backup manifest: {}", e))?;
+
+    if manifest.format_version > BACKUP_FORMAT_VERSION {
+        return Err(format!(
+            "Backup was made by a newer version (format {}), please update the app first",
+            manifest.format_version
+        ));
+    }
+    Ok(manifest)
+}
+
+/// Restore a backup archive over the current library.
+/// The live database is replaced in place, the config file is overwritten (callers should
+/// reload it) and cached images are extracted next to the existing ones.
+pub fn restore_backup(db: &mut Database, archive_path: &Path) -> Result<BackupManifest, String> {
+    let file = fs::File::open(archive_path).map_err(|e| format!("Failed to open backup: {}", e))?;
+    let mut archive = ZipArchive::new(file).map_err(|e| format!("Not a valid backup archive: {}", e))?;
+    let manifest = read_manifest(&mut archive)?;
+
+    // Database first - if this fails nothing else has been touched
+    let snapshot = TempFile::new("restore_snapshot");
+    {
+        let mut entry = archive
+            .by_name(DATABASE_ENTRY)
+            .map_err(|_| "Backup archive has no database".to_string())?;
+        let mut out = fs::File::create(&snapshot.0).map_err(|e| e.to_string())?;
+        std::io::copy(&mut entry, &mut out).map_err(|e| format!("Failed to extract database: {}", e))?;
+    }
+    db.restore_from(&snapshot.0)
+        .map_err(|e| format!("Failed to restore database: {}", e))?;
+
+    if let Ok(mut entry) = archive.by_name(CONFIG_ENTRY) {
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).map_err(|e| e.to_string())?;
+        fs::write(database::get_config_path(), contents)
+            .map_err(|e| format!("Failed to restore config: {}", e))?;
+    }
+
+    if manifest.includes_images {
+        let image_dir = PathBuf::from(database::get_image_cache_dir());
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).map_err(|e| e.to_string())?;
+            // enclosed_name rejects absolute paths and `..` components
+            let relative = match entry.enclosed_name().and_then(|p| p.strip_prefix(IMAGE_CACHE_PREFIX).ok()) {
+                Some(p) if !entry.is_dir() => p.to_path_buf(),
+                _ => continue,
+            };
+            let target = image_dir.join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            let mut out = fs::File::create(&target).map_err(|e| e.to_string())?;
+            std::io::copy(&mut entry, &mut out).map_err(|e| format!("Failed to extract {:?}: {}", target, e))?;
+        }
+    }
+
+    println!("[BACKUP] Restored backup from {:?} (created {})", archive_path, manifest.created_at);
+    Ok(manifest)
+}
+
+/// Write the library, watch state and streaming history to a versioned JSON file
+pub fn export_library(db: &Database, dest: &Path) -> Result<LibraryExport, String> {
+    let export = LibraryExport {
+        format_version: EXPORT_FORMAT_VERSION,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        media: db.get_portable_media().map_err(|e| e.to_string())?,
+        streaming_history: db.get_streaming_history(i32::MAX).map_err(|e| e.to_string())?,
+    };
+
+    let json = serde_json::to_string_pretty(&export).map_err(|e| e.to_string())?;
+    fs::write(dest, json).map_err(|e| format!("Failed to write export: {}", e))?;
+    println!("[EXPORT] Exported {} media rows and {} history entries to {:?}",
+             export.media.len(), export.streaming_history.len(), dest);
+    Ok(export)
+}
+
+/// Merge a JSON export into the library, rewriting local path prefixes as it goes
+pub fn import_library(db: &Database, source: &Path, rewrites: &[PathRewrite]) -> Result<ImportSummary, String> {
+    let contents = fs::read_to_string(source).map_err(|e| format!("Failed to read export: {}", e))?;
+    let export: LibraryExport =
+        serde_json::from_str(&contents).map_err(|e| format!("Invalid library export: {}", e))?;
+
+    if export.format_version > EXPORT_FORMAT_VERSION {
+        return Err(format!(
+            "Export was made by a newer version (format {}), please update the app first",
+            export.format_version
+        ));
+    }
+
+    let rewrite = |path: &str| rewrite_path(path, rewrites);
+    let media = db
+        .import_portable_media(&export.media, &rewrite)
+        .map_err(|e| format!("Failed to import library: {}", e))?;
+
+    let mut history_imported = 0;
+    for item in &export.streaming_history {
+        if db.upsert_streaming_history_item(item).map_err(|e| e.to_string())? {
+            history_imported += 1;
+        }
+    }
+
+    println!("[IMPORT] Media: {} inserted, {} updated, {} skipped; history: {} imported",
+             media.inserted, media.updated, media.skipped, history_imported);
+    Ok(ImportSummary { media, history_imported })
+}
+
+/// Apply the first matching prefix rewrite, normalising separators to match the new prefix
+fn rewrite_path(path: &str, rewrites: &[PathRewrite]) -> String {
+    for rw in rewrites {
+        if rw.from.is_empty() {
+            continue;
+        }
+        if let Some(rest) = path.strip_prefix(&rw.from) {
+            let sep = if rw.to.contains('\\') && !rw.to.contains('/') { '\\' } else { '/' };
+            let rest: String = rest.chars().map(|c| if c == '/' || c == '\\' { sep } else { c }).collect();
+            return format!("{}{}", rw.to, rest);
+        }
+    }
+    path.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrites(pairs: &[(&str, &str)]) -> Vec<PathRewrite> {
+        pairs.iter().map(|(from, to)| PathRewrite { from: from.to_string(), to: to.to_string() }).collect()
+    }
+
+    #[test]
+    fn rewrites_the_first_matching_prefix() {
+        let rules = rewrites(&[("D:\\Media", "/mnt/media"), ("D:", "/mnt/other")]);
+        assert_eq!(rewrite_path("D:\\Media\\Shows\\S01E01.mkv", &rules), "/mnt/media/Shows/S01E01.mkv");
+        assert_eq!(rewrite_path("D:\\Films\\Dune.mkv", &rules), "/mnt/other/Films/Dune.mkv");
+        assert_eq!(rewrite_path("E:\\Films\\Dune.mkv", &rules), "E:\\Films\\Dune.mkv");
+
+        // A Windows prefix gets Windows separators
+        let rules = rewrites(&[("/mnt/media", "E:\\Media")]);
+        assert_eq!(rewrite_path("/mnt/media/Movies/Arrival.mkv", &rules), "E:\\Media\\Movies\\Arrival.mkv");
+
+        // An empty prefix would match everything, so it's ignored
+        let rules = rewrites(&[("", "/mnt/media")]);
+        assert_eq!(rewrite_path("/home/me/Arrival.mkv", &rules), "/home/me/Arrival.mkv");
+    }
+
+    #[test]
+    fn export_merges_into_another_library() {
+        let source = Database::new(":memory:").unwrap();
+        let arrival = source.insert_movie("Arrival", Some(2016), None, None, "D:\\Media\\Movies\\Arrival.mkv", 6960.0, None).unwrap();
+        let dune = source.insert_movie("Dune", Some(2021), None, None, "D:\\Media\\Movies\\Dune.mkv", 9300.0, None).unwrap();
+        let show = source.insert_tvshow("Severance", Some(2022), None, None, "D:\\Media\\Shows\\Severance", None).unwrap();
+        source.insert_episode("Severance", "D:\\Media\\Shows\\Severance\\S01E01.mkv", show, 1, 1, 3420.0).unwrap();
+        source.update_progress(arrival, 1200.0, 6960.0).unwrap();
+        source.update_progress(dune, 300.0, 9300.0).unwrap();
+
+        let file = std::env::temp_dir().join(format!("streamvault-export-{}.json", std::process::id()));
+        let mut export = export_library(&source, &file).unwrap();
+        assert_eq!(export.media.len(), 4);
+        // Arrival was watched in the exported library after this one, Dune long before
+        for row in &mut export.media {
+            match row.title.as_str() {
+                "Arrival" => row.last_watched = Some("2099-01-01 00:00:00".to_string()),
+                "Dune" => row.last_watched = Some("2000-01-01 00:00:00".to_string()),
+                _ => {}
+            }
+        }
+        fs::write(&file, serde_json::to_string(&export).unwrap()).unwrap();
+
+        // The receiving library has both movies and an extra one, so ids differ from the export
+        let target = Database::new(":memory:").unwrap();
+        target.insert_movie("Heat", Some(1995), None, None, "/mnt/media/Movies/Heat.mkv", 10200.0, None).unwrap();
+        let own_arrival = target.insert_movie("Arrival", Some(2016), None, None, "/mnt/media/Movies/Arrival.mkv", 6960.0, None).unwrap();
+        let own_dune = target.insert_movie("Dune", Some(2021), None, None, "/mnt/media/Movies/Dune.mkv", 9300.0, None).unwrap();
+        target.update_progress(own_arrival, 60.0, 6960.0).unwrap();
+        target.update_progress(own_dune, 4000.0, 9300.0).unwrap();
+
+        let summary = import_library(&target, &file, &rewrites(&[("D:\\Media", "/mnt/media")]));
+        let _ = fs::remove_file(&file);
+        let summary = summary.unwrap();
+        assert_eq!((summary.media.inserted, summary.media.updated, summary.media.skipped), (2, 1, 1));
+
+        // Newer last_watched wins
+        let by_path = |path: &str| target.get_media_by_file_path(path).unwrap().unwrap();
+        assert_eq!(by_path("/mnt/media/Movies/Arrival.mkv").resume_position_seconds, Some(1200.0));
+        assert_eq!(by_path("/mnt/media/Movies/Dune.mkv").resume_position_seconds, Some(4000.0));
+
+        // The episode hangs off the show's id in this library
+        let imported_show = by_path("/mnt/media/Shows/Severance");
+        assert_ne!(imported_show.id, show);
+        assert_eq!(by_path("/mnt/media/Shows/Severance/S01E01.mkv").parent_id, Some(imported_show.id));
+    }
+}
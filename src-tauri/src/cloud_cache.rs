@@ -0,0 +1,165 @@
+// Cloud Cache Index Module
+// mpv's --stream-record writes each cloud stream to <cache_dir>/media_{id}/. This keeps the
+// cache_entries table in step with those folders and enforces the disk quota by evicting the
+// least recently played entries first. Pinned items, pinned series and anything with an
+// unfinished resume position are never evicted.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::database::{CacheEntry, Database};
+
+/// Folder mpv records a media item's stream into
+pub fn media_cache_dir(cache_dir: &str, media_id: i64) -> PathBuf {
+    Path::new(cache_dir).join(format!("media_{}", media_id))
+}
+
+/// Whether eviction must leave this entry alone
+pub fn is_protected(entry: &CacheEntry) -> bool {
+    entry.pinned || entry.series_pinned || entry.in_progress
+}
+
+fn dir_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Sync the index with the media_{id} folders actually on disk
+pub fn reconcile(db: &Database, cache_dir: &str) -> Result<(), String> {
+    let mut on_disk: HashMap<i64, (PathBuf, i64)> = HashMap::new();
+    if let Ok(entries) = std::fs::read_dir(cache_dir) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            let media_id = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix("media_"))
+                .and_then(|id| id.parse::<i64>().ok());
+            if let (Some(media_id), true) = (media_id, path.is_dir()) {
+                // Untracked folders count as last used when they were last written
+                let modified = entry
+                    .metadata()
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or(0);
+                on_disk.insert(media_id, (path, modified));
+            }
+        }
+    }
+
+    for entry in db.get_cache_entries().map_err(|e| e.to_string())? {
+        if !on_disk.contains_key(&entry.media_id) {
+            db.delete_cache_entry(entry.media_id).map_err(|e| e.to_string())?;
+        }
+    }
+
+    for (media_id, (path, modified)) in on_disk {
+        let size = dir_size(&path) as i64;
+        db.upsert_cache_entry(media_id, &path.to_string_lossy(), size, modified)
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(())
+}
+
+/// Record that a media item's cache was just used (creates the entry if needed)
+pub fn touch(db: &Database, cache_dir: &str, media_id: i64) -> Result<(), String> {
+    let path = media_cache_dir(cache_dir, media_id);
+    let now = chrono::Utc::now().timestamp();
+    db.upsert_cache_entry(media_id, &path.to_string_lossy(), dir_size(&path) as i64, now)
+        .map_err(|e| e.to_string())?;
+    db.touch_cache_entry(media_id, now).map_err(|e| e.to_string())
+}
+
+/// Evict least recently used entries until the cache fits in `quota_bytes`.
+/// `in_use` lists media currently playing, which are skipped too.
+/// Returns (entries evicted, bytes freed).
+pub fn enforce_quota(db: &Database, cache_dir: &str, quota_bytes: u64, in_use: &[i64]) -> Result<(usize, u64), String> {
+    reconcile(db, cache_dir)?;
+
+    let entries = db.get_cache_entries().map_err(|e| e.to_string())?;
+    let mut total: u64 = entries.iter().map(|e| e.size_bytes.max(0) as u64).sum();
+    let mut evicted = 0;
+    let mut freed: u64 = 0;
+
+    // Entries come back least recently used first
+    for entry in entries {
+        if total <= quota_bytes {
+            break;
+        }
+        if is_protected(&entry) || in_use.contains(&entry.media_id) {
+            continue;
+        }
+
+        if let Err(e) = std::fs::remove_dir_all(&entry.path) {
+            println!("[CACHE] Failed to evict {}: {}", entry.path, e);
+            continue;
+        }
+        db.delete_cache_entry(entry.media_id).map_err(|e| e.to_string())?;
+
+        let size = entry.size_bytes.max(0) as u64;
+        total = total.saturating_sub(size);
+        freed += size;
+        evicted += 1;
+        println!("[CACHE] Evicted {} ({:.1} MB)", entry.title, size as f64 / (1024.0 * 1024.0));
+    }
+
+    if total > quota_bytes {
+        println!("[CACHE] Still over quota after eviction - remaining entries are pinned or in progress");
+    }
+
+    Ok((evicted, freed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used_unprotected_entries() {
+        let cache_dir = std::env::temp_dir().join(format!("streamvault-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&cache_dir);
+        let cache = cache_dir.to_string_lossy().to_string();
+
+        let db = Database::new(":memory:").unwrap();
+        let movie = |title: &str| db.insert_movie(title, None, None, None, &format!("gdrive://{}", title), 1000.0, None).unwrap();
+        let (oldest, pinned, watching, playing, newer, newest) =
+            (movie("Heat"), movie("Ronin"), movie("Arrival"), movie("Dune"), movie("Sicario"), movie("Prisoners"));
+        let show = db.insert_tvshow("Severance", None, None, None, "gdrive://Severance", None).unwrap();
+        let episode = db.insert_episode("Severance", "gdrive://Severance/S01E01", show, 1, 1, 1000.0).unwrap();
+
+        // 1000 bytes each, last played in this order
+        let order = [oldest, pinned, watching, episode, playing, newer, newest];
+        for (last_access, &media_id) in order.iter().enumerate() {
+            let dir = media_cache_dir(&cache, media_id);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("video.mp4"), vec![0u8; 1000]).unwrap();
+            db.upsert_cache_entry(media_id, &dir.to_string_lossy(), 1000, last_access as i64).unwrap();
+        }
+        db.set_cache_entry_pinned(pinned, true).unwrap();
+        db.set_series_cache_pinned(show, true).unwrap();
+        db.update_progress(watching, 100.0, 1000.0).unwrap();
+
+        let evicted = enforce_quota(&db, &cache, 5500, &[playing]);
+        let remaining: Vec<i64> = db.get_cache_entries().unwrap().iter().map(|e| e.media_id).collect();
+        let newest_kept = media_cache_dir(&cache, newest).exists();
+        let oldest_kept = media_cache_dir(&cache, oldest).exists();
+        // Only pinned, series-pinned, in-progress and playing entries survive a zero quota
+        let emptied = enforce_quota(&db, &cache, 0, &[playing]);
+        let survivors: Vec<i64> = db.get_cache_entries().unwrap().iter().map(|e| e.media_id).collect();
+        let _ = std::fs::remove_dir_all(&cache_dir);
+
+        assert_eq!(evicted, Ok((2, 2000)));
+        assert_eq!(remaining, [pinned, watching, episode, playing, newest]);
+        assert!(newest_kept && !oldest_kept);
+        assert_eq!(emptied, Ok((1, 1000)));
+        assert_eq!(survivors, [pinned, watching, episode, playing]);
+    }
+}
@@ -1,8 +1,25 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::fs;
 use std::io::{Read, Write};
+use std::sync::Mutex;
 
 use crate::database::get_config_path;
+use crate::players::PlayerProfile;
+use crate::transcoder::QualityProfile;
+use crate::secrets;
+
+/// Secrets-store names for API keys and the Google client secret (never written to media_config.json)
+pub const TMDB_API_KEY_SECRET: &str = "tmdb_api_key";
+pub const GDRIVE_CLIENT_SECRET: &str = "gdrive_client_secret";
+pub const OPENSUBTITLES_API_KEY_SECRET: &str = "opensubtitles_api_key";
+pub const JELLYFIN_PASSWORD_SECRET: &str = "jellyfin_password";
+
+lazy_static::lazy_static! {
+    // Secrets the store could not return at load time. Their fields are empty in
+    // memory, so saving must leave them alone instead of deleting them
+    static ref UNREADABLE_SECRETS: Mutex<HashSet<&'static str>> = Mutex::new(HashSet::new());
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -10,51 +27,229 @@ pub struct Config {
     pub mpv_path: Option<String>,
     #[serde(default)]
     pub vlc_path: Option<String>,
+    // User-defined external players (built-in profiles are added at runtime)
+    #[serde(default)]
+    pub player_profiles: Vec<PlayerProfile>,
+    // Default mpv track languages (--alang/--slang), overridden by choices remembered per series
+    #[serde(default)]
+    pub track_audio_languages: Vec<String>,
+    #[serde(default)]
+    pub track_subtitle_languages: Vec<String>,
     #[serde(default)]
     pub ffprobe_path: Option<String>,
     #[serde(default)]
     pub ffmpeg_path: Option<String>,
+    // Transcode quality ladder, the profile used when the player doesn't pick one,
+    // and how many FFmpeg encodes may run at once
+    #[serde(default = "default_transcode_profiles")]
+    pub transcode_profiles: Vec<QualityProfile>,
+    #[serde(default = "default_transcode_profile")]
+    pub default_transcode_profile: String,
+    #[serde(default = "default_max_transcode_sessions")]
+    pub max_transcode_sessions: u32,
     #[serde(default)]
     pub tmdb_api_key: Option<String>,
+    // Subtitle downloads: preferred languages (ISO 639-1, best first) and the provider
+    #[serde(default = "default_subtitle_languages")]
+    pub subtitle_languages: Vec<String>,
+    #[serde(default)]
+    pub opensubtitles_api_key: Option<String>,
+    // Base URL of an OpenSubtitles-compatible API (defaults to api.opensubtitles.com)
+    #[serde(default)]
+    pub opensubtitles_url: Option<String>,
     // Cloud cache settings
     #[serde(default)]
     pub cloud_cache_enabled: bool,
     #[serde(default)]
     pub cloud_cache_dir: Option<String>,
+    // In-memory read-ahead buffer per stream (mpv demuxer cache)
     #[serde(default = "default_cloud_cache_max_mb")]
     pub cloud_cache_max_mb: u32,
+    // Total disk space the stream cache may use before least-recently-used entries are evicted
+    #[serde(default = "default_cloud_cache_quota_mb")]
+    pub cloud_cache_quota_mb: u32,
     #[serde(default = "default_cloud_cache_expiry_hours")]
     pub cloud_cache_expiry_hours: u32,
+    // Background fetch of the next cloud episode while the current one plays
+    #[serde(default = "default_prefetch_next_episode")]
+    pub prefetch_next_episode: bool,
+    // How much of the next episode to fetch, in minutes of playback (0 = the whole file)
+    #[serde(default = "default_prefetch_minutes")]
+    pub prefetch_minutes: u32,
+    // Bandwidth cap for prefetching in KB/s (0 = unlimited)
+    #[serde(default)]
+    pub prefetch_max_kbps: u32,
     // Cloud auto-scan interval in minutes (default 5 minutes)
     #[serde(default = "default_cloud_scan_interval_minutes")]
     pub cloud_scan_interval_minutes: u32,
+    // Offline downloads (defaults to <app data>/downloads)
+    #[serde(default)]
+    pub download_dir: Option<String>,
+    #[serde(default = "default_max_concurrent_downloads")]
+    pub max_concurrent_downloads: u32,
+    // Google Drive auth: "hosted" goes through the StreamVault auth server,
+    // "direct" runs OAuth + PKCE locally with the user's own client id
+    #[serde(default = "default_gdrive_auth_mode")]
+    pub gdrive_auth_mode: String,
+    #[serde(default)]
+    pub gdrive_client_id: Option<String>,
+    #[serde(default)]
+    pub gdrive_client_secret: Option<String>,
+    // DLNA/UPnP media server for TVs on the LAN (off by default)
+    #[serde(default)]
+    pub dlna_enabled: bool,
+    #[serde(default = "default_dlna_name")]
+    pub dlna_name: String,
+    #[serde(default = "default_dlna_port")]
+    pub dlna_port: u16,
+    // Jellyfin-compatible API for Jellyfin apps (off by default, needs a password)
+    #[serde(default)]
+    pub jellyfin_enabled: bool,
+    #[serde(default = "default_jellyfin_port")]
+    pub jellyfin_port: u16,
+    #[serde(default = "default_jellyfin_username")]
+    pub jellyfin_username: String,
+    #[serde(default)]
+    pub jellyfin_password: Option<String>,
+}
+
+fn default_subtitle_languages() -> Vec<String> {
+    vec!["en".to_string()]
+}
+
+fn default_transcode_profiles() -> Vec<QualityProfile> {
+    crate::transcoder::default_profiles()
+}
+
+fn default_transcode_profile() -> String {
+    QualityProfile::default().id
+}
+
+fn default_max_transcode_sessions() -> u32 {
+    2
+}
+
+fn default_dlna_name() -> String {
+    "StreamVault".to_string()
+}
+
+fn default_dlna_port() -> u16 {
+    8200
+}
+
+fn default_jellyfin_port() -> u16 {
+    8096
+}
+
+fn default_jellyfin_username() -> String {
+    "streamvault".to_string()
 }
 
 fn default_cloud_cache_max_mb() -> u32 {
     1024 // 1GB per movie
 }
 
+fn default_cloud_cache_quota_mb() -> u32 {
+    10240 // 10GB on disk
+}
+
 fn default_cloud_cache_expiry_hours() -> u32 {
     24 // Clean up after 24 hours
 }
 
+fn default_prefetch_next_episode() -> bool {
+    true
+}
+
+fn default_prefetch_minutes() -> u32 {
+    5
+}
+
 fn default_cloud_scan_interval_minutes() -> u32 {
     5 // Scan every 5 minutes by default
 }
 
+fn default_max_concurrent_downloads() -> u32 {
+    2
+}
+
+fn default_gdrive_auth_mode() -> String {
+    "hosted".to_string()
+}
+
+impl Config {
+    /// Whether Google auth should bypass the hosted backend
+    pub fn uses_direct_gdrive_auth(&self) -> bool {
+        self.gdrive_auth_mode == "direct"
+    }
+
+    /// Where offline downloads are stored
+    pub fn download_dir(&self) -> String {
+        match self.download_dir {
+            Some(ref dir) if !dir.is_empty() => dir.clone(),
+            _ => crate::database::get_app_data_dir().join("downloads").to_string_lossy().to_string(),
+        }
+    }
+
+    /// A transcode profile by id, falling back to the default profile
+    pub fn transcode_profile(&self, id: Option<&str>) -> Result<QualityProfile, String> {
+        let id = id.unwrap_or(&self.default_transcode_profile);
+        match self.transcode_profiles.iter().find(|p| p.id == id) {
+            Some(profile) => Ok(profile.clone()),
+            None if id == self.default_transcode_profile => Ok(QualityProfile::default()),
+            None => Err(format!("Unknown quality profile: {}", id)),
+        }
+    }
+
+    /// Fields that live in the secrets store instead of the config file
+    fn secret_fields(&mut self) -> [(&'static str, &mut Option<String>); 4] {
+        [
+            (TMDB_API_KEY_SECRET, &mut self.tmdb_api_key),
+            (OPENSUBTITLES_API_KEY_SECRET, &mut self.opensubtitles_api_key),
+            (GDRIVE_CLIENT_SECRET, &mut self.gdrive_client_secret),
+            (JELLYFIN_PASSWORD_SECRET, &mut self.jellyfin_password),
+        ]
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
             mpv_path: None,
             vlc_path: None,
+            player_profiles: Vec::new(),
+            track_audio_languages: Vec::new(),
+            track_subtitle_languages: Vec::new(),
             ffprobe_path: None,
             ffmpeg_path: None,
+            transcode_profiles: default_transcode_profiles(),
+            default_transcode_profile: default_transcode_profile(),
+            max_transcode_sessions: default_max_transcode_sessions(),
             tmdb_api_key: None,
+            subtitle_languages: default_subtitle_languages(),
+            opensubtitles_api_key: None,
+            opensubtitles_url: None,
             cloud_cache_enabled: false,
             cloud_cache_dir: None,
             cloud_cache_max_mb: 1024,
+            cloud_cache_quota_mb: default_cloud_cache_quota_mb(),
             cloud_cache_expiry_hours: 24,
+            prefetch_next_episode: default_prefetch_next_episode(),
+            prefetch_minutes: default_prefetch_minutes(),
+            prefetch_max_kbps: 0,
             cloud_scan_interval_minutes: 5,
+            download_dir: None,
+            max_concurrent_downloads: default_max_concurrent_downloads(),
+            gdrive_auth_mode: default_gdrive_auth_mode(),
+            gdrive_client_id: None,
+            gdrive_client_secret: None,
+            dlna_enabled: false,
+            dlna_name: default_dlna_name(),
+            dlna_port: default_dlna_port(),
+            jellyfin_enabled: false,
+            jellyfin_port: default_jellyfin_port(),
+            jellyfin_username: default_jellyfin_username(),
+            jellyfin_password: None,
         }
     }
 }
@@ -72,7 +267,35 @@ pub fn load_config() -> Result<Config, Box<dyn std::error::Error>> {
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
     
-    let config: Config = serde_json::from_str(&contents)?;
+    let mut config: Config = serde_json::from_str(&contents)?;
+
+    // Older versions kept secrets in plaintext - move them into the secrets store
+    let mut needs_migration = false;
+    let mut unreadable = UNREADABLE_SECRETS.lock().map_err(|e| e.to_string())?;
+    for (name, field) in config.secret_fields() {
+        match field.take() {
+            Some(value) if !value.is_empty() => {
+                println!("[CONFIG] Migrating {} into the secrets store", name);
+                *field = Some(value);
+                needs_migration = true;
+            }
+            _ => match secrets::get_secret(name) {
+                Ok(value) => {
+                    *field = value;
+                    unreadable.remove(name);
+                }
+                Err(e) => {
+                    println!("[CONFIG] Could not read {} from secrets store: {}", name, e);
+                    unreadable.insert(name);
+                }
+            },
+        }
+    }
+    drop(unreadable);
+    if needs_migration {
+        save_config(&config)?;
+    }
+
     Ok(config)
 }
 
@@ -84,7 +307,27 @@ pub fn save_config(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
         fs::create_dir_all(parent)?;
     }
     
-    let json = serde_json::to_string_pretty(config)?;
+    let mut on_disk = config.clone();
+    let mut unreadable = UNREADABLE_SECRETS.lock().map_err(|e| e.to_string())?;
+    for (name, field) in on_disk.secret_fields() {
+        match field.take() {
+            Some(value) if !value.is_empty() => {
+                secrets::set_secret(name, &value)?;
+                unreadable.remove(name);
+            }
+            // Empty only because the store was unavailable at load - keep the stored value
+            _ if unreadable.contains(name) => {
+                println!("[CONFIG] Keeping {} in secrets store (it could not be read at startup)", name);
+            }
+            _ => {
+                if let Err(e) = secrets::delete_secret(name) {
+                    println!("[CONFIG] Could not clear {} from secrets store: {}", name, e);
+                }
+            }
+        }
+    }
+    drop(unreadable);
+    let json = serde_json::to_string_pretty(&on_disk)?;
     let mut file = fs::File::create(&config_path)?;
     file.write_all(json.as_bytes())?;
     
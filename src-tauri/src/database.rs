@@ -69,6 +69,133 @@ pub struct ResumeInfo {
     pub duration: f64,
     pub time_str: String,
     pub progress_percent: f64,
+    /// Chapter the resume position falls in, so playback can also restart from its beginning
+    pub chapter: Option<Chapter>,
+}
+
+/// Detected intro and credits positions for an episode, in seconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SkipMarkers {
+    pub intro_start: Option<f64>,
+    pub intro_end: Option<f64>,
+    pub credits_start: Option<f64>,
+}
+
+/// Technical details of a media file, read by ffprobe
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MediaTechInfo {
+    /// Short container name, e.g. "mkv" or "mp4"
+    pub container: Option<String>,
+    pub duration_seconds: Option<f64>,
+    /// Overall bitrate in bits per second
+    pub bitrate: Option<i64>,
+    pub video_codec: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    /// "HDR10", "HLG" or "Dolby Vision" (None for SDR)
+    pub hdr: Option<String>,
+    pub audio_tracks: Vec<MediaStream>,
+    pub subtitle_tracks: Vec<MediaStream>,
+    pub chapters: Vec<Chapter>,
+}
+
+/// Audio or subtitle stream inside a media file. `index` is ffmpeg's stream index (-map 0:N).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MediaStream {
+    pub index: i64,
+    pub codec: String,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    /// Audio only
+    pub channels: Option<i64>,
+    pub channel_layout: Option<String>,
+    pub is_default: bool,
+    pub forced: bool,
+}
+
+/// Chapter of a media item, in seconds.
+/// source: "embedded" (read from the container) or "manual" (added by the user)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Chapter {
+    #[serde(default)]
+    pub id: i64,
+    pub start_seconds: f64,
+    pub end_seconds: f64,
+    pub title: Option<String>,
+    #[serde(default)]
+    pub source: String,
+}
+
+pub const CHAPTER_EMBEDDED: &str = "embedded";
+pub const CHAPTER_MANUAL: &str = "manual";
+
+/// Remembered track choice for a series. Languages are preferred over track numbers,
+/// which are only kept for tracks without a language tag.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TrackPreferences {
+    pub series_id: i64,
+    pub audio_lang: Option<String>,
+    pub audio_track: Option<i64>,
+    pub sub_lang: Option<String>,
+    pub sub_track: Option<i64>,
+    pub subs_off: bool,
+}
+
+/// Entry in the cloud stream cache index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub media_id: i64,
+    pub title: String,
+    pub path: String,
+    pub size_bytes: i64,
+    /// Unix timestamp of the last playback
+    pub last_access: i64,
+    pub pinned: bool,
+    pub series_pinned: bool,
+    /// Has a resume position that isn't finished yet
+    pub in_progress: bool,
+}
+
+/// Offline download of a cloud media item
+/// status: queued | downloading | paused | completed | failed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadRecord {
+    pub media_id: i64,
+    pub title: String,
+    pub cloud_file_id: String,
+    pub dest_path: String,
+    pub total_bytes: Option<i64>,
+    pub downloaded_bytes: i64,
+    pub md5_checksum: Option<String>,
+    pub status: String,
+    pub error: Option<String>,
+}
+
+/// Access token issued to a Jellyfin client at login
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JellyfinSession {
+    pub token: String,
+    pub device_id: String,
+    pub device_name: String,
+    pub client: String,
+    pub version: String,
+}
+
+/// External subtitle file linked to a media item.
+/// `location` is a local path, or the Drive file id when `is_cloud` is set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subtitle {
+    pub id: i64,
+    pub media_id: i64,
+    pub file_name: String,
+    pub location: String,
+    pub is_cloud: bool,
+    pub language: Option<String>,
+    pub label: String,
+    pub format: String,
+    pub forced: bool,
+    pub sdh: bool,
+    pub source: String,
 }
 
 /// Cached episode metadata from TMDB
@@ -107,6 +234,55 @@ pub struct StreamingHistoryItem {
     pub last_watched: String,
 }
 
+/// A full media row in a machine-independent shape (for portable library export/import)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortableMediaRow {
+    pub id: i64,
+    pub title: String,
+    pub year: Option<i32>,
+    pub overview: Option<String>,
+    pub poster_path: Option<String>,
+    pub file_path: String,
+    pub media_type: String,
+    pub parent_id: Option<i64>,
+    pub season_number: Option<i32>,
+    pub episode_number: Option<i32>,
+    pub duration_seconds: Option<f64>,
+    pub resume_position_seconds: Option<f64>,
+    pub last_watched: Option<String>,
+    pub tmdb_id: Option<String>,
+    pub episode_title: Option<String>,
+    pub still_path: Option<String>,
+    pub is_cloud: bool,
+    pub cloud_file_id: Option<String>,
+    pub cloud_folder_id: Option<String>,
+    #[serde(default)]
+    pub cloud_account_id: Option<String>,
+}
+
+/// A tracked Google Drive folder
+#[derive(Debug, Clone, Serialize)]
+pub struct CloudFolder {
+    pub folder_id: String,
+    pub folder_name: String,
+    pub auto_scan: bool,
+    /// Owning account (None = primary account)
+    pub account_id: Option<String>,
+    /// Shared Drive the folder lives in (None = My Drive)
+    pub drive_id: Option<String>,
+}
+
+/// Counts returned by a portable library import
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PortableImportCounts {
+    pub inserted: usize,
+    pub updated: usize,
+    pub skipped: usize,
+}
+
+/// (cloud_file_id, cloud_folder_id, cloud_account_id) of an indexed cloud file
+pub type CloudFileLocation = (String, Option<String>, Option<String>);
+
 pub struct Database {
     conn: Connection,
 }
@@ -187,6 +363,44 @@ impl Database {
         if !columns.contains(&"cloud_folder_id".to_string()) {
             self.conn.execute("ALTER TABLE media ADD COLUMN cloud_folder_id TEXT DEFAULT NULL", [])?;
         }
+        // Which connected Google account owns the file (NULL = primary account)
+        if !columns.contains(&"cloud_account_id".to_string()) {
+            self.conn.execute("ALTER TABLE media ADD COLUMN cloud_account_id TEXT DEFAULT NULL", [])?;
+        }
+
+        // Detected intro/credits positions in seconds (NULL = not detected)
+        if !columns.contains(&"intro_start".to_string()) {
+            self.conn.execute("ALTER TABLE media ADD COLUMN intro_start REAL DEFAULT NULL", [])?;
+        }
+        if !columns.contains(&"intro_end".to_string()) {
+            self.conn.execute("ALTER TABLE media ADD COLUMN intro_end REAL DEFAULT NULL", [])?;
+        }
+        if !columns.contains(&"credits_start".to_string()) {
+            self.conn.execute("ALTER TABLE media ADD COLUMN credits_start REAL DEFAULT NULL", [])?;
+        }
+
+        // Technical info from ffprobe (probed_at NULL = never analysed)
+        if !columns.contains(&"container".to_string()) {
+            self.conn.execute("ALTER TABLE media ADD COLUMN container TEXT DEFAULT NULL", [])?;
+        }
+        if !columns.contains(&"bitrate".to_string()) {
+            self.conn.execute("ALTER TABLE media ADD COLUMN bitrate INTEGER DEFAULT NULL", [])?;
+        }
+        if !columns.contains(&"video_codec".to_string()) {
+            self.conn.execute("ALTER TABLE media ADD COLUMN video_codec TEXT DEFAULT NULL", [])?;
+        }
+        if !columns.contains(&"video_width".to_string()) {
+            self.conn.execute("ALTER TABLE media ADD COLUMN video_width INTEGER DEFAULT NULL", [])?;
+        }
+        if !columns.contains(&"video_height".to_string()) {
+            self.conn.execute("ALTER TABLE media ADD COLUMN video_height INTEGER DEFAULT NULL", [])?;
+        }
+        if !columns.contains(&"hdr_format".to_string()) {
+            self.conn.execute("ALTER TABLE media ADD COLUMN hdr_format TEXT DEFAULT NULL", [])?;
+        }
+        if !columns.contains(&"probed_at".to_string()) {
+            self.conn.execute("ALTER TABLE media ADD COLUMN probed_at INTEGER DEFAULT NULL", [])?;
+        }
 
         // Create cached_episode_metadata table for pre-fetched episode info from TMDB
         self.conn.execute(
@@ -259,6 +473,10 @@ impl Database {
             [],
         ).ok(); // Ignore error if column already exists
 
+        // Owning account (NULL = primary account) and Shared Drive (NULL = My Drive)
+        self.conn.execute("ALTER TABLE cloud_folders ADD COLUMN account_id TEXT", []).ok();
+        self.conn.execute("ALTER TABLE cloud_folders ADD COLUMN drive_id TEXT", []).ok();
+
         // Create app_settings table for storing global settings like the changes token
         self.conn.execute(
             "CREATE TABLE IF NOT EXISTS app_settings (
@@ -269,6 +487,133 @@ impl Database {
             [],
         )?;
 
+        // Index of the cloud stream cache (one media_{id} folder per entry) for LRU eviction
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS cache_entries (
+                media_id INTEGER PRIMARY KEY,
+                path TEXT NOT NULL,
+                size_bytes INTEGER NOT NULL DEFAULT 0,
+                last_access INTEGER NOT NULL,
+                pinned INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS cache_pinned_series (
+                series_id INTEGER PRIMARY KEY
+            )",
+            [],
+        )?;
+
+        // Audio/subtitle tracks picked in mpv, re-applied to later episodes of the series
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS track_preferences (
+                series_id INTEGER PRIMARY KEY,
+                audio_lang TEXT,
+                audio_track INTEGER,
+                sub_lang TEXT,
+                sub_track INTEGER,
+                subs_off INTEGER NOT NULL DEFAULT 0,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        // Offline downloads of cloud media (one row per media item)
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS downloads (
+                media_id INTEGER PRIMARY KEY,
+                cloud_file_id TEXT NOT NULL,
+                dest_path TEXT NOT NULL,
+                total_bytes INTEGER,
+                downloaded_bytes INTEGER NOT NULL DEFAULT 0,
+                md5_checksum TEXT,
+                status TEXT NOT NULL DEFAULT 'queued',
+                error TEXT,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        // Audio and embedded subtitle streams found by ffprobe
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS media_streams (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                media_id INTEGER NOT NULL,
+                stream_index INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                codec TEXT NOT NULL,
+                language TEXT,
+                title TEXT,
+                channels INTEGER,
+                channel_layout TEXT,
+                is_default INTEGER NOT NULL DEFAULT 0,
+                forced INTEGER NOT NULL DEFAULT 0,
+                UNIQUE(media_id, stream_index)
+            )",
+            [],
+        )?;
+        self.conn.execute("CREATE INDEX IF NOT EXISTS idx_media_streams_media ON media_streams(media_id)", [])?;
+
+        // Chapters embedded in the container or added by hand (source 'embedded' / 'manual')
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS chapters (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                media_id INTEGER NOT NULL,
+                start_seconds REAL NOT NULL,
+                end_seconds REAL NOT NULL,
+                title TEXT,
+                source TEXT NOT NULL DEFAULT 'embedded'
+            )",
+            [],
+        )?;
+        self.conn.execute("CREATE INDEX IF NOT EXISTS idx_chapters_media ON chapters(media_id)", [])?;
+
+        // External subtitle files found next to local or Drive videos
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS subtitles (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                media_id INTEGER NOT NULL,
+                file_name TEXT NOT NULL,
+                location TEXT NOT NULL,
+                is_cloud INTEGER NOT NULL DEFAULT 0,
+                language TEXT,
+                label TEXT NOT NULL,
+                format TEXT NOT NULL,
+                forced INTEGER NOT NULL DEFAULT 0,
+                sdh INTEGER NOT NULL DEFAULT 0,
+                source TEXT NOT NULL DEFAULT 'local',
+                UNIQUE(media_id, location)
+            )",
+            [],
+        )?;
+        self.conn.execute("CREATE INDEX IF NOT EXISTS idx_subtitles_media ON subtitles(media_id)", [])?;
+
+        // Logged-in Jellyfin clients, one token per device
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS jellyfin_sessions (
+                token TEXT PRIMARY KEY,
+                device_id TEXT NOT NULL,
+                device_name TEXT NOT NULL,
+                client TEXT NOT NULL,
+                version TEXT NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+            [],
+        )?;
+
+        // Where a subtitle came from: "local", "drive" or a download provider such as "opensubtitles"
+        let subtitle_columns: Vec<String> = self.conn
+            .prepare("PRAGMA table_info(subtitles)")?
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(|r| r.ok())
+            .collect();
+        if !subtitle_columns.contains(&"source".to_string()) {
+            self.conn.execute("ALTER TABLE subtitles ADD COLUMN source TEXT NOT NULL DEFAULT 'local'", [])?;
+            self.conn.execute("UPDATE subtitles SET source = 'drive' WHERE is_cloud = 1", [])?;
+        }
+
         Ok(())
     }
 
@@ -348,6 +693,23 @@ impl Database {
         items.filter_map(|r| r.ok()).collect::<Vec<_>>().into_iter().map(Ok).collect()
     }
     
+    /// The episode after this one in its series (by season, then episode number)
+    pub fn get_next_episode(&self, media_id: i64) -> Result<Option<MediaItem>> {
+        let media = self.get_media_by_id(media_id)?;
+        let series_id = match media.parent_id {
+            Some(id) if media.media_type == "tvepisode" => id,
+            _ => return Ok(None),
+        };
+
+        let episodes = self.get_episodes(series_id)?;
+        let next = episodes
+            .iter()
+            .position(|e| e.id == media_id)
+            .and_then(|i| episodes.get(i + 1))
+            .cloned();
+        Ok(next)
+    }
+
     pub fn get_watch_history(&self, limit: i32) -> Result<Vec<MediaItem>> {
         let mut stmt = self.conn.prepare(
             "SELECT
@@ -413,6 +775,7 @@ impl Database {
                 duration,
                 time_str: "00:00:00".to_string(),
                 progress_percent: 0.0,
+                chapter: None,
             });
         }
         
@@ -423,20 +786,35 @@ impl Database {
         let seconds = (position % 60.0) as i32;
         let time_str = format!("{:02}:{:02}:{:02}", hours, minutes, seconds);
         
+        // Only worth offering when the chapter started a while before the exact position
+        let chapter = if has_progress {
+            self.get_chapters(media_id)?
+                .into_iter()
+                .find(|c| c.start_seconds <= position && position < c.end_seconds)
+                .filter(|c| position - c.start_seconds >= 5.0)
+        } else {
+            None
+        };
+        
         Ok(ResumeInfo {
             has_progress,
             position,
             duration,
             time_str,
             progress_percent,
+            chapter,
         })
     }
     
     pub fn update_progress(&self, media_id: i64, current_time: f64, duration: f64) -> Result<()> {
-        // Clear progress if >= 95%
+        // Clear progress if >= 95%, or once the end credits have started
         let progress_percent = if duration > 0.0 { current_time / duration } else { 0.0 };
+        let in_credits = self
+            .get_skip_markers(media_id)?
+            .credits_start
+            .is_some_and(|credits| duration > 0.0 && current_time >= credits);
         
-        if progress_percent >= 0.95 {
+        if progress_percent >= 0.95 || in_credits {
             self.conn.execute(
                 "UPDATE media SET resume_position_seconds = 0, duration_seconds = ?, 
                  last_watched = datetime('now') WHERE id = ?",
@@ -950,11 +1328,12 @@ impl Database {
         cloud_file_id: &str,
         cloud_folder_id: &str,
         tmdb_id: Option<&str>,
+        cloud_account_id: Option<&str>,
     ) -> Result<i64> {
         self.conn.execute(
-            "INSERT INTO media (title, year, overview, poster_path, file_path, media_type, tmdb_id, is_cloud, cloud_file_id, cloud_folder_id)
-             VALUES (?, ?, ?, ?, ?, 'movie', ?, 1, ?, ?)",
-            params![title, year, overview, poster_path, file_name, tmdb_id, cloud_file_id, cloud_folder_id],
+            "INSERT INTO media (title, year, overview, poster_path, file_path, media_type, tmdb_id, is_cloud, cloud_file_id, cloud_folder_id, cloud_account_id)
+             VALUES (?, ?, ?, ?, ?, 'movie', ?, 1, ?, ?, ?)",
+            params![title, year, overview, poster_path, file_name, tmdb_id, cloud_file_id, cloud_folder_id, cloud_account_id],
         )?;
         Ok(self.conn.last_insert_rowid())
     }
@@ -991,17 +1370,33 @@ impl Database {
         episode_title: Option<&str>,
         overview: Option<&str>,
         still_path: Option<&str>,
+        cloud_account_id: Option<&str>,
     ) -> Result<i64> {
         self.conn.execute(
             "INSERT INTO media (title, file_path, media_type, parent_id, season_number, episode_number,
-                               is_cloud, cloud_file_id, cloud_folder_id, episode_title, overview, still_path)
-             VALUES (?, ?, 'tvepisode', ?, ?, ?, 1, ?, ?, ?, ?, ?)",
+                               is_cloud, cloud_file_id, cloud_folder_id, episode_title, overview, still_path, cloud_account_id)
+             VALUES (?, ?, 'tvepisode', ?, ?, ?, 1, ?, ?, ?, ?, ?, ?)",
             params![title, file_name, parent_id, season, episode, cloud_file_id, cloud_folder_id,
-                   episode_title, overview, still_path],
+                   episode_title, overview, still_path, cloud_account_id],
         )?;
         Ok(self.conn.last_insert_rowid())
     }
 
+    /// Get the Google account that owns a cloud file (None = primary account)
+    pub fn get_cloud_account_for_file(&self, cloud_file_id: &str) -> Result<Option<String>> {
+        let result = self.conn.query_row(
+            "SELECT cloud_account_id FROM media WHERE cloud_file_id = ? LIMIT 1",
+            params![cloud_file_id],
+            |row| row.get::<_, Option<String>>(0),
+        );
+
+        match result {
+            Ok(account) => Ok(account),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Check if a cloud file already exists in the database
     pub fn cloud_file_exists(&self, cloud_file_id: &str) -> bool {
         self.conn
@@ -1033,16 +1428,25 @@ impl Database {
             "DELETE FROM media WHERE cloud_folder_id = ?",
             params![cloud_folder_id],
         )?;
+        self.conn.execute("DELETE FROM subtitles WHERE media_id NOT IN (SELECT id FROM media)", [])?;
+        self.conn.execute("DELETE FROM media_streams WHERE media_id NOT IN (SELECT id FROM media)", [])?;
+        self.conn.execute("DELETE FROM chapters WHERE media_id NOT IN (SELECT id FROM media)", [])?;
         Ok(deleted)
     }
 
     // ==================== CLOUD FOLDER MANAGEMENT ====================
 
     /// Add a cloud folder to track
-    pub fn add_cloud_folder(&self, folder_id: &str, folder_name: &str) -> Result<i64> {
+    pub fn add_cloud_folder(
+        &self,
+        folder_id: &str,
+        folder_name: &str,
+        account_id: Option<&str>,
+        drive_id: Option<&str>,
+    ) -> Result<i64> {
         self.conn.execute(
-            "INSERT OR REPLACE INTO cloud_folders (folder_id, folder_name, auto_scan) VALUES (?, ?, 1)",
-            params![folder_id, folder_name],
+            "INSERT OR REPLACE INTO cloud_folders (folder_id, folder_name, auto_scan, account_id, drive_id) VALUES (?, ?, 1, ?, ?)",
+            params![folder_id, folder_name, account_id, drive_id],
         )?;
         Ok(self.conn.last_insert_rowid())
     }
@@ -1057,17 +1461,19 @@ impl Database {
     }
 
     /// Get all cloud folders
-    pub fn get_cloud_folders(&self) -> Result<Vec<(String, String, bool)>> {
+    pub fn get_cloud_folders(&self) -> Result<Vec<CloudFolder>> {
         let mut stmt = self.conn.prepare(
-            "SELECT folder_id, folder_name, auto_scan FROM cloud_folders ORDER BY created_at"
+            "SELECT folder_id, folder_name, auto_scan, account_id, drive_id FROM cloud_folders ORDER BY created_at"
         )?;
 
         let items = stmt.query_map([], |row| {
-            Ok((
-                row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?,
-                row.get::<_, i32>(2)? == 1,
-            ))
+            Ok(CloudFolder {
+                folder_id: row.get(0)?,
+                folder_name: row.get(1)?,
+                auto_scan: row.get::<_, i32>(2)? == 1,
+                account_id: row.get(3)?,
+                drive_id: row.get(4)?,
+            })
         })?;
 
         items.collect()
@@ -1095,6 +1501,608 @@ impl Database {
         items.collect()
     }
 
+    /// Get (cloud_file_id, cloud_folder_id, cloud_account_id) for every indexed cloud file
+    pub fn get_cloud_file_locations(&self) -> Result<Vec<CloudFileLocation>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT cloud_file_id, cloud_folder_id, cloud_account_id FROM media WHERE cloud_file_id IS NOT NULL"
+        )?;
+
+        let items = stmt.query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+
+        items.collect()
+    }
+
+    /// Remove media entries by cloud file ID, returning the ids and titles that were removed
+    pub fn remove_cloud_files(&self, cloud_file_ids: &[String]) -> Result<Vec<(i64, String)>> {
+        let mut removed = Vec::new();
+        for cloud_file_id in cloud_file_ids {
+            let entry: Option<(i64, String)> = self.conn.query_row(
+                "SELECT id, title FROM media WHERE cloud_file_id = ?",
+                params![cloud_file_id],
+                |row| Ok((row.get(0)?, row.get(1)?))
+            ).ok();
+
+            if let Some(entry) = entry {
+                self.conn.execute("DELETE FROM media WHERE cloud_file_id = ?", params![cloud_file_id])?;
+                removed.push(entry);
+            }
+        }
+        if !removed.is_empty() {
+            self.conn.execute("DELETE FROM subtitles WHERE media_id NOT IN (SELECT id FROM media)", [])?;
+            self.conn.execute("DELETE FROM media_streams WHERE media_id NOT IN (SELECT id FROM media)", [])?;
+            self.conn.execute("DELETE FROM chapters WHERE media_id NOT IN (SELECT id FROM media)", [])?;
+        }
+        Ok(removed)
+    }
+
+    /// Library id of an indexed Drive file
+    pub fn get_media_id_by_cloud_file(&self, cloud_file_id: &str) -> Result<Option<i64>> {
+        match self.conn.query_row(
+            "SELECT id FROM media WHERE cloud_file_id = ? LIMIT 1",
+            params![cloud_file_id],
+            |row| row.get(0),
+        ) {
+            Ok(id) => Ok(Some(id)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // ==================== SUBTITLES ====================
+
+    const SUBTITLE_COLUMNS: &'static str =
+        "id, media_id, file_name, location, is_cloud, language, label, format, forced, sdh, source";
+
+    fn map_subtitle(row: &rusqlite::Row) -> Result<Subtitle> {
+        Ok(Subtitle {
+            id: row.get(0)?,
+            media_id: row.get(1)?,
+            file_name: row.get(2)?,
+            location: row.get(3)?,
+            is_cloud: row.get::<_, i32>(4)? != 0,
+            language: row.get(5)?,
+            label: row.get(6)?,
+            format: row.get(7)?,
+            forced: row.get::<_, i32>(8)? != 0,
+            sdh: row.get::<_, i32>(9)? != 0,
+            source: row.get(10)?,
+        })
+    }
+
+    /// Replace a media item's subtitles from one source with what a scan or download just found.
+    /// Each entry is (file name, location, parsed info). Rows are upserted by location so a
+    /// subtitle keeps its id (and any /subtitle/{id} URL a player holds) across rescans.
+    pub fn replace_subtitles(
+        &self,
+        media_id: i64,
+        source: &str,
+        is_cloud: bool,
+        subtitles: &[(String, String, crate::subtitles::SubtitleInfo)],
+    ) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for (file_name, location, info) in subtitles {
+            Self::upsert_subtitle(&tx, media_id, source, is_cloud, file_name, location, info)?;
+        }
+
+        // Drop this source's rows that weren't found again
+        let existing: Vec<(i64, String)> = {
+            let mut stmt = tx.prepare("SELECT id, location FROM subtitles WHERE media_id = ? AND source = ?")?;
+            let rows = stmt.query_map(params![media_id, source], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_>>()?
+        };
+        for (id, location) in existing {
+            if !subtitles.iter().any(|(_, found, _)| *found == location) {
+                tx.execute("DELETE FROM subtitles WHERE id = ?", params![id])?;
+            }
+        }
+        tx.commit()
+    }
+
+    fn upsert_subtitle(
+        conn: &Connection,
+        media_id: i64,
+        source: &str,
+        is_cloud: bool,
+        file_name: &str,
+        location: &str,
+        info: &crate::subtitles::SubtitleInfo,
+    ) -> Result<()> {
+        conn.execute(
+            "INSERT INTO subtitles (media_id, file_name, location, is_cloud, language, label, format, forced, sdh, source)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(media_id, location) DO UPDATE SET
+                file_name = excluded.file_name, is_cloud = excluded.is_cloud, language = excluded.language,
+                label = excluded.label, format = excluded.format, forced = excluded.forced,
+                sdh = excluded.sdh, source = excluded.source",
+            params![media_id, file_name, location, is_cloud as i32, info.language, info.label,
+                    info.format, info.forced as i32, info.sdh as i32, source],
+        )?;
+        Ok(())
+    }
+
+    /// Add (or replace) a single subtitle, keeping the media item's other ones
+    pub fn add_subtitle(
+        &self,
+        media_id: i64,
+        source: &str,
+        file_name: &str,
+        location: &str,
+        info: &crate::subtitles::SubtitleInfo,
+    ) -> Result<Subtitle> {
+        Self::upsert_subtitle(&self.conn, media_id, source, false, file_name, location, info)?;
+        self.conn.query_row(
+            &format!("SELECT {} FROM subtitles WHERE media_id = ? AND location = ?", Self::SUBTITLE_COLUMNS),
+            params![media_id, location],
+            Self::map_subtitle,
+        )
+    }
+
+    /// Subtitles for a media item - full tracks before forced ones, then by language
+    pub fn get_subtitles(&self, media_id: i64) -> Result<Vec<Subtitle>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM subtitles WHERE media_id = ? ORDER BY forced, language IS NULL, language, label",
+            Self::SUBTITLE_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![media_id], Self::map_subtitle)?;
+        rows.collect()
+    }
+
+    pub fn get_subtitle(&self, subtitle_id: i64) -> Result<Subtitle> {
+        self.conn.query_row(
+            &format!("SELECT {} FROM subtitles WHERE id = ?", Self::SUBTITLE_COLUMNS),
+            params![subtitle_id],
+            Self::map_subtitle,
+        )
+    }
+
+    // ==================== CLOUD CACHE INDEX ====================
+
+    /// All cache entries, least recently used first
+    pub fn get_cache_entries(&self) -> Result<Vec<CacheEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT c.media_id, COALESCE(m.title, ''), c.path, c.size_bytes, c.last_access, c.pinned,
+                    p.series_id IS NOT NULL,
+                    COALESCE(m.resume_position_seconds > 0 AND m.duration_seconds > 0
+                             AND m.resume_position_seconds < m.duration_seconds * 0.95, 0)
+             FROM cache_entries c
+             LEFT JOIN media m ON m.id = c.media_id
+             LEFT JOIN cache_pinned_series p ON p.series_id = m.parent_id
+             ORDER BY c.last_access"
+        )?;
+
+        let items = stmt.query_map([], |row| {
+            Ok(CacheEntry {
+                media_id: row.get(0)?,
+                title: row.get(1)?,
+                path: row.get(2)?,
+                size_bytes: row.get(3)?,
+                last_access: row.get(4)?,
+                pinned: row.get::<_, i32>(5)? == 1,
+                series_pinned: row.get::<_, i32>(6)? == 1,
+                in_progress: row.get::<_, i32>(7)? == 1,
+            })
+        })?;
+
+        items.collect()
+    }
+
+    /// Add a cache entry or refresh its size (last access is kept for existing entries)
+    pub fn upsert_cache_entry(&self, media_id: i64, path: &str, size_bytes: i64, last_access: i64) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO cache_entries (media_id, path, size_bytes, last_access) VALUES (?, ?, ?, ?)
+             ON CONFLICT(media_id) DO UPDATE SET path = excluded.path, size_bytes = excluded.size_bytes",
+            params![media_id, path, size_bytes, last_access],
+        )?;
+        Ok(())
+    }
+
+    pub fn touch_cache_entry(&self, media_id: i64, last_access: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE cache_entries SET last_access = ? WHERE media_id = ?",
+            params![last_access, media_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_cache_entry(&self, media_id: i64) -> Result<()> {
+        self.conn.execute("DELETE FROM cache_entries WHERE media_id = ?", params![media_id])?;
+        Ok(())
+    }
+
+    pub fn set_cache_entry_pinned(&self, media_id: i64, pinned: bool) -> Result<()> {
+        self.conn.execute(
+            "UPDATE cache_entries SET pinned = ? WHERE media_id = ?",
+            params![pinned as i32, media_id],
+        )?;
+        Ok(())
+    }
+
+    /// Pin a series so none of its cached episodes are evicted (including ones cached later)
+    pub fn set_series_cache_pinned(&self, series_id: i64, pinned: bool) -> Result<()> {
+        if pinned {
+            self.conn.execute(
+                "INSERT OR IGNORE INTO cache_pinned_series (series_id) VALUES (?)",
+                params![series_id],
+            )?;
+        } else {
+            self.conn.execute("DELETE FROM cache_pinned_series WHERE series_id = ?", params![series_id])?;
+        }
+        Ok(())
+    }
+
+    // ==================== SKIP MARKERS ====================
+
+    pub fn get_skip_markers(&self, media_id: i64) -> Result<SkipMarkers> {
+        match self.conn.query_row(
+            "SELECT intro_start, intro_end, credits_start FROM media WHERE id = ?",
+            params![media_id],
+            |row| {
+                Ok(SkipMarkers {
+                    intro_start: row.get(0)?,
+                    intro_end: row.get(1)?,
+                    credits_start: row.get(2)?,
+                })
+            },
+        ) {
+            Ok(markers) => Ok(markers),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(SkipMarkers::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_skip_markers(&self, media_id: i64, markers: &SkipMarkers) -> Result<()> {
+        self.conn.execute(
+            "UPDATE media SET intro_start = ?, intro_end = ?, credits_start = ? WHERE id = ?",
+            params![markers.intro_start, markers.intro_end, markers.credits_start, media_id],
+        )?;
+        Ok(())
+    }
+
+    // ==================== TECHNICAL INFO ====================
+
+    /// Store ffprobe results, replacing earlier ones. The probed duration also fills in
+    /// `duration_seconds`, which otherwise is only known after playback.
+    pub fn save_media_tech_info(&self, media_id: i64, info: &MediaTechInfo) -> Result<()> {
+        self.conn.execute(
+            "UPDATE media SET container = ?, bitrate = ?, video_codec = ?, video_width = ?, video_height = ?,
+                    hdr_format = ?, duration_seconds = COALESCE(?, duration_seconds), probed_at = strftime('%s', 'now')
+             WHERE id = ?",
+            params![info.container, info.bitrate, info.video_codec, info.width, info.height,
+                    info.hdr, info.duration_seconds, media_id],
+        )?;
+
+        self.conn.execute("DELETE FROM media_streams WHERE media_id = ?", params![media_id])?;
+        let streams = info.audio_tracks.iter().map(|s| ("audio", s))
+            .chain(info.subtitle_tracks.iter().map(|s| ("subtitle", s)));
+        for (kind, stream) in streams {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO media_streams (media_id, stream_index, kind, codec, language, title, channels, channel_layout, is_default, forced)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                params![media_id, stream.index, kind, stream.codec, stream.language, stream.title,
+                        stream.channels, stream.channel_layout, stream.is_default as i32, stream.forced as i32],
+            )?;
+        }
+
+        self.conn.execute(
+            "DELETE FROM chapters WHERE media_id = ? AND source = ?",
+            params![media_id, CHAPTER_EMBEDDED],
+        )?;
+        for chapter in &info.chapters {
+            self.conn.execute(
+                "INSERT INTO chapters (media_id, start_seconds, end_seconds, title, source) VALUES (?, ?, ?, ?, ?)",
+                params![media_id, chapter.start_seconds, chapter.end_seconds, chapter.title, CHAPTER_EMBEDDED],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Stored ffprobe results, or None if the item hasn't been analysed
+    pub fn get_media_tech_info(&self, media_id: i64) -> Result<Option<MediaTechInfo>> {
+        let mut info = match self.conn.query_row(
+            "SELECT container, duration_seconds, bitrate, video_codec, video_width, video_height, hdr_format
+             FROM media WHERE id = ? AND probed_at IS NOT NULL",
+            params![media_id],
+            |row| {
+                Ok(MediaTechInfo {
+                    container: row.get(0)?,
+                    duration_seconds: row.get(1)?,
+                    bitrate: row.get(2)?,
+                    video_codec: row.get(3)?,
+                    width: row.get(4)?,
+                    height: row.get(5)?,
+                    hdr: row.get(6)?,
+                    ..Default::default()
+                })
+            },
+        ) {
+            Ok(info) => info,
+            Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let mut stmt = self.conn.prepare(
+            "SELECT kind, stream_index, codec, language, title, channels, channel_layout, is_default, forced
+             FROM media_streams WHERE media_id = ? ORDER BY stream_index",
+        )?;
+        let streams = stmt.query_map(params![media_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                MediaStream {
+                    index: row.get(1)?,
+                    codec: row.get(2)?,
+                    language: row.get(3)?,
+                    title: row.get(4)?,
+                    channels: row.get(5)?,
+                    channel_layout: row.get(6)?,
+                    is_default: row.get::<_, i32>(7)? != 0,
+                    forced: row.get::<_, i32>(8)? != 0,
+                },
+            ))
+        })?;
+
+        for (kind, stream) in streams.filter_map(|r| r.ok()) {
+            if kind == "audio" {
+                info.audio_tracks.push(stream);
+            } else {
+                info.subtitle_tracks.push(stream);
+            }
+        }
+        info.chapters = self.get_chapters(media_id)?;
+        Ok(Some(info))
+    }
+
+    // ==================== CHAPTERS ====================
+
+    /// Chapters of a media item. Manual markers replace the embedded chapters when there
+    /// are any. A manual marker runs until the next one (or the end of the video).
+    pub fn get_chapters(&self, media_id: i64) -> Result<Vec<Chapter>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, start_seconds, end_seconds, title, source FROM chapters
+             WHERE media_id = ? ORDER BY start_seconds",
+        )?;
+        let rows = stmt.query_map(params![media_id], |row| {
+            Ok(Chapter {
+                id: row.get(0)?,
+                start_seconds: row.get(1)?,
+                end_seconds: row.get(2)?,
+                title: row.get(3)?,
+                source: row.get(4)?,
+            })
+        })?;
+        let all: Vec<Chapter> = rows.filter_map(|r| r.ok()).collect();
+
+        let has_manual = all.iter().any(|c| c.source == CHAPTER_MANUAL);
+        let mut chapters: Vec<Chapter> = all
+            .into_iter()
+            .filter(|c| !has_manual || c.source == CHAPTER_MANUAL)
+            .collect();
+
+        if has_manual {
+            let duration: Option<f64> = self.conn.query_row(
+                "SELECT duration_seconds FROM media WHERE id = ?",
+                params![media_id],
+                |row| row.get(0),
+            ).unwrap_or(None);
+            let starts: Vec<f64> = chapters.iter().skip(1).map(|c| c.start_seconds).collect();
+            for (i, chapter) in chapters.iter_mut().enumerate() {
+                chapter.end_seconds = starts
+                    .get(i)
+                    .copied()
+                    .or(duration.filter(|d| *d > chapter.start_seconds))
+                    .unwrap_or(f64::MAX);
+            }
+        }
+        Ok(chapters)
+    }
+
+    /// Add a manual chapter marker
+    pub fn add_chapter(&self, media_id: i64, start_seconds: f64, title: Option<&str>) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO chapters (media_id, start_seconds, end_seconds, title, source) VALUES (?, ?, ?, ?, ?)",
+            params![media_id, start_seconds, start_seconds, title, CHAPTER_MANUAL],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Remove a manual chapter marker (embedded chapters come back with the next probe anyway)
+    pub fn delete_chapter(&self, media_id: i64, chapter_id: i64) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM chapters WHERE id = ? AND media_id = ? AND source = ?",
+            params![chapter_id, media_id, CHAPTER_MANUAL],
+        )?;
+        Ok(())
+    }
+
+    // ==================== TRACK PREFERENCES ====================
+
+    pub fn get_track_preferences(&self, series_id: i64) -> Result<Option<TrackPreferences>> {
+        match self.conn.query_row(
+            "SELECT series_id, audio_lang, audio_track, sub_lang, sub_track, subs_off
+             FROM track_preferences WHERE series_id = ?",
+            params![series_id],
+            |row| {
+                Ok(TrackPreferences {
+                    series_id: row.get(0)?,
+                    audio_lang: row.get(1)?,
+                    audio_track: row.get(2)?,
+                    sub_lang: row.get(3)?,
+                    sub_track: row.get(4)?,
+                    subs_off: row.get::<_, i32>(5)? != 0,
+                })
+            },
+        ) {
+            Ok(prefs) => Ok(Some(prefs)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn set_track_preferences(&self, prefs: &TrackPreferences) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO track_preferences
+                (series_id, audio_lang, audio_track, sub_lang, sub_track, subs_off, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)",
+            params![prefs.series_id, prefs.audio_lang, prefs.audio_track, prefs.sub_lang,
+                    prefs.sub_track, prefs.subs_off as i32],
+        )?;
+        Ok(())
+    }
+
+    pub fn clear_track_preferences(&self, series_id: i64) -> Result<()> {
+        self.conn.execute("DELETE FROM track_preferences WHERE series_id = ?", params![series_id])?;
+        Ok(())
+    }
+
+    // ==================== OFFLINE DOWNLOADS ====================
+
+    const DOWNLOAD_COLUMNS: &'static str =
+        "d.media_id, COALESCE(m.title, ''), d.cloud_file_id, d.dest_path, d.total_bytes,
+         d.downloaded_bytes, d.md5_checksum, d.status, d.error";
+
+    fn map_download_record(row: &rusqlite::Row) -> Result<DownloadRecord> {
+        Ok(DownloadRecord {
+            media_id: row.get(0)?,
+            title: row.get(1)?,
+            cloud_file_id: row.get(2)?,
+            dest_path: row.get(3)?,
+            total_bytes: row.get(4)?,
+            downloaded_bytes: row.get(5)?,
+            md5_checksum: row.get(6)?,
+            status: row.get(7)?,
+            error: row.get(8)?,
+        })
+    }
+
+    /// Queue (or re-queue) a download. Existing progress on disk is kept for resuming.
+    pub fn queue_download(
+        &self,
+        media_id: i64,
+        cloud_file_id: &str,
+        dest_path: &str,
+        total_bytes: Option<i64>,
+        md5_checksum: Option<&str>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO downloads (media_id, cloud_file_id, dest_path, total_bytes, md5_checksum, status)
+             VALUES (?1, ?2, ?3, ?4, ?5, 'queued')
+             ON CONFLICT(media_id) DO UPDATE SET
+                cloud_file_id = ?2, dest_path = ?3, total_bytes = ?4, md5_checksum = ?5,
+                status = 'queued', error = NULL, updated_at = CURRENT_TIMESTAMP",
+            params![media_id, cloud_file_id, dest_path, total_bytes, md5_checksum],
+        )?;
+        Ok(())
+    }
+
+    /// All downloads, oldest first
+    pub fn get_downloads(&self) -> Result<Vec<DownloadRecord>> {
+        let sql = format!(
+            "SELECT {} FROM downloads d LEFT JOIN media m ON m.id = d.media_id ORDER BY d.created_at",
+            Self::DOWNLOAD_COLUMNS
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let items = stmt.query_map([], Self::map_download_record)?;
+        items.collect()
+    }
+
+    pub fn get_download(&self, media_id: i64) -> Result<Option<DownloadRecord>> {
+        let sql = format!(
+            "SELECT {} FROM downloads d LEFT JOIN media m ON m.id = d.media_id WHERE d.media_id = ?",
+            Self::DOWNLOAD_COLUMNS
+        );
+        match self.conn.query_row(&sql, params![media_id], Self::map_download_record) {
+            Ok(record) => Ok(Some(record)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Next queued downloads in queue order
+    pub fn get_queued_downloads(&self, limit: usize) -> Result<Vec<DownloadRecord>> {
+        let sql = format!(
+            "SELECT {} FROM downloads d LEFT JOIN media m ON m.id = d.media_id
+             WHERE d.status = 'queued' ORDER BY d.created_at LIMIT ?",
+            Self::DOWNLOAD_COLUMNS
+        );
+        let mut stmt = self.conn.prepare(&sql)?;
+        let items = stmt.query_map(params![limit as i64], Self::map_download_record)?;
+        items.collect()
+    }
+
+    pub fn update_download_progress(&self, media_id: i64, downloaded_bytes: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE downloads SET downloaded_bytes = ?, updated_at = CURRENT_TIMESTAMP WHERE media_id = ?",
+            params![downloaded_bytes, media_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn set_download_status(&self, media_id: i64, status: &str, error: Option<&str>) -> Result<()> {
+        self.conn.execute(
+            "UPDATE downloads SET status = ?, error = ?, updated_at = CURRENT_TIMESTAMP WHERE media_id = ?",
+            params![status, error, media_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_download(&self, media_id: i64) -> Result<()> {
+        self.conn.execute("DELETE FROM downloads WHERE media_id = ?", params![media_id])?;
+        Ok(())
+    }
+
+    /// Downloads that were running when the app last exited go back in the queue
+    pub fn requeue_interrupted_downloads(&self) -> Result<usize> {
+        self.conn.execute(
+            "UPDATE downloads SET status = 'queued' WHERE status = 'downloading'",
+            [],
+        )
+    }
+
+    // ==================== JELLYFIN SESSIONS ====================
+
+    /// Store a new login, replacing any earlier token of the same device
+    pub fn add_jellyfin_session(&self, session: &JellyfinSession) -> Result<()> {
+        self.conn.execute("DELETE FROM jellyfin_sessions WHERE device_id = ?", params![session.device_id])?;
+        self.conn.execute(
+            "INSERT INTO jellyfin_sessions (token, device_id, device_name, client, version) VALUES (?, ?, ?, ?, ?)",
+            params![session.token, session.device_id, session.device_name, session.client, session.version],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_jellyfin_session(&self, token: &str) -> Result<Option<JellyfinSession>> {
+        let result = self.conn.query_row(
+            "SELECT token, device_id, device_name, client, version FROM jellyfin_sessions WHERE token = ?",
+            params![token],
+            |row| {
+                Ok(JellyfinSession {
+                    token: row.get(0)?,
+                    device_id: row.get(1)?,
+                    device_name: row.get(2)?,
+                    client: row.get(3)?,
+                    version: row.get(4)?,
+                })
+            },
+        );
+
+        match result {
+            Ok(session) => Ok(Some(session)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn delete_jellyfin_session(&self, token: &str) -> Result<()> {
+        self.conn.execute("DELETE FROM jellyfin_sessions WHERE token = ?", params![token])?;
+        Ok(())
+    }
+
+    /// Log every client out (after the credentials change)
+    pub fn clear_jellyfin_sessions(&self) -> Result<usize> {
+        self.conn.execute("DELETE FROM jellyfin_sessions", [])
+    }
+
     // ==================== APP SETTINGS (for Changes Token etc.) ====================
 
     /// Get a setting value by key
@@ -1121,14 +2129,44 @@ impl Database {
         Ok(())
     }
 
-    /// Get the Google Drive changes page token
-    pub fn get_gdrive_changes_token(&self) -> Result<Option<String>> {
-        self.get_setting("gdrive_changes_token")
+    /// Settings key for one change feed. The legacy single-account My Drive feed keeps its old key.
+    fn changes_token_key(account_id: &str, drive_id: Option<&str>) -> String {
+        match drive_id {
+            None if account_id == crate::gdrive::LEGACY_ACCOUNT_ID => "gdrive_changes_token".to_string(),
+            None => format!("gdrive_changes_token:{}", account_id),
+            Some(drive) => format!("gdrive_changes_token:{}:{}", account_id, drive),
+        }
     }
 
-    /// Set the Google Drive changes page token
-    pub fn set_gdrive_changes_token(&self, token: &str) -> Result<()> {
-        self.set_setting("gdrive_changes_token", token)
+    /// Get the Google Drive changes page token for an account's My Drive or a Shared Drive
+    pub fn get_gdrive_changes_token(&self, account_id: &str, drive_id: Option<&str>) -> Result<Option<String>> {
+        self.get_setting(&Self::changes_token_key(account_id, drive_id))
+    }
+
+    /// Set the Google Drive changes page token for an account's My Drive or a Shared Drive
+    pub fn set_gdrive_changes_token(&self, account_id: &str, drive_id: Option<&str>, token: &str) -> Result<()> {
+        self.set_setting(&Self::changes_token_key(account_id, drive_id), token)
+    }
+
+    /// Move indexed files, tracked folders and change feed tokens from one Drive account id
+    /// to another (used when the legacy single-account slot is renamed to its email)
+    pub fn rename_cloud_account(&self, from: &str, to: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("UPDATE media SET cloud_account_id = ?2 WHERE cloud_account_id = ?1", params![from, to])?;
+        tx.execute("UPDATE cloud_folders SET account_id = ?2 WHERE account_id = ?1", params![from, to])?;
+        tx.execute(
+            "UPDATE OR REPLACE app_settings SET key = ?2 WHERE key = ?1",
+            params![Self::changes_token_key(from, None), Self::changes_token_key(to, None)],
+        )?;
+        // Shared Drive feeds: gdrive_changes_token:<account>:<drive>
+        let old_prefix = format!("gdrive_changes_token:{}:", from);
+        let new_prefix = format!("gdrive_changes_token:{}:", to);
+        tx.execute(
+            "UPDATE OR REPLACE app_settings SET key = ?2 || substr(key, length(?1) + 1)
+             WHERE substr(key, 1, length(?1)) = ?1",
+            params![old_prefix, new_prefix],
+        )?;
+        tx.commit()
     }
 
     /// Get all episodes user has for a series (returns id, season_number, episode_number)
@@ -1453,6 +2491,10 @@ impl Database {
         let params: Vec<&dyn rusqlite::ToSql> = ids.iter().map(|id| id as &dyn rusqlite::ToSql).collect();
         self.conn.execute(&query, params.as_slice())?;
         
+        self.conn.execute("DELETE FROM subtitles WHERE media_id NOT IN (SELECT id FROM media)", [])?;
+        self.conn.execute("DELETE FROM media_streams WHERE media_id NOT IN (SELECT id FROM media)", [])?;
+        self.conn.execute("DELETE FROM chapters WHERE media_id NOT IN (SELECT id FROM media)", [])?;
+        
         Ok(file_paths)
     }
     
@@ -1617,6 +2659,10 @@ impl Database {
 
         // Delete all data from media table
         self.conn.execute("DELETE FROM media", [])?;
+        self.conn.execute("DELETE FROM subtitles", [])?;
+        self.conn.execute("DELETE FROM track_preferences", [])?;
+        self.conn.execute("DELETE FROM media_streams", [])?;
+        self.conn.execute("DELETE FROM chapters", [])?;
 
         // Delete all cached episode metadata (important - stale cache causes missing images)
         self.conn.execute("DELETE FROM cached_episode_metadata", [])?;
@@ -1652,6 +2698,192 @@ impl Database {
         Ok(())
     }
 
+    // ==================== BACKUP & PORTABLE EXPORT ====================
+
+    /// Write a consistent snapshot of the live database to `dest` using SQLite's online backup API
+    pub fn backup_to(&self, dest: &std::path::Path) -> Result<()> {
+        self.conn.backup(rusqlite::DatabaseName::Main, dest, None)
+    }
+
+    /// Replace the live database contents with the snapshot at `src`, then re-run migrations
+    /// so snapshots taken by older versions get any columns added since
+    pub fn restore_from(&mut self, src: &std::path::Path) -> Result<()> {
+        self.conn.restore(rusqlite::DatabaseName::Main, src, None::<fn(rusqlite::backup::Progress)>)?;
+        self.init()
+    }
+
+    /// Get every media row with all columns (for portable export)
+    pub fn get_portable_media(&self) -> Result<Vec<PortableMediaRow>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, title, year, overview, poster_path, file_path, media_type, parent_id,
+                    season_number, episode_number, duration_seconds, resume_position_seconds,
+                    last_watched, tmdb_id, episode_title, still_path,
+                    COALESCE(is_cloud, 0), cloud_file_id, cloud_folder_id, cloud_account_id
+             FROM media
+             ORDER BY CASE WHEN parent_id IS NULL THEN 0 ELSE 1 END, id"
+        )?;
+
+        let items = stmt.query_map([], |row| {
+            Ok(PortableMediaRow {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                year: row.get(2)?,
+                overview: row.get(3)?,
+                poster_path: row.get(4)?,
+                file_path: row.get(5)?,
+                media_type: row.get(6)?,
+                parent_id: row.get(7)?,
+                season_number: row.get(8)?,
+                episode_number: row.get(9)?,
+                duration_seconds: row.get(10)?,
+                resume_position_seconds: row.get(11)?,
+                last_watched: row.get(12)?,
+                tmdb_id: row.get(13)?,
+                episode_title: row.get(14)?,
+                still_path: row.get(15)?,
+                is_cloud: row.get::<_, i32>(16)? == 1,
+                cloud_file_id: row.get(17)?,
+                cloud_folder_id: row.get(18)?,
+                cloud_account_id: row.get(19)?,
+            })
+        })?;
+
+        items.collect()
+    }
+
+    /// Merge exported media rows into this library.
+    /// Rows are matched by cloud_file_id (cloud) or file_path (local) - matches only get their
+    /// watch state updated when the imported state is newer, everything else is inserted with
+    /// parent IDs remapped. `rewrite_path` is applied to local paths before matching.
+    pub fn import_portable_media(
+        &self,
+        rows: &[PortableMediaRow],
+        rewrite_path: &dyn Fn(&str) -> String,
+    ) -> Result<PortableImportCounts> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut counts = PortableImportCounts::default();
+        let mut id_map: std::collections::HashMap<i64, i64> = std::collections::HashMap::new();
+
+        // Parents (movies/shows) must exist before their episodes can be remapped
+        let mut ordered: Vec<&PortableMediaRow> = rows.iter().collect();
+        ordered.sort_by_key(|r| r.parent_id.is_some());
+
+        for row in ordered {
+            let file_path = if row.is_cloud {
+                row.file_path.clone()
+            } else {
+                rewrite_path(&row.file_path)
+            };
+
+            let existing_id: Option<i64> = match (&row.cloud_file_id, row.is_cloud) {
+                (Some(cloud_id), true) => tx.query_row(
+                    "SELECT id FROM media WHERE cloud_file_id = ?",
+                    params![cloud_id],
+                    |r| r.get(0),
+                ).ok(),
+                _ => tx.query_row(
+                    "SELECT id FROM media WHERE file_path = ?",
+                    params![file_path],
+                    |r| r.get(0),
+                ).ok(),
+            };
+
+            if let Some(id) = existing_id {
+                id_map.insert(row.id, id);
+                if row.last_watched.is_some() {
+                    let changed = tx.execute(
+                        "UPDATE media SET resume_position_seconds = ?,
+                         duration_seconds = CASE WHEN ? > 0 THEN ? ELSE duration_seconds END,
+                         last_watched = ?
+                         WHERE id = ? AND (last_watched IS NULL OR last_watched < ?)",
+                        params![
+                            row.resume_position_seconds.unwrap_or(0.0),
+                            row.duration_seconds.unwrap_or(0.0),
+                            row.duration_seconds.unwrap_or(0.0),
+                            row.last_watched,
+                            id,
+                            row.last_watched,
+                        ],
+                    )?;
+                    if changed > 0 {
+                        counts.updated += 1;
+                        continue;
+                    }
+                }
+                counts.skipped += 1;
+                continue;
+            }
+
+            let parent_id = match row.parent_id {
+                Some(old_parent) => match id_map.get(&old_parent) {
+                    Some(&new_parent) => Some(new_parent),
+                    None => {
+                        println!("[IMPORT] Skipping '{}' - parent {} was not imported", row.title, old_parent);
+                        counts.skipped += 1;
+                        continue;
+                    }
+                },
+                None => None,
+            };
+
+            tx.execute(
+                "INSERT INTO media (title, year, overview, poster_path, file_path, media_type, parent_id,
+                                    season_number, episode_number, duration_seconds, resume_position_seconds,
+                                    last_watched, tmdb_id, episode_title, still_path,
+                                    is_cloud, cloud_file_id, cloud_folder_id, cloud_account_id)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    row.title, row.year, row.overview, row.poster_path, file_path, row.media_type, parent_id,
+                    row.season_number, row.episode_number, row.duration_seconds.unwrap_or(0.0),
+                    row.resume_position_seconds.unwrap_or(0.0), row.last_watched, row.tmdb_id,
+                    row.episode_title, row.still_path, row.is_cloud as i32, row.cloud_file_id, row.cloud_folder_id,
+                    row.cloud_account_id,
+                ],
+            )?;
+            id_map.insert(row.id, tx.last_insert_rowid());
+            counts.inserted += 1;
+        }
+
+        tx.commit()?;
+        Ok(counts)
+    }
+
+    /// Insert or update a streaming history entry, keeping whichever side was watched last
+    pub fn upsert_streaming_history_item(&self, item: &StreamingHistoryItem) -> Result<bool> {
+        let existing: Option<(i64, String)> = self.conn.query_row(
+            "SELECT id, last_watched FROM streaming_history
+             WHERE tmdb_id = ? AND media_type = ?
+             AND COALESCE(season, -1) = COALESCE(?, -1)
+             AND COALESCE(episode, -1) = COALESCE(?, -1)",
+            params![item.tmdb_id, item.media_type, item.season, item.episode],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        ).ok();
+
+        match existing {
+            Some((_, last_watched)) if last_watched >= item.last_watched => Ok(false),
+            Some((id, _)) => {
+                self.conn.execute(
+                    "UPDATE streaming_history SET title = ?, poster_path = COALESCE(?, poster_path),
+                     resume_position_seconds = ?, duration_seconds = ?, last_watched = ?
+                     WHERE id = ?",
+                    params![item.title, item.poster_path, item.resume_position_seconds,
+                           item.duration_seconds, item.last_watched, id],
+                )?;
+                Ok(true)
+            }
+            None => {
+                self.conn.execute(
+                    "INSERT INTO streaming_history (tmdb_id, media_type, title, poster_path, season, episode,
+                                                    resume_position_seconds, duration_seconds, last_watched)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                    params![item.tmdb_id, item.media_type, item.title, item.poster_path, item.season,
+                           item.episode, item.resume_position_seconds, item.duration_seconds, item.last_watched],
+                )?;
+                Ok(true)
+            }
+        }
+    }
+
     fn map_media_item(row: &rusqlite::Row) -> rusqlite::Result<MediaItem> {
         let duration: Option<f64> = row.get(7)?;
         let resume_pos: Option<f64> = row.get(8)?;
@@ -0,0 +1,1424 @@
+// DLNA Module
+// UPnP MediaServer so smart TVs and other renderers on the LAN can browse and play the
+// library. SSDP (239.255.255.250:1900) answers M-SEARCH and announces the device; an HTTP
+// server on `dlna_port` serves the device description, a ContentDirectory browsing
+// Movies / TV Shows / Series / Season / Episode with posters from image_cache, and the
+// media itself. Local files and offline copies are served with Range support, Drive files
+// are relayed from the stream proxy, and codecs TVs can't decode go through the transcoder.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::net::{IpAddr, Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::thread::JoinHandle;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tiny_http::{Header, Request, Response, Server, StatusCode};
+
+use crate::database::{self, Database, MediaItem, MediaTechInfo};
+use crate::downloads;
+use crate::stream_proxy;
+use crate::transcoder::{self, QualityProfile, TranscodeInput, TranscodeOptions};
+
+const SSDP_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SSDP_PORT: u16 = 1900;
+// How long renderers may cache an announcement, and how often it's repeated
+const SSDP_MAX_AGE: u64 = 1800;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(600);
+const SERVER_HEADER: &str = concat!("StreamVault/", env!("CARGO_PKG_VERSION"), " UPnP/1.0 DLNADOC/1.50");
+
+const MEDIA_SERVER: &str = "urn:schemas-upnp-org:device:MediaServer:1";
+const CONTENT_DIRECTORY: &str = "urn:schemas-upnp-org:service:ContentDirectory:1";
+const CONNECTION_MANAGER: &str = "urn:schemas-upnp-org:service:ConnectionManager:1";
+
+// Codecs TVs decode natively; anything else is transcoded to H.264/AAC
+const TV_VIDEO_CODECS: [&str; 5] = ["h264", "hevc", "mpeg2video", "mpeg4", "vc1"];
+const TV_AUDIO_CODECS: [&str; 6] = ["aac", "ac3", "eac3", "mp3", "mp2", "pcm_s16le"];
+// Containers that rarely play on TVs whatever they hold
+const UNSUPPORTED_CONTAINERS: [&str; 3] = ["rm", "rmvb", "flv"];
+
+// DLNA flags: direct files are byte-seekable, transcodes seek by time (TimeSeekRange.dlna.org)
+const DLNA_DIRECT: &str = "DLNA.ORG_OP=01;DLNA.ORG_CI=0;DLNA.ORG_FLAGS=01700000000000000000000000000000";
+const DLNA_TRANSCODED: &str = "DLNA.ORG_OP=10;DLNA.ORG_CI=1;DLNA.ORG_FLAGS=01700000000000000000000000000000";
+
+/// Server settings, built from the config
+#[derive(Debug, Clone, PartialEq)]
+pub struct DlnaSettings {
+    pub friendly_name: String,
+    pub port: u16,
+    pub ffmpeg_path: Option<String>,
+    /// Quality used when a file has to be transcoded
+    pub profile: QualityProfile,
+}
+
+/// Shown in Settings so the user can check the server is reachable
+#[derive(Debug, Clone, Serialize)]
+pub struct DlnaStatus {
+    pub running: bool,
+    pub friendly_name: Option<String>,
+    pub port: Option<u16>,
+    /// Device description URL on the LAN address
+    pub description_url: Option<String>,
+}
+
+/// Who we are on SSDP
+#[derive(Debug, Clone)]
+struct SsdpIdentity {
+    uuid: String,
+    http_port: u16,
+}
+
+/// What the HTTP handlers need
+struct DlnaContext {
+    identity: SsdpIdentity,
+    settings: DlnaSettings,
+    /// ContentDirectory SystemUpdateID; changes each run so renderers drop cached listings
+    update_id: u32,
+}
+
+struct RunningServer {
+    http: Arc<Server>,
+    stop: Arc<AtomicBool>,
+    /// The HTTP accept loop and SSDP responder, joined on stop so a restart can rebind
+    threads: Vec<JoinHandle<()>>,
+    identity: SsdpIdentity,
+    friendly_name: String,
+}
+
+lazy_static::lazy_static! {
+    static ref SERVER: Mutex<Option<RunningServer>> = Mutex::new(None);
+}
+
+/// Start, restart or stop the server to match the settings (None = disabled)
+pub fn apply(settings: Option<DlnaSettings>) -> Result<(), String> {
+    stop();
+    let settings = match settings {
+        Some(settings) => settings,
+        None => return Ok(()),
+    };
+
+    let ssdp = ssdp_socket()?;
+    let http = Server::http(("0.0.0.0", settings.port))
+        .map_err(|e| format!("Failed to start DLNA server on port {}: {}", settings.port, e))?;
+    let http_port = http
+        .server_addr()
+        .to_ip()
+        .map(|addr| addr.port())
+        .ok_or_else(|| "DLNA server is not bound to a TCP port".to_string())?;
+
+    let http = Arc::new(http);
+    let stop = Arc::new(AtomicBool::new(false));
+    let identity = SsdpIdentity { uuid: device_uuid(), http_port };
+    let friendly_name = settings.friendly_name.clone();
+    let context = Arc::new(DlnaContext {
+        identity: identity.clone(),
+        settings,
+        update_id: chrono::Utc::now().timestamp() as u32,
+    });
+
+    let server = http.clone();
+    let http_thread = std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            // TVs open several connections at once (browsing, probing, ranged reads)
+            let context = context.clone();
+            std::thread::spawn(move || handle_request(request, &context));
+        }
+        println!("[DLNA] HTTP server stopped");
+    });
+
+    let (ssdp_identity, ssdp_stop) = (identity.clone(), stop.clone());
+    let ssdp_thread = std::thread::spawn(move || run_ssdp(ssdp, ssdp_identity, ssdp_stop));
+
+    let (announce_identity, announce_stop) = (identity.clone(), stop.clone());
+    std::thread::spawn(move || announce_loop(announce_identity, announce_stop));
+
+    println!("[DLNA] \"{}\" serving on port {} ({})", friendly_name, http_port, identity.uuid);
+    let mut running = SERVER.lock().map_err(|e| e.to_string())?;
+    *running = Some(RunningServer { http, stop, threads: vec![http_thread, ssdp_thread], identity, friendly_name });
+    Ok(())
+}
+
+/// Stop the server and tell renderers it's gone
+pub fn stop() {
+    let server = match SERVER.lock() {
+        Ok(mut server) => server.take(),
+        Err(_) => None,
+    };
+    if let Some(server) = server {
+        server.stop.store(true, Ordering::SeqCst);
+        server.http.unblock();
+        for thread in server.threads {
+            let _ = thread.join();
+        }
+        send_notify(&server.identity, "ssdp:byebye");
+        println!("[DLNA] Server stopped");
+    }
+}
+
+pub fn status() -> DlnaStatus {
+    let server = SERVER.lock().ok();
+    match server.as_ref().and_then(|s| s.as_ref()) {
+        Some(server) => DlnaStatus {
+            running: true,
+            friendly_name: Some(server.friendly_name.clone()),
+            port: Some(server.identity.http_port),
+            description_url: local_ip_for(IpAddr::V4(SSDP_ADDR)).map(|ip| server.identity.location(ip)),
+        },
+        None => DlnaStatus { running: false, friendly_name: None, port: None, description_url: None },
+    }
+}
+
+/// Stable device id, so TVs keep recognising the server across restarts
+fn device_uuid() -> String {
+    let seed = machine_uid::get()
+        .unwrap_or_else(|_| database::get_app_data_dir().to_string_lossy().to_string());
+    let hash = Sha256::digest(format!("streamvault-dlna:{}", seed).as_bytes());
+    let hex: String = hash.iter().take(16).map(|b| format!("{:02x}", b)).collect();
+    format!("uuid:{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+// ============================================================================
+// SSDP
+// ============================================================================
+
+impl SsdpIdentity {
+    fn location(&self, ip: IpAddr) -> String {
+        format!("http://{}:{}/description.xml", ip, self.http_port)
+    }
+
+    /// (NT/ST, USN) pairs the device is announced and found by
+    fn notification_types(&self) -> Vec<(String, String)> {
+        let mut types = vec![
+            ("upnp:rootdevice".to_string(), format!("{}::upnp:rootdevice", self.uuid)),
+            (self.uuid.clone(), self.uuid.clone()),
+        ];
+        for urn in [MEDIA_SERVER, CONTENT_DIRECTORY, CONNECTION_MANAGER] {
+            types.push((urn.to_string(), format!("{}::{}", self.uuid, urn)));
+        }
+        types
+    }
+}
+
+/// UDP socket on the SSDP port, joined to the multicast group
+fn ssdp_socket() -> Result<UdpSocket, String> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))
+        .map_err(|e| format!("Failed to create SSDP socket: {}", e))?;
+    // Other UPnP software (and Windows' own SSDP service) listens on 1900 too
+    socket.set_reuse_address(true).map_err(|e| e.to_string())?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true).map_err(|e| e.to_string())?;
+    socket
+        .bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, SSDP_PORT).into())
+        .map_err(|e| format!("Failed to bind SSDP port {}: {}", SSDP_PORT, e))?;
+
+    let socket: UdpSocket = socket.into();
+    socket
+        .join_multicast_v4(&SSDP_ADDR, &Ipv4Addr::UNSPECIFIED)
+        .map_err(|e| format!("Failed to join SSDP multicast group: {}", e))?;
+    Ok(socket)
+}
+
+/// Answer M-SEARCH requests until stopped
+fn run_ssdp(socket: UdpSocket, identity: SsdpIdentity, stop: Arc<AtomicBool>) {
+    let _ = socket.set_read_timeout(Some(Duration::from_secs(1)));
+    let mut buf = [0u8; 2048];
+    while !stop.load(Ordering::SeqCst) {
+        let (len, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            // Read timeout (checks the stop flag) or an ICMP error from an earlier reply
+            Err(_) => continue,
+        };
+        let message = String::from_utf8_lossy(&buf[..len]);
+        let location = match local_ip_for(peer.ip()) {
+            Some(ip) => identity.location(ip),
+            None => continue,
+        };
+        for response in search_responses(&message, &identity, &location) {
+            let _ = socket.send_to(response.as_bytes(), peer);
+        }
+    }
+    println!("[DLNA] SSDP responder stopped");
+}
+
+/// Replies to an SSDP message: one per matching search target for an M-SEARCH, none otherwise
+fn search_responses(message: &str, identity: &SsdpIdentity, location: &str) -> Vec<String> {
+    let mut lines = message.lines();
+    if !lines.next().is_some_and(|line| line.trim().starts_with("M-SEARCH")) {
+        return Vec::new();
+    }
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_uppercase(), value.trim().to_string()))
+        .collect();
+    if headers.get("MAN").map(|man| man.trim_matches('"')) != Some("ssdp:discover") {
+        return Vec::new();
+    }
+    let target = match headers.get("ST") {
+        Some(target) => target.as_str(),
+        None => return Vec::new(),
+    };
+
+    let date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S GMT");
+    identity
+        .notification_types()
+        .into_iter()
+        .filter(|(nt, _)| target == "ssdp:all" || target == nt)
+        .map(|(nt, usn)| {
+            format!(
+                "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age={}\r\nDATE: {}\r\nEXT:\r\nLOCATION: {}\r\nSERVER: {}\r\nST: {}\r\nUSN: {}\r\nContent-Length: 0\r\n\r\n",
+                SSDP_MAX_AGE, date, location, SERVER_HEADER, nt, usn
+            )
+        })
+        .collect()
+}
+
+/// Send ssdp:alive now and every ANNOUNCE_INTERVAL until stopped
+fn announce_loop(identity: SsdpIdentity, stop: Arc<AtomicBool>) {
+    let mut last_announce: Option<Instant> = None;
+    while !stop.load(Ordering::SeqCst) {
+        if last_announce.is_none_or(|at| at.elapsed() >= ANNOUNCE_INTERVAL) {
+            send_notify(&identity, "ssdp:alive");
+            last_announce = Some(Instant::now());
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// Multicast a NOTIFY for every notification type ("ssdp:alive" or "ssdp:byebye")
+fn send_notify(identity: &SsdpIdentity, nts: &str) {
+    let socket = match UdpSocket::bind("0.0.0.0:0") {
+        Ok(socket) => socket,
+        Err(e) => {
+            println!("[DLNA] Failed to send {}: {}", nts, e);
+            return;
+        }
+    };
+    let _ = socket.set_multicast_ttl_v4(2);
+    let location = local_ip_for(IpAddr::V4(SSDP_ADDR)).map(|ip| identity.location(ip));
+
+    for (nt, usn) in identity.notification_types() {
+        let mut message = format!(
+            "NOTIFY * HTTP/1.1\r\nHOST: {}:{}\r\nNT: {}\r\nNTS: {}\r\nUSN: {}\r\n",
+            SSDP_ADDR, SSDP_PORT, nt, nts, usn
+        );
+        if nts == "ssdp:alive" {
+            let location = match location {
+                Some(ref location) => location,
+                None => return,
+            };
+            message.push_str(&format!(
+                "CACHE-CONTROL: max-age={}\r\nLOCATION: {}\r\nSERVER: {}\r\n",
+                SSDP_MAX_AGE, location, SERVER_HEADER
+            ));
+        }
+        message.push_str("\r\n");
+        let _ = socket.send_to(message.as_bytes(), (SSDP_ADDR, SSDP_PORT));
+    }
+}
+
+/// Local address of the interface that reaches `peer` (no packet is sent)
+fn local_ip_for(peer: IpAddr) -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect((peer, SSDP_PORT)).ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+// ============================================================================
+// HTTP
+// ============================================================================
+
+fn handle_request(request: Request, context: &DlnaContext) {
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+    let method = request.method().as_str().to_ascii_uppercase();
+    let readable = method == "GET" || method == "HEAD";
+
+    match path.as_str() {
+        "/description.xml" if readable => respond_xml(request, device_description(context)),
+        "/ContentDirectory.xml" if readable => respond_xml(request, content_directory_scpd()),
+        "/ConnectionManager.xml" if readable => respond_xml(request, connection_manager_scpd()),
+        "/control/ContentDirectory" if method == "POST" => handle_soap(request, CONTENT_DIRECTORY, context),
+        "/control/ConnectionManager" if method == "POST" => handle_soap(request, CONNECTION_MANAGER, context),
+        p if p.starts_with("/event/") && (method == "SUBSCRIBE" || method == "UNSUBSCRIBE") => {
+            // Nothing is evented, but renderers expect a subscription to be accepted
+            let headers = [
+                header("SID", &format!("{}-{}", context.identity.uuid, p.trim_start_matches("/event/"))),
+                header("TIMEOUT", &format!("Second-{}", SSDP_MAX_AGE)),
+            ];
+            respond_empty(request, 200, headers.into_iter().flatten().collect());
+        }
+        p if readable && p.starts_with("/media/") => match p["/media/".len()..].parse() {
+            Ok(media_id) => {
+                let settings = &context.settings;
+                serve_media(request, media_id, settings.ffmpeg_path.as_deref(), &settings.profile, None, None)
+            }
+            Err(_) => respond_empty(request, 404, Vec::new()),
+        },
+        p if readable && p.starts_with("/art/") => match p["/art/".len()..].parse() {
+            Ok(media_id) => serve_art(request, media_id),
+            Err(_) => respond_empty(request, 404, Vec::new()),
+        },
+        _ => respond_empty(request, 404, Vec::new()),
+    }
+}
+
+/// http://host:port as the renderer addressed us, for URLs inside listings
+fn base_url(request: &Request, context: &DlnaContext) -> String {
+    if let Some(host) = request_header(request, "Host") {
+        return format!("http://{}", host);
+    }
+    let ip = request
+        .remote_addr()
+        .and_then(|peer| local_ip_for(peer.ip()))
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    format!("http://{}:{}", ip, context.identity.http_port)
+}
+
+fn header(name: &str, value: &str) -> Option<Header> {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).ok()
+}
+
+fn request_header(request: &Request, name: &str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.as_str().as_str().eq_ignore_ascii_case(name))
+        .map(|h| h.value.as_str().to_string())
+}
+
+fn respond_empty(request: Request, status: u16, headers: Vec<Header>) {
+    let _ = request.respond(Response::new(StatusCode(status), headers, std::io::empty(), Some(0), None));
+}
+
+fn respond_xml(request: Request, xml: String) {
+    respond_xml_status(request, 200, xml);
+}
+
+fn respond_xml_status(request: Request, status: u16, xml: String) {
+    let headers = [
+        header("Content-Type", "text/xml; charset=\"utf-8\""),
+        header("Server", SERVER_HEADER),
+    ];
+    let response = Response::from_string(xml).with_status_code(status);
+    let response = headers.into_iter().flatten().fold(response, |r, h| r.with_header(h));
+    let _ = request.respond(response);
+}
+
+/// Content-Type plus the DLNA headers TVs look for before playing
+fn media_headers(mime: &str, features: &str) -> Vec<Header> {
+    [
+        header("Content-Type", mime),
+        header("Server", SERVER_HEADER),
+        header("transferMode.dlna.org", "Streaming"),
+        header("contentFeatures.dlna.org", features),
+    ]
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+// ============================================================================
+// Device and service descriptions
+// ============================================================================
+
+fn device_description(context: &DlnaContext) -> String {
+    let service = |urn: &str, name: &str| {
+        format!(
+            "<service><serviceType>{}</serviceType><serviceId>urn:upnp-org:serviceId:{name}</serviceId>\
+             <SCPDURL>/{name}.xml</SCPDURL><controlURL>/control/{name}</controlURL>\
+             <eventSubURL>/event/{name}</eventSubURL></service>",
+            urn,
+            name = name
+        )
+    };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <root xmlns=\"urn:schemas-upnp-org:device-1-0\" xmlns:dlna=\"urn:schemas-dlna-org:device-1-0\">\
+         <specVersion><major>1</major><minor>0</minor></specVersion>\
+         <device><deviceType>{}</deviceType><friendlyName>{}</friendlyName>\
+         <manufacturer>StreamVault</manufacturer><modelName>StreamVault</modelName>\
+         <modelNumber>{}</modelNumber><UDN>{}</UDN><dlna:X_DLNADOC>DMS-1.50</dlna:X_DLNADOC>\
+         <serviceList>{}{}</serviceList></device></root>",
+        MEDIA_SERVER,
+        xml_escape(&context.settings.friendly_name),
+        env!("CARGO_PKG_VERSION"),
+        context.identity.uuid,
+        service(CONTENT_DIRECTORY, "ContentDirectory"),
+        service(CONNECTION_MANAGER, "ConnectionManager"),
+    )
+}
+
+/// An action's name and its (argument, direction, related state variable) list
+type ScpdAction<'a> = (&'a str, &'a [(&'a str, &'a str, &'a str)]);
+
+/// Service description, with state variables as (name, data type, evented)
+fn scpd(actions: &[ScpdAction], variables: &[(&str, &str, bool)]) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <scpd xmlns=\"urn:schemas-upnp-org:service-1-0\">\
+         <specVersion><major>1</major><minor>0</minor></specVersion><actionList>",
+    );
+    for (name, arguments) in actions {
+        xml.push_str(&format!("<action><name>{}</name><argumentList>", name));
+        for (argument, direction, variable) in arguments.iter() {
+            xml.push_str(&format!(
+                "<argument><name>{}</name><direction>{}</direction><relatedStateVariable>{}</relatedStateVariable></argument>",
+                argument, direction, variable
+            ));
+        }
+        xml.push_str("</argumentList></action>");
+    }
+    xml.push_str("</actionList><serviceStateTable>");
+    for (name, data_type, evented) in variables {
+        xml.push_str(&format!(
+            "<stateVariable sendEvents=\"{}\"><name>{}</name><dataType>{}</dataType></stateVariable>",
+            if *evented { "yes" } else { "no" },
+            name,
+            data_type
+        ));
+    }
+    xml.push_str("</serviceStateTable></scpd>");
+    xml
+}
+
+fn content_directory_scpd() -> String {
+    scpd(
+        &[
+            ("Browse", &[
+                ("ObjectID", "in", "A_ARG_TYPE_ObjectID"),
+                ("BrowseFlag", "in", "A_ARG_TYPE_BrowseFlag"),
+                ("Filter", "in", "A_ARG_TYPE_Filter"),
+                ("StartingIndex", "in", "A_ARG_TYPE_Index"),
+                ("RequestedCount", "in", "A_ARG_TYPE_Count"),
+                ("SortCriteria", "in", "A_ARG_TYPE_SortCriteria"),
+                ("Result", "out", "A_ARG_TYPE_Result"),
+                ("NumberReturned", "out", "A_ARG_TYPE_Count"),
+                ("TotalMatches", "out", "A_ARG_TYPE_Count"),
+                ("UpdateID", "out", "A_ARG_TYPE_UpdateID"),
+            ]),
+            ("GetSearchCapabilities", &[("SearchCaps", "out", "SearchCapabilities")]),
+            ("GetSortCapabilities", &[("SortCaps", "out", "SortCapabilities")]),
+            ("GetSystemUpdateID", &[("Id", "out", "SystemUpdateID")]),
+        ],
+        &[
+            ("A_ARG_TYPE_ObjectID", "string", false),
+            ("A_ARG_TYPE_BrowseFlag", "string", false),
+            ("A_ARG_TYPE_Filter", "string", false),
+            ("A_ARG_TYPE_Index", "ui4", false),
+            ("A_ARG_TYPE_Count", "ui4", false),
+            ("A_ARG_TYPE_SortCriteria", "string", false),
+            ("A_ARG_TYPE_Result", "string", false),
+            ("A_ARG_TYPE_UpdateID", "ui4", false),
+            ("SearchCapabilities", "string", false),
+            ("SortCapabilities", "string", false),
+            ("SystemUpdateID", "ui4", true),
+        ],
+    )
+}
+
+fn connection_manager_scpd() -> String {
+    scpd(
+        &[
+            ("GetProtocolInfo", &[("Source", "out", "SourceProtocolInfo"), ("Sink", "out", "SinkProtocolInfo")]),
+            ("GetCurrentConnectionIDs", &[("ConnectionIDs", "out", "CurrentConnectionIDs")]),
+            ("GetCurrentConnectionInfo", &[
+                ("ConnectionID", "in", "A_ARG_TYPE_ConnectionID"),
+                ("RcsID", "out", "A_ARG_TYPE_RcsID"),
+                ("AVTransportID", "out", "A_ARG_TYPE_AVTransportID"),
+                ("ProtocolInfo", "out", "A_ARG_TYPE_ProtocolInfo"),
+                ("PeerConnectionManager", "out", "A_ARG_TYPE_ConnectionManager"),
+                ("PeerConnectionID", "out", "A_ARG_TYPE_ConnectionID"),
+                ("Direction", "out", "A_ARG_TYPE_Direction"),
+                ("Status", "out", "A_ARG_TYPE_ConnectionStatus"),
+            ]),
+        ],
+        &[
+            ("SourceProtocolInfo", "string", true),
+            ("SinkProtocolInfo", "string", true),
+            ("CurrentConnectionIDs", "string", true),
+            ("A_ARG_TYPE_ConnectionID", "i4", false),
+            ("A_ARG_TYPE_RcsID", "i4", false),
+            ("A_ARG_TYPE_AVTransportID", "i4", false),
+            ("A_ARG_TYPE_ProtocolInfo", "string", false),
+            ("A_ARG_TYPE_ConnectionManager", "string", false),
+            ("A_ARG_TYPE_Direction", "string", false),
+            ("A_ARG_TYPE_ConnectionStatus", "string", false),
+        ],
+    )
+}
+
+// ============================================================================
+// SOAP control
+// ============================================================================
+
+fn handle_soap(mut request: Request, service: &str, context: &DlnaContext) {
+    let mut body = String::new();
+    if request.as_reader().read_to_string(&mut body).is_err() {
+        return respond_empty(request, 400, Vec::new());
+    }
+    // SOAPACTION: "urn:schemas-upnp-org:service:ContentDirectory:1#Browse"
+    let action = request_header(&request, "SOAPACTION")
+        .and_then(|value| value.trim_matches('"').split('#').nth(1).map(str::to_string))
+        .unwrap_or_default();
+    let base_url = base_url(&request, context);
+
+    let result: Result<Vec<(&str, String)>, (u16, String)> = match (service, action.as_str()) {
+        (CONTENT_DIRECTORY, "Browse") => browse(&body, &base_url, context).map_err(|e| (701, e)),
+        (CONTENT_DIRECTORY, "GetSystemUpdateID") => Ok(vec![("Id", context.update_id.to_string())]),
+        (CONTENT_DIRECTORY, "GetSearchCapabilities") => Ok(vec![("SearchCaps", String::new())]),
+        (CONTENT_DIRECTORY, "GetSortCapabilities") => Ok(vec![("SortCaps", String::new())]),
+        (CONNECTION_MANAGER, "GetProtocolInfo") => Ok(vec![("Source", source_protocol_info()), ("Sink", String::new())]),
+        (CONNECTION_MANAGER, "GetCurrentConnectionIDs") => Ok(vec![("ConnectionIDs", "0".to_string())]),
+        (CONNECTION_MANAGER, "GetCurrentConnectionInfo") => Ok(vec![
+            ("RcsID", "-1".to_string()),
+            ("AVTransportID", "-1".to_string()),
+            ("ProtocolInfo", String::new()),
+            ("PeerConnectionManager", String::new()),
+            ("PeerConnectionID", "-1".to_string()),
+            ("Direction", "Output".to_string()),
+            ("Status", "OK".to_string()),
+        ]),
+        _ => Err((401, "Invalid Action".to_string())),
+    };
+
+    match result {
+        Ok(arguments) => {
+            let arguments: String = arguments
+                .iter()
+                .map(|(name, value)| format!("<{0}>{1}</{0}>", name, xml_escape(value)))
+                .collect();
+            respond_xml(request, soap_envelope(&format!(
+                "<u:{0}Response xmlns:u=\"{1}\">{2}</u:{0}Response>",
+                action, service, arguments
+            )));
+        }
+        Err((code, description)) => {
+            println!("[DLNA] {} failed: {}", action, description);
+            respond_xml_status(request, 500, soap_envelope(&format!(
+                "<s:Fault><faultcode>s:Client</faultcode><faultstring>UPnPError</faultstring><detail>\
+                 <UPnPError xmlns=\"urn:schemas-upnp-org:control-1-0\"><errorCode>{}</errorCode>\
+                 <errorDescription>{}</errorDescription></UPnPError></detail></s:Fault>",
+                code,
+                xml_escape(&description)
+            )));
+        }
+    }
+}
+
+fn soap_envelope(body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+         s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body>{}</s:Body></s:Envelope>",
+        body
+    )
+}
+
+/// Formats the server can send, for ConnectionManager
+fn source_protocol_info() -> String {
+    ["video/mp4", "video/x-matroska", "video/x-msvideo", "video/mp2t", "video/quicktime", "video/mpeg", "video/webm"]
+        .iter()
+        .map(|mime| format!("http-get:*:{}:*", mime))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// ============================================================================
+// ContentDirectory
+// ============================================================================
+
+/// ContentDirectory object ids
+#[derive(Debug, Clone, PartialEq)]
+enum Object {
+    Root,
+    Movies,
+    Shows,
+    Series(i64),
+    Season(i64, i32),
+    Video(i64),
+}
+
+impl Object {
+    fn parse(id: &str) -> Option<Object> {
+        let parts: Vec<&str> = id.split('/').collect();
+        match parts.as_slice() {
+            ["0"] => Some(Object::Root),
+            ["movies"] => Some(Object::Movies),
+            ["shows"] => Some(Object::Shows),
+            ["series", id] => Some(Object::Series(id.parse().ok()?)),
+            ["season", series, season] => Some(Object::Season(series.parse().ok()?, season.parse().ok()?)),
+            ["video", id] => Some(Object::Video(id.parse().ok()?)),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> String {
+        match self {
+            Object::Root => "0".to_string(),
+            Object::Movies => "movies".to_string(),
+            Object::Shows => "shows".to_string(),
+            Object::Series(id) => format!("series/{}", id),
+            Object::Season(series, season) => format!("season/{}/{}", series, season),
+            Object::Video(id) => format!("video/{}", id),
+        }
+    }
+}
+
+/// A DIDL-Lite entry
+enum Entry {
+    Container {
+        object: Object,
+        parent: String,
+        title: String,
+        child_count: usize,
+        /// Media id whose artwork is served at /art/{id}
+        art: Option<i64>,
+    },
+    Video {
+        media: Box<MediaItem>,
+        parent: String,
+        title: String,
+        duration: Option<f64>,
+        mime: &'static str,
+        transcoded: bool,
+        art: Option<i64>,
+    },
+}
+
+fn browse(body: &str, base_url: &str, context: &DlnaContext) -> Result<Vec<(&'static str, String)>, String> {
+    let db = Database::new(&database::get_database_path()).map_err(|e| e.to_string())?;
+    browse_library(&db, body, base_url, context)
+}
+
+fn browse_library(
+    db: &Database,
+    body: &str,
+    base_url: &str,
+    context: &DlnaContext,
+) -> Result<Vec<(&'static str, String)>, String> {
+    let object_id = xml_value(body, "ObjectID").unwrap_or_else(|| "0".to_string());
+    let object = Object::parse(&object_id).ok_or_else(|| format!("No such object: {}", object_id))?;
+    let start: usize = xml_value(body, "StartingIndex").and_then(|v| v.trim().parse().ok()).unwrap_or(0);
+    let count: usize = xml_value(body, "RequestedCount").and_then(|v| v.trim().parse().ok()).unwrap_or(0);
+
+    let entries = if xml_value(body, "BrowseFlag").as_deref() == Some("BrowseMetadata") {
+        vec![metadata(db, &object, context)?]
+    } else {
+        children(db, &object, context)?
+    };
+
+    let total = entries.len();
+    // RequestedCount 0 means everything from StartingIndex on
+    let page: Vec<Entry> = entries
+        .into_iter()
+        .skip(start)
+        .take(if count == 0 { usize::MAX } else { count })
+        .collect();
+
+    Ok(vec![
+        ("Result", didl(&page, base_url)),
+        ("NumberReturned", page.len().to_string()),
+        ("TotalMatches", total.to_string()),
+        ("UpdateID", context.update_id.to_string()),
+    ])
+}
+
+fn children(db: &Database, object: &Object, context: &DlnaContext) -> Result<Vec<Entry>, String> {
+    match *object {
+        Object::Root => {
+            let movies = db.get_library("movie", None).map_err(|e| e.to_string())?.len();
+            let shows = db.get_library("tvshow", None).map_err(|e| e.to_string())?.len();
+            Ok(vec![
+                folder(Object::Movies, "0", "Movies", movies),
+                folder(Object::Shows, "0", "TV Shows", shows),
+            ])
+        }
+        Object::Movies => Ok(db
+            .get_library("movie", None)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .map(|movie| video_entry(db, movie, Object::Movies.id(), context))
+            .collect()),
+        Object::Shows => {
+            let shows = db.get_library("tvshow", None).map_err(|e| e.to_string())?;
+            Ok(shows
+                .into_iter()
+                .map(|show| {
+                    let seasons = seasons(db, show.id).len();
+                    series_entry(show, seasons)
+                })
+                .collect())
+        }
+        Object::Series(series_id) => Ok(seasons(db, series_id)
+            .into_iter()
+            .map(|(season, episodes)| season_entry(series_id, season, episodes))
+            .collect()),
+        Object::Season(series_id, season) => {
+            let parent = object.id();
+            Ok(db
+                .get_episodes(series_id)
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .filter(|episode| episode.season_number.unwrap_or(0) == season)
+                .map(|episode| video_entry(db, episode, parent.clone(), context))
+                .collect())
+        }
+        Object::Video(_) => Ok(Vec::new()),
+    }
+}
+
+fn metadata(db: &Database, object: &Object, context: &DlnaContext) -> Result<Entry, String> {
+    match *object {
+        Object::Root => Ok(folder(Object::Root, "-1", &context.settings.friendly_name, 2)),
+        Object::Movies => {
+            let movies = db.get_library("movie", None).map_err(|e| e.to_string())?.len();
+            Ok(folder(Object::Movies, "0", "Movies", movies))
+        }
+        Object::Shows => {
+            let shows = db.get_library("tvshow", None).map_err(|e| e.to_string())?.len();
+            Ok(folder(Object::Shows, "0", "TV Shows", shows))
+        }
+        Object::Series(series_id) => {
+            let show = db.get_media_by_id(series_id).map_err(|_| format!("No such object: {}", object.id()))?;
+            let seasons = seasons(db, series_id).len();
+            Ok(series_entry(show, seasons))
+        }
+        Object::Season(series_id, season) => {
+            let episodes = seasons(db, series_id).get(&season).copied().unwrap_or(0);
+            Ok(season_entry(series_id, season, episodes))
+        }
+        Object::Video(media_id) => {
+            let media = db.get_media_by_id(media_id).map_err(|_| format!("No such object: {}", object.id()))?;
+            let parent = match (media.parent_id, media.season_number) {
+                (Some(series_id), season) => Object::Season(series_id, season.unwrap_or(0)).id(),
+                _ => Object::Movies.id(),
+            };
+            Ok(video_entry(db, media, parent, context))
+        }
+    }
+}
+
+/// Episode count per season number of a series
+fn seasons(db: &Database, series_id: i64) -> BTreeMap<i32, usize> {
+    let mut seasons = BTreeMap::new();
+    for episode in db.get_episodes(series_id).unwrap_or_default() {
+        *seasons.entry(episode.season_number.unwrap_or(0)).or_insert(0) += 1;
+    }
+    seasons
+}
+
+fn folder(object: Object, parent: &str, title: &str, child_count: usize) -> Entry {
+    Entry::Container { object, parent: parent.to_string(), title: title.to_string(), child_count, art: None }
+}
+
+fn series_entry(show: MediaItem, seasons: usize) -> Entry {
+    let art = artwork_path(&show).map(|_| show.id);
+    Entry::Container {
+        object: Object::Series(show.id),
+        parent: Object::Shows.id(),
+        title: show.title,
+        child_count: seasons,
+        art,
+    }
+}
+
+fn season_entry(series_id: i64, season: i32, episodes: usize) -> Entry {
+    let title = if season == 0 { "Specials".to_string() } else { format!("Season {}", season) };
+    Entry::Container {
+        object: Object::Season(series_id, season),
+        parent: Object::Series(series_id).id(),
+        title,
+        child_count: episodes,
+        art: Some(series_id),
+    }
+}
+
+fn video_entry(db: &Database, media: MediaItem, parent: String, context: &DlnaContext) -> Entry {
+    let info = db.get_media_tech_info(media.id).ok().flatten();
+    let file_name = media.file_path.clone().unwrap_or_default();
+    let transcoded = context.settings.ffmpeg_path.is_some() && needs_transcode(info.as_ref(), &file_name);
+    let title = match media.episode_number {
+        Some(episode) if media.media_type == "tvepisode" => format!(
+            "{}. {}",
+            episode,
+            media.episode_title.clone().unwrap_or_else(|| format!("Episode {}", episode))
+        ),
+        _ => media.title.clone(),
+    };
+    let art = if artwork_path(&media).is_some() { Some(media.id) } else { media.parent_id };
+    Entry::Video {
+        duration: info.as_ref().and_then(|i| i.duration_seconds).or(media.duration_seconds),
+        mime: if transcoded { "video/mp4" } else { video_mime(&file_name) },
+        media: Box::new(media),
+        parent,
+        title,
+        transcoded,
+        art,
+    }
+}
+
+fn didl(entries: &[Entry], base_url: &str) -> String {
+    let mut xml = String::from(
+        "<DIDL-Lite xmlns=\"urn:schemas-upnp-org:metadata-1-0/DIDL-Lite/\" \
+         xmlns:dc=\"http://purl.org/dc/elements/1.1/\" \
+         xmlns:upnp=\"urn:schemas-upnp-org:metadata-1-0/upnp/\" \
+         xmlns:dlna=\"urn:schemas-dlna-org:metadata-1-0/\">",
+    );
+    let art_uri = |art: &Option<i64>| {
+        art.map(|id| {
+            format!("<upnp:albumArtURI dlna:profileID=\"JPEG_TN\">{}/art/{}</upnp:albumArtURI>", base_url, id)
+        })
+        .unwrap_or_default()
+    };
+
+    for entry in entries {
+        match entry {
+            Entry::Container { object, parent, title, child_count, art } => {
+                xml.push_str(&format!(
+                    "<container id=\"{}\" parentID=\"{}\" restricted=\"1\" searchable=\"0\" childCount=\"{}\">\
+                     <dc:title>{}</dc:title><upnp:class>object.container.storageFolder</upnp:class>{}</container>",
+                    xml_escape(&object.id()),
+                    xml_escape(parent),
+                    child_count,
+                    xml_escape(title),
+                    art_uri(art)
+                ));
+            }
+            Entry::Video { media, parent, title, duration, mime, transcoded, art } => {
+                let class = if media.media_type == "movie" { "object.item.videoItem.movie" } else { "object.item.videoItem" };
+                let date = media.year.map(|year| format!("<dc:date>{}-01-01</dc:date>", year)).unwrap_or_default();
+                let description = media
+                    .overview
+                    .as_ref()
+                    .map(|overview| format!("<dc:description>{}</dc:description>", xml_escape(overview)))
+                    .unwrap_or_default();
+                let duration = duration
+                    .map(|seconds| format!(" duration=\"{}\"", didl_duration(seconds)))
+                    .unwrap_or_default();
+                let features = if *transcoded { DLNA_TRANSCODED } else { DLNA_DIRECT };
+                xml.push_str(&format!(
+                    "<item id=\"{}\" parentID=\"{}\" restricted=\"1\"><dc:title>{}</dc:title>\
+                     <upnp:class>{}</upnp:class>{}{}{}\
+                     <res protocolInfo=\"http-get:*:{}:{}\"{}>{}/media/{}</res></item>",
+                    xml_escape(&Object::Video(media.id).id()),
+                    xml_escape(parent),
+                    xml_escape(title),
+                    class,
+                    date,
+                    description,
+                    art_uri(art),
+                    mime,
+                    features,
+                    duration,
+                    base_url,
+                    media.id
+                ));
+            }
+        }
+    }
+
+    xml.push_str("</DIDL-Lite>");
+    xml
+}
+
+/// H:MM:SS.mmm, as DIDL-Lite wants durations
+fn didl_duration(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+fn extension(file_name: &str) -> String {
+    Path::new(file_name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn video_mime(file_name: &str) -> &'static str {
+    match extension(file_name).as_str() {
+        "mkv" => "video/x-matroska",
+        "avi" | "divx" | "xvid" => "video/x-msvideo",
+        "ts" | "m2ts" | "mts" => "video/mp2t",
+        "mov" => "video/quicktime",
+        "webm" => "video/webm",
+        "wmv" => "video/x-ms-wmv",
+        "mpg" | "mpeg" | "vob" => "video/mpeg",
+        _ => "video/mp4",
+    }
+}
+
+/// Whether a TV is unlikely to play the file as is. Files not probed yet are tried directly.
+pub(crate) fn needs_transcode(info: Option<&MediaTechInfo>, file_name: &str) -> bool {
+    if UNSUPPORTED_CONTAINERS.contains(&extension(file_name).as_str()) {
+        return true;
+    }
+    let info = match info {
+        Some(info) => info,
+        None => return false,
+    };
+    let video_ok = info
+        .video_codec
+        .as_deref()
+        .is_none_or(|codec| TV_VIDEO_CODECS.contains(&codec));
+    // TVs play the default audio track
+    let audio_ok = info
+        .audio_tracks
+        .iter()
+        .find(|track| track.is_default)
+        .or_else(|| info.audio_tracks.first())
+        .is_none_or(|track| TV_AUDIO_CODECS.contains(&track.codec.as_str()));
+    !(video_ok && audio_ok)
+}
+
+// ============================================================================
+// Media and artwork
+// ============================================================================
+
+/// Where a media item's bytes come from
+enum MediaSource {
+    /// A local video, or the verified offline copy of a Drive file
+    File(String),
+    /// A Drive file through the stream proxy
+    Proxy(String),
+}
+
+fn media_source(db: &Database, media: &MediaItem) -> Result<MediaSource, String> {
+    if media.is_cloud.unwrap_or(false) {
+        let offline = db.get_download(media.id).ok().flatten().and_then(|record| downloads::verified_path(&record));
+        return match offline {
+            Some(path) => Ok(MediaSource::File(path)),
+            None => stream_proxy::media_url(media.id).map(MediaSource::Proxy),
+        };
+    }
+    media
+        .file_path
+        .clone()
+        .filter(|path| Path::new(path).exists())
+        .map(MediaSource::File)
+        .ok_or_else(|| format!("File not found for {}", media.title))
+}
+
+/// Stream a media item to a LAN client: the file itself, relayed from the stream proxy, or
+/// transcoded to MP4. `transcode` forces the choice (None decides from the codecs); without
+/// FFmpeg everything is sent as is. A transcode starts `start_time` seconds in. The Jellyfin
+/// API streams through here too.
+pub(crate) fn serve_media(
+    request: Request,
+    media_id: i64,
+    ffmpeg_path: Option<&str>,
+    profile: &QualityProfile,
+    transcode: Option<bool>,
+    start_time: Option<f64>,
+) {
+    let prepared = (|| -> Result<(MediaItem, MediaSource, bool, Option<f64>), String> {
+        let db = Database::new(&database::get_database_path()).map_err(|e| e.to_string())?;
+        let media = db.get_media_by_id(media_id).map_err(|e| e.to_string())?;
+        let info = db.get_media_tech_info(media_id).ok().flatten();
+        let file_name = media.file_path.clone().unwrap_or_default();
+        let transcode = ffmpeg_path.is_some()
+            && transcode.unwrap_or_else(|| needs_transcode(info.as_ref(), &file_name));
+        let duration = info.and_then(|i| i.duration_seconds).or(media.duration_seconds);
+        let source = media_source(&db, &media)?;
+        Ok((media, source, transcode, duration))
+    })();
+
+    let (media, source, transcode, duration) = match prepared {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            println!("[DLNA] Can't serve media {}: {}", media_id, e);
+            return respond_empty(request, 404, Vec::new());
+        }
+    };
+
+    if !transcode {
+        let mime = video_mime(media.file_path.as_deref().unwrap_or(""));
+        return match source {
+            MediaSource::File(path) => serve_file(request, &path, mime),
+            MediaSource::Proxy(url) => relay(request, &url, media_headers(mime, DLNA_DIRECT)),
+        };
+    }
+
+    // A TV seeking in a transcode asks for a time; the encode starts there
+    let mut headers = media_headers("video/mp4", DLNA_TRANSCODED);
+    let seek = request_header(&request, "TimeSeekRange.dlna.org").and_then(|value| parse_time_seek(&value));
+    if let Some(seek) = seek {
+        let range = match duration {
+            Some(duration) => format!("npt={}-{}/{}", didl_duration(seek), didl_duration(duration), didl_duration(duration)),
+            None => format!("npt={}-", didl_duration(seek)),
+        };
+        headers.extend(header("TimeSeekRange.dlna.org", &range));
+    }
+    let start_time = seek.or(start_time);
+
+    // TVs probe with HEAD before playing; don't start an encode for that
+    if request.method().as_str().eq_ignore_ascii_case("HEAD") {
+        return respond_empty(request, 200, headers);
+    }
+
+    let input = match source {
+        MediaSource::File(path) => TranscodeInput::file(&path),
+        MediaSource::Proxy(url) => TranscodeInput { location: url, headers: None },
+    };
+    let options = TranscodeOptions { profile: profile.clone(), ..Default::default() };
+    let ffmpeg_path = ffmpeg_path.unwrap_or_default();
+    match transcoder::start_transcode(ffmpeg_path, Arc::new(move || Ok(input.clone())), start_time, &options) {
+        Ok((session_id, url)) => {
+            println!("[DLNA] Transcoding \"{}\" (session {})", media.title, session_id);
+            relay(request, &url, headers);
+            let _ = transcoder::stop_transcode(session_id);
+        }
+        Err(e) => {
+            println!("[DLNA] Failed to transcode \"{}\": {}", media.title, e);
+            respond_empty(request, 503, Vec::new());
+        }
+    }
+}
+
+/// Serve a file from disk, honouring a single Range
+fn serve_file(request: Request, path: &str, mime: &str) {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return respond_empty(request, 404, Vec::new()),
+    };
+    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let mut headers = media_headers(mime, DLNA_DIRECT);
+    headers.extend(header("Accept-Ranges", "bytes"));
+
+    let range = request_header(&request, "Range").and_then(|value| parse_range(&value, len));
+    match range {
+        Some((start, end)) => {
+            if file.seek(SeekFrom::Start(start)).is_err() {
+                return respond_empty(request, 416, Vec::new());
+            }
+            headers.extend(header("Content-Range", &format!("bytes {}-{}/{}", start, end, len)));
+            let length = end - start + 1;
+            let _ = request.respond(Response::new(StatusCode(206), headers, file.take(length), Some(length as usize), None));
+        }
+        None => {
+            let _ = request.respond(Response::new(StatusCode(200), headers, file, Some(len as usize), None));
+        }
+    }
+}
+
+/// `bytes=START-[END]` or `bytes=-SUFFIX` within a file of `len` bytes, as an inclusive range
+fn parse_range(value: &str, len: u64) -> Option<(u64, u64)> {
+    let spec = value.trim().strip_prefix("bytes=")?.split(',').next()?.trim();
+    let (start, end) = spec.split_once('-')?;
+    if len == 0 {
+        return None;
+    }
+    let (start, end) = if start.is_empty() {
+        let suffix: u64 = end.parse().ok()?;
+        (len.saturating_sub(suffix), len - 1)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = if end.is_empty() { len - 1 } else { end.parse::<u64>().ok()?.min(len - 1) };
+        (start, end)
+    };
+    (start <= end).then_some((start, end))
+}
+
+/// `npt=START-[END]` from a TimeSeekRange.dlna.org header, as the start in seconds.
+/// Times are plain seconds or H:MM:SS(.mmm).
+fn parse_time_seek(value: &str) -> Option<f64> {
+    let start = value.trim().strip_prefix("npt=")?.split('-').next()?.trim();
+    let seconds = start.split(':').try_fold(0.0, |total, part| part.parse::<f64>().ok().map(|n| total * 60.0 + n))?;
+    (seconds.is_finite() && seconds >= 0.0 && start.split(':').count() <= 3).then_some(seconds)
+}
+
+/// Pass a request through to a local HTTP stream (the Drive proxy or a transcode)
+fn relay(request: Request, url: &str, mut headers: Vec<Header>) {
+    // Streams run for hours; no overall timeout
+    let client = match reqwest::blocking::Client::builder().timeout(None::<Duration>).build() {
+        Ok(client) => client,
+        Err(_) => return respond_empty(request, 500, Vec::new()),
+    };
+    let mut upstream = client.get(url);
+    if let Some(range) = request_header(&request, "Range") {
+        upstream = upstream.header("Range", range);
+    }
+
+    match upstream.send() {
+        Ok(response) => {
+            for name in ["Content-Range", "Accept-Ranges"] {
+                if let Some(value) = response.headers().get(name).and_then(|v| v.to_str().ok()) {
+                    headers.extend(header(name, value));
+                }
+            }
+            let status = response.status().as_u16();
+            let length = response.content_length().map(|len| len as usize);
+            let _ = request.respond(Response::new(StatusCode(status), headers, response, length, None));
+        }
+        Err(e) => {
+            println!("[DLNA] Upstream stream failed: {}", e);
+            respond_empty(request, 502, Vec::new());
+        }
+    }
+}
+
+/// Poster for movies and shows, the still for episodes
+pub(crate) fn artwork_path(media: &MediaItem) -> Option<PathBuf> {
+    let image = if media.media_type == "tvepisode" {
+        media.still_path.as_ref().or(media.poster_path.as_ref())
+    } else {
+        media.poster_path.as_ref()
+    }?;
+    let path = Path::new(&database::get_image_cache_dir()).join(image.replace("image_cache/", ""));
+    path.exists().then_some(path)
+}
+
+pub(crate) fn serve_art(request: Request, media_id: i64) {
+    let path = Database::new(&database::get_database_path())
+        .ok()
+        .and_then(|db| db.get_media_by_id(media_id).ok())
+        .and_then(|media| artwork_path(&media));
+    let file = path.as_ref().and_then(|path| File::open(path).ok().map(|file| (path, file)));
+
+    match file {
+        Some((path, file)) => {
+            let mime = if extension(&path.to_string_lossy()) == "png" { "image/png" } else { "image/jpeg" };
+            let len = file.metadata().map(|m| m.len() as usize).ok();
+            let headers = [header("Content-Type", mime), header("Server", SERVER_HEADER)];
+            let _ = request.respond(Response::new(StatusCode(200), headers.into_iter().flatten().collect(), file, len, None));
+        }
+        None => respond_empty(request, 404, Vec::new()),
+    }
+}
+
+// ============================================================================
+// XML helpers
+// ============================================================================
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Text of the first `<tag>` element in a SOAP body
+fn xml_value(xml: &str, tag: &str) -> Option<String> {
+    let open = format!("<{}", tag);
+    let start = xml
+        .match_indices(&open)
+        .map(|(i, _)| i)
+        .find(|&i| matches!(xml[i + open.len()..].chars().next(), Some('>') | Some(' ') | Some('/')))?;
+    let rest = &xml[start..];
+    let open_end = rest.find('>')?;
+    if rest[..open_end].ends_with('/') {
+        return Some(String::new());
+    }
+    let body = &rest[open_end + 1..];
+    let close = body.find("</")?;
+    Some(xml_unescape(&body[..close]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::MediaStream;
+
+    #[test]
+    fn answers_m_search_on_loopback() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let identity = SsdpIdentity { uuid: "uuid:test-device".to_string(), http_port: 8200 };
+        let stop = Arc::new(AtomicBool::new(false));
+        let responder = {
+            let stop = stop.clone();
+            std::thread::spawn(move || run_ssdp(server, identity, stop))
+        };
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let search = format!(
+            "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 1\r\nST: {}\r\n\r\n",
+            MEDIA_SERVER
+        );
+        client.send_to(search.as_bytes(), server_addr).unwrap();
+
+        let mut buf = [0u8; 2048];
+        let (len, _) = client.recv_from(&mut buf).unwrap();
+        let response = String::from_utf8_lossy(&buf[..len]).to_string();
+        stop.store(true, Ordering::SeqCst);
+        responder.join().unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(&format!("ST: {}\r\n", MEDIA_SERVER)));
+        assert!(response.contains(&format!("USN: uuid:test-device::{}\r\n", MEDIA_SERVER)));
+        assert!(response.contains("LOCATION: http://127.0.0.1:8200/description.xml\r\n"));
+    }
+
+    fn context() -> DlnaContext {
+        DlnaContext {
+            identity: SsdpIdentity { uuid: "uuid:test-device".to_string(), http_port: 8200 },
+            settings: DlnaSettings {
+                friendly_name: "StreamVault".to_string(),
+                port: 8200,
+                ffmpeg_path: None,
+                profile: QualityProfile {
+                    id: "720p".to_string(),
+                    name: "720p".to_string(),
+                    max_height: Some(720),
+                    video_kbps: Some(4000),
+                    crf: 21,
+                },
+            },
+            update_id: 7,
+        }
+    }
+
+    fn browse_request(object_id: &str, start: usize, count: usize) -> String {
+        format!(
+            "<s:Envelope><s:Body><u:Browse xmlns:u=\"{}\"><ObjectID>{}</ObjectID>\
+             <BrowseFlag>BrowseDirectChildren</BrowseFlag><Filter>*</Filter>\
+             <StartingIndex>{}</StartingIndex><RequestedCount>{}</RequestedCount>\
+             <SortCriteria></SortCriteria></u:Browse></s:Body></s:Envelope>",
+            CONTENT_DIRECTORY, object_id, start, count
+        )
+    }
+
+    #[test]
+    fn parses_byte_ranges() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_range("bytes=500-", 1000), Some((500, 999)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        // A suffix longer than the file means the whole file
+        assert_eq!(parse_range("bytes=-5000", 1000), Some((0, 999)));
+        assert_eq!(parse_range("bytes=900-5000", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=10-5", 1000), None);
+        assert_eq!(parse_range("bytes=0-", 0), None);
+        assert_eq!(parse_range("items=0-1", 1000), None);
+    }
+
+    #[test]
+    fn parses_time_seeks() {
+        assert_eq!(parse_time_seek("npt=123.5-"), Some(123.5));
+        assert_eq!(parse_time_seek("npt=0:02:03.500-0:05:00.000"), Some(123.5));
+        assert_eq!(parse_time_seek(" npt=1:00:00- "), Some(3600.0));
+        assert_eq!(parse_time_seek("npt=now-"), None);
+        assert_eq!(parse_time_seek("npt=-5-"), None);
+        assert_eq!(parse_time_seek("bytes=0-"), None);
+    }
+
+    #[test]
+    fn reads_soap_values() {
+        let body = browse_request("season/4/2", 0, 0);
+        assert_eq!(xml_value(&body, "ObjectID").as_deref(), Some("season/4/2"));
+        assert_eq!(xml_value(&body, "SortCriteria").as_deref(), Some(""));
+        assert_eq!(xml_value("<Filter/>", "Filter").as_deref(), Some(""));
+        // <ObjectIDs> is not <ObjectID>
+        assert_eq!(xml_value("<ObjectIDs>1</ObjectIDs><ObjectID>2</ObjectID>", "ObjectID").as_deref(), Some("2"));
+        assert_eq!(xml_value("<Title>Tom &amp; Jerry</Title>", "Title").as_deref(), Some("Tom & Jerry"));
+        assert_eq!(xml_value(&body, "Missing"), None);
+    }
+
+    #[test]
+    fn object_ids_round_trip() {
+        for object in [
+            Object::Root,
+            Object::Movies,
+            Object::Shows,
+            Object::Series(4),
+            Object::Season(4, 0),
+            Object::Video(12),
+        ] {
+            assert_eq!(Object::parse(&object.id()), Some(object));
+        }
+        assert_eq!(Object::parse("video/abc"), None);
+        assert_eq!(Object::parse("season/4"), None);
+        assert_eq!(Object::parse(""), None);
+    }
+
+    #[test]
+    fn didl_escapes_titles_and_links_media() {
+        let entries = [
+            folder(Object::Movies, "0", "Movies & More", 3),
+            season_entry(4, 0, 2),
+        ];
+        let xml = didl(&entries, "http://10.0.0.2:8200");
+        assert!(xml.starts_with("<DIDL-Lite "));
+        assert!(xml.ends_with("</DIDL-Lite>"));
+        assert!(xml.contains("<container id=\"movies\" parentID=\"0\" restricted=\"1\" searchable=\"0\" childCount=\"3\">"));
+        assert!(xml.contains("<dc:title>Movies &amp; More</dc:title>"));
+        assert!(xml.contains("<dc:title>Specials</dc:title>"));
+        assert!(xml.contains("http://10.0.0.2:8200/art/4</upnp:albumArtURI>"));
+        assert_eq!(didl_duration(3723.5), "1:02:03.500");
+    }
+
+    #[test]
+    fn transcodes_only_what_tvs_cannot_play() {
+        let track = |codec: &str, is_default: bool| MediaStream {
+            codec: codec.to_string(),
+            is_default,
+            ..Default::default()
+        };
+        let info = |video: &str, audio: Vec<MediaStream>| MediaTechInfo {
+            video_codec: Some(video.to_string()),
+            audio_tracks: audio,
+            ..Default::default()
+        };
+
+        assert!(!needs_transcode(None, "Movie.mkv"));
+        assert!(needs_transcode(None, "Movie.rmvb"));
+        assert!(!needs_transcode(Some(&info("h264", vec![track("aac", true)])), "Movie.mkv"));
+        assert!(needs_transcode(Some(&info("av1", vec![track("aac", true)])), "Movie.mkv"));
+        // Only the default track counts
+        assert!(needs_transcode(Some(&info("hevc", vec![track("aac", false), track("dts", true)])), "Movie.mkv"));
+        assert!(!needs_transcode(Some(&info("hevc", vec![track("dts", false), track("ac3", true)])), "Movie.mkv"));
+    }
+
+    #[test]
+    fn browse_pages_through_children() {
+        let db = Database::new(":memory:").unwrap();
+        for title in ["Alien", "Brazil", "Casablanca", "Dune", "Heat"] {
+            db.insert_movie(title, None, None, None, &format!("/m/{}.mkv", title), 0.0, None).unwrap();
+        }
+        let context = context();
+        let value = |result: &[(&str, String)], name: &str| {
+            result.iter().find(|(key, _)| *key == name).map(|(_, value)| value.clone()).unwrap()
+        };
+
+        let page = browse_library(&db, &browse_request("movies", 1, 2), "http://host", &context).unwrap();
+        assert_eq!(value(&page, "NumberReturned"), "2");
+        assert_eq!(value(&page, "TotalMatches"), "5");
+        assert_eq!(value(&page, "UpdateID"), "7");
+        assert_eq!(value(&page, "Result").matches("<item ").count(), 2);
+
+        // RequestedCount 0 returns the rest
+        let rest = browse_library(&db, &browse_request("movies", 3, 0), "http://host", &context).unwrap();
+        assert_eq!(value(&rest, "NumberReturned"), "2");
+
+        let past_end = browse_library(&db, &browse_request("movies", 9, 5), "http://host", &context).unwrap();
+        assert_eq!(value(&past_end, "NumberReturned"), "0");
+        assert_eq!(value(&past_end, "TotalMatches"), "5");
+
+        let root = browse_library(&db, &browse_request("0", 0, 0), "http://host", &context).unwrap();
+        assert!(value(&root, "Result").contains("childCount=\"5\""));
+
+        assert!(browse_library(&db, &browse_request("nope", 0, 0), "http://host", &context).is_err());
+    }
+}
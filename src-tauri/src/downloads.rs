@@ -0,0 +1,450 @@
+// Offline Download Manager Module
+// Downloads Drive files for offline playback. Queue state lives in the `downloads` table so
+// it survives restarts; each download writes to `<name>.part`, resumes from the bytes already
+// on disk with a Range request, and is only renamed into place once its size and md5 match
+// what Drive reported.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use md5::{Digest, Md5};
+use serde::Serialize;
+
+use crate::database::{self, DownloadRecord};
+use crate::stream_proxy::{self, SourceResolver};
+
+// Retries per download for dropped connections before it is marked failed
+const MAX_ATTEMPTS: u32 = 5;
+const CHUNK_SIZE: usize = 256 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Progress event payload (emitted as `download-progress`)
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadProgress {
+    pub media_id: i64,
+    pub downloaded_bytes: u64,
+    pub total_bytes: Option<u64>,
+    pub status: String,
+    pub error: Option<String>,
+}
+
+pub type ProgressCallback = Arc<dyn Fn(&DownloadProgress) + Send + Sync>;
+
+struct DownloadContext {
+    resolver: SourceResolver,
+    on_progress: ProgressCallback,
+}
+
+lazy_static::lazy_static! {
+    static ref CONTEXT: Mutex<Option<DownloadContext>> = Mutex::new(None);
+    // Stop flags for downloads that are currently running, by media id
+    static ref ACTIVE: Mutex<HashMap<i64, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+}
+
+static MAX_CONCURRENT: AtomicUsize = AtomicUsize::new(2);
+
+/// Set up the manager and pick up anything left queued (or interrupted) from the last run
+pub fn init(resolver: SourceResolver, on_progress: ProgressCallback, max_concurrent: u32) {
+    set_max_concurrent(max_concurrent);
+    if let Ok(mut context) = CONTEXT.lock() {
+        *context = Some(DownloadContext { resolver, on_progress });
+    }
+
+    if let Ok(db) = database::Database::new(&database::get_database_path()) {
+        match db.requeue_interrupted_downloads() {
+            Ok(0) => {}
+            Ok(n) => println!("[DOWNLOAD] Re-queued {} interrupted download(s)", n),
+            Err(e) => println!("[DOWNLOAD] Failed to re-queue downloads: {}", e),
+        }
+    }
+    pump();
+}
+
+pub fn set_max_concurrent(max_concurrent: u32) {
+    MAX_CONCURRENT.store(max_concurrent.max(1) as usize, Ordering::SeqCst);
+}
+
+/// Start queued downloads until the concurrency limit is reached
+pub fn pump() {
+    let (resolver, on_progress) = match CONTEXT.lock().ok().and_then(|c| {
+        c.as_ref().map(|c| (c.resolver.clone(), c.on_progress.clone()))
+    }) {
+        Some(context) => context,
+        None => return,
+    };
+
+    let mut active = match ACTIVE.lock() {
+        Ok(active) => active,
+        Err(_) => return,
+    };
+    let limit = MAX_CONCURRENT.load(Ordering::SeqCst);
+    if active.len() >= limit {
+        return;
+    }
+
+    let db = match database::Database::new(&database::get_database_path()) {
+        Ok(db) => db,
+        Err(e) => {
+            println!("[DOWNLOAD] Failed to open database: {}", e);
+            return;
+        }
+    };
+    // Ask for extra rows in case some of the queued ones are still winding down
+    let queued = db.get_queued_downloads(limit + active.len()).unwrap_or_default();
+
+    for record in queued {
+        if active.len() >= limit {
+            break;
+        }
+        if active.contains_key(&record.media_id) {
+            continue;
+        }
+        if let Err(e) = db.set_download_status(record.media_id, "downloading", None) {
+            println!("[DOWNLOAD] Failed to start {}: {}", record.title, e);
+            continue;
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        active.insert(record.media_id, stop.clone());
+
+        let resolver = resolver.clone();
+        let on_progress = on_progress.clone();
+        std::thread::spawn(move || run_download(record, resolver, on_progress, stop));
+    }
+}
+
+/// Ask a running download to stop (its partial file is kept for resuming)
+pub fn stop(media_id: i64) -> bool {
+    match ACTIVE.lock() {
+        Ok(active) => match active.get(&media_id) {
+            Some(flag) => {
+                flag.store(true, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        },
+        Err(_) => false,
+    }
+}
+
+/// Path of a finished download whose file is still intact on disk
+pub fn verified_path(record: &DownloadRecord) -> Option<String> {
+    if record.status != "completed" {
+        return None;
+    }
+    let size = fs::metadata(&record.dest_path).ok()?.len();
+    match record.total_bytes {
+        Some(total) if total as u64 != size => None,
+        _ => Some(record.dest_path.clone()),
+    }
+}
+
+/// Delete a download's files (finished copy and any partial data)
+pub fn remove_files(record: &DownloadRecord) {
+    let dest = PathBuf::from(&record.dest_path);
+    fs::remove_file(part_path(&dest)).ok();
+    fs::remove_file(&dest).ok();
+    if let Some(dir) = dest.parent() {
+        fs::remove_dir(dir).ok(); // Only succeeds once the media folder is empty
+    }
+}
+
+fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(".part");
+    dest.with_file_name(name)
+}
+
+/// Bytes of a download already on disk
+fn part_len(record: &DownloadRecord) -> u64 {
+    fs::metadata(part_path(Path::new(&record.dest_path))).map(|m| m.len()).unwrap_or(0)
+}
+
+fn run_download(record: DownloadRecord, resolver: SourceResolver, on_progress: ProgressCallback, stop: Arc<AtomicBool>) {
+    let media_id = record.media_id;
+    println!("[DOWNLOAD] Starting {} ({})", record.title, record.dest_path);
+
+    let db = database::Database::new(&database::get_database_path()).ok();
+    let total = record.total_bytes.map(|t| t as u64);
+    let report = |downloaded: u64, status: &str, error: Option<String>| {
+        if let Some(ref db) = db {
+            db.update_download_progress(media_id, downloaded as i64).ok();
+        }
+        on_progress(&DownloadProgress {
+            media_id,
+            downloaded_bytes: downloaded,
+            total_bytes: total,
+            status: status.to_string(),
+            error,
+        });
+    };
+
+    let result = download_file(&record, &resolver, &stop, &|downloaded| report(downloaded, "downloading", None));
+
+    if let Ok(mut active) = ACTIVE.lock() {
+        active.remove(&media_id);
+    }
+
+    match result {
+        Ok(Some(size)) => {
+            println!("[DOWNLOAD] ✓ Finished {} ({} bytes)", record.title, size);
+            if let Some(ref db) = db {
+                db.set_download_status(media_id, "completed", None).ok();
+            }
+            report(size, "completed", None);
+        }
+        Ok(None) => {
+            // Stopped on request - whoever stopped it owns the status (paused or removed)
+            println!("[DOWNLOAD] Stopped {}", record.title);
+            report(part_len(&record), "paused", None);
+        }
+        Err(e) => {
+            println!("[DOWNLOAD] ✗ {} failed: {}", record.title, e);
+            if let Some(ref db) = db {
+                db.set_download_status(media_id, "failed", Some(&e)).ok();
+            }
+            // The .part file stays for a retry, so show how much of it is already there
+            report(part_len(&record), "failed", Some(e));
+        }
+    }
+
+    pump();
+}
+
+/// Download into the .part file, verify and move into place.
+/// Returns the final size, or None if the download was stopped.
+fn download_file(
+    record: &DownloadRecord,
+    resolver: &SourceResolver,
+    stop: &AtomicBool,
+    on_progress: &dyn Fn(u64),
+) -> Result<Option<u64>, String> {
+    let dest = PathBuf::from(&record.dest_path);
+    let part = part_path(&dest);
+    if let Some(dir) = dest.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create download folder: {}", e))?;
+    }
+
+    let total = record.total_bytes.map(|t| t as u64);
+    let client = reqwest::blocking::Client::builder()
+        .timeout(None)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut attempts = 0;
+
+    loop {
+        let mut offset = fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+        if let Some(total) = total {
+            if offset > total {
+                fs::File::create(&part).map_err(|e| e.to_string())?; // Stale data - start over
+                offset = 0;
+            }
+            if offset == total {
+                break;
+            }
+        }
+
+        let range = if offset > 0 { Some(format!("bytes={}-", offset)) } else { None };
+        let mut response = stream_proxy::send_upstream(&client, resolver, record.media_id, range.as_deref())?;
+        match response.status().as_u16() {
+            206 => {}
+            200 => {
+                if offset > 0 {
+                    // Range ignored - the body starts from byte 0
+                    fs::File::create(&part).map_err(|e| e.to_string())?;
+                    offset = 0;
+                }
+            }
+            416 => break, // Nothing left to fetch
+            status => return Err(format!("Drive returned {}", status)),
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&part)
+            .map_err(|e| format!("Failed to open {}: {}", part.display(), e))?;
+
+        let error = match copy_chunks(&mut response, &mut file, offset, stop, on_progress) {
+            Ok(None) => return Ok(None),
+            Ok(Some(end)) if total.is_none_or(|t| end >= t) => break,
+            Ok(Some(end)) => format!("connection closed at byte {}", end),
+            Err(e) => e.to_string(),
+        };
+
+        attempts += 1;
+        if attempts >= MAX_ATTEMPTS {
+            return Err(format!("Gave up after {} attempts: {}", attempts, error));
+        }
+        println!("[DOWNLOAD] {} interrupted ({}), retrying", record.title, error);
+        std::thread::sleep(Duration::from_secs(2u64.pow(attempts)));
+    }
+
+    let size = verify(&part, total, record.md5_checksum.as_deref())?;
+    fs::rename(&part, &dest).map_err(|e| format!("Failed to move download into place: {}", e))?;
+    Ok(Some(size))
+}
+
+/// Append the response body to the file. Returns the end offset, or None if stopped.
+fn copy_chunks(
+    response: &mut reqwest::blocking::Response,
+    file: &mut fs::File,
+    mut offset: u64,
+    stop: &AtomicBool,
+    on_progress: &dyn Fn(u64),
+) -> io::Result<Option<u64>> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut last_report = Instant::now();
+
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            file.flush()?;
+            return Ok(None);
+        }
+
+        let n = response.read(&mut buffer)?;
+        if n == 0 {
+            file.flush()?;
+            on_progress(offset);
+            return Ok(Some(offset));
+        }
+        file.write_all(&buffer[..n])?;
+        offset += n as u64;
+
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            on_progress(offset);
+            last_report = Instant::now();
+        }
+    }
+}
+
+/// Check the finished file against Drive's size and md5. Corrupt data is discarded.
+fn verify(part: &Path, expected_size: Option<u64>, expected_md5: Option<&str>) -> Result<u64, String> {
+    let size = fs::metadata(part).map_err(|e| e.to_string())?.len();
+    if let Some(expected) = expected_size {
+        if size != expected {
+            fs::remove_file(part).ok();
+            return Err(format!("Size mismatch: expected {} bytes, got {}", expected, size));
+        }
+    }
+
+    if let Some(expected) = expected_md5 {
+        let mut file = fs::File::open(part).map_err(|e| e.to_string())?;
+        let mut hasher = Md5::new();
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+        loop {
+            let n = file.read(&mut buffer).map_err(|e| e.to_string())?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+        }
+        let actual: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+        if !actual.eq_ignore_ascii_case(expected) {
+            fs::remove_file(part).ok();
+            return Err("Checksum mismatch - the download was corrupted".to_string());
+        }
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_proxy::UpstreamSource;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("streamvault-download-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn md5_hex(data: &[u8]) -> String {
+        Md5::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn record(dest: &Path, data: &[u8]) -> DownloadRecord {
+        DownloadRecord {
+            media_id: 1,
+            title: "Movie".to_string(),
+            cloud_file_id: "file".to_string(),
+            dest_path: dest.to_string_lossy().to_string(),
+            total_bytes: Some(data.len() as i64),
+            downloaded_bytes: 0,
+            md5_checksum: Some(md5_hex(data)),
+            status: "downloading".to_string(),
+            error: None,
+        }
+    }
+
+    /// Serve `data` once, honouring a `bytes=N-` Range, and hand back the Range it was asked for
+    fn serve_once(data: Vec<u8>) -> (SourceResolver, std::thread::JoinHandle<Option<String>>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file", server.server_addr());
+        let handle = std::thread::spawn(move || {
+            let request = server.recv().unwrap();
+            let range = request
+                .headers()
+                .iter()
+                .find(|h| h.field.equiv("Range"))
+                .map(|h| h.value.as_str().to_string());
+            let start = range
+                .as_deref()
+                .and_then(|r| r.strip_prefix("bytes="))
+                .and_then(|r| r.trim_end_matches('-').parse::<usize>().ok());
+            let response = match start {
+                Some(start) => tiny_http::Response::from_data(data[start..].to_vec()).with_status_code(206),
+                None => tiny_http::Response::from_data(data),
+            };
+            request.respond(response).unwrap();
+            range
+        });
+        let resolver: SourceResolver = Arc::new(move |_, _| {
+            Ok(UpstreamSource { url: url.clone(), access_token: "token".to_string() })
+        });
+        (resolver, handle)
+    }
+
+    #[test]
+    fn resumes_from_the_partial_file() {
+        let dir = test_dir("resume");
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let dest = dir.join("Movie.mkv");
+        fs::write(part_path(&dest), &data[..100_000]).unwrap();
+
+        let (resolver, server) = serve_once(data.clone());
+        let size = download_file(&record(&dest, &data), &resolver, &AtomicBool::new(false), &|_| {}).unwrap();
+
+        assert_eq!(server.join().unwrap().as_deref(), Some("bytes=100000-"));
+        assert_eq!(size, Some(data.len() as u64));
+        assert_eq!(fs::read(&dest).unwrap(), data);
+        assert!(!part_path(&dest).exists());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn verify_checks_size_and_checksum() {
+        let dir = test_dir("verify");
+        let part = dir.join("Movie.mkv.part");
+        let data = b"streamvault test data".to_vec();
+
+        fs::write(&part, &data).unwrap();
+        assert_eq!(verify(&part, Some(data.len() as u64), Some(&md5_hex(&data).to_uppercase())), Ok(data.len() as u64));
+
+        // Corrupt data is discarded so the next attempt starts over
+        assert!(verify(&part, Some(data.len() as u64), Some(&md5_hex(b"something else"))).is_err());
+        assert!(!part.exists());
+
+        fs::write(&part, &data[..4]).unwrap();
+        assert!(verify(&part, Some(data.len() as u64), None).is_err());
+        assert!(!part.exists());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
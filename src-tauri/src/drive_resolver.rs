@@ -0,0 +1,413 @@
+// Drive Folder Ancestry Module
+// The Changes API reports every change in a drive and only gives each item's direct
+// parents. To decide whether a change belongs to a tracked cloud folder we walk up the
+// parent chain, keeping folder parents cached between polls so each folder costs at most
+// one metadata lookup.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+
+use crate::database::CloudFileLocation;
+use crate::gdrive::{DriveChange, DriveItem, GoogleDriveClient};
+use crate::subtitles;
+
+// Parent chains deeper than this are treated as outside every tracked folder
+const MAX_ANCESTRY_DEPTH: usize = 64;
+
+/// Where the resolver looks up items it hasn't cached: the Drive API, or a fake in tests
+pub trait DriveLookup {
+    fn resolve_account(&self, account_id: Option<&str>) -> Result<String, String>;
+    fn file_metadata(&self, account_id: &str, file_id: &str) -> impl Future<Output = Result<DriveItem, String>> + Send;
+}
+
+impl DriveLookup for GoogleDriveClient {
+    fn resolve_account(&self, account_id: Option<&str>) -> Result<String, String> {
+        GoogleDriveClient::resolve_account(self, account_id)
+    }
+
+    async fn file_metadata(&self, account_id: &str, file_id: &str) -> Result<DriveItem, String> {
+        self.get_file_metadata(Some(account_id), file_id).await
+    }
+}
+
+/// Cached parent map for Drive items seen by change detection
+#[derive(Default)]
+pub struct FolderAncestry {
+    parents: HashMap<String, Vec<String>>,
+    // Folder names, so a rename can be told apart from an unrelated touch
+    folder_names: HashMap<String, String>,
+    // Trashed or deleted items - nothing below them counts as tracked
+    dead: HashSet<String>,
+    // Set once every indexed file's ancestry has been cached; until then a folder seen
+    // for the first time might be leaving a tracked folder with files we can't place
+    primed: bool,
+}
+
+/// Changes sorted into what the library needs to do
+#[derive(Default)]
+pub struct FeedChanges {
+    /// Videos under a tracked folder, with the account they belong to
+    pub added: Vec<(DriveItem, String)>,
+    /// Cloud file IDs that were deleted, trashed or moved out of tracked folders
+    pub removed: Vec<String>,
+    /// Folders (with account) that moved into a tracked folder - their contents need listing
+    pub folders_moved_in: Vec<(String, String)>,
+    /// A folder moved, was trashed or deleted - indexed files need re-checking
+    pub folders_changed: bool,
+    /// Folders (with account) where subtitles or videos appeared - subtitles need re-matching
+    pub subtitle_folders: Vec<(String, String)>,
+}
+
+impl FeedChanges {
+    pub fn merge(&mut self, other: FeedChanges) {
+        self.added.extend(other.added);
+        self.removed.extend(other.removed);
+        self.folders_moved_in.extend(other.folders_moved_in);
+        self.folders_changed |= other.folders_changed;
+        self.subtitle_folders.extend(other.subtitle_folders);
+    }
+
+    /// Drop duplicates and removals of files that were re-added later in the batch
+    pub fn dedup(&mut self) {
+        let mut seen = HashSet::new();
+        self.added.retain(|(file, _)| seen.insert(file.id.clone()));
+        let mut removed_seen = HashSet::new();
+        self.removed.retain(|id| !seen.contains(id) && removed_seen.insert(id.clone()));
+        let mut folders_seen = HashSet::new();
+        self.subtitle_folders.retain(|folder| folders_seen.insert(folder.clone()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.subtitle_folders.is_empty()
+    }
+}
+
+impl FolderAncestry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record an item's current parents (and a folder's name). Returns what changed
+    /// compared to the cached copy, or None if the item wasn't cached.
+    fn remember(&mut self, item: &DriveItem) -> Option<bool> {
+        let was_dead = if item.trashed.unwrap_or(false) {
+            !self.dead.insert(item.id.clone())
+        } else {
+            self.dead.remove(&item.id)
+        };
+        let parents = item.parents.clone().unwrap_or_default();
+        let previous = self.parents.insert(item.id.clone(), parents.clone());
+        let renamed = item.is_folder()
+            && self
+                .folder_names
+                .insert(item.id.clone(), item.name.clone())
+                .is_some_and(|name| name != item.name);
+        previous.map(|previous| was_dead || renamed || previous != parents)
+    }
+
+    fn forget(&mut self, item_id: &str) -> bool {
+        self.dead.insert(item_id.to_string());
+        self.parents.remove(item_id).is_some()
+    }
+
+    /// Walk up from `parents` until a tracked folder is found.
+    /// Unknown folders are looked up once and cached; lookup failures are returned so a
+    /// flaky API call never turns into a removal.
+    pub async fn tracked_root(
+        &mut self,
+        client: &impl DriveLookup,
+        account_id: &str,
+        parents: &[String],
+        tracked: &HashSet<String>,
+    ) -> Result<Option<String>, String> {
+        let mut pending: Vec<(String, usize)> = parents.iter().map(|p| (p.clone(), 1)).collect();
+        let mut visited = HashSet::new();
+
+        while let Some((folder_id, depth)) = pending.pop() {
+            if depth > MAX_ANCESTRY_DEPTH || self.dead.contains(&folder_id) || !visited.insert(folder_id.clone()) {
+                continue;
+            }
+            if tracked.contains(&folder_id) {
+                return Ok(Some(folder_id));
+            }
+
+            let folder_parents = match self.parents.get(&folder_id) {
+                Some(cached) => cached.clone(),
+                None => {
+                    let folder = client.file_metadata(account_id, &folder_id).await?;
+                    self.remember(&folder);
+                    if folder.trashed.unwrap_or(false) {
+                        continue;
+                    }
+                    folder.parents.unwrap_or_default()
+                }
+            };
+            pending.extend(folder_parents.into_iter().map(|p| (p, depth + 1)));
+        }
+
+        Ok(None)
+    }
+
+    /// Sort one feed's raw changes into additions and removals
+    pub async fn classify(
+        &mut self,
+        client: &impl DriveLookup,
+        account_id: &str,
+        changes: Vec<DriveChange>,
+        tracked: &HashSet<String>,
+    ) -> FeedChanges {
+        let mut result = FeedChanges::default();
+
+        for change in changes {
+            let file = match change.file {
+                Some(file) if !change.removed.unwrap_or(false) => file,
+                _ => {
+                    // Deleted outright or no longer visible - only the ID is left
+                    if let Some(file_id) = change.file_id {
+                        if self.forget(&file_id) {
+                            result.folders_changed = true;
+                        }
+                        result.removed.push(file_id);
+                    }
+                    continue;
+                }
+            };
+
+            if file.trashed.unwrap_or(false) {
+                self.remember(&file);
+                if file.is_folder() {
+                    result.folders_changed = true;
+                } else if file.is_video() {
+                    result.removed.push(file.id);
+                }
+                continue;
+            }
+
+            if file.is_folder() {
+                let parents = file.parents.clone().unwrap_or_default();
+                let moved = self.parents.get(&file.id).map(|previous| *previous != parents);
+                match self.remember(&file) {
+                    Some(false) => continue, // Touched, not moved or renamed
+                    Some(true) => result.folders_changed = true,
+                    // First sighting: nothing to compare against. Indexed files below it can
+                    // only be placed by a full re-check until the cache is primed.
+                    None => result.folders_changed |= !self.primed,
+                }
+                if moved == Some(false) {
+                    continue; // Renamed in place
+                }
+                match self.tracked_root(client, account_id, &parents, tracked).await {
+                    Ok(Some(_)) => result.folders_moved_in.push((file.id, account_id.to_string())),
+                    Ok(None) => {}
+                    Err(e) => println!("[DRIVE RESOLVER] Could not resolve folder {}: {}", file.name, e),
+                }
+            } else if file.is_video() {
+                self.remember(&file);
+                let parents = file.parents.clone().unwrap_or_default();
+                match self.tracked_root(client, account_id, &parents, tracked).await {
+                    Ok(Some(_)) => {
+                        if let Some(parent) = parents.first() {
+                            result.subtitle_folders.push((parent.clone(), account_id.to_string()));
+                        }
+                        result.added.push((file, account_id.to_string()));
+                    }
+                    Ok(None) => result.removed.push(file.id),
+                    Err(e) => println!("[DRIVE RESOLVER] Could not resolve {}: {}", file.name, e),
+                }
+            } else if subtitles::is_subtitle_file(&file.name) {
+                self.remember(&file);
+                let parents = file.parents.clone().unwrap_or_default();
+                match (parents.first(), self.tracked_root(client, account_id, &parents, tracked).await) {
+                    (Some(parent), Ok(Some(_))) => result.subtitle_folders.push((parent.clone(), account_id.to_string())),
+                    (_, Err(e)) => println!("[DRIVE RESOLVER] Could not resolve {}: {}", file.name, e),
+                    _ => {}
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Re-check indexed files after folder moves.
+    /// `files` is (cloud_file_id, cloud_folder_id, account_id); returns the IDs no longer
+    /// under any tracked folder. The indexed folder is only the tracked root for files from
+    /// a full scan, so files without cached parents are looked up for their real ones.
+    pub async fn untracked_files(
+        &mut self,
+        client: &impl DriveLookup,
+        files: &[CloudFileLocation],
+        tracked: &HashSet<String>,
+    ) -> Vec<String> {
+        let mut untracked = Vec::new();
+        let mut complete = true;
+
+        for (file_id, _, account_id) in files {
+            let account = match client.resolve_account(account_id.as_deref()) {
+                Ok(account) => account,
+                Err(_) => {
+                    complete = false;
+                    continue;
+                }
+            };
+            let parents = match self.parents.get(file_id) {
+                Some(cached) => cached.clone(),
+                None => match client.file_metadata(&account, file_id).await {
+                    Ok(file) => {
+                        self.remember(&file);
+                        if file.trashed.unwrap_or(false) {
+                            untracked.push(file_id.clone());
+                            continue;
+                        }
+                        file.parents.unwrap_or_default()
+                    }
+                    Err(e) => {
+                        // A flaky lookup must never turn into a removal
+                        println!("[DRIVE RESOLVER] Could not re-check {}: {}", file_id, e);
+                        complete = false;
+                        continue;
+                    }
+                },
+            };
+            if self.dead.contains(file_id) {
+                untracked.push(file_id.clone());
+                continue;
+            }
+            match self.tracked_root(client, &account, &parents, tracked).await {
+                Ok(Some(_)) => {}
+                Ok(None) => untracked.push(file_id.clone()),
+                Err(e) => {
+                    println!("[DRIVE RESOLVER] Could not re-check {}: {}", file_id, e);
+                    complete = false;
+                }
+            }
+        }
+
+        self.primed |= complete;
+        untracked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    const ACCOUNT: &str = "me@example.com";
+
+    // (name, parents, is folder) by item id
+    type FakeItems = HashMap<String, (String, Vec<String>, bool)>;
+
+    /// In-memory Drive
+    #[derive(Default)]
+    struct FakeDrive {
+        items: Mutex<FakeItems>,
+        lookups: Mutex<usize>,
+    }
+
+    impl FakeDrive {
+        fn put(&self, id: &str, name: &str, parent: &str, folder: bool) {
+            let parents = if parent.is_empty() { Vec::new() } else { vec![parent.to_string()] };
+            self.items.lock().unwrap().insert(id.to_string(), (name.to_string(), parents, folder));
+        }
+
+        fn item(&self, id: &str) -> DriveItem {
+            let (name, parents, folder) = self.items.lock().unwrap()[id].clone();
+            DriveItem {
+                id: id.to_string(),
+                name,
+                mime_type: if folder { crate::gdrive::FOLDER_MIME_TYPE.to_string() } else { "video/x-matroska".to_string() },
+                size: None,
+                modified_time: None,
+                parents: Some(parents),
+                web_content_link: None,
+                trashed: Some(false),
+                md5_checksum: None,
+            }
+        }
+
+        fn change(&self, id: &str) -> DriveChange {
+            DriveChange { kind: None, removed: Some(false), file: Some(self.item(id)), file_id: Some(id.to_string()), change_type: None }
+        }
+    }
+
+    impl DriveLookup for FakeDrive {
+        fn resolve_account(&self, account_id: Option<&str>) -> Result<String, String> {
+            Ok(account_id.unwrap_or(ACCOUNT).to_string())
+        }
+
+        async fn file_metadata(&self, _account_id: &str, file_id: &str) -> Result<DriveItem, String> {
+            *self.lookups.lock().unwrap() += 1;
+            if self.items.lock().unwrap().contains_key(file_id) {
+                Ok(self.item(file_id))
+            } else {
+                Err(format!("{} not found", file_id))
+            }
+        }
+    }
+
+    /// root (tracked) / Show / S1 / ep1.mkv, plus an untracked "elsewhere" folder.
+    /// ep1 was indexed by a full scan, so its recorded folder is the tracked root.
+    fn library() -> (FakeDrive, HashSet<String>, Vec<CloudFileLocation>) {
+        let drive = FakeDrive::default();
+        drive.put("my-drive", "My Drive", "", true);
+        drive.put("root", "Media", "my-drive", true);
+        drive.put("elsewhere", "Elsewhere", "my-drive", true);
+        drive.put("show", "Show", "root", true);
+        drive.put("s1", "S1", "show", true);
+        drive.put("ep1", "Show.S01E01.mkv", "s1", false);
+        let tracked = HashSet::from(["root".to_string()]);
+        let indexed = vec![("ep1".to_string(), Some("root".to_string()), Some(ACCOUNT.to_string()))];
+        (drive, tracked, indexed)
+    }
+
+    #[tokio::test]
+    async fn subfolder_moved_out_untracks_its_files() {
+        let (drive, tracked, indexed) = library();
+        let mut ancestry = FolderAncestry::new();
+
+        drive.put("show", "Show", "elsewhere", true);
+        let changes = ancestry.classify(&drive, ACCOUNT, vec![drive.change("show")], &tracked).await;
+        assert!(changes.folders_changed);
+        assert!(changes.folders_moved_in.is_empty());
+
+        let untracked = ancestry.untracked_files(&drive, &indexed, &tracked).await;
+        assert_eq!(untracked, vec!["ep1".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn subfolder_moved_in_is_listed() {
+        let (drive, tracked, indexed) = library();
+        let mut ancestry = FolderAncestry::new();
+        assert!(ancestry.untracked_files(&drive, &indexed, &tracked).await.is_empty());
+
+        drive.put("movies", "Movies", "elsewhere", true);
+        let changes = ancestry.classify(&drive, ACCOUNT, vec![drive.change("movies")], &tracked).await;
+        assert!(changes.folders_moved_in.is_empty());
+        assert!(!changes.folders_changed, "a new folder outside the library needs no re-check once primed");
+
+        drive.put("movies", "Movies", "root", true);
+        let changes = ancestry.classify(&drive, ACCOUNT, vec![drive.change("movies")], &tracked).await;
+        assert_eq!(changes.folders_moved_in, vec![("movies".to_string(), ACCOUNT.to_string())]);
+        assert!(changes.folders_changed);
+    }
+
+    #[tokio::test]
+    async fn renamed_folder_is_a_change_but_not_a_move() {
+        let (drive, tracked, indexed) = library();
+        let mut ancestry = FolderAncestry::new();
+        assert!(ancestry.untracked_files(&drive, &indexed, &tracked).await.is_empty());
+
+        // Touched without changes
+        let changes = ancestry.classify(&drive, ACCOUNT, vec![drive.change("s1")], &tracked).await;
+        assert!(!changes.folders_changed);
+
+        drive.put("s1", "Season 1", "show", true);
+        let changes = ancestry.classify(&drive, ACCOUNT, vec![drive.change("s1")], &tracked).await;
+        assert!(changes.folders_changed);
+        assert!(changes.folders_moved_in.is_empty());
+
+        let lookups = *drive.lookups.lock().unwrap();
+        assert!(ancestry.untracked_files(&drive, &indexed, &tracked).await.is_empty());
+        assert_eq!(*drive.lookups.lock().unwrap(), lookups, "re-check is served from the cache");
+    }
+}
@@ -2,6 +2,12 @@
 //! Handles OAuth2 authentication and Google Drive API operations
 
 use base64::Engine;
+use oauth2::basic::BasicClient;
+use oauth2::reqwest::async_http_client;
+use oauth2::{
+    AuthType, AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken, PkceCodeChallenge,
+    PkceCodeVerifier, RedirectUrl, RefreshToken, Scope, TokenResponse, TokenUrl,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
@@ -9,8 +15,10 @@ use std::io::{BufRead, BufReader, Write};
 use std::net::TcpListener;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::database::get_app_data_dir;
+use crate::secrets;
 
 // Backend auth server URL (handles OAuth securely)
 // This keeps client_id and client_secret on the server
@@ -19,6 +27,81 @@ const AUTH_SERVER_URL: &str = "https://streamvault-backend-server.onrender.com";
 // Google Drive API
 const DRIVE_API_BASE: &str = "https://www.googleapis.com/drive/v3";
 
+// Makes files.list include Shared Drive items
+const ALL_DRIVES_PARAMS: &str = "supportsAllDrives=true&includeItemsFromAllDrives=true&corpora=allDrives";
+
+// changes.list takes no corpora; Shared Drive items only belong in a feed scoped to a driveId
+const MY_DRIVE_CHANGES_PARAMS: &str = "supportsAllDrives=true";
+const SHARED_DRIVE_CHANGES_PARAMS: &str = "supportsAllDrives=true&includeItemsFromAllDrives=true";
+
+// Mime types we index as playable video, and Drive's folder type
+pub const VIDEO_MIME_TYPES: [&str; 9] = [
+    "video/mp4",
+    "video/x-matroska",
+    "video/avi",
+    "video/quicktime",
+    "video/webm",
+    "video/x-m4v",
+    "video/x-ms-wmv",
+    "video/x-flv",
+    "video/mp2t",
+];
+pub const FOLDER_MIME_TYPE: &str = "application/vnd.google-apps.folder";
+
+// Google OAuth endpoints for the direct (PKCE) flow
+const GOOGLE_AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const OAUTH_SCOPES: [&str; 3] = [
+    "https://www.googleapis.com/auth/drive",
+    "https://www.googleapis.com/auth/userinfo.email",
+    "https://www.googleapis.com/auth/userinfo.profile",
+];
+// How long the loopback listener waits for the browser before the sign-in is abandoned
+const OAUTH_CALLBACK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Why Drive refused to serve a file's content
+#[derive(Debug, Clone, PartialEq)]
+pub enum DriveError {
+    /// The file's (or account's) download quota is used up; resets within 24 hours
+    QuotaExceeded(String),
+    /// Too many requests in a short time
+    RateLimited(String),
+    /// Any other 403: no access to the file, or Drive blocked it
+    Forbidden(String),
+    Other(String),
+}
+
+impl DriveError {
+    /// Classify an error response from the Drive API
+    /// (`{"error": {"errors": [{"reason": "downloadQuotaExceeded", ...}], "message": ...}}`)
+    pub fn from_response(status: u16, body: &str) -> Self {
+        let json: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        let message = json["error"]["message"]
+            .as_str()
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| format!("Drive returned HTTP {}", status));
+        let reason = json["error"]["errors"][0]["reason"].as_str().unwrap_or("");
+
+        match (status, reason) {
+            (_, "downloadQuotaExceeded" | "quotaExceeded" | "dailyLimitExceeded") => DriveError::QuotaExceeded(message),
+            (429, _) | (_, "rateLimitExceeded" | "userRateLimitExceeded") => DriveError::RateLimited(message),
+            (403, _) => DriveError::Forbidden(message),
+            _ => DriveError::Other(format!("Drive API error: {}", message)),
+        }
+    }
+}
+
+impl std::fmt::Display for DriveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DriveError::QuotaExceeded(m) => write!(f, "Drive download quota exceeded: {}", m),
+            DriveError::RateLimited(m) => write!(f, "Drive rate limit hit: {}", m),
+            DriveError::Forbidden(m) => write!(f, "Drive refused access: {}", m),
+            DriveError::Other(m) => write!(f, "{}", m),
+        }
+    }
+}
+
 /// Stored OAuth tokens
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GoogleTokens {
@@ -26,6 +109,10 @@ pub struct GoogleTokens {
     pub refresh_token: Option<String>,
     pub expires_at: Option<i64>,
     pub token_type: String,
+    /// Set when the tokens came from the direct PKCE flow - refresh then happens locally,
+    /// with the client secret read from the secrets store rather than kept here
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
 }
 
 /// Google Drive account info
@@ -51,6 +138,20 @@ pub struct DriveItem {
     pub parents: Option<Vec<String>>,
     #[serde(default)]
     pub web_content_link: Option<String>,
+    #[serde(default)]
+    pub trashed: Option<bool>,
+    #[serde(default)]
+    pub md5_checksum: Option<String>,
+}
+
+impl DriveItem {
+    pub fn is_folder(&self) -> bool {
+        self.mime_type == FOLDER_MIME_TYPE
+    }
+
+    pub fn is_video(&self) -> bool {
+        VIDEO_MIME_TYPES.contains(&self.mime_type.as_str())
+    }
 }
 
 /// Response from Drive API files.list
@@ -81,29 +182,84 @@ pub struct DriveChange {
     pub change_type: Option<String>,
 }
 
+/// Direct OAuth flow in progress (between gdrive_start_auth and gdrive_complete_auth)
+pub struct PendingPkceAuth {
+    client: BasicClient,
+    client_id: String,
+    pkce_verifier: PkceCodeVerifier,
+    csrf_state: CsrfToken,
+    listener: TcpListener,
+}
+
+/// A Shared Drive (team drive) visible to an account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedDrive {
+    pub id: String,
+    pub name: String,
+}
+
+/// A connected Google account
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectedAccount {
+    pub account_id: String,
+    pub is_primary: bool,
+}
+
 /// Google Drive client state
+/// Holds tokens for every connected account. Methods take an optional account id -
+/// `None` means the primary (first connected) account.
 pub struct GoogleDriveClient {
-    tokens: Arc<Mutex<Option<GoogleTokens>>>,
+    /// Account ids in connection order (the first one is primary)
+    accounts: Arc<Mutex<Vec<String>>>,
+    tokens: Arc<Mutex<HashMap<String, GoogleTokens>>>,
+    pending_auth: Mutex<Option<PendingPkceAuth>>,
     http_client: reqwest::Client,
 }
 
 impl GoogleDriveClient {
     pub fn new() -> Self {
-        let tokens = load_tokens().ok();
+        let (accounts, tokens) = load_all_tokens();
         Self {
+            accounts: Arc::new(Mutex::new(accounts)),
             tokens: Arc::new(Mutex::new(tokens)),
+            pending_auth: Mutex::new(None),
             http_client: reqwest::Client::new(),
         }
     }
 
-    /// Check if user is authenticated
+    /// Check if at least one account is connected
     pub fn is_authenticated(&self) -> bool {
-        self.tokens.lock().unwrap().is_some()
+        !self.tokens.lock().unwrap().is_empty()
     }
 
-    /// Get the current access token, refreshing if needed
-    pub async fn get_access_token(&self) -> Result<String, String> {
-        let tokens = self.tokens.lock().unwrap().clone();
+    /// All connected accounts, primary first
+    pub fn list_accounts(&self) -> Vec<ConnectedAccount> {
+        self.accounts
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .map(|(i, id)| ConnectedAccount {
+                account_id: id.clone(),
+                is_primary: i == 0,
+            })
+            .collect()
+    }
+
+    /// Resolve an optional account id to a connected account (falls back to the primary)
+    pub fn resolve_account(&self, account_id: Option<&str>) -> Result<String, String> {
+        let accounts = self.accounts.lock().unwrap();
+        match account_id {
+            Some(id) if accounts.iter().any(|a| a == id) => Ok(id.to_string()),
+            Some(id) => Err(format!("Google account '{}' is not connected", id)),
+            None => accounts.first().cloned().ok_or_else(|| "Not authenticated".to_string()),
+        }
+    }
+
+    /// Get the current access token for an account, refreshing if needed
+    pub async fn get_access_token(&self, account_id: Option<&str>) -> Result<String, String> {
+        let account_id = self.resolve_account(account_id)?;
+        let tokens = self.tokens.lock().unwrap().get(&account_id).cloned();
 
         match tokens {
             Some(t) => {
@@ -113,7 +269,12 @@ impl GoogleDriveClient {
                     if now >= expires_at - 60 {
                         // Token expired or about to expire, refresh it
                         if let Some(refresh_token) = &t.refresh_token {
-                            return self.refresh_access_token(refresh_token).await;
+                            if let Some(client_id) = &t.client_id {
+                                return self
+                                    .refresh_access_token_locally(&account_id, client_id, refresh_token)
+                                    .await;
+                            }
+                            return self.refresh_access_token(&account_id, refresh_token).await;
                         }
                         return Err("Token expired and no refresh token available".to_string());
                     }
@@ -124,8 +285,18 @@ impl GoogleDriveClient {
         }
     }
 
+    /// Mark an account's access token as expired so the next get_access_token refreshes it
+    /// (used when Google rejects a token before its recorded expiry)
+    pub fn expire_access_token(&self, account_id: Option<&str>) {
+        if let Ok(account_id) = self.resolve_account(account_id) {
+            if let Some(t) = self.tokens.lock().unwrap().get_mut(&account_id) {
+                t.expires_at = Some(0);
+            }
+        }
+    }
+
     /// Refresh the access token via backend proxy
-    async fn refresh_access_token(&self, refresh_token: &str) -> Result<String, String> {
+    async fn refresh_access_token(&self, account_id: &str, refresh_token: &str) -> Result<String, String> {
         let response = self.http_client
             .post(format!("{}/auth/refresh", AUTH_SERVER_URL))
             .json(&serde_json::json!({
@@ -155,47 +326,277 @@ impl GoogleDriveClient {
 
         // Update stored tokens
         let mut tokens = self.tokens.lock().unwrap();
-        if let Some(ref mut t) = *tokens {
+        if let Some(t) = tokens.get_mut(account_id) {
             t.access_token = access_token.clone();
             t.expires_at = Some(expires_at);
-            save_tokens(t).ok();
+            save_tokens(account_id, t).ok();
         }
 
         Ok(access_token)
     }
 
-    /// Store tokens after successful authentication
-    pub fn store_tokens(&self, tokens: GoogleTokens) -> Result<(), String> {
-        save_tokens(&tokens)?;
-        *self.tokens.lock().unwrap() = Some(tokens);
-        Ok(())
+    /// Refresh the access token directly against Google's token endpoint (direct auth mode)
+    async fn refresh_access_token_locally(
+        &self,
+        account_id: &str,
+        client_id: &str,
+        refresh_token: &str,
+    ) -> Result<String, String> {
+        let client_secret = secrets::get_secret(crate::config::GDRIVE_CLIENT_SECRET)?.filter(|s| !s.is_empty());
+        let client = build_oauth_client(client_id, client_secret.as_deref())?;
+        let token_response = client
+            .exchange_refresh_token(&RefreshToken::new(refresh_token.to_string()))
+            .request_async(async_http_client)
+            .await
+            .map_err(|e| format!("Token refresh failed: {}", e))?;
+
+        let access_token = token_response.access_token().secret().clone();
+        let expires_in = token_response.expires_in().map(|d| d.as_secs() as i64).unwrap_or(3600);
+        let expires_at = chrono::Utc::now().timestamp() + expires_in;
+
+        // Update stored tokens (Google may rotate the refresh token)
+        let mut tokens = self.tokens.lock().unwrap();
+        if let Some(t) = tokens.get_mut(account_id) {
+            t.access_token = access_token.clone();
+            t.expires_at = Some(expires_at);
+            if let Some(new_refresh) = token_response.refresh_token() {
+                t.refresh_token = Some(new_refresh.secret().clone());
+            }
+            save_tokens(account_id, t).ok();
+        }
+
+        Ok(access_token)
     }
 
-    /// Clear stored tokens (logout)
+    /// Begin the direct OAuth + PKCE flow. Binds a loopback listener on a random port
+    /// and returns the Google consent URL to open in the browser.
+    pub fn start_pkce_auth(&self, client_id: &str, client_secret: Option<&str>) -> Result<String, String> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .map_err(|e| format!("Failed to start OAuth callback server: {}", e))?;
+        let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        let redirect_uri = format!("http://127.0.0.1:{}/callback", port);
+
+        let client = build_oauth_client(client_id, client_secret)?
+            .set_redirect_uri(RedirectUrl::new(redirect_uri).map_err(|e| e.to_string())?);
+        let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+
+        let mut request = client
+            .authorize_url(|| CsrfToken::new(generate_state()))
+            .set_pkce_challenge(pkce_challenge)
+            // Needed to get a refresh token back
+            .add_extra_param("access_type", "offline")
+            .add_extra_param("prompt", "consent");
+        for scope in OAUTH_SCOPES {
+            request = request.add_scope(Scope::new(scope.to_string()));
+        }
+        let (auth_url, csrf_state) = request.url();
+
+        println!("[GDRIVE] Direct OAuth callback server listening on port {}", port);
+        *self.pending_auth.lock().unwrap() = Some(PendingPkceAuth {
+            client,
+            client_id: client_id.to_string(),
+            pkce_verifier,
+            csrf_state,
+            listener,
+        });
+
+        Ok(auth_url.to_string())
+    }
+
+    /// Whether a direct OAuth flow is waiting for its callback
+    pub fn has_pending_pkce_auth(&self) -> bool {
+        self.pending_auth.lock().unwrap().is_some()
+    }
+
+    /// Wait for the loopback redirect of a direct OAuth flow and exchange the code for tokens
+    pub async fn complete_pkce_auth(&self) -> Result<GoogleTokens, String> {
+        let pending = self
+            .pending_auth
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or("No OAuth flow in progress")?;
+
+        let listener = pending.listener;
+        let expected_state = pending.csrf_state.secret().clone();
+        let code = tokio::task::spawn_blocking(move || {
+            accept_oauth_callback(&listener, |request_line| extract_auth_code(request_line, &expected_state))
+        })
+        .await
+        .map_err(|e| format!("OAuth callback task failed: {}", e))??;
+
+        let token_response = pending
+            .client
+            .exchange_code(AuthorizationCode::new(code))
+            .set_pkce_verifier(pending.pkce_verifier)
+            .request_async(async_http_client)
+            .await
+            .map_err(|e| format!("Failed to exchange authorization code: {}", e))?;
+
+        let expires_in = token_response.expires_in().map(|d| d.as_secs() as i64).unwrap_or(3600);
+
+        Ok(GoogleTokens {
+            access_token: token_response.access_token().secret().clone(),
+            refresh_token: token_response.refresh_token().map(|t| t.secret().clone()),
+            expires_at: Some(chrono::Utc::now().timestamp() + expires_in),
+            token_type: "Bearer".to_string(),
+            client_id: Some(pending.client_id),
+        })
+    }
+
+    /// Store tokens after successful authentication.
+    /// The account is keyed by its email; reconnecting an account replaces its tokens.
+    /// `move_records(from, to)` re-keys the library's records when the legacy slot is renamed.
+    pub async fn add_account(
+        &self,
+        tokens: GoogleTokens,
+        move_records: impl FnOnce(&str, &str) -> Result<(), String> + Send,
+    ) -> Result<DriveAccountInfo, String> {
+        let info = self.fetch_account_info(&tokens.access_token).await?;
+        if info.email.is_empty() {
+            return Err("Google did not return an email for this account".to_string());
+        }
+
+        // Tokens migrated from single-account versions are stored as LEGACY_ACCOUNT_ID -
+        // if this is the same account, take over its slot so it stays primary
+        let has_legacy = self.tokens.lock().unwrap().contains_key(LEGACY_ACCOUNT_ID);
+        if has_legacy {
+            let legacy_email = match self.get_access_token(Some(LEGACY_ACCOUNT_ID)).await {
+                Ok(token) => self.fetch_account_info(&token).await.ok().map(|i| i.email),
+                Err(_) => None,
+            };
+            if legacy_email.as_deref() == Some(info.email.as_str()) {
+                // Library rows first - if that fails the legacy slot is still intact
+                move_records(LEGACY_ACCOUNT_ID, &info.email)?;
+                self.rename_account(LEGACY_ACCOUNT_ID, &info.email)?;
+            }
+        }
+
+        save_tokens(&info.email, &tokens)?;
+        self.tokens.lock().unwrap().insert(info.email.clone(), tokens);
+        {
+            let mut accounts = self.accounts.lock().unwrap();
+            if !accounts.contains(&info.email) {
+                accounts.push(info.email.clone());
+            }
+            save_account_index(&accounts)?;
+        }
+
+        println!("[GDRIVE] Connected account {}", info.email);
+        Ok(info)
+    }
+
+    fn rename_account(&self, from: &str, to: &str) -> Result<(), String> {
+        let tokens = self.tokens.lock().unwrap().remove(from);
+        if let Some(t) = tokens {
+            save_tokens(to, &t)?;
+            self.tokens.lock().unwrap().insert(to.to_string(), t);
+        }
+        secrets::delete_secret(&tokens_secret_name(from))?;
+        let mut accounts = self.accounts.lock().unwrap();
+        for id in accounts.iter_mut() {
+            if id == from {
+                *id = to.to_string();
+            }
+        }
+        save_account_index(&accounts)
+    }
+
+    /// Disconnect one account
+    pub fn remove_account(&self, account_id: &str) -> Result<(), String> {
+        self.tokens.lock().unwrap().remove(account_id);
+        secrets::delete_secret(&tokens_secret_name(account_id))?;
+        let mut accounts = self.accounts.lock().unwrap();
+        accounts.retain(|a| a != account_id);
+        save_account_index(&accounts)
+    }
+
+    /// Clear stored tokens for every account (logout)
     pub fn clear_tokens(&self) -> Result<(), String> {
-        *self.tokens.lock().unwrap() = None;
+        let ids: Vec<String> = self.accounts.lock().unwrap().clone();
+        for id in ids {
+            self.remove_account(&id)?;
+        }
+        self.tokens.lock().unwrap().clear();
         let path = get_tokens_path();
         if path.exists() {
             fs::remove_file(path).map_err(|e| format!("Failed to remove tokens: {}", e))?;
         }
-        Ok(())
+        secrets::delete_secret(LEGACY_TOKENS_SECRET)?;
+        secrets::delete_secret(ACCOUNTS_SECRET)
+    }
+
+    /// Re-read tokens from the secrets store (e.g. after it was unlocked or rotated)
+    pub fn reload_tokens(&self) {
+        let (accounts, tokens) = load_all_tokens();
+        *self.accounts.lock().unwrap() = accounts;
+        *self.tokens.lock().unwrap() = tokens;
+    }
+
+    /// List Shared Drives the account can see
+    pub async fn list_shared_drives(&self, account_id: Option<&str>) -> Result<Vec<SharedDrive>, String> {
+        let access_token = self.get_access_token(account_id).await?;
+        let mut drives = Vec::new();
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let mut url = format!("{}/drives?pageSize=100&fields=drives(id,name),nextPageToken", DRIVE_API_BASE);
+            if let Some(ref token) = page_token {
+                url.push_str(&format!("&pageToken={}", token));
+            }
+
+            let response = self.http_client
+                .get(&url)
+                .header("Authorization", format!("Bearer {}", access_token))
+                .send()
+                .await
+                .map_err(|e| format!("Failed to list shared drives: {}", e))?;
+
+            if !response.status().is_success() {
+                let error_text = response.text().await.unwrap_or_default();
+                return Err(format!("Drive API error: {}", error_text));
+            }
+
+            let result: serde_json::Value = response
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse response: {}", e))?;
+
+            if let Some(items) = result["drives"].as_array() {
+                drives.extend(items.iter().filter_map(|d| {
+                    Some(SharedDrive {
+                        id: d["id"].as_str()?.to_string(),
+                        name: d["name"].as_str().unwrap_or("Shared Drive").to_string(),
+                    })
+                }));
+            }
+
+            match result["nextPageToken"].as_str() {
+                Some(next) => page_token = Some(next.to_string()),
+                None => break,
+            }
+        }
+
+        Ok(drives)
     }
 
     /// List files in a folder
     pub async fn list_files(
         &self,
+        account_id: Option<&str>,
         folder_id: Option<&str>,
         page_token: Option<&str>,
     ) -> Result<DriveListResponse, String> {
-        let access_token = self.get_access_token().await?;
+        let access_token = self.get_access_token(account_id).await?;
 
         let parent = folder_id.unwrap_or("root");
         let query = format!("'{}' in parents and trashed = false", parent);
 
         let mut url = format!(
-            "{}/files?q={}&fields=files(id,name,mimeType,size,modifiedTime,parents,webContentLink),nextPageToken&pageSize=100&orderBy=name",
+            "{}/files?q={}&fields=files(id,name,mimeType,size,modifiedTime,parents,webContentLink),nextPageToken&pageSize=100&orderBy=name&{}",
             DRIVE_API_BASE,
-            urlencoding::encode(&query)
+            urlencoding::encode(&query),
+            ALL_DRIVES_PARAMS
         );
 
         if let Some(token) = page_token {
@@ -221,8 +622,8 @@ impl GoogleDriveClient {
     }
 
     /// List only folders
-    pub async fn list_folders(&self, parent_id: Option<&str>) -> Result<Vec<DriveItem>, String> {
-        let access_token = self.get_access_token().await?;
+    pub async fn list_folders(&self, account_id: Option<&str>, parent_id: Option<&str>) -> Result<Vec<DriveItem>, String> {
+        let access_token = self.get_access_token(account_id).await?;
 
         let parent = parent_id.unwrap_or("root");
         let query = format!(
@@ -231,9 +632,10 @@ impl GoogleDriveClient {
         );
 
         let url = format!(
-            "{}/files?q={}&fields=files(id,name,mimeType,modifiedTime,parents)&pageSize=100&orderBy=name",
+            "{}/files?q={}&fields=files(id,name,mimeType,modifiedTime,parents)&pageSize=100&orderBy=name&{}",
             DRIVE_API_BASE,
-            urlencoding::encode(&query)
+            urlencoding::encode(&query),
+            ALL_DRIVES_PARAMS
         );
 
         let response = self.http_client
@@ -259,24 +661,13 @@ impl GoogleDriveClient {
     /// List video files in a folder (recursive option)
     pub async fn list_video_files(
         &self,
+        account_id: Option<&str>,
         folder_id: &str,
         recursive: bool,
     ) -> Result<Vec<DriveItem>, String> {
-        let access_token = self.get_access_token().await?;
-
-        let video_mimes = [
-            "video/mp4",
-            "video/x-matroska",
-            "video/avi",
-            "video/quicktime",
-            "video/webm",
-            "video/x-m4v",
-            "video/x-ms-wmv",
-            "video/x-flv",
-            "video/mp2t",
-        ];
-
-        let mime_conditions: Vec<String> = video_mimes
+        let access_token = self.get_access_token(account_id).await?;
+
+        let mime_conditions: Vec<String> = VIDEO_MIME_TYPES
             .iter()
             .map(|m| format!("mimeType = '{}'", m))
             .collect();
@@ -292,9 +683,10 @@ impl GoogleDriveClient {
 
         loop {
             let mut url = format!(
-                "{}/files?q={}&fields=files(id,name,mimeType,size,modifiedTime,parents,webContentLink),nextPageToken&pageSize=100",
+                "{}/files?q={}&fields=files(id,name,mimeType,size,modifiedTime,parents,webContentLink),nextPageToken&pageSize=100&{}",
                 DRIVE_API_BASE,
-                urlencoding::encode(&query)
+                urlencoding::encode(&query),
+                ALL_DRIVES_PARAMS
             );
 
             if let Some(ref token) = page_token {
@@ -329,9 +721,9 @@ impl GoogleDriveClient {
 
         // If recursive, also scan subfolders
         if recursive {
-            let subfolders = self.list_folders(Some(folder_id)).await?;
+            let subfolders = self.list_folders(account_id, Some(folder_id)).await?;
             for folder in subfolders {
-                let subfolder_files = Box::pin(self.list_video_files(&folder.id, true)).await?;
+                let subfolder_files = Box::pin(self.list_video_files(account_id, &folder.id, true)).await?;
                 all_files.extend(subfolder_files);
             }
         }
@@ -339,19 +731,123 @@ impl GoogleDriveClient {
         Ok(all_files)
     }
 
+    /// Every file below a folder, with the folder names leading to it (relative to `folder_id`)
+    pub async fn list_tree(&self, account_id: Option<&str>, folder_id: &str) -> Result<Vec<(Vec<String>, DriveItem)>, String> {
+        let mut files = Vec::new();
+        let mut pending: Vec<(String, Vec<String>)> = vec![(folder_id.to_string(), Vec::new())];
+
+        while let Some((current, dirs)) = pending.pop() {
+            let mut page_token: Option<String> = None;
+            loop {
+                let page = self.list_files(account_id, Some(&current), page_token.as_deref()).await?;
+                for item in page.files {
+                    if item.is_folder() {
+                        let mut child_dirs = dirs.clone();
+                        child_dirs.push(item.name.clone());
+                        pending.push((item.id, child_dirs));
+                    } else {
+                        files.push((dirs.clone(), item));
+                    }
+                }
+                match page.next_page_token {
+                    Some(token) => page_token = Some(token),
+                    None => break,
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Download a small file (e.g. a subtitle) into memory
+    pub async fn download_file(&self, account_id: Option<&str>, file_id: &str) -> Result<Vec<u8>, String> {
+        let (url, access_token) = self.get_stream_url(account_id, file_id).await?;
+
+        let response = self.http_client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to download file: {}", e))?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(format!("Drive API error: {}", error_text));
+        }
+
+        response
+            .bytes()
+            .await
+            .map(|b| b.to_vec())
+            .map_err(|e| format!("Failed to read file: {}", e))
+    }
+
+    /// Read bytes `start..=end` of a file
+    pub async fn download_range(&self, account_id: Option<&str>, file_id: &str, start: u64, end: u64) -> Result<Vec<u8>, String> {
+        let (url, access_token) = self.get_stream_url(account_id, file_id).await?;
+
+        let response = self.http_client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("Range", format!("bytes={}-{}", start, end))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to download file range: {}", e))?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(format!("Drive API error: {}", error_text));
+        }
+
+        response
+            .bytes()
+            .await
+            .map(|b| b.to_vec())
+            .map_err(|e| format!("Failed to read file: {}", e))
+    }
+
     /// Get a streaming URL for a file (with auth header)
-    pub async fn get_stream_url(&self, file_id: &str) -> Result<(String, String), String> {
-        let access_token = self.get_access_token().await?;
-        let url = format!("{}/files/{}?alt=media", DRIVE_API_BASE, file_id);
+    pub async fn get_stream_url(&self, account_id: Option<&str>, file_id: &str) -> Result<(String, String), String> {
+        let access_token = self.get_access_token(account_id).await?;
+        let url = format!("{}/files/{}?alt=media&supportsAllDrives=true", DRIVE_API_BASE, file_id);
         Ok((url, access_token))
     }
 
+    /// Stream URL and token for a file, after checking Drive will actually serve it.
+    /// Reads the first byte so quota and permission errors surface before a player or
+    /// FFmpeg gets an opaque HTTP failure; a rejected token is refreshed once.
+    pub async fn check_download(&self, account_id: Option<&str>, file_id: &str) -> Result<(String, String), DriveError> {
+        for attempt in 0..2 {
+            let (url, access_token) = self.get_stream_url(account_id, file_id).await.map_err(DriveError::Other)?;
+
+            let response = self.http_client
+                .get(&url)
+                .header("Authorization", format!("Bearer {}", access_token))
+                .header("Range", "bytes=0-0")
+                .send()
+                .await
+                .map_err(|e| DriveError::Other(format!("Failed to reach Drive: {}", e)))?;
+
+            let status = response.status().as_u16();
+            if response.status().is_success() {
+                return Ok((url, access_token));
+            }
+            if status == 401 && attempt == 0 {
+                self.expire_access_token(account_id);
+                continue;
+            }
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(DriveError::from_response(status, &error_text));
+        }
+        Err(DriveError::Other("Drive rejected the refreshed access token".to_string()))
+    }
+
     /// Get file metadata
-    pub async fn get_file_metadata(&self, file_id: &str) -> Result<DriveItem, String> {
-        let access_token = self.get_access_token().await?;
+    pub async fn get_file_metadata(&self, account_id: Option<&str>, file_id: &str) -> Result<DriveItem, String> {
+        let access_token = self.get_access_token(account_id).await?;
 
         let url = format!(
-            "{}/files/{}?fields=id,name,mimeType,size,modifiedTime,parents,webContentLink",
+            "{}/files/{}?fields=id,name,mimeType,size,modifiedTime,parents,webContentLink,trashed,md5Checksum&supportsAllDrives=true",
             DRIVE_API_BASE,
             file_id
         );
@@ -375,10 +871,10 @@ impl GoogleDriveClient {
     }
 
     /// Delete a file from Google Drive
-    pub async fn delete_file(&self, file_id: &str) -> Result<(), String> {
-        let access_token = self.get_access_token().await?;
+    pub async fn delete_file(&self, account_id: Option<&str>, file_id: &str) -> Result<(), String> {
+        let access_token = self.get_access_token(account_id).await?;
 
-        let url = format!("{}/files/{}", DRIVE_API_BASE, file_id);
+        let url = format!("{}/files/{}?supportsAllDrives=true", DRIVE_API_BASE, file_id);
 
         let response = self.http_client
             .delete(&url)
@@ -398,9 +894,12 @@ impl GoogleDriveClient {
     }
 
     /// Get account info
-    pub async fn get_account_info(&self) -> Result<DriveAccountInfo, String> {
-        let access_token = self.get_access_token().await?;
+    pub async fn get_account_info(&self, account_id: Option<&str>) -> Result<DriveAccountInfo, String> {
+        let access_token = self.get_access_token(account_id).await?;
+        self.fetch_account_info(&access_token).await
+    }
 
+    async fn fetch_account_info(&self, access_token: &str) -> Result<DriveAccountInfo, String> {
         // Get user info
         let user_url = "https://www.googleapis.com/oauth2/v2/userinfo";
         let user_response = self.http_client
@@ -457,11 +956,14 @@ impl GoogleDriveClient {
     // ==================== Changes API (Efficient Delta Sync) ====================
 
     /// Get the start page token for tracking changes
-    /// Call this once when setting up change tracking
-    pub async fn get_changes_start_token(&self) -> Result<String, String> {
-        let access_token = self.get_access_token().await?;
+    /// Call this once when setting up change tracking. `drive_id` selects a Shared Drive's feed.
+    pub async fn get_changes_start_token(&self, account_id: Option<&str>, drive_id: Option<&str>) -> Result<String, String> {
+        let access_token = self.get_access_token(account_id).await?;
 
-        let url = format!("{}/changes/startPageToken", DRIVE_API_BASE);
+        let mut url = format!("{}/changes/startPageToken?supportsAllDrives=true", DRIVE_API_BASE);
+        if let Some(drive) = drive_id {
+            url.push_str(&format!("&driveId={}", urlencoding::encode(drive)));
+        }
 
         let response = self.http_client
             .get(&url)
@@ -488,14 +990,23 @@ impl GoogleDriveClient {
 
     /// Get changes since the given page token
     /// Returns new/modified files and a new token for the next check
-    pub async fn get_changes(&self, page_token: &str) -> Result<DriveChangesResponse, String> {
-        let access_token = self.get_access_token().await?;
+    pub async fn get_changes(
+        &self,
+        account_id: Option<&str>,
+        drive_id: Option<&str>,
+        page_token: &str,
+    ) -> Result<DriveChangesResponse, String> {
+        let access_token = self.get_access_token(account_id).await?;
 
-        let url = format!(
-            "{}/changes?pageToken={}&fields=changes(fileId,removed,file(id,name,mimeType,size,modifiedTime,parents)),newStartPageToken,nextPageToken&pageSize=100&includeRemoved=true&spaces=drive",
+        let mut url = format!(
+            "{}/changes?pageToken={}&fields=changes(fileId,removed,file(id,name,mimeType,size,modifiedTime,parents,trashed)),newStartPageToken,nextPageToken&pageSize=100&includeRemoved=true&spaces=drive&{}",
             DRIVE_API_BASE,
-            page_token
+            page_token,
+            if drive_id.is_some() { SHARED_DRIVE_CHANGES_PARAMS } else { MY_DRIVE_CHANGES_PARAMS }
         );
+        if let Some(drive) = drive_id {
+            url.push_str(&format!("&driveId={}", urlencoding::encode(drive)));
+        }
 
         let response = self.http_client
             .get(&url)
@@ -515,49 +1026,30 @@ impl GoogleDriveClient {
             .map_err(|e| format!("Failed to parse changes response: {}", e))
     }
 
-    /// Check for new video files since last token
-    /// Returns (new_video_files, new_token)
-    pub async fn get_video_changes(&self, page_token: &str) -> Result<(Vec<DriveItem>, String), String> {
-        let mut all_video_files = Vec::new();
+    /// Collect every change since the given token, following pagination
+    /// Returns (changes, new_token) - callers decide which changes matter
+    pub async fn get_all_changes(
+        &self,
+        account_id: Option<&str>,
+        drive_id: Option<&str>,
+        page_token: &str,
+    ) -> Result<(Vec<DriveChange>, String), String> {
+        let mut all_changes = Vec::new();
         let mut current_token = page_token.to_string();
 
-        let video_mimes = [
-            "video/mp4",
-            "video/x-matroska",
-            "video/avi",
-            "video/quicktime",
-            "video/webm",
-            "video/x-m4v",
-            "video/x-ms-wmv",
-            "video/x-flv",
-            "video/mp2t",
-        ];
-
         loop {
-            let changes = self.get_changes(&current_token).await?;
-
-            // Filter for video files that weren't removed
-            for change in changes.changes {
-                if change.removed.unwrap_or(false) {
-                    continue;
-                }
-
-                if let Some(file) = change.file {
-                    if video_mimes.contains(&file.mime_type.as_str()) {
-                        all_video_files.push(file);
-                    }
-                }
-            }
+            let changes = self.get_changes(account_id, drive_id, &current_token).await?;
+            all_changes.extend(changes.changes);
 
             // Check if we need to paginate
             if let Some(next_token) = changes.next_page_token {
                 current_token = next_token;
             } else if let Some(new_token) = changes.new_start_page_token {
                 // No more pages, return the new token for next time
-                return Ok((all_video_files, new_token));
+                return Ok((all_changes, new_token));
             } else {
                 // Shouldn't happen, but use current token as fallback
-                return Ok((all_video_files, current_token));
+                return Ok((all_changes, current_token));
             }
         }
     }
@@ -627,6 +1119,7 @@ pub fn parse_tokens_from_callback(url: &str) -> Result<GoogleTokens, String> {
         refresh_token,
         expires_at: Some(expires_at),
         token_type,
+        client_id: None,
     })
 }
 
@@ -639,13 +1132,36 @@ pub async fn wait_for_oauth_callback() -> Result<GoogleTokens, String> {
 
     println!("[GDRIVE] OAuth callback server listening on port 8085");
 
-    // Set a timeout for the connection
-    listener.set_nonblocking(false).ok();
+    tokio::task::spawn_blocking(move || accept_oauth_callback(&listener, extract_tokens_from_request))
+        .await
+        .map_err(|e| format!("OAuth callback task failed: {}", e))?
+}
 
-    // Accept one connection
-    let (mut stream, _) = listener
-        .accept()
-        .map_err(|e| format!("Failed to accept OAuth callback: {}", e))?;
+/// Accept one redirect on the loopback listener, parse it and show the success page
+fn accept_oauth_callback<T>(
+    listener: &TcpListener,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<T, String> {
+    // Poll so an abandoned browser flow gives the port back instead of blocking forever
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Failed to start OAuth callback server: {}", e))?;
+    let deadline = Instant::now() + OAUTH_CALLBACK_TIMEOUT;
+    let mut stream = loop {
+        match listener.accept() {
+            Ok((stream, _)) => break stream,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return Err("Timed out waiting for Google sign-in - please try again".to_string());
+                }
+                std::thread::sleep(Duration::from_millis(200));
+            }
+            Err(e) => return Err(format!("Failed to accept OAuth callback: {}", e)),
+        }
+    };
+    // Accepted sockets can inherit non-blocking mode on some platforms
+    stream.set_nonblocking(false).ok();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).ok();
 
     // Read the HTTP request
     let buf_reader = BufReader::new(&stream);
@@ -655,10 +1171,10 @@ pub async fn wait_for_oauth_callback() -> Result<GoogleTokens, String> {
         .ok_or("No request received")?
         .map_err(|e| format!("Failed to read request: {}", e))?;
 
-    println!("[GDRIVE] Received callback: {}", request_line);
+    println!("[GDRIVE] Received callback");
 
-    // Parse the request to get tokens or error
-    let tokens = extract_tokens_from_request(&request_line)?;
+    // Parse the request to get tokens / code or error
+    let result = parse(&request_line)?;
 
     // Send a success response
     let response_body = r#"
@@ -694,7 +1210,7 @@ pub async fn wait_for_oauth_callback() -> Result<GoogleTokens, String> {
     stream.write_all(response.as_bytes()).ok();
     stream.flush().ok();
 
-    Ok(tokens)
+    Ok(result)
 }
 
 /// Extract tokens from callback request
@@ -769,6 +1285,7 @@ fn extract_tokens_from_request(request_line: &str) -> Result<GoogleTokens, Strin
         refresh_token,
         expires_at: Some(expires_at),
         token_type,
+        client_id: None,
     })
 }
 
@@ -783,32 +1300,86 @@ pub async fn exchange_code_for_tokens(_code: &str) -> Result<GoogleTokens, Strin
 
 // ==================== Helpers ====================
 
+/// Legacy plaintext token file (migrated into the secrets store on first load)
 fn get_tokens_path() -> PathBuf {
     get_app_data_dir().join("gdrive_tokens.json")
 }
 
-fn save_tokens(tokens: &GoogleTokens) -> Result<(), String> {
-    let path = get_tokens_path();
-    let json = serde_json::to_string_pretty(tokens)
+/// Single-account secret used before multi-account support
+const LEGACY_TOKENS_SECRET: &str = "gdrive_tokens";
+/// Account id given to tokens migrated from the single-account layout
+pub const LEGACY_ACCOUNT_ID: &str = "default";
+/// Ordered list of connected account ids
+const ACCOUNTS_SECRET: &str = "gdrive_accounts";
+
+fn tokens_secret_name(account_id: &str) -> String {
+    format!("gdrive_tokens:{}", account_id)
+}
+
+fn save_tokens(account_id: &str, tokens: &GoogleTokens) -> Result<(), String> {
+    let json = serde_json::to_string(tokens)
         .map_err(|e| format!("Failed to serialize tokens: {}", e))?;
 
-    // Ensure directory exists
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent).ok();
+    secrets::set_secret(&tokens_secret_name(account_id), &json).map_err(|e| format!("Failed to save tokens: {}", e))
+}
+
+fn save_account_index(accounts: &[String]) -> Result<(), String> {
+    if accounts.is_empty() {
+        return secrets::delete_secret(ACCOUNTS_SECRET);
     }
+    let json = serde_json::to_string(accounts).map_err(|e| e.to_string())?;
+    secrets::set_secret(ACCOUNTS_SECRET, &json)
+}
 
-    fs::write(&path, json).map_err(|e| format!("Failed to save tokens: {}", e))
+/// Load every connected account's tokens, migrating older single-account storage
+fn load_all_tokens() -> (Vec<String>, HashMap<String, GoogleTokens>) {
+    let mut accounts: Vec<String> = secrets::get_secret(ACCOUNTS_SECRET)
+        .ok()
+        .flatten()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+
+    if accounts.is_empty() {
+        if let Ok(tokens) = load_legacy_tokens() {
+            if save_tokens(LEGACY_ACCOUNT_ID, &tokens).is_ok() {
+                accounts.push(LEGACY_ACCOUNT_ID.to_string());
+                save_account_index(&accounts).ok();
+                secrets::delete_secret(LEGACY_TOKENS_SECRET).ok();
+                fs::remove_file(get_tokens_path()).ok();
+                println!("[GDRIVE] Migrated single-account tokens");
+            }
+        }
+    }
+
+    let mut tokens = HashMap::new();
+    accounts.retain(|id| {
+        match secrets::get_secret(&tokens_secret_name(id)).ok().flatten()
+            .and_then(|json| serde_json::from_str::<GoogleTokens>(&json).ok())
+        {
+            Some(t) => {
+                tokens.insert(id.clone(), t);
+                true
+            }
+            None => false,
+        }
+    });
+
+    (accounts, tokens)
 }
 
-fn load_tokens() -> Result<GoogleTokens, String> {
-    let path = get_tokens_path();
-    let json = fs::read_to_string(&path)
-        .map_err(|e| format!("Failed to read tokens: {}", e))?;
+/// Tokens from the single-account secret or the plaintext file written by older versions
+fn load_legacy_tokens() -> Result<GoogleTokens, String> {
+    if let Some(json) = secrets::get_secret(LEGACY_TOKENS_SECRET)? {
+        return serde_json::from_str(&json).map_err(|e| format!("Failed to parse tokens: {}", e));
+    }
 
+    let json = fs::read_to_string(get_tokens_path())
+        .map_err(|e| format!("Failed to read tokens: {}", e))?;
     serde_json::from_str(&json).map_err(|e| format!("Failed to parse tokens: {}", e))
 }
 
-fn extract_auth_code(request_line: &str) -> Result<String, String> {
+/// Pull the authorization code out of a loopback redirect, checking the CSRF state
+fn extract_auth_code(request_line: &str, expected_state: &str) -> Result<String, String> {
     // Parse: GET /callback?code=XXX&state=YYY HTTP/1.1
     let parts: Vec<&str> = request_line.split_whitespace().collect();
     if parts.len() < 2 {
@@ -834,12 +1405,28 @@ fn extract_auth_code(request_line: &str) -> Result<String, String> {
         })
         .collect();
 
+    if params.get("state").copied() != Some(expected_state) {
+        return Err("OAuth state mismatch - please try connecting again".to_string());
+    }
+
     params
         .get("code")
-        .map(|s| s.to_string())
+        .map(|s| percent_encoding::percent_decode_str(s).decode_utf8_lossy().to_string())
         .ok_or("No code in callback URL".to_string())
 }
 
+/// OAuth client for Google's endpoints with a user-supplied client id
+fn build_oauth_client(client_id: &str, client_secret: Option<&str>) -> Result<BasicClient, String> {
+    Ok(BasicClient::new(
+        ClientId::new(client_id.to_string()),
+        client_secret.map(|s| ClientSecret::new(s.to_string())),
+        AuthUrl::new(GOOGLE_AUTH_URL.to_string()).map_err(|e| e.to_string())?,
+        Some(TokenUrl::new(GOOGLE_TOKEN_URL.to_string()).map_err(|e| e.to_string())?),
+    )
+    // Google expects desktop client credentials in the form body
+    .set_auth_type(AuthType::RequestBody))
+}
+
 fn generate_state() -> String {
     use rand::Rng;
     let mut rng = rand::thread_rng();
@@ -0,0 +1,313 @@
+// Intro Detection Module
+// Finds intros and end credits by comparing audio across episodes of a season: the theme
+// song and credits music repeat, the rest of the episode doesn't. Each episode's audio is
+// decoded by ffmpeg to 8 kHz mono and reduced to an energy signature - per 100 ms frame,
+// whether loudness rose compared with 1, 2, 4 and 8 frames earlier. Two episodes are slid
+// against each other and the longest stretch where those bits agree is the shared part.
+
+use std::process::{Command, Stdio};
+
+use crate::database::SkipMarkers;
+
+const SAMPLE_RATE: usize = 8000;
+const FRAME_SECONDS: f64 = 0.1;
+const FRAME_SAMPLES: usize = (SAMPLE_RATE as f64 * FRAME_SECONDS) as usize;
+
+// Where to look: the start of the episode for the intro, the end for credits
+const INTRO_SCAN_SECONDS: f64 = 600.0;
+const CREDITS_SCAN_SECONDS: f64 = 420.0;
+
+// Plausible lengths for a shared segment
+const MIN_INTRO_SECONDS: f64 = 15.0;
+const MAX_INTRO_SECONDS: f64 = 150.0;
+const MIN_CREDITS_SECONDS: f64 = 15.0;
+
+// A frame counts as matching when most frames around it agree (2 s window, 70%)
+const MATCH_WINDOW: usize = 20;
+const MATCH_THRESHOLD: usize = 14;
+// Consecutive matching frames that mark the real edge of a shared segment
+const EDGE_RUN: usize = 3;
+
+// Frames quieter than this (log10 of mean square) are silence and never match -
+// otherwise every pair of episodes would "share" their silent gaps
+const SILENCE_LEVEL: f32 = 2.0;
+
+// Each episode is compared with this many neighbours in the season
+const COMPARE_NEIGHBOURS: usize = 2;
+
+/// Energy signature of part of an episode
+#[derive(Debug, Clone)]
+pub struct AudioSignature {
+    /// Where the analysed audio starts in the episode, in seconds
+    pub start: f64,
+    features: Vec<u8>,
+    loud: Vec<bool>,
+}
+
+/// An episode to analyse. `source` is anything ffmpeg can open (path or proxy URL).
+#[derive(Debug, Clone)]
+pub struct EpisodeSource {
+    pub media_id: i64,
+    pub source: String,
+    pub duration: Option<f64>,
+}
+
+/// Stretch shared by two signatures, in frames
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    /// Where it starts in the first signature
+    start: usize,
+    len: usize,
+}
+
+/// Build a signature from 16-bit mono PCM at 8 kHz
+pub fn signature_from_pcm(samples: &[i16], start: f64) -> AudioSignature {
+    let energies: Vec<f32> = samples
+        .chunks_exact(FRAME_SAMPLES)
+        .map(|frame| {
+            let sum: f64 = frame.iter().map(|&s| (s as f64) * (s as f64)).sum();
+            ((sum / frame.len() as f64) + 1.0).log10() as f32
+        })
+        .collect();
+
+    let features = (0..energies.len())
+        .map(|i| {
+            [1, 2, 4, 8].iter().enumerate().fold(0u8, |bits, (bit, &lag)| {
+                if i >= lag && energies[i] > energies[i - lag] {
+                    bits | (1 << bit)
+                } else {
+                    bits
+                }
+            })
+        })
+        .collect();
+    let loud = energies.iter().map(|&e| e >= SILENCE_LEVEL).collect();
+
+    AudioSignature { start, features, loud }
+}
+
+/// Decode `duration` seconds of audio from `start` and build its signature
+pub fn extract_signature(ffmpeg_path: &str, source: &str, start: f64, duration: f64) -> Result<AudioSignature, String> {
+    let output = Command::new(ffmpeg_path)
+        .args(["-nostdin", "-v", "error"])
+        .args(["-ss", &format!("{:.3}", start)])
+        .args(["-t", &format!("{:.3}", duration)])
+        .args(["-i", source])
+        .args(["-vn", "-ac", "1", "-ar", &SAMPLE_RATE.to_string(), "-f", "s16le", "-"])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+
+    if !output.status.success() {
+        return Err(format!("ffmpeg failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    let samples: Vec<i16> = output
+        .stdout
+        .chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+    if samples.len() < FRAME_SAMPLES * MATCH_WINDOW {
+        return Err("Not enough audio to analyse".to_string());
+    }
+
+    Ok(signature_from_pcm(&samples, start))
+}
+
+/// Longest stretch where the two signatures line up, at any alignment
+fn longest_common_segment(a: &AudioSignature, b: &AudioSignature) -> Option<Segment> {
+    let (len_a, len_b) = (a.features.len() as isize, b.features.len() as isize);
+    let mut best: Option<Segment> = None;
+    let mut matched: Vec<bool> = Vec::new();
+
+    // Frame i of `a` lines up with frame i - shift of `b`
+    for shift in -(len_b - 1)..len_a {
+        let first = shift.max(0) as usize;
+        let last = (len_b + shift).min(len_a) as usize;
+        if last - first < MATCH_WINDOW || best.is_some_and(|s| last - first <= s.len) {
+            continue;
+        }
+
+        matched.clear();
+        matched.extend((first..last).map(|i| {
+            let j = (i as isize - shift) as usize;
+            a.loud[i] && b.loud[j] && (a.features[i] ^ b.features[j]).count_ones() <= 1
+        }));
+
+        // Smooth over a window so a few noisy frames don't split a run
+        let last_window = matched.len() - MATCH_WINDOW;
+        let mut window = matched[..MATCH_WINDOW].iter().filter(|&&m| m).count();
+        let mut run_start: Option<usize> = None;
+        for k in 0..=last_window {
+            if k > 0 {
+                window += matched[k + MATCH_WINDOW - 1] as usize;
+                window -= matched[k - 1] as usize;
+            }
+            let good = window >= MATCH_THRESHOLD;
+            if good && run_start.is_none() {
+                run_start = Some(k);
+            }
+            if good && k < last_window {
+                continue;
+            }
+
+            if let Some(first_window) = run_start.take() {
+                // Trim to where frames match several in a row - single frames at the
+                // edges often agree by chance and would push the markers into the episode
+                let last_frame = if good { k } else { k - 1 } + MATCH_WINDOW;
+                let solid = |f: usize| f + EDGE_RUN <= last_frame && matched[f..f + EDGE_RUN].iter().all(|&m| m);
+                let start = (first_window..last_frame).find(|&f| solid(f)).unwrap_or(first_window);
+                let end = (start..last_frame).rev().find(|&f| solid(f)).map_or(last_frame, |f| f + EDGE_RUN);
+                let len = end - start;
+                if best.is_none_or(|s| len > s.len) {
+                    best = Some(Segment { start: first + start, len });
+                }
+            }
+        }
+    }
+
+    best
+}
+
+/// Longest shared segment between `signature` and any of `others`, as (start, end) seconds
+/// in the episode, if its length is within the given bounds
+fn shared_segment(signature: &AudioSignature, others: &[&AudioSignature], min_seconds: f64, max_seconds: f64) -> Option<(f64, f64)> {
+    others
+        .iter()
+        .filter_map(|other| longest_common_segment(signature, other))
+        .map(|segment| {
+            let start = signature.start + segment.start as f64 * FRAME_SECONDS;
+            (start, start + segment.len as f64 * FRAME_SECONDS)
+        })
+        .filter(|(start, end)| end - start >= min_seconds && end - start <= max_seconds)
+        .max_by(|x, y| (x.1 - x.0).total_cmp(&(y.1 - y.0)))
+}
+
+/// The closest other episodes to compare an episode against
+fn neighbours<T>(items: &[Option<T>], index: usize) -> Vec<&T> {
+    let mut found = Vec::new();
+    for distance in 1..items.len() {
+        for candidate in [index.checked_sub(distance), Some(index + distance)] {
+            if let Some(item) = candidate.and_then(|i| items.get(i)).and_then(|i| i.as_ref()) {
+                if found.len() < COMPARE_NEIGHBOURS {
+                    found.push(item);
+                }
+            }
+        }
+        if found.len() >= COMPARE_NEIGHBOURS {
+            break;
+        }
+    }
+    found
+}
+
+/// Detect intro and credits for the episodes of one season (in episode order).
+/// `on_progress` is called with (episodes analysed, total) while audio is being decoded.
+pub fn detect_season(
+    ffmpeg_path: &str,
+    episodes: &[EpisodeSource],
+    on_progress: &mut dyn FnMut(usize, usize),
+) -> Vec<(i64, SkipMarkers)> {
+    if episodes.len() < 2 {
+        return Vec::new();
+    }
+
+    let mut intros = Vec::new();
+    let mut credits = Vec::new();
+    for (i, episode) in episodes.iter().enumerate() {
+        let intro = extract_signature(ffmpeg_path, &episode.source, 0.0, INTRO_SCAN_SECONDS)
+            .map_err(|e| println!("[INTRO] Media {}: {}", episode.media_id, e))
+            .ok();
+
+        // Credits need to know where the episode ends
+        let credit = episode
+            .duration
+            .filter(|d| *d > CREDITS_SCAN_SECONDS * 2.0)
+            .and_then(|duration| {
+                let start = duration - CREDITS_SCAN_SECONDS;
+                extract_signature(ffmpeg_path, &episode.source, start, CREDITS_SCAN_SECONDS)
+                    .map_err(|e| println!("[INTRO] Media {} credits: {}", episode.media_id, e))
+                    .ok()
+            });
+
+        intros.push(intro);
+        credits.push(credit);
+        on_progress(i + 1, episodes.len());
+    }
+
+    episodes
+        .iter()
+        .enumerate()
+        .map(|(i, episode)| {
+            let intro = intros[i]
+                .as_ref()
+                .and_then(|sig| shared_segment(sig, &neighbours(&intros, i), MIN_INTRO_SECONDS, MAX_INTRO_SECONDS));
+            let credits_start = credits[i]
+                .as_ref()
+                .and_then(|sig| shared_segment(sig, &neighbours(&credits, i), MIN_CREDITS_SECONDS, CREDITS_SCAN_SECONDS))
+                .map(|(start, _)| start);
+
+            let markers = SkipMarkers {
+                intro_start: intro.map(|(start, _)| start),
+                intro_end: intro.map(|(_, end)| end),
+                credits_start,
+            };
+            (episode.media_id, markers)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `seconds` of audio whose loudness changes randomly every frame, the same for the same seed
+    fn noise(seed: u64, seconds: usize) -> Vec<i16> {
+        let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let frames = (seconds as f64 / FRAME_SECONDS) as usize;
+        let mut samples = Vec::with_capacity(frames * FRAME_SAMPLES);
+        for _ in 0..frames {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let amplitude = 200 + ((state >> 33) % 8000) as i16;
+            samples.extend((0..FRAME_SAMPLES).map(|k| if k % 2 == 0 { amplitude } else { -amplitude }));
+        }
+        samples
+    }
+
+    fn episode(parts: &[Vec<i16>]) -> AudioSignature {
+        signature_from_pcm(&parts.concat(), 0.0)
+    }
+
+    #[test]
+    fn finds_a_shared_segment_at_different_offsets() {
+        let theme = noise(1, 40);
+        let a = episode(&[noise(2, 30), theme.clone(), noise(3, 20)]);
+        let b = episode(&[noise(4, 75), theme, noise(5, 10)]);
+
+        let segment = longest_common_segment(&a, &b).unwrap();
+        assert!((295..=305).contains(&segment.start), "start {}", segment.start);
+        assert!((390..=400).contains(&segment.len), "len {}", segment.len);
+
+        let (start, end) = shared_segment(&a, &[&b], MIN_INTRO_SECONDS, MAX_INTRO_SECONDS).unwrap();
+        assert!((start - 30.0).abs() <= 0.5 && (end - 70.0).abs() <= 0.5, "{}..{}", start, end);
+        // Seen from the other episode it starts later
+        let (start, _) = shared_segment(&b, &[&a], MIN_INTRO_SECONDS, MAX_INTRO_SECONDS).unwrap();
+        assert!((start - 75.0).abs() <= 0.5, "{}", start);
+    }
+
+    #[test]
+    fn unrelated_audio_shares_nothing() {
+        let a = episode(&[noise(10, 90)]);
+        let b = episode(&[noise(11, 90)]);
+        assert!(longest_common_segment(&a, &b).is_none_or(|s| (s.len as f64) * FRAME_SECONDS < MIN_INTRO_SECONDS));
+        assert!(shared_segment(&a, &[&b], MIN_INTRO_SECONDS, MAX_INTRO_SECONDS).is_none());
+    }
+
+    #[test]
+    fn shared_silence_is_not_an_intro() {
+        let silence = vec![0i16; 40 * SAMPLE_RATE];
+        let a = episode(&[noise(20, 30), silence.clone(), noise(21, 30)]);
+        let b = episode(&[noise(22, 50), silence, noise(23, 30)]);
+        assert!(shared_segment(&a, &[&b], MIN_INTRO_SECONDS, MAX_INTRO_SECONDS).is_none());
+    }
+}
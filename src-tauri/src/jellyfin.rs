@@ -0,0 +1,1406 @@
+// Jellyfin API Module
+// Opt-in server speaking enough of the Jellyfin HTTP API for Jellyfin apps (Findroid,
+// Swiftfin, the web client) to log in, browse, play and report progress. Everything is
+// backed by the local database: movies and shows appear as two libraries, shows expand
+// into seasons and episodes, streams take the same paths as DLNA (the file, the Drive
+// proxy or the transcoder) and progress reports move the resume position.
+// Jellyfin ids are GUIDs, so ours encode the kind of object next to the media id.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tiny_http::{Header, Request, Response, Server, StatusCode};
+
+use crate::database::{self, Database, JellyfinSession, MediaItem, MediaStream, MediaTechInfo};
+use crate::dlna;
+use crate::transcoder::QualityProfile;
+
+const SERVER_NAME: &str = "StreamVault";
+// Reported server version; apps refuse servers older than the API they were built for
+const SERVER_VERSION: &str = "10.9.11";
+const TICKS_PER_SECOND: f64 = 10_000_000.0;
+// Subtitle codecs clients can render as text (the rest are image based)
+const TEXT_SUBTITLE_CODECS: [&str; 5] = ["subrip", "ass", "ssa", "webvtt", "mov_text"];
+// Failed logins allowed from one address before each further try has to wait, doubling up to the cap
+const FREE_LOGIN_ATTEMPTS: u32 = 5;
+const MAX_LOGIN_BACKOFF: Duration = Duration::from_secs(300);
+// Addresses whose last failed login is this old start over
+const FAILED_LOGIN_MEMORY: Duration = Duration::from_secs(3600);
+const CORS_HEADERS: [(&str, &str); 3] = [
+    ("Access-Control-Allow-Origin", "*"),
+    ("Access-Control-Allow-Methods", "GET, POST, DELETE, OPTIONS"),
+    ("Access-Control-Allow-Headers", "Authorization, X-Emby-Authorization, X-Emby-Token, Content-Type"),
+];
+
+/// Server settings, built from the config
+#[derive(Debug, Clone, PartialEq)]
+pub struct JellyfinSettings {
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    pub ffmpeg_path: Option<String>,
+    /// Quality of transcoded streams
+    pub profile: QualityProfile,
+}
+
+/// What the handlers need: the settings plus the ids clients remember us by
+struct ApiContext {
+    settings: JellyfinSettings,
+    server_id: String,
+    user_id: String,
+    /// Failed login count and time of the last one, by client address
+    failed_logins: Mutex<HashMap<Option<IpAddr>, (u32, Instant)>>,
+}
+
+lazy_static::lazy_static! {
+    /// The running server and its accept loop, joined on stop so a restart can rebind
+    static ref SERVER: Mutex<Option<(Arc<Server>, JoinHandle<()>)>> = Mutex::new(None);
+}
+
+/// Start, restart or stop the server to match the settings (None = disabled)
+pub fn apply(settings: Option<JellyfinSettings>) -> Result<(), String> {
+    stop();
+    let settings = match settings {
+        Some(settings) => settings,
+        None => return Ok(()),
+    };
+
+    let http = Server::http(("0.0.0.0", settings.port))
+        .map_err(|e| format!("Failed to start Jellyfin API on port {}: {}", settings.port, e))?;
+    let http = Arc::new(http);
+    let port = settings.port;
+
+    // Stable across restarts so clients keep their saved server and login
+    let seed = machine_uid::get()
+        .unwrap_or_else(|_| database::get_app_data_dir().to_string_lossy().to_string());
+    let server_id = hex_hash(&format!("streamvault-jellyfin:{}", seed));
+    let user_id = hex_hash(&format!("{}:{}", server_id, settings.username.to_lowercase()));
+    let context = Arc::new(ApiContext { settings, server_id, user_id, failed_logins: Mutex::new(HashMap::new()) });
+
+    let server = http.clone();
+    let accept_thread = std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let context = context.clone();
+            std::thread::spawn(move || handle_request(request, &context));
+        }
+        println!("[JELLYFIN] API server stopped");
+    });
+
+    println!("[JELLYFIN] API listening on port {}", port);
+    *SERVER.lock().map_err(|e| e.to_string())? = Some((http, accept_thread));
+    Ok(())
+}
+
+pub fn stop() {
+    let server = match SERVER.lock() {
+        Ok(mut server) => server.take(),
+        Err(_) => None,
+    };
+    if let Some((server, accept_thread)) = server {
+        server.unblock();
+        let _ = accept_thread.join();
+    }
+}
+
+/// How long an address has to wait after `failures` failed logins
+fn login_backoff(failures: u32) -> Duration {
+    match failures.checked_sub(FREE_LOGIN_ATTEMPTS) {
+        None => Duration::ZERO,
+        Some(extra) => Duration::from_secs(1u64 << extra.min(16)).min(MAX_LOGIN_BACKOFF),
+    }
+}
+
+/// Compare a password without leaking how much of it matched through the timing
+fn same_secret(given: &str, expected: &str) -> bool {
+    let (given, expected) = (Sha256::digest(given.as_bytes()), Sha256::digest(expected.as_bytes()));
+    given.iter().zip(expected.iter()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// First 32 hex digits of a SHA-256, the shape of a Jellyfin GUID
+fn hex_hash(text: &str) -> String {
+    Sha256::digest(text.as_bytes()).iter().take(16).map(|b| format!("{:02x}", b)).collect()
+}
+
+// ============================================================================
+// Requests and replies
+// ============================================================================
+
+/// The parts of an HTTP request the API looks at
+#[derive(Debug, Default)]
+struct ApiRequest {
+    method: String,
+    /// Path segments, lowercased (Jellyfin routes are case-insensitive)
+    path: Vec<String>,
+    /// Query parameters by lowercased name
+    query: HashMap<String, String>,
+    /// Fields of the `MediaBrowser Client="..", Device="..", ...` header, by lowercased name
+    auth: HashMap<String, String>,
+    token: Option<String>,
+    host: Option<String>,
+    /// Client address, for login throttling
+    remote_ip: Option<IpAddr>,
+    body: Value,
+}
+
+impl ApiRequest {
+    fn parse(method: &str, url: &str, headers: &[(&str, &str)], body: &str) -> Self {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(field, _)| field.eq_ignore_ascii_case(name))
+                .map(|(_, value)| *value)
+        };
+
+        let query: HashMap<String, String> = url::form_urlencoded::parse(query.as_bytes())
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.into_owned()))
+            .collect();
+        let auth = header("Authorization")
+            .or_else(|| header("X-Emby-Authorization"))
+            .map(parse_authorization)
+            .unwrap_or_default();
+        // Apps send the token in the auth header, a token header, or ?api_key= on stream URLs
+        let token = header("X-Emby-Token")
+            .or_else(|| header("X-MediaBrowser-Token"))
+            .map(str::to_string)
+            .or_else(|| auth.get("token").cloned())
+            .or_else(|| query.get("api_key").or_else(|| query.get("apikey")).cloned())
+            .filter(|token| !token.is_empty());
+
+        ApiRequest {
+            method: method.to_ascii_uppercase(),
+            path: path.split('/').filter(|s| !s.is_empty()).map(|s| s.to_ascii_lowercase()).collect(),
+            query,
+            auth,
+            token,
+            host: header("Host").map(str::to_string),
+            remote_ip: None,
+            body: serde_json::from_str(body).unwrap_or(Value::Null),
+        }
+    }
+
+    /// A non-empty query parameter (`name` lowercase)
+    fn query(&self, name: &str) -> Option<&str> {
+        self.query.get(name).map(String::as_str).filter(|value| !value.is_empty())
+    }
+
+    fn query_flag(&self, name: &str) -> Option<bool> {
+        self.query(name).map(|value| value.eq_ignore_ascii_case("true"))
+    }
+
+    /// Comma-separated query parameter, lowercased
+    fn query_list(&self, name: &str) -> Vec<String> {
+        self.query(name)
+            .map(|value| value.split(',').map(|v| v.trim().to_ascii_lowercase()).filter(|v| !v.is_empty()).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// `MediaBrowser Client="Findroid", Device="Pixel%207", Token="..."` into its fields
+fn parse_authorization(value: &str) -> HashMap<String, String> {
+    let fields = match value.trim().split_once(' ') {
+        Some((scheme, fields)) if scheme.eq_ignore_ascii_case("MediaBrowser") || scheme.eq_ignore_ascii_case("Emby") => fields,
+        _ => return HashMap::new(),
+    };
+    fields
+        .split(',')
+        .filter_map(|field| field.split_once('='))
+        .map(|(name, value)| {
+            let value = value.trim().trim_matches('"');
+            let value = percent_encoding::percent_decode_str(value).decode_utf8_lossy().to_string();
+            (name.trim().to_ascii_lowercase(), value)
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+enum Reply {
+    Json(u16, Value),
+    Empty(u16),
+    /// `start_time` is where a transcode starts, in seconds (resume)
+    Stream { media_id: i64, transcode: Option<bool>, start_time: Option<f64> },
+    Image(i64),
+}
+
+fn ok(value: Value) -> Result<Reply, String> {
+    Ok(Reply::Json(200, value))
+}
+
+fn handle_request(mut request: Request, context: &ApiContext) {
+    let cors: Vec<Header> = CORS_HEADERS
+        .iter()
+        .filter_map(|(name, value)| Header::from_bytes(name.as_bytes(), value.as_bytes()).ok())
+        .collect();
+    if request.method().as_str().eq_ignore_ascii_case("OPTIONS") {
+        let _ = request.respond(Response::new(StatusCode(204), cors, std::io::empty(), Some(0), None));
+        return;
+    }
+
+    let mut body = String::new();
+    let _ = request.as_reader().read_to_string(&mut body);
+    let headers: Vec<(String, String)> = request
+        .headers()
+        .iter()
+        .map(|h| (h.field.as_str().as_str().to_string(), h.value.as_str().to_string()))
+        .collect();
+    let headers: Vec<(&str, &str)> = headers.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+    let mut api_request = ApiRequest::parse(request.method().as_str(), request.url(), &headers, &body);
+    api_request.remote_ip = request.remote_addr().map(|addr| addr.ip());
+
+    let reply = match Database::new(&database::get_database_path()) {
+        Ok(db) => route(&api_request, &db, context),
+        Err(e) => {
+            println!("[JELLYFIN] Failed to open database: {}", e);
+            Reply::Empty(500)
+        }
+    };
+
+    match reply {
+        Reply::Json(status, value) => {
+            let mut response = Response::from_string(value.to_string()).with_status_code(status);
+            for header in cors {
+                response = response.with_header(header);
+            }
+            if let Ok(content_type) = Header::from_bytes(&b"Content-Type"[..], &b"application/json; charset=utf-8"[..]) {
+                response = response.with_header(content_type);
+            }
+            let _ = request.respond(response);
+        }
+        Reply::Empty(status) => {
+            let _ = request.respond(Response::new(StatusCode(status), cors, std::io::empty(), Some(0), None));
+        }
+        Reply::Stream { media_id, transcode, start_time } => {
+            let settings = &context.settings;
+            dlna::serve_media(request, media_id, settings.ffmpeg_path.as_deref(), &settings.profile, transcode, start_time);
+        }
+        Reply::Image(media_id) => dlna::serve_art(request, media_id),
+    }
+}
+
+fn route(req: &ApiRequest, db: &Database, ctx: &ApiContext) -> Reply {
+    let path: Vec<&str> = req.path.iter().map(String::as_str).collect();
+    let method = req.method.as_str();
+
+    // Reachable before logging in (images are loaded without a token, as on Jellyfin)
+    match (method, path.as_slice()) {
+        (_, ["system", "info", "public"]) => return Reply::Json(200, system_info(req, ctx)),
+        (_, ["system", "ping"]) => return Reply::Json(200, json!(SERVER_NAME)),
+        (_, ["branding", "configuration"]) => {
+            return Reply::Json(200, json!({ "LoginDisclaimer": "", "CustomCss": "", "SplashscreenEnabled": false }))
+        }
+        (_, ["users", "public"]) => return Reply::Json(200, json!([])),
+        ("POST", ["users", "authenticatebyname"]) => return authenticate(req, db, ctx),
+        (_, ["items", id, "images", kind, ..]) => return image(db, id, kind),
+        _ => {}
+    }
+
+    let session = match req.token.as_deref().map(|token| db.get_jellyfin_session(token)) {
+        Some(Ok(Some(session))) => session,
+        _ => return Reply::Empty(401),
+    };
+
+    let result = match (method, path.as_slice()) {
+        (_, ["system", "info"]) => ok(system_info(req, ctx)),
+        (_, ["users", "me"]) | (_, ["users", _]) => ok(user_dto(ctx)),
+        (_, ["users", _, "views"]) | (_, ["userviews"]) => views(db, ctx),
+        (_, ["users", _, "items", "resume"]) | (_, ["useritems", "resume"]) => resume(req, db, ctx),
+        (_, ["users", _, "items", "latest"]) | (_, ["items", "latest"]) => latest(req, db, ctx),
+        (_, ["users", _, "items"]) | (_, ["items"]) => items(req, db, ctx),
+        (_, ["users", _, "items", id]) | (_, ["items", id]) => item(db, ctx, id),
+        (_, ["items", id, "playbackinfo"]) => playback_info(req, db, ctx, &session, id),
+        (_, ["shows", "nextup"]) => next_up(req, db, ctx),
+        (_, ["shows", id, "seasons"]) => seasons(req, db, ctx, id),
+        (_, ["shows", id, "episodes"]) => episodes(req, db, ctx, id),
+        (_, ["videos", id, file]) if file.starts_with("stream") => Ok(stream(req, id)),
+        ("POST", ["sessions", "playing"]) | ("POST", ["sessions", "playing", "progress"]) | ("POST", ["sessions", "playing", "stopped"]) => {
+            report_progress(req, db)
+        }
+        ("POST", ["sessions", "capabilities", ..]) => Ok(Reply::Empty(204)),
+        ("POST", ["sessions", "logout"]) => db
+            .delete_jellyfin_session(&session.token)
+            .map(|_| Reply::Empty(204))
+            .map_err(|e| e.to_string()),
+        (_, ["displaypreferences", id]) => ok(display_preferences(req, id)),
+        _ => Ok(Reply::Empty(404)),
+    };
+
+    result.unwrap_or_else(|e| {
+        println!("[JELLYFIN] {} /{} failed: {}", method, path.join("/"), e);
+        Reply::Empty(500)
+    })
+}
+
+// ============================================================================
+// Ids
+// ============================================================================
+
+/// What a Jellyfin id points at. Encoded as 32 hex digits: kind, season number, media id.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ItemId {
+    MoviesView,
+    ShowsView,
+    Media(i64),
+    Season(i64, i32),
+}
+
+impl ItemId {
+    fn parse(id: &str) -> Option<ItemId> {
+        let hex: String = id.chars().filter(|c| *c != '-').collect();
+        if hex.len() != 32 || !hex.is_ascii() {
+            return None;
+        }
+        let kind = u32::from_str_radix(&hex[0..8], 16).ok()?;
+        let season = u32::from_str_radix(&hex[8..16], 16).ok()? as i32;
+        let media = u64::from_str_radix(&hex[16..32], 16).ok()? as i64;
+        match kind {
+            1 => Some(ItemId::Media(media)),
+            2 => Some(ItemId::Season(media, season)),
+            3 => Some(ItemId::MoviesView),
+            4 => Some(ItemId::ShowsView),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ItemId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (kind, season, media) = match *self {
+            ItemId::Media(id) => (1, 0, id),
+            ItemId::Season(series, season) => (2, season, series),
+            ItemId::MoviesView => (3, 0, 0),
+            ItemId::ShowsView => (4, 0, 0),
+        };
+        write!(f, "{:08x}{:08x}{:016x}", kind, season as u32, media as u64)
+    }
+}
+
+fn ticks(seconds: f64) -> i64 {
+    (seconds * TICKS_PER_SECOND) as i64
+}
+
+/// SQLite's "YYYY-MM-DD HH:MM:SS" (UTC) as an ISO 8601 timestamp
+fn iso_date(sqlite_date: &str) -> String {
+    format!("{}Z", sqlite_date.trim().replace(' ', "T"))
+}
+
+// ============================================================================
+// DTOs
+// ============================================================================
+
+fn system_info(req: &ApiRequest, ctx: &ApiContext) -> Value {
+    json!({
+        "LocalAddress": req.host.as_ref().map(|host| format!("http://{}", host)),
+        "ServerName": SERVER_NAME,
+        "Version": SERVER_VERSION,
+        "ProductName": "Jellyfin Server",
+        "OperatingSystem": std::env::consts::OS,
+        "Id": ctx.server_id,
+        "StartupWizardCompleted": true,
+    })
+}
+
+fn user_dto(ctx: &ApiContext) -> Value {
+    json!({
+        "Name": ctx.settings.username,
+        "ServerId": ctx.server_id,
+        "Id": ctx.user_id,
+        "HasPassword": true,
+        "HasConfiguredPassword": true,
+        "HasConfiguredEasyPassword": false,
+        "EnableAutoLogin": false,
+        "Configuration": user_configuration(),
+        "Policy": user_policy(),
+    })
+}
+
+fn user_configuration() -> Value {
+    json!({
+        "PlayDefaultAudioTrack": true,
+        "SubtitleLanguagePreference": "",
+        "DisplayMissingEpisodes": false,
+        "GroupedFolders": [],
+        "SubtitleMode": "Default",
+        "DisplayCollectionsView": false,
+        "EnableLocalPassword": false,
+        "OrderedViews": [],
+        "LatestItemsExcludes": [],
+        "MyMediaExcludes": [],
+        "HidePlayedInLatest": true,
+        "RememberAudioSelections": true,
+        "RememberSubtitleSelections": true,
+        "EnableNextEpisodeAutoPlay": true,
+    })
+}
+
+/// An ordinary, non-admin user allowed to play, transcode and download
+fn user_policy() -> Value {
+    json!({
+        "IsAdministrator": false,
+        "IsHidden": true,
+        "IsDisabled": false,
+        "BlockedTags": [],
+        "AllowedTags": [],
+        "EnableUserPreferenceAccess": true,
+        "AccessSchedules": [],
+        "BlockUnratedItems": [],
+        "EnableRemoteControlOfOtherUsers": false,
+        "EnableSharedDeviceControl": false,
+        "EnableRemoteAccess": true,
+        "EnableLiveTvManagement": false,
+        "EnableLiveTvAccess": false,
+        "EnableMediaPlayback": true,
+        "EnableAudioPlaybackTranscoding": true,
+        "EnableVideoPlaybackTranscoding": true,
+        "EnablePlaybackRemuxing": true,
+        "ForceRemoteSourceTranscoding": false,
+        "EnableContentDeletion": false,
+        "EnableContentDeletionFromFolders": [],
+        "EnableContentDownloading": true,
+        "EnableSyncTranscoding": false,
+        "EnableMediaConversion": false,
+        "EnabledDevices": [],
+        "EnableAllDevices": true,
+        "EnabledChannels": [],
+        "EnableAllChannels": false,
+        "EnabledFolders": [],
+        "EnableAllFolders": true,
+        "InvalidLoginAttemptCount": 0,
+        "LoginAttemptsBeforeLockout": -1,
+        "MaxActiveSessions": 0,
+        "EnablePublicSharing": false,
+        "BlockedMediaFolders": [],
+        "BlockedChannels": [],
+        "RemoteClientBitrateLimit": 0,
+        "AuthenticationProviderId": "Jellyfin.Server.Implementations.Users.DefaultAuthenticationProvider",
+        "PasswordResetProviderId": "Jellyfin.Server.Implementations.Users.DefaultPasswordResetProvider",
+        "SyncPlayAccess": "None",
+    })
+}
+
+fn session_info(ctx: &ApiContext, session: &JellyfinSession) -> Value {
+    json!({
+        "Id": hex_hash(&session.token),
+        "UserId": ctx.user_id,
+        "UserName": ctx.settings.username,
+        "Client": session.client,
+        "DeviceName": session.device_name,
+        "DeviceId": session.device_id,
+        "ApplicationVersion": session.version,
+        "ServerId": ctx.server_id,
+        "IsActive": true,
+        "SupportsMediaControl": false,
+        "SupportsRemoteControl": false,
+        "HasCustomDeviceName": false,
+        "LastActivityDate": chrono::Utc::now().to_rfc3339(),
+        "PlayState": { "CanSeek": false, "IsPaused": false, "IsMuted": false, "RepeatMode": "RepeatNone", "PlaybackOrder": "Default" },
+        "AdditionalUsers": [],
+        "Capabilities": {
+            "PlayableMediaTypes": [],
+            "SupportedCommands": [],
+            "SupportsMediaControl": false,
+            "SupportsPersistentIdentifier": true,
+        },
+        "PlayableMediaTypes": [],
+        "SupportedCommands": [],
+        "NowPlayingQueue": [],
+        "NowPlayingQueueFullItems": [],
+    })
+}
+
+/// Tag that changes when the artwork file does, so clients know to refetch
+fn image_tags(media: &MediaItem) -> Value {
+    match dlna::artwork_path(media) {
+        Some(path) => json!({ "Primary": hex_hash(&path.to_string_lossy()) }),
+        None => json!({}),
+    }
+}
+
+fn folder_user_data(id: &str) -> Value {
+    json!({ "PlaybackPositionTicks": 0, "PlayCount": 0, "IsFavorite": false, "Played": false, "Key": id })
+}
+
+fn user_data(media: &MediaItem, id: &str) -> Value {
+    let position = media.resume_position_seconds.unwrap_or(0.0);
+    let duration = media.duration_seconds.unwrap_or(0.0);
+    // Finishing clears the resume position but keeps last_watched
+    let played = media.last_watched.is_some() && position <= 0.0;
+    let mut data = json!({
+        "PlaybackPositionTicks": ticks(position),
+        "PlayCount": if played { 1 } else { 0 },
+        "IsFavorite": false,
+        "Played": played,
+        "Key": id,
+    });
+    if position > 0.0 && duration > 0.0 {
+        data["PlayedPercentage"] = json!(position / duration * 100.0);
+    }
+    if let Some(ref last_watched) = media.last_watched {
+        data["LastPlayedDate"] = json!(iso_date(last_watched));
+    }
+    data
+}
+
+fn view_dto(ctx: &ApiContext, view: ItemId, child_count: usize) -> Value {
+    let (name, collection_type) = match view {
+        ItemId::MoviesView => ("Movies", "movies"),
+        _ => ("TV Shows", "tvshows"),
+    };
+    let id = view.to_string();
+    json!({
+        "Name": name,
+        "ServerId": ctx.server_id,
+        "Id": id,
+        "Type": "CollectionFolder",
+        "CollectionType": collection_type,
+        "IsFolder": true,
+        "ChildCount": child_count,
+        "ImageTags": {},
+        "BackdropImageTags": [],
+        "LocationType": "FileSystem",
+        "UserData": folder_user_data(&id),
+    })
+}
+
+fn series_dto(ctx: &ApiContext, show: &MediaItem, seasons: usize) -> Value {
+    let id = ItemId::Media(show.id).to_string();
+    json!({
+        "Name": show.title,
+        "ServerId": ctx.server_id,
+        "Id": id,
+        "Type": "Series",
+        "IsFolder": true,
+        "ParentId": ItemId::ShowsView.to_string(),
+        "ProductionYear": show.year,
+        "Overview": show.overview,
+        "ChildCount": seasons,
+        "ProviderIds": provider_ids(show),
+        "ImageTags": image_tags(show),
+        "BackdropImageTags": [],
+        "LocationType": "FileSystem",
+        "UserData": folder_user_data(&id),
+    })
+}
+
+fn season_dto(ctx: &ApiContext, show: &MediaItem, season: i32, episodes: usize) -> Value {
+    let id = ItemId::Season(show.id, season).to_string();
+    let name = if season == 0 { "Specials".to_string() } else { format!("Season {}", season) };
+    let series_tag = image_tags(show)["Primary"].clone();
+    json!({
+        "Name": name,
+        "ServerId": ctx.server_id,
+        "Id": id,
+        "Type": "Season",
+        "IsFolder": true,
+        "IndexNumber": season,
+        "ParentId": ItemId::Media(show.id).to_string(),
+        "SeriesId": ItemId::Media(show.id).to_string(),
+        "SeriesName": show.title,
+        "ChildCount": episodes,
+        "ImageTags": image_tags(show),
+        "SeriesPrimaryImageTag": series_tag,
+        "BackdropImageTags": [],
+        "LocationType": "FileSystem",
+        "UserData": folder_user_data(&id),
+    })
+}
+
+/// A movie or episode. `series` is looked up when not given.
+fn video_dto(ctx: &ApiContext, db: &Database, media: &MediaItem, series: Option<&MediaItem>) -> Value {
+    let id = ItemId::Media(media.id).to_string();
+    let file_name = media.file_path.clone().unwrap_or_default();
+    let mut dto = json!({
+        "Name": media.title,
+        "ServerId": ctx.server_id,
+        "Id": id,
+        "Type": "Movie",
+        "MediaType": "Video",
+        "IsFolder": false,
+        "ProductionYear": media.year,
+        "Overview": media.overview,
+        "Container": extension(&file_name),
+        "ProviderIds": provider_ids(media),
+        "ImageTags": image_tags(media),
+        "BackdropImageTags": [],
+        "LocationType": "FileSystem",
+        "UserData": user_data(media, &id),
+    });
+    if let Some(duration) = media.duration_seconds.filter(|d| *d > 0.0) {
+        dto["RunTimeTicks"] = json!(ticks(duration));
+    }
+
+    if media.media_type == "tvepisode" {
+        let looked_up;
+        let series = match series {
+            Some(series) => Some(series),
+            None => {
+                looked_up = media.parent_id.and_then(|id| db.get_media_by_id(id).ok());
+                looked_up.as_ref()
+            }
+        };
+        let season = media.season_number.unwrap_or(0);
+        let episode = media.episode_number.unwrap_or(0);
+        dto["Type"] = json!("Episode");
+        dto["Name"] = json!(media.episode_title.clone().unwrap_or_else(|| format!("Episode {}", episode)));
+        dto["IndexNumber"] = json!(episode);
+        dto["ParentIndexNumber"] = json!(season);
+        if let Some(series) = series {
+            let season_id = ItemId::Season(series.id, season).to_string();
+            dto["SeriesId"] = json!(ItemId::Media(series.id).to_string());
+            dto["SeriesName"] = json!(series.title);
+            dto["SeasonId"] = json!(season_id);
+            dto["ParentId"] = json!(season_id);
+            dto["SeasonName"] = json!(if season == 0 { "Specials".to_string() } else { format!("Season {}", season) });
+            dto["SeriesPrimaryImageTag"] = image_tags(series)["Primary"].clone();
+            dto["ProductionYear"] = json!(series.year);
+        }
+    } else {
+        dto["ParentId"] = json!(ItemId::MoviesView.to_string());
+    }
+    dto
+}
+
+fn provider_ids(media: &MediaItem) -> Value {
+    match media.tmdb_id {
+        Some(ref tmdb_id) => json!({ "Tmdb": tmdb_id }),
+        None => json!({}),
+    }
+}
+
+fn extension(file_name: &str) -> String {
+    Path::new(file_name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Episode count per season number
+fn season_counts(episodes: &[MediaItem]) -> Vec<(i32, usize)> {
+    let mut counts: Vec<(i32, usize)> = Vec::new();
+    for episode in episodes {
+        let season = episode.season_number.unwrap_or(0);
+        match counts.iter_mut().find(|(number, _)| *number == season) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((season, 1)),
+        }
+    }
+    counts.sort_by_key(|(season, _)| *season);
+    counts
+}
+
+fn query_result(items: Vec<Value>, start_index: usize, total: usize) -> Value {
+    json!({ "Items": items, "TotalRecordCount": total, "StartIndex": start_index })
+}
+
+// ============================================================================
+// Endpoints
+// ============================================================================
+
+fn authenticate(req: &ApiRequest, db: &Database, ctx: &ApiContext) -> Reply {
+    let mut failed_logins = match ctx.failed_logins.lock() {
+        Ok(failed_logins) => failed_logins,
+        Err(_) => return Reply::Empty(500),
+    };
+    if let Some(&(failures, last)) = failed_logins.get(&req.remote_ip) {
+        if last.elapsed() < login_backoff(failures) {
+            return Reply::Empty(429);
+        }
+    }
+
+    let field = |name: &str| req.body.get(name).and_then(Value::as_str).unwrap_or("");
+    let username = field("Username");
+    let password_ok = same_secret(field("Pw"), &ctx.settings.password);
+    if !username.eq_ignore_ascii_case(&ctx.settings.username) || !password_ok {
+        failed_logins.retain(|_, (_, last)| last.elapsed() < FAILED_LOGIN_MEMORY);
+        let entry = failed_logins.entry(req.remote_ip).or_insert((0, Instant::now()));
+        *entry = (entry.0 + 1, Instant::now());
+        println!("[JELLYFIN] Rejected login for \"{}\" from {:?}", username, req.remote_ip);
+        return Reply::Empty(401);
+    }
+    failed_logins.remove(&req.remote_ip);
+    drop(failed_logins);
+
+    let device = |name: &str, fallback: &str| req.auth.get(name).cloned().unwrap_or_else(|| fallback.to_string());
+    let token: String = rand::thread_rng()
+        .gen::<[u8; 16]>()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let session = JellyfinSession {
+        token,
+        device_id: device("deviceid", "unknown"),
+        device_name: device("device", "Unknown device"),
+        client: device("client", "Unknown client"),
+        version: device("version", ""),
+    };
+    if let Err(e) = db.add_jellyfin_session(&session) {
+        println!("[JELLYFIN] Failed to save session: {}", e);
+        return Reply::Empty(500);
+    }
+
+    println!("[JELLYFIN] {} on {} logged in", session.client, session.device_name);
+    Reply::Json(200, json!({
+        "User": user_dto(ctx),
+        "SessionInfo": session_info(ctx, &session),
+        "AccessToken": session.token,
+        "ServerId": ctx.server_id,
+    }))
+}
+
+fn views(db: &Database, ctx: &ApiContext) -> Result<Reply, String> {
+    let movies = db.get_library("movie", None).map_err(|e| e.to_string())?.len();
+    let shows = db.get_library("tvshow", None).map_err(|e| e.to_string())?.len();
+    let views = vec![view_dto(ctx, ItemId::MoviesView, movies), view_dto(ctx, ItemId::ShowsView, shows)];
+    ok(query_result(views, 0, 2))
+}
+
+fn item_dto(db: &Database, ctx: &ApiContext, id: ItemId) -> Result<Option<Value>, String> {
+    match id {
+        ItemId::MoviesView | ItemId::ShowsView => {
+            let media_type = if id == ItemId::MoviesView { "movie" } else { "tvshow" };
+            let count = db.get_library(media_type, None).map_err(|e| e.to_string())?.len();
+            Ok(Some(view_dto(ctx, id, count)))
+        }
+        ItemId::Media(media_id) => {
+            let media = match db.get_media_by_id(media_id) {
+                Ok(media) => media,
+                Err(_) => return Ok(None),
+            };
+            if media.media_type == "tvshow" {
+                let episodes = db.get_episodes(media.id).map_err(|e| e.to_string())?;
+                Ok(Some(series_dto(ctx, &media, season_counts(&episodes).len())))
+            } else {
+                Ok(Some(video_dto(ctx, db, &media, None)))
+            }
+        }
+        ItemId::Season(series_id, season) => {
+            let show = match db.get_media_by_id(series_id) {
+                Ok(show) => show,
+                Err(_) => return Ok(None),
+            };
+            let episodes = db.get_episodes(series_id).map_err(|e| e.to_string())?;
+            let count = episodes.iter().filter(|e| e.season_number.unwrap_or(0) == season).count();
+            Ok(Some(season_dto(ctx, &show, season, count)))
+        }
+    }
+}
+
+fn item(db: &Database, ctx: &ApiContext, id: &str) -> Result<Reply, String> {
+    match ItemId::parse(id) {
+        Some(id) => Ok(item_dto(db, ctx, id)?.map_or(Reply::Empty(404), |dto| Reply::Json(200, dto))),
+        None => Ok(Reply::Empty(404)),
+    }
+}
+
+fn movie_dtos(db: &Database, ctx: &ApiContext) -> Result<Vec<Value>, String> {
+    let movies = db.get_library("movie", None).map_err(|e| e.to_string())?;
+    Ok(movies.iter().map(|movie| video_dto(ctx, db, movie, None)).collect())
+}
+
+fn series_dtos(db: &Database, ctx: &ApiContext) -> Result<Vec<Value>, String> {
+    let shows = db.get_library("tvshow", None).map_err(|e| e.to_string())?;
+    shows
+        .iter()
+        .map(|show| {
+            let episodes = db.get_episodes(show.id).map_err(|e| e.to_string())?;
+            Ok(series_dto(ctx, show, season_counts(&episodes).len()))
+        })
+        .collect()
+}
+
+/// Episodes of a show, optionally of one season
+fn episode_dtos(db: &Database, ctx: &ApiContext, show: &MediaItem, season: Option<i32>) -> Result<Vec<Value>, String> {
+    let episodes = db.get_episodes(show.id).map_err(|e| e.to_string())?;
+    Ok(episodes
+        .iter()
+        .filter(|episode| season.is_none_or(|season| episode.season_number.unwrap_or(0) == season))
+        .map(|episode| video_dto(ctx, db, episode, Some(show)))
+        .collect())
+}
+
+fn all_episode_dtos(db: &Database, ctx: &ApiContext) -> Result<Vec<Value>, String> {
+    let mut episodes = Vec::new();
+    for show in db.get_library("tvshow", None).map_err(|e| e.to_string())? {
+        episodes.extend(episode_dtos(db, ctx, &show, None)?);
+    }
+    Ok(episodes)
+}
+
+/// Contents of a library, show or season
+fn children(db: &Database, ctx: &ApiContext, parent: ItemId, wants_episodes: bool) -> Result<Vec<Value>, String> {
+    match parent {
+        ItemId::MoviesView => movie_dtos(db, ctx),
+        ItemId::ShowsView if wants_episodes => all_episode_dtos(db, ctx),
+        ItemId::ShowsView => series_dtos(db, ctx),
+        ItemId::Media(series_id) => {
+            let show = match db.get_media_by_id(series_id) {
+                Ok(show) if show.media_type == "tvshow" => show,
+                _ => return Ok(Vec::new()),
+            };
+            if wants_episodes {
+                return episode_dtos(db, ctx, &show, None);
+            }
+            let episodes = db.get_episodes(series_id).map_err(|e| e.to_string())?;
+            Ok(season_counts(&episodes)
+                .into_iter()
+                .map(|(season, count)| season_dto(ctx, &show, season, count))
+                .collect())
+        }
+        ItemId::Season(series_id, season) => match db.get_media_by_id(series_id) {
+            Ok(show) => episode_dtos(db, ctx, &show, Some(season)),
+            Err(_) => Ok(Vec::new()),
+        },
+    }
+}
+
+/// GET /Items: by Ids, by ParentId, or a recursive search of the whole library
+fn items(req: &ApiRequest, db: &Database, ctx: &ApiContext) -> Result<Reply, String> {
+    let types = req.query_list("includeitemtypes");
+    let recursive = req.query_flag("recursive").unwrap_or(false);
+    let wants_episodes = types.iter().any(|t| t == "episode");
+
+    let items = if let Some(ids) = req.query("ids") {
+        let mut items = Vec::new();
+        for id in ids.split(',').filter_map(ItemId::parse) {
+            items.extend(item_dto(db, ctx, id)?);
+        }
+        items
+    } else if let Some(parent) = req.query("parentid") {
+        match ItemId::parse(parent) {
+            Some(parent) => children(db, ctx, parent, wants_episodes)?,
+            None => Vec::new(),
+        }
+    } else if types.is_empty() && !recursive {
+        vec![
+            item_dto(db, ctx, ItemId::MoviesView)?.unwrap_or_default(),
+            item_dto(db, ctx, ItemId::ShowsView)?.unwrap_or_default(),
+        ]
+    } else {
+        let mut items = Vec::new();
+        if types.is_empty() || types.iter().any(|t| t == "movie") {
+            items.extend(movie_dtos(db, ctx)?);
+        }
+        if types.is_empty() || types.iter().any(|t| t == "series") {
+            items.extend(series_dtos(db, ctx)?);
+        }
+        if wants_episodes {
+            items.extend(all_episode_dtos(db, ctx)?);
+        }
+        items
+    };
+
+    ok(filter_and_page(req, items))
+}
+
+/// Apply the type, search and played filters, sorting and paging shared by list endpoints
+fn filter_and_page(req: &ApiRequest, mut items: Vec<Value>) -> Value {
+    let item_type = |item: &Value| item["Type"].as_str().unwrap_or("").to_ascii_lowercase();
+    let types = req.query_list("includeitemtypes");
+    if !types.is_empty() {
+        items.retain(|item| types.contains(&item_type(item)));
+    }
+    let excluded = req.query_list("excludeitemtypes");
+    items.retain(|item| !excluded.contains(&item_type(item)));
+
+    if let Some(term) = req.query("searchterm") {
+        let term = term.to_lowercase();
+        items.retain(|item| item["Name"].as_str().unwrap_or("").to_lowercase().contains(&term));
+    }
+    for filter in req.query_list("filters") {
+        match filter.as_str() {
+            "isresumable" => items.retain(|item| item["UserData"]["PlaybackPositionTicks"].as_i64().unwrap_or(0) > 0),
+            "isplayed" => items.retain(|item| item["UserData"]["Played"].as_bool().unwrap_or(false)),
+            "isunplayed" => items.retain(|item| !item["UserData"]["Played"].as_bool().unwrap_or(false)),
+            // Favourites aren't tracked
+            "isfavorite" => items.clear(),
+            _ => {}
+        }
+    }
+
+    sort_items(&mut items, req.query_list("sortby").first().map(String::as_str), req.query("sortorder"));
+
+    let total = items.len();
+    let start = req.query("startindex").and_then(|v| v.parse().ok()).unwrap_or(0);
+    let limit = req.query("limit").and_then(|v| v.parse().ok()).unwrap_or(usize::MAX);
+    let page = items.into_iter().skip(start).take(limit).collect();
+    query_result(page, start, total)
+}
+
+fn sort_items(items: &mut [Value], sort_by: Option<&str>, order: Option<&str>) {
+    let text = |item: &Value, field: &str| item[field].as_str().unwrap_or("").to_lowercase();
+    match sort_by {
+        Some("sortname") | Some("name") => items.sort_by_key(|item| text(item, "Name")),
+        Some("productionyear") | Some("premieredate") => items.sort_by_key(|item| item["ProductionYear"].as_i64()),
+        Some("dateplayed") => items.sort_by_key(|item| item["UserData"]["LastPlayedDate"].as_str().map(str::to_string)),
+        // Ids grow with the media id, so they order items by when they were added
+        Some("datecreated") => items.sort_by_key(|item| text(item, "Id")),
+        Some("random") => items.shuffle(&mut rand::thread_rng()),
+        _ => return,
+    }
+    if order.is_some_and(|order| order.eq_ignore_ascii_case("descending")) {
+        items.reverse();
+    }
+}
+
+/// Movies and episodes with a resume position, most recently watched first
+fn resume(req: &ApiRequest, db: &Database, ctx: &ApiContext) -> Result<Reply, String> {
+    let items = db
+        .get_watch_history(100)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|media| media.resume_position_seconds.unwrap_or(0.0) > 0.0)
+        .map(|media| video_dto(ctx, db, &media, None))
+        .collect();
+    ok(filter_and_page(req, items))
+}
+
+/// Recently added movies or shows of a library (a plain array, unlike other lists)
+fn latest(req: &ApiRequest, db: &Database, ctx: &ApiContext) -> Result<Reply, String> {
+    let mut items = match req.query("parentid").and_then(ItemId::parse) {
+        Some(ItemId::ShowsView) => series_dtos(db, ctx)?,
+        Some(ItemId::MoviesView) => movie_dtos(db, ctx)?,
+        _ => {
+            let mut items = movie_dtos(db, ctx)?;
+            items.extend(series_dtos(db, ctx)?);
+            items
+        }
+    };
+    sort_items(&mut items, Some("datecreated"), Some("descending"));
+    let limit = req.query("limit").and_then(|v| v.parse().ok()).unwrap_or(16);
+    items.truncate(limit);
+    ok(Value::Array(items))
+}
+
+/// The episode after the last one finished, per recently watched show
+fn next_up(req: &ApiRequest, db: &Database, ctx: &ApiContext) -> Result<Reply, String> {
+    let only_series = match req.query("seriesid").map(ItemId::parse) {
+        Some(Some(ItemId::Media(id))) => Some(id),
+        Some(_) => return ok(query_result(Vec::new(), 0, 0)),
+        None => None,
+    };
+
+    let mut seen = HashSet::new();
+    let mut items = Vec::new();
+    for watched in db.get_watch_history(200).map_err(|e| e.to_string())? {
+        let series_id = match watched.parent_id {
+            Some(id) if watched.media_type == "tvepisode" => id,
+            _ => continue,
+        };
+        if !seen.insert(series_id) || only_series.is_some_and(|only| only != series_id) {
+            continue;
+        }
+        // In-progress episodes are listed under Resume instead
+        if watched.resume_position_seconds.unwrap_or(0.0) > 0.0 {
+            continue;
+        }
+        if let Some(next) = db.get_next_episode(watched.id).map_err(|e| e.to_string())? {
+            items.push(video_dto(ctx, db, &next, None));
+        }
+    }
+    ok(filter_and_page(req, items))
+}
+
+fn seasons(req: &ApiRequest, db: &Database, ctx: &ApiContext, id: &str) -> Result<Reply, String> {
+    match ItemId::parse(id) {
+        Some(series @ ItemId::Media(_)) => ok(filter_and_page(req, children(db, ctx, series, false)?)),
+        _ => Ok(Reply::Empty(404)),
+    }
+}
+
+/// Episodes of a show, narrowed by SeasonId or Season (number)
+fn episodes(req: &ApiRequest, db: &Database, ctx: &ApiContext, id: &str) -> Result<Reply, String> {
+    let series_id = match ItemId::parse(id) {
+        Some(ItemId::Media(series_id)) => series_id,
+        _ => return Ok(Reply::Empty(404)),
+    };
+    let show = match db.get_media_by_id(series_id) {
+        Ok(show) => show,
+        Err(_) => return Ok(Reply::Empty(404)),
+    };
+    let season = match req.query("seasonid").and_then(ItemId::parse) {
+        Some(ItemId::Season(_, season)) => Some(season),
+        _ => req.query("season").and_then(|v| v.parse().ok()),
+    };
+    ok(filter_and_page(req, episode_dtos(db, ctx, &show, season)?))
+}
+
+fn image(db: &Database, id: &str, kind: &str) -> Reply {
+    if kind != "primary" && kind != "thumb" {
+        return Reply::Empty(404);
+    }
+    let media_id = match ItemId::parse(id) {
+        Some(ItemId::Media(id)) => id,
+        // Seasons use the show's poster
+        Some(ItemId::Season(series_id, _)) => series_id,
+        _ => return Reply::Empty(404),
+    };
+    match db.get_media_by_id(media_id) {
+        Ok(media) if dlna::artwork_path(&media).is_some() => Reply::Image(media.id),
+        Ok(media) => media.parent_id.map_or(Reply::Empty(404), Reply::Image),
+        Err(_) => Reply::Empty(404),
+    }
+}
+
+fn stream_dto(kind: &str, stream: &MediaStream) -> Value {
+    let display_title = [stream.title.clone(), stream.language.clone(), Some(stream.codec.to_uppercase())]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" - ");
+    json!({
+        "Type": kind,
+        "Index": stream.index,
+        "Codec": stream.codec,
+        "Language": stream.language,
+        "Title": stream.title,
+        "DisplayTitle": display_title,
+        "Channels": stream.channels,
+        "ChannelLayout": stream.channel_layout,
+        "IsDefault": stream.is_default,
+        "IsForced": stream.forced,
+        "IsExternal": false,
+        "IsInterlaced": false,
+        "IsTextSubtitleStream": kind == "Subtitle" && TEXT_SUBTITLE_CODECS.contains(&stream.codec.as_str()),
+        "SupportsExternalStream": false,
+    })
+}
+
+fn media_streams(info: Option<&MediaTechInfo>) -> Vec<Value> {
+    let info = match info {
+        Some(info) => info,
+        None => return Vec::new(),
+    };
+    let mut streams = Vec::new();
+    if let Some(ref codec) = info.video_codec {
+        streams.push(json!({
+            "Type": "Video",
+            "Index": 0,
+            "Codec": codec,
+            "Width": info.width,
+            "Height": info.height,
+            "VideoRange": if info.hdr.is_some() { "HDR" } else { "SDR" },
+            "IsDefault": true,
+            "IsForced": false,
+            "IsExternal": false,
+            "IsInterlaced": false,
+            "IsTextSubtitleStream": false,
+            "SupportsExternalStream": false,
+        }));
+    }
+    streams.extend(info.audio_tracks.iter().map(|track| stream_dto("Audio", track)));
+    streams.extend(info.subtitle_tracks.iter().map(|track| stream_dto("Subtitle", track)));
+    streams
+}
+
+/// One media source per item: direct play, or a transcode when the codecs need it or the
+/// client turned direct play off
+fn playback_info(
+    req: &ApiRequest,
+    db: &Database,
+    ctx: &ApiContext,
+    session: &JellyfinSession,
+    id: &str,
+) -> Result<Reply, String> {
+    let media = match ItemId::parse(id).map(|id| (id, id)) {
+        Some((item_id, ItemId::Media(media_id))) => match db.get_media_by_id(media_id) {
+            Ok(media) => (item_id.to_string(), media),
+            Err(_) => return Ok(Reply::Empty(404)),
+        },
+        _ => return Ok(Reply::Empty(404)),
+    };
+    let (item_id, media) = media;
+    let info = db.get_media_tech_info(media.id).map_err(|e| e.to_string())?;
+    let file_name = media.file_path.clone().unwrap_or_default();
+
+    let direct_allowed = req.body.get("EnableDirectPlay").and_then(Value::as_bool).unwrap_or(true)
+        && req.query_flag("enabledirectplay").unwrap_or(true);
+    let transcode = ctx.settings.ffmpeg_path.is_some()
+        && (!direct_allowed || dlna::needs_transcode(info.as_ref(), &file_name));
+    let duration = info.as_ref().and_then(|i| i.duration_seconds).or(media.duration_seconds);
+
+    let mut source = json!({
+        "Id": item_id,
+        "Name": media.title,
+        "Protocol": "File",
+        "Type": "Default",
+        "Container": extension(&file_name),
+        "RunTimeTicks": duration.map(ticks),
+        "Bitrate": info.as_ref().and_then(|i| i.bitrate),
+        "IsRemote": false,
+        "SupportsDirectPlay": !transcode,
+        "SupportsDirectStream": !transcode,
+        "SupportsTranscoding": ctx.settings.ffmpeg_path.is_some(),
+        "SupportsProbing": true,
+        "IsInfiniteStream": false,
+        "RequiresOpening": false,
+        "RequiresClosing": false,
+        "RequiresLooping": false,
+        "ReadAtNativeFramerate": false,
+        "IgnoreDts": false,
+        "IgnoreIndex": false,
+        "GenPtsInput": false,
+        "MediaStreams": media_streams(info.as_ref()),
+        "MediaAttachments": [],
+        "Formats": [],
+    });
+    if transcode {
+        // The encode starts at the resume point, so the client plays the stream from 0
+        let start = start_time(req).map(|seconds| format!("&StartTimeTicks={}", ticks(seconds))).unwrap_or_default();
+        source["TranscodingUrl"] = json!(format!(
+            "/Videos/{0}/stream.mp4?MediaSourceId={0}&Static=false&Transcode=true{1}&api_key={2}",
+            item_id, start, session.token
+        ));
+        source["TranscodingSubProtocol"] = json!("http");
+        source["TranscodingContainer"] = json!("mp4");
+    }
+
+    let play_session_id: String = rand::thread_rng().gen::<[u8; 16]>().iter().map(|b| format!("{:02x}", b)).collect();
+    ok(json!({ "MediaSources": [source], "PlaySessionId": play_session_id }))
+}
+
+/// `Static=true` is direct play; the TranscodingUrl from PlaybackInfo asks for `Transcode=true`
+fn stream(req: &ApiRequest, id: &str) -> Reply {
+    match ItemId::parse(id) {
+        Some(ItemId::Media(media_id)) => {
+            let transcode = if req.query_flag("transcode") == Some(true) {
+                Some(true)
+            } else if req.query_flag("static") == Some(true) {
+                Some(false)
+            } else {
+                None
+            };
+            Reply::Stream { media_id, transcode, start_time: start_time(req) }
+        }
+        _ => Reply::Empty(404),
+    }
+}
+
+/// `StartTimeTicks` from the query or the PlaybackInfo body, in seconds
+fn start_time(req: &ApiRequest) -> Option<f64> {
+    req.query("starttimeticks")
+        .and_then(|value| value.parse::<i64>().ok())
+        .or_else(|| req.body.get("StartTimeTicks").and_then(Value::as_i64))
+        .filter(|ticks| *ticks > 0)
+        .map(|ticks| ticks as f64 / TICKS_PER_SECOND)
+}
+
+/// Sessions/Playing, Playing/Progress and Playing/Stopped all carry ItemId and PositionTicks
+fn report_progress(req: &ApiRequest, db: &Database) -> Result<Reply, String> {
+    let media_id = match req.body.get("ItemId").and_then(Value::as_str).and_then(ItemId::parse) {
+        Some(ItemId::Media(media_id)) => media_id,
+        _ => return Ok(Reply::Empty(400)),
+    };
+    let position_ticks = req.body.get("PositionTicks").and_then(Value::as_i64).unwrap_or(0);
+    // Starting from the top says nothing new, and would mark the item as played
+    if position_ticks <= 0 {
+        return Ok(Reply::Empty(204));
+    }
+
+    let media = db.get_media_by_id(media_id).map_err(|e| e.to_string())?;
+    let duration = media
+        .duration_seconds
+        .filter(|d| *d > 0.0)
+        .or_else(|| db.get_media_tech_info(media_id).ok().flatten().and_then(|i| i.duration_seconds))
+        .unwrap_or(0.0);
+    db.update_progress(media_id, position_ticks as f64 / TICKS_PER_SECOND, duration)
+        .map_err(|e| e.to_string())?;
+    Ok(Reply::Empty(204))
+}
+
+/// The web client stores view settings here; ours are fixed
+fn display_preferences(req: &ApiRequest, id: &str) -> Value {
+    json!({
+        "Id": id,
+        "SortBy": "SortName",
+        "SortOrder": "Ascending",
+        "RememberIndexing": false,
+        "RememberSorting": false,
+        "PrimaryImageHeight": 250,
+        "PrimaryImageWidth": 250,
+        "ScrollDirection": "Horizontal",
+        "ShowBackdrop": true,
+        "ShowSidebar": false,
+        "CustomPrefs": {},
+        "Client": req.query("client").unwrap_or("emby"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Synthetic requests, written by hand in the shape Findroid (login) and Swiftfin (browsing
+    // and playback) use: the MediaBrowser authorization header, the AuthenticateByName body and
+    // a PlaybackInfo body with a cut-down DeviceProfile (this server doesn't read it). They are
+    // not captured traffic.
+    const FINDROID_AUTH: &str =
+        "MediaBrowser Client=\"Findroid\", Device=\"Pixel%207\", DeviceId=\"a5b3c1d2e4f60718\", Version=\"0.15.4\"";
+    const LOGIN_BODY: &str = r#"{"Username":"viewer","Pw":"secret"}"#;
+    const PLAYBACK_INFO_BODY: &str = r#"{"UserId":"USER","StartTimeTicks":0,"IsPlayback":true,"AutoOpenLiveStream":true,"MaxStreamingBitrate":20000000,"DeviceProfile":{"Name":"Swiftfin","MaxStreamingBitrate":20000000}}"#;
+
+    fn context() -> ApiContext {
+        ApiContext {
+            settings: JellyfinSettings {
+                port: 8096,
+                username: "viewer".to_string(),
+                password: "secret".to_string(),
+                ffmpeg_path: None,
+                profile: QualityProfile::default(),
+            },
+            server_id: hex_hash("server"),
+            user_id: hex_hash("user"),
+            failed_logins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// A movie and a show with two episodes in season 1
+    fn library() -> (Database, i64, i64, i64) {
+        let db = Database::new(":memory:").unwrap();
+        let movie = db.insert_movie("Arrival", Some(2016), Some("Linguist meets heptapods"), None, "/media/Arrival.mkv", 6960.0, Some("329865")).unwrap();
+        let show = db.insert_tvshow("Severance", Some(2022), None, None, "/media/Severance", None).unwrap();
+        let episode = db.insert_episode("Severance", "/media/Severance/S01E01.mkv", show, 1, 1, 3420.0).unwrap();
+        db.insert_episode("Severance", "/media/Severance/S01E02.mkv", show, 1, 2, 3300.0).unwrap();
+        (db, movie, show, episode)
+    }
+
+    fn send(db: &Database, method: &str, url: &str, headers: &[(&str, &str)], body: &str) -> Reply {
+        route(&ApiRequest::parse(method, url, headers, body), db, &context())
+    }
+
+    fn json_body(reply: Reply) -> Value {
+        match reply {
+            Reply::Json(200, value) => value,
+            other => panic!("expected JSON, got {:?}", other),
+        }
+    }
+
+    fn login(db: &Database) -> String {
+        let reply = send(db, "POST", "/Users/AuthenticateByName", &[("Authorization", FINDROID_AUTH), ("Content-Type", "application/json")], LOGIN_BODY);
+        json_body(reply)["AccessToken"].as_str().unwrap().to_string()
+    }
+
+    fn swiftfin_auth(token: &str) -> String {
+        format!("MediaBrowser Client=\"Swiftfin iOS\", Device=\"iPhone\", DeviceId=\"F1E2D3C4\", Version=\"1.0.0\", Token=\"{}\"", token)
+    }
+
+    #[test]
+    fn authenticates_by_name_and_guards_the_api() {
+        let (db, _, _, _) = library();
+
+        let wrong = r#"{"Username":"viewer","Pw":"guess"}"#;
+        assert!(matches!(send(&db, "POST", "/Users/AuthenticateByName", &[("Authorization", FINDROID_AUTH)], wrong), Reply::Empty(401)));
+        assert!(matches!(send(&db, "GET", "/Users/Me", &[], ""), Reply::Empty(401)));
+
+        let reply = json_body(send(&db, "POST", "/Users/AuthenticateByName", &[("Authorization", FINDROID_AUTH)], LOGIN_BODY));
+        let token = reply["AccessToken"].as_str().unwrap();
+        assert_eq!(reply["ServerId"], context().server_id);
+        assert_eq!(reply["User"]["Id"], context().user_id);
+        assert_eq!(reply["SessionInfo"]["DeviceName"], "Pixel 7");
+        assert_eq!(db.get_jellyfin_session(token).unwrap().unwrap().client, "Findroid");
+
+        let me = json_body(send(&db, "GET", "/Users/Me", &[("X-Emby-Token", token)], ""));
+        assert_eq!(me["Name"], "viewer");
+        let public = json_body(send(&db, "GET", "/System/Info/Public", &[("Host", "192.168.1.20:8096")], ""));
+        assert_eq!(public["LocalAddress"], "http://192.168.1.20:8096");
+    }
+
+    #[test]
+    fn backs_off_repeated_failed_logins() {
+        let (db, _, _, _) = library();
+        let context = context();
+        let login_from = |ip: [u8; 4], body: &str| {
+            let mut req = ApiRequest::parse("POST", "/Users/AuthenticateByName", &[("Authorization", FINDROID_AUTH)], body);
+            req.remote_ip = Some(IpAddr::from(ip));
+            route(&req, &db, &context)
+        };
+
+        let wrong = r#"{"Username":"viewer","Pw":"secreT"}"#;
+        for _ in 0..FREE_LOGIN_ATTEMPTS {
+            assert!(matches!(login_from([192, 168, 1, 50], wrong), Reply::Empty(401)));
+        }
+        // Even the right password waits out the back-off, but only for that address
+        assert!(matches!(login_from([192, 168, 1, 50], LOGIN_BODY), Reply::Empty(429)));
+        assert!(matches!(login_from([192, 168, 1, 51], LOGIN_BODY), Reply::Json(200, _)));
+
+        assert_eq!(login_backoff(FREE_LOGIN_ATTEMPTS - 1), Duration::ZERO);
+        assert_eq!(login_backoff(FREE_LOGIN_ATTEMPTS), Duration::from_secs(1));
+        assert_eq!(login_backoff(FREE_LOGIN_ATTEMPTS + 3), Duration::from_secs(8));
+        assert_eq!(login_backoff(u32::MAX), MAX_LOGIN_BACKOFF);
+        assert!(same_secret("secret", "secret"));
+        assert!(!same_secret("secret", "secret "));
+    }
+
+    #[test]
+    fn browses_libraries_shows_and_plays() {
+        let (db, movie, show, _) = library();
+        let token = login(&db);
+        let auth = swiftfin_auth(&token);
+        let headers = [("Authorization", auth.as_str())];
+
+        let views = json_body(send(&db, "GET", "/UserViews?userId=USER", &headers, ""));
+        assert_eq!(views["TotalRecordCount"], 2);
+        let movies_id = views["Items"][0]["Id"].as_str().unwrap().to_string();
+        assert_eq!(views["Items"][1]["CollectionType"], "tvshows");
+
+        let url = format!("/Users/USER/Items?ParentId={}&SortBy=SortName&SortOrder=Ascending&Recursive=true&IncludeItemTypes=Movie&StartIndex=0&Limit=50&Fields=Overview", movies_id);
+        let movies = json_body(send(&db, "GET", &url, &headers, ""));
+        assert_eq!(movies["Items"][0]["Name"], "Arrival");
+        assert_eq!(movies["Items"][0]["RunTimeTicks"], 69_600_000_000i64);
+        assert_eq!(movies["Items"][0]["ProviderIds"]["Tmdb"], "329865");
+
+        let show_id = ItemId::Media(show).to_string();
+        let seasons = json_body(send(&db, "GET", &format!("/Shows/{}/Seasons?userId=USER&fields=ItemCounts", show_id), &headers, ""));
+        assert_eq!(seasons["Items"][0]["Name"], "Season 1");
+        let season_id = seasons["Items"][0]["Id"].as_str().unwrap().to_string();
+
+        let url = format!("/Shows/{}/Episodes?userId=USER&seasonId={}&fields=Overview", show_id, season_id);
+        let episodes = json_body(send(&db, "GET", &url, &headers, ""));
+        assert_eq!(episodes["TotalRecordCount"], 2);
+        assert_eq!(episodes["Items"][1]["IndexNumber"], 2);
+        assert_eq!(episodes["Items"][1]["SeriesName"], "Severance");
+        assert_eq!(episodes["Items"][1]["SeasonId"], season_id.as_str());
+
+        let movie_id = ItemId::Media(movie).to_string();
+        let url = format!("/Items/{}/PlaybackInfo?UserId=USER&StartTimeTicks=0&IsPlayback=true&AutoOpenLiveStream=true&MaxStreamingBitrate=20000000", movie_id);
+        let info = json_body(send(&db, "POST", &url, &headers, PLAYBACK_INFO_BODY));
+        assert_eq!(info["MediaSources"][0]["SupportsDirectPlay"], true);
+        assert_eq!(info["MediaSources"][0]["Container"], "mkv");
+
+        // Players fetch the stream without headers, with the token in the query
+        let url = format!("/Videos/{0}/stream?static=true&mediaSourceId={0}&api_key={1}", movie_id, token);
+        assert!(matches!(send(&db, "GET", &url, &[], ""), Reply::Stream { media_id, transcode: Some(false), start_time: None } if media_id == movie));
+        // A transcode starts at the client's resume point
+        let url = format!("/Videos/{0}/stream.mp4?MediaSourceId={0}&Static=false&Transcode=true&StartTimeTicks=6000000000&api_key={1}", movie_id, token);
+        assert!(matches!(send(&db, "GET", &url, &[], ""), Reply::Stream { transcode: Some(true), start_time: Some(start), .. } if start == 600.0));
+        let url = format!("/Videos/{0}/stream?static=true&mediaSourceId={0}", movie_id);
+        assert!(matches!(send(&db, "GET", &url, &[], ""), Reply::Empty(401)));
+    }
+
+    #[test]
+    fn progress_reports_move_the_resume_position() {
+        let (db, _, _, episode) = library();
+        let token = login(&db);
+        let headers = [("X-Emby-Token", token.as_str())];
+        let item_id = ItemId::Media(episode).to_string();
+
+        let body = format!(
+            r#"{{"ItemId":"{0}","MediaSourceId":"{0}","PlaySessionId":"4f1c2a","PositionTicks":6000000000,"IsPaused":false,"CanSeek":true,"PlayMethod":"DirectPlay"}}"#,
+            item_id
+        );
+        assert!(matches!(send(&db, "POST", "/Sessions/Playing/Progress", &headers, &body), Reply::Empty(204)));
+        assert_eq!(db.get_resume_info(episode).unwrap().position, 600.0);
+
+        let resume = json_body(send(&db, "GET", "/UserItems/Resume?userId=USER&limit=12&mediaTypes=Video", &headers, ""));
+        assert_eq!(resume["Items"][0]["Id"], item_id.as_str());
+        assert_eq!(resume["Items"][0]["UserData"]["PlaybackPositionTicks"], 6_000_000_000i64);
+        assert_eq!(resume["Items"][0]["SeriesName"], "Severance");
+    }
+}
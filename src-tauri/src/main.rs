@@ -8,6 +8,22 @@ mod tmdb;
 mod mpv_ipc;
 mod gdrive;
 mod transcoder;
+mod backup;
+mod secrets;
+mod drive_resolver;
+mod stream_proxy;
+mod downloads;
+mod cloud_cache;
+mod prefetch;
+mod vlc_ipc;
+mod players;
+mod subtitles;
+mod opensubtitles;
+mod intro_detect;
+mod trickplay;
+mod media_probe;
+mod dlna;
+mod jellyfin;
 
 use tauri_plugin_autostart::MacosLauncher;
 
@@ -43,7 +59,11 @@ pub struct AppState {
     pub config: Mutex<config::Config>,
     pub is_scanning: Arc<AtomicBool>,
     pub active_mpv_sessions: Mutex<HashMap<i64, MpvSession>>,
+    // VLC sessions share the session shape; their monitor threads remove them on exit
+    pub active_vlc_sessions: Mutex<HashMap<i64, MpvSession>>,
     pub gdrive_client: gdrive::GoogleDriveClient,
+    // Parent map for change detection; async lock since lookups hit the Drive API
+    pub drive_ancestry: tokio::sync::Mutex<drive_resolver::FolderAncestry>,
 }
 
 // API Response types
@@ -241,14 +261,51 @@ async fn gdrive_is_connected(state: State<'_, AppState>) -> Result<bool, String>
 #[tauri::command]
 async fn gdrive_get_account_info(
     state: State<'_, AppState>,
+    account_id: Option<String>,
 ) -> Result<gdrive::DriveAccountInfo, String> {
-    state.gdrive_client.get_account_info().await
+    state.gdrive_client.get_account_info(account_id.as_deref()).await
+}
+
+/// List connected Google accounts (primary first)
+#[tauri::command]
+async fn gdrive_list_accounts(state: State<'_, AppState>) -> Result<Vec<gdrive::ConnectedAccount>, String> {
+    Ok(state.gdrive_client.list_accounts())
+}
+
+/// List Shared Drives visible to an account
+#[tauri::command]
+async fn gdrive_list_shared_drives(
+    state: State<'_, AppState>,
+    account_id: Option<String>,
+) -> Result<Vec<gdrive::SharedDrive>, String> {
+    state.gdrive_client.list_shared_drives(account_id.as_deref()).await
 }
 
 /// Start Google Drive OAuth flow - returns auth URL
+/// In "direct" auth mode this runs OAuth + PKCE locally with the configured client id,
+/// otherwise it goes through the hosted auth server
 #[tauri::command]
-async fn gdrive_start_auth() -> Result<String, String> {
-    let auth_url = gdrive::get_auth_url();
+async fn gdrive_start_auth(state: State<'_, AppState>) -> Result<String, String> {
+    let direct_client = {
+        let config = state.config.lock().map_err(|e| e.to_string())?;
+        if config.uses_direct_gdrive_auth() {
+            let client_id = config
+                .gdrive_client_id
+                .clone()
+                .filter(|id| !id.trim().is_empty())
+                .ok_or("Direct Google auth needs a client ID - add one in Settings")?;
+            Some((client_id, config.gdrive_client_secret.clone().filter(|s| !s.is_empty())))
+        } else {
+            None
+        }
+    };
+
+    let auth_url = match direct_client {
+        Some((client_id, client_secret)) => {
+            state.gdrive_client.start_pkce_auth(client_id.trim(), client_secret.as_deref())?
+        }
+        None => gdrive::get_auth_url(),
+    };
 
     // Open the URL in the default browser
     if let Err(e) = open::that(&auth_url) {
@@ -265,25 +322,50 @@ async fn gdrive_start_auth() -> Result<String, String> {
 async fn gdrive_complete_auth(state: State<'_, AppState>) -> Result<gdrive::DriveAccountInfo, String> {
     println!("[GDRIVE] Waiting for OAuth callback...");
 
-    // Wait for tokens from backend (it redirects to localhost with tokens)
-    let tokens = gdrive::wait_for_oauth_callback().await?;
-    println!("[GDRIVE] Received tokens from backend");
+    let tokens = if state.gdrive_client.has_pending_pkce_auth() {
+        // Direct mode: exchange the code ourselves
+        let tokens = state.gdrive_client.complete_pkce_auth().await?;
+        println!("[GDRIVE] Received tokens from Google");
+        tokens
+    } else {
+        // Wait for tokens from backend (it redirects to localhost with tokens)
+        let tokens = gdrive::wait_for_oauth_callback().await?;
+        println!("[GDRIVE] Received tokens from backend");
+        tokens
+    };
 
-    // Store tokens
-    state.gdrive_client.store_tokens(tokens)?;
+    // Store tokens under the account's email and return its info
+    let info = state
+        .gdrive_client
+        .add_account(tokens, |from, to| {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            db.rename_cloud_account(from, to).map_err(|e| e.to_string())
+        })
+        .await?;
     println!("[GDRIVE] Tokens stored successfully");
-
-    // Get and return account info
-    state.gdrive_client.get_account_info().await
+    Ok(info)
 }
 
-/// Disconnect from Google Drive
+/// Disconnect one Google account, or all of them when no account is given
 #[tauri::command]
-async fn gdrive_disconnect(state: State<'_, AppState>) -> Result<ApiResponse, String> {
-    state.gdrive_client.clear_tokens()?;
-    Ok(ApiResponse {
-        message: "Disconnected from Google Drive".to_string(),
-    })
+async fn gdrive_disconnect(
+    state: State<'_, AppState>,
+    account_id: Option<String>,
+) -> Result<ApiResponse, String> {
+    match account_id {
+        Some(id) => {
+            state.gdrive_client.remove_account(&id)?;
+            Ok(ApiResponse {
+                message: format!("Disconnected {}", id),
+            })
+        }
+        None => {
+            state.gdrive_client.clear_tokens()?;
+            Ok(ApiResponse {
+                message: "Disconnected from Google Drive".to_string(),
+            })
+        }
+    }
 }
 
 /// Complete OAuth with manually entered authorization code
@@ -301,8 +383,9 @@ async fn gdrive_auth_with_code(
 async fn gdrive_list_folders(
     state: State<'_, AppState>,
     parent_id: Option<String>,
+    account_id: Option<String>,
 ) -> Result<Vec<gdrive::DriveItem>, String> {
-    state.gdrive_client.list_folders(parent_id.as_deref()).await
+    state.gdrive_client.list_folders(account_id.as_deref(), parent_id.as_deref()).await
 }
 
 /// List all files in a folder
@@ -310,8 +393,9 @@ async fn gdrive_list_folders(
 async fn gdrive_list_files(
     state: State<'_, AppState>,
     folder_id: Option<String>,
+    account_id: Option<String>,
 ) -> Result<gdrive::DriveListResponse, String> {
-    state.gdrive_client.list_files(folder_id.as_deref(), None).await
+    state.gdrive_client.list_files(account_id.as_deref(), folder_id.as_deref(), None).await
 }
 
 /// List video files in a folder (with optional recursive scan)
@@ -320,8 +404,9 @@ async fn gdrive_list_video_files(
     state: State<'_, AppState>,
     folder_id: String,
     recursive: bool,
+    account_id: Option<String>,
 ) -> Result<Vec<gdrive::DriveItem>, String> {
-    state.gdrive_client.list_video_files(&folder_id, recursive).await
+    state.gdrive_client.list_video_files(account_id.as_deref(), &folder_id, recursive).await
 }
 
 /// Get streaming URL for a Google Drive file
@@ -330,7 +415,8 @@ async fn gdrive_get_stream_url(
     state: State<'_, AppState>,
     file_id: String,
 ) -> Result<(String, String), String> {
-    state.gdrive_client.get_stream_url(&file_id).await
+    let account_id = cloud_account_for_file(&state, &file_id);
+    state.gdrive_client.get_stream_url(account_id.as_deref(), &file_id).await
 }
 
 /// Get file metadata from Google Drive
@@ -339,7 +425,57 @@ async fn gdrive_get_file_metadata(
     state: State<'_, AppState>,
     file_id: String,
 ) -> Result<gdrive::DriveItem, String> {
-    state.gdrive_client.get_file_metadata(&file_id).await
+    let account_id = cloud_account_for_file(&state, &file_id);
+    state.gdrive_client.get_file_metadata(account_id.as_deref(), &file_id).await
+}
+
+/// Look up which connected account owns an indexed cloud file (None = primary account)
+fn cloud_account_for_file(state: &AppState, cloud_file_id: &str) -> Option<String> {
+    state.db.lock().ok()?.get_cloud_account_for_file(cloud_file_id).ok().flatten()
+}
+
+/// Resolve media ids to Drive download URLs (stream proxy and offline downloads)
+fn drive_source_resolver(app_handle: AppHandle) -> stream_proxy::SourceResolver {
+    Arc::new(move |media_id, force_refresh| {
+        let state: tauri::State<'_, AppState> = app_handle.state();
+        let cloud_file_id = {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            db.get_media_by_id(media_id).map_err(|e| e.to_string())?.cloud_file_id
+        }
+        .ok_or_else(|| format!("Media {} is not a cloud file", media_id))?;
+
+        let account_id = cloud_account_for_file(&state, &cloud_file_id);
+        if force_refresh {
+            state.gdrive_client.expire_access_token(account_id.as_deref());
+        }
+        let (url, access_token) = tauri::async_runtime::block_on(
+            state.gdrive_client.get_stream_url(account_id.as_deref(), &cloud_file_id),
+        )?;
+        Ok(stream_proxy::UpstreamSource { url, access_token })
+    })
+}
+
+/// Loads external subtitle files for the proxy - from disk, or from Drive for cloud media
+fn subtitle_file_resolver(app_handle: AppHandle) -> stream_proxy::SubtitleResolver {
+    Arc::new(move |subtitle_id| {
+        let state: tauri::State<'_, AppState> = app_handle.state();
+        let (subtitle, cloud_file_id) = {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            let subtitle = db.get_subtitle(subtitle_id).map_err(|e| e.to_string())?;
+            let media = db.get_media_by_id(subtitle.media_id).map_err(|e| e.to_string())?;
+            (subtitle, media.cloud_file_id)
+        };
+
+        let bytes = if subtitle.is_cloud {
+            let account_id = cloud_file_id.and_then(|id| cloud_account_for_file(&state, &id));
+            tauri::async_runtime::block_on(
+                state.gdrive_client.download_file(account_id.as_deref(), &subtitle.location),
+            )?
+        } else {
+            std::fs::read(&subtitle.location).map_err(|e| format!("Failed to read {}: {}", subtitle.location, e))?
+        };
+        Ok((bytes, subtitle.format))
+    })
 }
 
 /// Cloud folder info for indexing
@@ -359,6 +495,7 @@ struct CloudIndexResult {
     skipped_count: usize,
     movies_count: usize,
     tv_count: usize,
+    removed_count: usize,
     message: String,
 }
 
@@ -370,11 +507,15 @@ async fn gdrive_scan_folder(
     window: Window,
     folder_id: String,
     folder_name: String,
+    account_id: Option<String>,
 ) -> Result<CloudIndexResult, String> {
     println!("[CLOUD] Starting scan of folder: {} (auto-detect)", folder_name);
 
+    // Index under the concrete account so the media keeps working if the primary account changes
+    let account_id = Some(state.gdrive_client.resolve_account(account_id.as_deref())?);
+
     // Get video files from the folder
-    let files = state.gdrive_client.list_video_files(&folder_id, true).await?;
+    let files = state.gdrive_client.list_video_files(account_id.as_deref(), &folder_id, true).await?;
     println!("[CLOUD] Found {} video files", files.len());
 
     // Get API key from config
@@ -391,6 +532,7 @@ async fn gdrive_scan_folder(
 
     // Clone data for the blocking task
     let folder_id_clone = folder_id.clone();
+    let account_id_clone = account_id.clone();
 
     // Get database path for creating new connection in blocking task
     let db_path = database::get_database_path();
@@ -543,6 +685,7 @@ async fn gdrive_scan_folder(
                     ep_title.as_deref(),
                     ep_overview.as_deref(),
                     ep_still.as_deref(),
+                    account_id_clone.as_deref(),
                 ) {
                     println!("[CLOUD] Failed to insert episode: {}", e);
                     continue;
@@ -584,6 +727,7 @@ async fn gdrive_scan_folder(
                     &file.id,
                     &folder_id_clone,
                     tmdb_id.as_deref(),
+                    account_id_clone.as_deref(),
                 ) {
                     println!("[CLOUD] Failed to insert movie: {}", e);
                     continue;
@@ -600,6 +744,10 @@ async fn gdrive_scan_folder(
 
     let (indexed_count, skipped_count, movies_count, tv_count) = result;
 
+    if let Err(e) = index_cloud_subtitles(&state, account_id.as_deref(), &folder_id, "[CLOUD]").await {
+        println!("[CLOUD] Subtitle discovery failed for {}: {}", folder_name, e);
+    }
+
     // Emit completion
     window.emit("cloud-scan-complete", serde_json::json!({
         "folder": folder_name,
@@ -623,10 +771,21 @@ async fn gdrive_scan_folder(
         skipped_count,
         movies_count,
         tv_count,
+        removed_count: 0,
         message,
     })
 }
 
+/// Drop a cloud folder's library entries along with their seek thumbnails
+fn delete_cloud_folder_media(db: &database::Database, folder_id: &str) -> Result<usize, String> {
+    let media = db.get_cloud_media_by_folder(folder_id).map_err(|e| e.to_string())?;
+    let deleted = db.delete_cloud_folder_media(folder_id).map_err(|e| e.to_string())?;
+    for item in &media {
+        trickplay::remove(item.id);
+    }
+    Ok(deleted)
+}
+
 /// Delete all indexed media from a cloud folder
 #[tauri::command]
 async fn gdrive_delete_folder_media(
@@ -635,7 +794,7 @@ async fn gdrive_delete_folder_media(
     folder_id: String,
 ) -> Result<ApiResponse, String> {
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    let deleted = db.delete_cloud_folder_media(&folder_id).map_err(|e| e.to_string())?;
+    let deleted = delete_cloud_folder_media(&db, &folder_id)?;
 
     window.emit("library-updated", ()).ok();
 
@@ -652,9 +811,14 @@ async fn add_cloud_folder(
     state: State<'_, AppState>,
     folder_id: String,
     folder_name: String,
+    account_id: Option<String>,
+    drive_id: Option<String>,
 ) -> Result<ApiResponse, String> {
+    // Store the concrete account so the folder keeps working if the primary account changes
+    let account_id = state.gdrive_client.resolve_account(account_id.as_deref()).ok();
     let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.add_cloud_folder(&folder_id, &folder_name).map_err(|e| e.to_string())?;
+    db.add_cloud_folder(&folder_id, &folder_name, account_id.as_deref(), drive_id.as_deref())
+        .map_err(|e| e.to_string())?;
     Ok(ApiResponse {
         message: format!("Added cloud folder: {}", folder_name),
     })
@@ -670,7 +834,7 @@ async fn remove_cloud_folder(
     let db = state.db.lock().map_err(|e| e.to_string())?;
 
     // Delete media from this folder
-    let deleted_media = db.delete_cloud_folder_media(&folder_id).map_err(|e| e.to_string())?;
+    let deleted_media = delete_cloud_folder_media(&db, &folder_id)?;
 
     // Remove folder from tracking
     db.remove_cloud_folder(&folder_id).map_err(|e| e.to_string())?;
@@ -690,11 +854,13 @@ async fn get_cloud_folders(
     let db = state.db.lock().map_err(|e| e.to_string())?;
     let folders = db.get_cloud_folders().map_err(|e| e.to_string())?;
 
-    Ok(folders.into_iter().map(|(id, name, auto_scan)| {
+    Ok(folders.into_iter().map(|folder| {
         serde_json::json!({
-            "id": id,
-            "name": name,
-            "auto_scan": auto_scan
+            "id": folder.folder_id,
+            "name": folder.folder_name,
+            "auto_scan": folder.auto_scan,
+            "account_id": folder.account_id,
+            "drive_id": folder.drive_id
         })
     }).collect())
 }
@@ -718,6 +884,7 @@ async fn scan_all_cloud_folders(
             skipped_count: 0,
             movies_count: 0,
             tv_count: 0,
+            removed_count: 0,
             message: "No cloud folders configured".to_string(),
         });
     }
@@ -727,11 +894,12 @@ async fn scan_all_cloud_folders(
     let mut total_movies = 0;
     let mut total_tv = 0;
 
-    for (folder_id, folder_name, _) in folders {
+    for folder in folders {
+        let (folder_id, folder_name, account_id) = (folder.folder_id, folder.folder_name, folder.account_id);
         println!("[CLOUD SCAN] Scanning folder: {} ({})", folder_name, folder_id);
 
         // Get video files from the folder
-        let files = match state.gdrive_client.list_video_files(&folder_id, true).await {
+        let files = match state.gdrive_client.list_video_files(account_id.as_deref(), &folder_id, true).await {
             Ok(f) => f,
             Err(e) => {
                 println!("[CLOUD SCAN] Error listing files for {}: {}", folder_name, e);
@@ -753,6 +921,7 @@ async fn scan_all_cloud_folders(
 
         // Clone data for the blocking task
         let folder_id_clone = folder_id.clone();
+        let account_id_clone = account_id.clone();
         let db_path = database::get_database_path();
 
         // Run the blocking indexing work in a separate thread
@@ -840,7 +1009,8 @@ async fn scan_all_cloud_folders(
                         };
 
                     if db.insert_cloud_episode(&show_title, &file.name, db_show_id, season, episode,
-                        &file.id, &folder_id_clone, ep_title.as_deref(), ep_overview.as_deref(), ep_still.as_deref()).is_err() {
+                        &file.id, &folder_id_clone, ep_title.as_deref(), ep_overview.as_deref(), ep_still.as_deref(),
+                        account_id_clone.as_deref()).is_err() {
                         continue;
                     }
 
@@ -855,7 +1025,7 @@ async fn scan_all_cloud_folders(
                     };
 
                     if db.insert_cloud_movie(&title, year, overview.as_deref(), poster_path.as_deref(),
-                        &file.name, &file.id, &folder_id_clone, tmdb_id.as_deref()).is_err() {
+                        &file.name, &file.id, &folder_id_clone, tmdb_id.as_deref(), account_id_clone.as_deref()).is_err() {
                         continue;
                     }
 
@@ -873,6 +1043,10 @@ async fn scan_all_cloud_folders(
         total_movies += movies;
         total_tv += tv;
 
+        if let Err(e) = index_cloud_subtitles(&state, account_id.as_deref(), &folder_id, "[CLOUD SCAN]").await {
+            println!("[CLOUD SCAN] Subtitle discovery failed for {}: {}", folder_name, e);
+        }
+
         // Update last scanned timestamp
         if let Ok(db) = state.db.lock() {
             let _ = db.update_cloud_folder_scanned(&folder_id);
@@ -901,10 +1075,186 @@ async fn scan_all_cloud_folders(
         skipped_count: total_skipped,
         movies_count: total_movies,
         tv_count: total_tv,
+        removed_count: 0,
         message,
     })
 }
 
+/// Link subtitle files below a Drive folder to the indexed videos they belong to.
+/// Videos found in the walk get their Drive subtitles replaced, so deleted ones drop out.
+async fn index_cloud_subtitles(state: &AppState, account_id: Option<&str>, folder_id: &str, log_tag: &str) -> Result<usize, String> {
+    let tree = state.gdrive_client.list_tree(account_id, folder_id).await?;
+    let files: Vec<subtitles::FoundFile<(String, String)>> = tree
+        .into_iter()
+        .filter(|(_, item)| item.is_video() || subtitles::is_subtitle_file(&item.name))
+        .map(|(dirs, item)| subtitles::FoundFile { dirs, name: item.name.clone(), key: (item.id, item.name) })
+        .collect();
+
+    let mut by_video: HashMap<String, Vec<(String, String, subtitles::SubtitleInfo)>> = HashMap::new();
+    for ((video_id, _), (sub_id, sub_name), info) in subtitles::match_subtitles(&files) {
+        by_video.entry(video_id).or_default().push((sub_name, sub_id, info));
+    }
+
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let mut linked = 0;
+    for file in files.iter().filter(|f| !subtitles::is_subtitle_file(&f.name)) {
+        let media_id = match db.get_media_id_by_cloud_file(&file.key.0).map_err(|e| e.to_string())? {
+            Some(id) => id,
+            None => continue,
+        };
+        let found = by_video.remove(&file.key.0).unwrap_or_default();
+        linked += found.len();
+        db.replace_subtitles(media_id, subtitles::SOURCE_DRIVE, true, &found).map_err(|e| e.to_string())?;
+    }
+
+    if linked > 0 {
+        println!("{} Linked {} subtitle file(s)", log_tag, linked);
+    }
+    Ok(linked)
+}
+
+/// Re-match subtitles in the folders a change feed touched
+async fn index_changed_subtitles(state: &AppState, folders: &[(String, String)], log_tag: &str) {
+    for (folder_id, account_id) in folders {
+        let scan_folder = subtitle_scan_folder(state, account_id, folder_id).await;
+        if let Err(e) = index_cloud_subtitles(state, Some(account_id), &scan_folder, log_tag).await {
+            println!("{} Subtitle discovery failed for folder {}: {}", log_tag, folder_id, e);
+        }
+    }
+}
+
+/// Where to look for subtitles after a change: the folder itself, or the release folder
+/// above it when the change happened inside Subs/ or Subs/<episode>/
+async fn subtitle_scan_folder(state: &AppState, account_id: &str, folder_id: &str) -> String {
+    let mut current = folder_id.to_string();
+    for _ in 0..2 {
+        let folder = match state.gdrive_client.get_file_metadata(Some(account_id), &current).await {
+            Ok(folder) => folder,
+            Err(_) => break,
+        };
+        let parent = folder.parents.and_then(|p| p.into_iter().next());
+        if subtitles::is_subs_folder(&folder.name) {
+            return parent.unwrap_or(current);
+        }
+        match parent {
+            Some(parent) => current = parent,
+            None => break,
+        }
+    }
+    folder_id.to_string()
+}
+
+/// Poll every Drive change feed - each connected account's My Drive plus each Shared Drive
+/// that has a tracked folder. Feeds without a token are initialized and picked up next poll.
+/// Changes are resolved against the tracked folders: videos anywhere below one come back as
+/// additions, while deletes, trashing and moves out of tracked folders come back as removals.
+async fn poll_change_feeds(state: &AppState, log_tag: &str) -> Result<drive_resolver::FeedChanges, String> {
+    let mut feeds: Vec<(String, Option<String>)> = state
+        .gdrive_client
+        .list_accounts()
+        .into_iter()
+        .map(|a| (a.account_id, None))
+        .collect();
+
+    let folders = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.get_cloud_folders().map_err(|e| e.to_string())?
+    };
+    let tracked: std::collections::HashSet<String> = folders.iter().map(|f| f.folder_id.clone()).collect();
+    if tracked.is_empty() {
+        return Ok(drive_resolver::FeedChanges::default());
+    }
+    for folder in folders {
+        if let (Some(drive_id), Ok(account_id)) =
+            (folder.drive_id, state.gdrive_client.resolve_account(folder.account_id.as_deref()))
+        {
+            if !feeds.iter().any(|(a, d)| a == &account_id && d.as_deref() == Some(drive_id.as_str())) {
+                feeds.push((account_id, Some(drive_id)));
+            }
+        }
+    }
+
+    // Held for the whole poll so a manual check and the background poll never process a feed twice
+    let mut ancestry = state.drive_ancestry.lock().await;
+    let mut changes = drive_resolver::FeedChanges::default();
+
+    for (account_id, drive_id) in feeds {
+        let feed_name = format!("{}/{}", account_id, drive_id.as_deref().unwrap_or("My Drive"));
+        let current_token = {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            db.get_gdrive_changes_token(&account_id, drive_id.as_deref()).map_err(|e| e.to_string())?
+        };
+
+        let page_token = match current_token {
+            Some(token) => token,
+            None => {
+                // First time for this feed - get the start token, changes show up on the next poll
+                match state.gdrive_client.get_changes_start_token(Some(&account_id), drive_id.as_deref()).await {
+                    Ok(start_token) => {
+                        let db = state.db.lock().map_err(|e| e.to_string())?;
+                        db.set_gdrive_changes_token(&account_id, drive_id.as_deref(), &start_token)
+                            .map_err(|e| e.to_string())?;
+                        println!("{} Initialized changes tracking for {}", log_tag, feed_name);
+                    }
+                    Err(e) => println!("{} Failed to initialize {}: {}", log_tag, feed_name, e),
+                }
+                continue;
+            }
+        };
+
+        match state.gdrive_client.get_all_changes(Some(&account_id), drive_id.as_deref(), &page_token).await {
+            Ok((feed_changes, new_token)) => {
+                let resolved = ancestry.classify(&state.gdrive_client, &account_id, feed_changes, &tracked).await;
+                let db = state.db.lock().map_err(|e| e.to_string())?;
+                db.set_gdrive_changes_token(&account_id, drive_id.as_deref(), &new_token)
+                    .map_err(|e| e.to_string())?;
+                changes.merge(resolved);
+            }
+            Err(e) => println!("{} Failed to get changes for {}: {}", log_tag, feed_name, e),
+        }
+    }
+
+    // A folder moved into a tracked folder brings its existing videos along
+    for (folder_id, account_id) in std::mem::take(&mut changes.folders_moved_in) {
+        match state.gdrive_client.list_video_files(Some(&account_id), &folder_id, true).await {
+            Ok(files) => changes.added.extend(files.into_iter().map(|f| (f, account_id.clone()))),
+            Err(e) => println!("{} Failed to list moved folder {}: {}", log_tag, folder_id, e),
+        }
+    }
+
+    // Folders moved, trashed or deleted - re-check everything already indexed
+    if changes.folders_changed {
+        let indexed = {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            db.get_cloud_file_locations().map_err(|e| e.to_string())?
+        };
+        let untracked = ancestry.untracked_files(&state.gdrive_client, &indexed, &tracked).await;
+        changes.removed.extend(untracked);
+    }
+
+    changes.dedup();
+    Ok(changes)
+}
+
+/// Drop library entries whose Drive files were deleted or left the tracked folders
+fn remove_untracked_cloud_files(state: &AppState, file_ids: &[String], log_tag: &str) -> Result<usize, String> {
+    if file_ids.is_empty() {
+        return Ok(0);
+    }
+
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let removed = db.remove_cloud_files(file_ids).map_err(|e| e.to_string())?;
+    for (media_id, title) in &removed {
+        println!("{}   ✗ Removed: {}", log_tag, title);
+        trickplay::remove(*media_id);
+    }
+    if !removed.is_empty() {
+        db.cleanup_empty_series().map_err(|e| e.to_string())?;
+    }
+
+    Ok(removed.len())
+}
+
 /// Check for new cloud files using the efficient Changes API
 /// This is MUCH lighter than scanning all folders - only returns changed files
 #[tauri::command]
@@ -925,49 +1275,16 @@ async fn check_cloud_changes(
             skipped_count: 0,
             movies_count: 0,
             tv_count: 0,
+            removed_count: 0,
             message: "Not connected to Google Drive".to_string(),
         });
     }
 
-    // Get or initialize the changes token
-    let current_token = {
-        let db = state.db.lock().map_err(|e| e.to_string())?;
-        db.get_gdrive_changes_token().map_err(|e| e.to_string())?
-    };
-
-    let page_token = match current_token {
-        Some(token) => {
-            println!("[CLOUD CHANGES] Using existing token: {}...", &token[..token.len().min(20)]);
-            token
-        },
-        None => {
-            // First time - get the start token
-            println!("[CLOUD CHANGES] No token found - initializing changes tracking...");
-            let start_token = state.gdrive_client.get_changes_start_token().await?;
-            println!("[CLOUD CHANGES] Got start token: {}...", &start_token[..start_token.len().min(20)]);
-
-            // Save it
-            let db = state.db.lock().map_err(|e| e.to_string())?;
-            db.set_gdrive_changes_token(&start_token).map_err(|e| e.to_string())?;
-            println!("[CLOUD CHANGES] Token saved - will detect changes on next poll");
-
-            // Return empty result - we'll catch changes on next poll
-            return Ok(CloudIndexResult {
-                success: true,
-                indexed_count: 0,
-                skipped_count: 0,
-                movies_count: 0,
-                tv_count: 0,
-                message: "Changes tracking initialized".to_string(),
-            });
-        }
-    };
-
     // Get tracked folder IDs
     let tracked_folders: std::collections::HashSet<String> = {
         let db = state.db.lock().map_err(|e| e.to_string())?;
         let folders = db.get_cloud_folders().map_err(|e| e.to_string())?;
-        folders.into_iter().map(|(id, _, _)| id).collect()
+        folders.into_iter().map(|folder| folder.folder_id).collect()
     };
 
     if tracked_folders.is_empty() {
@@ -978,27 +1295,23 @@ async fn check_cloud_changes(
             skipped_count: 0,
             movies_count: 0,
             tv_count: 0,
+            removed_count: 0,
             message: "No cloud folders configured".to_string(),
         });
     }
 
     println!("[CLOUD CHANGES] Tracking {} folder(s)", tracked_folders.len());
 
-    // Get changes since last check
+    // Get changes since last check (every account's My Drive plus tracked Shared Drives)
     let api_start = std::time::Instant::now();
-    let (changed_files, new_token) = state.gdrive_client.get_video_changes(&page_token).await?;
+    let mut changes = poll_change_feeds(&state, "[CLOUD CHANGES]").await?;
+    let subtitle_folders = std::mem::take(&mut changes.subtitle_folders);
     let api_duration = api_start.elapsed();
     println!("[CLOUD CHANGES] Changes API call took {:?}", api_duration);
 
-    // Save the new token immediately
-    {
-        let db = state.db.lock().map_err(|e| e.to_string())?;
-        db.set_gdrive_changes_token(&new_token).map_err(|e| e.to_string())?;
-    }
-
-    if changed_files.is_empty() {
+    if changes.is_empty() {
         let total_duration = start_time.elapsed();
-        println!("[CLOUD CHANGES] No changes detected (total: {:?})", total_duration);
+        println!("[CLOUD CHANGES] No changes in tracked folders (total: {:?})", total_duration);
         println!("[CLOUD CHANGES] ══════════════════════════════════════════");
         return Ok(CloudIndexResult {
             success: true,
@@ -1006,37 +1319,23 @@ async fn check_cloud_changes(
             skipped_count: 0,
             movies_count: 0,
             tv_count: 0,
+            removed_count: 0,
             message: "No new files detected".to_string(),
         });
     }
 
-    println!("[CLOUD CHANGES] ┌─────────────────────────────────────────");
-    println!("[CLOUD CHANGES] │ DETECTED {} changed video file(s)!", changed_files.len());
-    for file in &changed_files {
-        println!("[CLOUD CHANGES] │   • {}", file.name);
+    // Deleted, trashed or moved-out files leave the library first
+    let removed_count = remove_untracked_cloud_files(&state, &changes.removed, "[CLOUD CHANGES]")?;
+    if removed_count > 0 {
+        println!("[CLOUD CHANGES] Removed {} file(s) no longer in tracked folders", removed_count);
+        window.emit("library-updated", ()).ok();
     }
-    println!("[CLOUD CHANGES] └─────────────────────────────────────────");
-
-    // Filter to only files in our tracked folders
-    let files_to_index: Vec<gdrive::DriveItem> = changed_files
-        .into_iter()
-        .filter(|file| {
-            if let Some(ref parents) = file.parents {
-                let in_tracked = parents.iter().any(|p| tracked_folders.contains(p));
-                if !in_tracked {
-                    println!("[CLOUD CHANGES] Skipping {} (not in tracked folders)", file.name);
-                }
-                in_tracked
-            } else {
-                println!("[CLOUD CHANGES] Skipping {} (no parent folder)", file.name);
-                false
-            }
-        })
-        .collect();
 
+    let files_to_index = changes.added;
     if files_to_index.is_empty() {
+        index_changed_subtitles(&state, &subtitle_folders, "[CLOUD CHANGES]").await;
         let total_duration = start_time.elapsed();
-        println!("[CLOUD CHANGES] No files in tracked folders (total: {:?})", total_duration);
+        println!("[CLOUD CHANGES] No new files in tracked folders (total: {:?})", total_duration);
         println!("[CLOUD CHANGES] ══════════════════════════════════════════");
         return Ok(CloudIndexResult {
             success: true,
@@ -1044,10 +1343,18 @@ async fn check_cloud_changes(
             skipped_count: 0,
             movies_count: 0,
             tv_count: 0,
-            message: "No new files in tracked folders".to_string(),
+            removed_count,
+            message: format!("Removed {} files", removed_count),
         });
     }
 
+    println!("[CLOUD CHANGES] ┌─────────────────────────────────────────");
+    println!("[CLOUD CHANGES] │ DETECTED {} new video file(s) in tracked folders!", files_to_index.len());
+    for (file, _) in &files_to_index {
+        println!("[CLOUD CHANGES] │   • {}", file.name);
+    }
+    println!("[CLOUD CHANGES] └─────────────────────────────────────────");
+
     println!("[CLOUD CHANGES] {} file(s) to index in tracked folders", files_to_index.len());
 
     // Emit event to show indexing has started
@@ -1069,8 +1376,8 @@ async fn check_cloud_changes(
     // PHASE 1: Add files immediately without metadata
     let phase1_result = {
         let db_path_clone = db_path.clone();
-        let files_to_index_clone: Vec<_> = files_to_index.iter().map(|f| {
-            (f.id.clone(), f.name.clone(), f.parents.clone())
+        let files_to_index_clone: Vec<_> = files_to_index.iter().map(|(f, account_id)| {
+            (f.id.clone(), f.name.clone(), f.parents.clone(), account_id.clone())
         }).collect();
 
         tokio::task::spawn_blocking(move || {
@@ -1087,7 +1394,7 @@ async fn check_cloud_changes(
             // Cache for TV show IDs to avoid creating duplicates
             let mut tv_show_cache: std::collections::HashMap<String, i64> = std::collections::HashMap::new();
 
-            for (file_id, file_name, parents) in files_to_index_clone {
+            for (file_id, file_name, parents, account_id) in files_to_index_clone {
                 // Check if already indexed (by cloud_file_id OR by file_path)
                 if db.cloud_file_exists(&file_id) {
                     println!("[CLOUD CHANGES]   ⊘ Skipping (already indexed by file_id): {}", file_name);
@@ -1157,7 +1464,7 @@ async fn check_cloud_changes(
 
                     // Insert episode without metadata
                     match db.insert_cloud_episode(&show_title, &file_name, db_show_id, season, episode,
-                        &file_id, &folder_id, None, None, None) {
+                        &file_id, &folder_id, None, None, None, Some(&account_id)) {
                         Ok(ep_id) => {
                             let display_title = format!("{} S{:02}E{:02}", show_title, season, episode);
                             println!("[CLOUD CHANGES]   ✓ Added (no metadata): {}", display_title);
@@ -1172,7 +1479,7 @@ async fn check_cloud_changes(
                 } else {
                     // Insert movie without metadata
                     match db.insert_cloud_movie(&parsed.title, parsed.year, None, None,
-                        &file_name, &file_id, &folder_id, None) {
+                        &file_name, &file_id, &folder_id, None, Some(&account_id)) {
                         Ok(movie_id) => {
                             println!("[CLOUD CHANGES]   ✓ Added (no metadata): {}", parsed.title);
                             indexed_items.push((movie_id, parsed.title, file_id, false, None, None, folder_id));
@@ -1379,6 +1686,8 @@ async fn check_cloud_changes(
         }
     }
 
+    index_changed_subtitles(&state, &subtitle_folders, "[CLOUD CHANGES]").await;
+
     let total_duration = start_time.elapsed();
     let message = if indexed_count > 0 {
         format!("Indexed {} new files ({} movies, {} TV)", indexed_count, movies_count, tv_count)
@@ -1387,7 +1696,7 @@ async fn check_cloud_changes(
     };
 
     println!("[CLOUD CHANGES] ══════════════════════════════════════════");
-    println!("[CLOUD CHANGES] SUMMARY: {} indexed, {} skipped", indexed_count, skipped_count);
+    println!("[CLOUD CHANGES] SUMMARY: {} indexed, {} skipped, {} removed", indexed_count, skipped_count, removed_count);
     println!("[CLOUD CHANGES] Total time: {:?}", total_duration);
     println!("[CLOUD CHANGES] ══════════════════════════════════════════");
 
@@ -1397,6 +1706,7 @@ async fn check_cloud_changes(
         skipped_count,
         movies_count,
         tv_count,
+        removed_count,
         message,
     })
 }
@@ -1427,6 +1737,9 @@ async fn clear_all_app_data(
         std::fs::create_dir_all(cache_path).ok();
     }
 
+    // Seek preview thumbnails belong to the deleted media
+    trickplay::remove_all();
+
     println!("[RESET] App data reset complete!");
 
     Ok(ApiResponse {
@@ -1434,6 +1747,161 @@ async fn clear_all_app_data(
     })
 }
 
+// Create a backup archive of the library (database, config, changes token, optional images)
+#[tauri::command]
+async fn create_backup(
+    state: State<'_, AppState>,
+    dest_path: String,
+    include_images: bool,
+) -> Result<backup::BackupManifest, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    backup::create_backup(&db, std::path::Path::new(&dest_path), include_images)
+}
+
+// Restore a backup archive, replacing the current library and config
+#[tauri::command]
+async fn restore_backup(
+    state: State<'_, AppState>,
+    window: Window,
+    archive_path: String,
+) -> Result<backup::BackupManifest, String> {
+    let manifest = {
+        let mut db = state.db.lock().map_err(|e| e.to_string())?;
+        backup::restore_backup(&mut db, std::path::Path::new(&archive_path))?
+    };
+
+    // Pick up the restored config
+    if let Ok(restored) = config::load_config() {
+        let mut config = state.config.lock().map_err(|e| e.to_string())?;
+        *config = restored;
+    }
+
+    let _ = window.emit("library-updated", ());
+    Ok(manifest)
+}
+
+// Export library, watch state and streaming history as portable JSON
+#[tauri::command]
+async fn export_library(
+    state: State<'_, AppState>,
+    dest_path: String,
+) -> Result<ApiResponse, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let export = backup::export_library(&db, std::path::Path::new(&dest_path))?;
+    Ok(ApiResponse {
+        message: format!(
+            "Exported {} items and {} streaming history entries.",
+            export.media.len(),
+            export.streaming_history.len()
+        ),
+    })
+}
+
+// Import a portable JSON export, rewriting moved path prefixes
+#[tauri::command]
+async fn import_library(
+    state: State<'_, AppState>,
+    window: Window,
+    source_path: String,
+    path_rewrites: Option<Vec<backup::PathRewrite>>,
+) -> Result<backup::ImportSummary, String> {
+    let summary = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        backup::import_library(
+            &db,
+            std::path::Path::new(&source_path),
+            &path_rewrites.unwrap_or_default(),
+        )?
+    };
+
+    // Imported local videos may have moved; link the subtitles at their new paths
+    std::thread::spawn(|| {
+        let db = match database::Database::new(&database::get_database_path()) {
+            Ok(db) => db,
+            Err(e) => return println!("[IMPORT] Failed to open database for subtitle linking: {}", e),
+        };
+        let local_videos = db
+            .get_all_media()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|m| !m.is_cloud.unwrap_or(false) && m.media_type != "tvshow");
+        let mut linked = 0;
+        for media in local_videos {
+            if let Some(ref file_path) = media.file_path {
+                linked += media_manager::link_local_subtitles(&db, media.id, file_path);
+            }
+        }
+        println!("[IMPORT] Linked {} local subtitle file(s)", linked);
+    });
+
+    let _ = window.emit("library-updated", ());
+    Ok(summary)
+}
+
+// Get which backend holds secrets and whether it is locked
+#[tauri::command]
+async fn get_secrets_status() -> Result<secrets::SecretsStatus, String> {
+    secrets::status()
+}
+
+// Re-read secrets into memory after the store becomes readable
+fn reload_secrets(state: &AppState) -> Result<(), String> {
+    state.gdrive_client.reload_tokens();
+    let tmdb_api_key = secrets::get_secret(config::TMDB_API_KEY_SECRET)?;
+    let gdrive_client_secret = secrets::get_secret(config::GDRIVE_CLIENT_SECRET)?;
+    let opensubtitles_api_key = secrets::get_secret(config::OPENSUBTITLES_API_KEY_SECRET)?;
+    let jellyfin_password = secrets::get_secret(config::JELLYFIN_PASSWORD_SECRET)?;
+    let mut config = state.config.lock().map_err(|e| e.to_string())?;
+    config.tmdb_api_key = tmdb_api_key;
+    config.gdrive_client_secret = gdrive_client_secret;
+    config.opensubtitles_api_key = opensubtitles_api_key;
+    // The Jellyfin API can't start without its password, which may only now be readable
+    let jellyfin_changed = config.jellyfin_password != jellyfin_password;
+    config.jellyfin_password = jellyfin_password;
+    if jellyfin_changed {
+        jellyfin::apply(jellyfin_settings(&config))?;
+    }
+    Ok(())
+}
+
+// Unlock a passphrase-protected secrets store
+#[tauri::command]
+async fn unlock_secrets(
+    state: State<'_, AppState>,
+    passphrase: String,
+) -> Result<secrets::SecretsStatus, String> {
+    let status = secrets::unlock(&passphrase)?;
+    reload_secrets(&state)?;
+    Ok(status)
+}
+
+// Re-encrypt all secrets under a new key (optionally switching to / from a passphrase)
+#[tauri::command]
+async fn rotate_secrets(
+    state: State<'_, AppState>,
+    new_passphrase: Option<String>,
+) -> Result<secrets::SecretsStatus, String> {
+    let status = secrets::rotate(new_passphrase.as_deref())?;
+    reload_secrets(&state)?;
+    Ok(status)
+}
+
+// Delete every stored token and API key
+#[tauri::command]
+async fn wipe_secrets(state: State<'_, AppState>) -> Result<ApiResponse, String> {
+    secrets::wipe()?;
+    state.gdrive_client.clear_tokens()?;
+    {
+        let mut config = state.config.lock().map_err(|e| e.to_string())?;
+        config.tmdb_api_key = None;
+        config.gdrive_client_secret = None;
+    }
+
+    Ok(ApiResponse {
+        message: "All stored tokens and API keys have been removed.".to_string(),
+    })
+}
+
 // Response for cleanup operation
 #[derive(serde::Serialize)]
 struct CleanupResponse {
@@ -1601,7 +2069,7 @@ async fn delete_media_files(
     if !cloud_file_ids_to_delete.is_empty() {
         println!("[DELETE] Deleting {} cloud files from Google Drive", cloud_file_ids_to_delete.len());
         for cloud_file_id in cloud_file_ids_to_delete {
-            match state.gdrive_client.delete_file(&cloud_file_id).await {
+            match state.gdrive_client.delete_file(cloud_account_for_file(&state, &cloud_file_id).as_deref(), &cloud_file_id).await {
                 Ok(_) => {
                     println!("[DELETE] Successfully deleted cloud file: {}", cloud_file_id);
                     deleted_count += 1;
@@ -1619,6 +2087,10 @@ async fn delete_media_files(
         let db = state.db.lock().map_err(|e| e.to_string())?;
         db.delete_media_entries(&media_ids).map_err(|e| e.to_string())?;
     }
+    // Generated seek thumbnails go with the entries
+    for &media_id in &media_ids {
+        trickplay::remove(media_id);
+    }
 
     // Clean up empty parent directories (only for local files)
     cleanup_empty_parent_dirs(&deleted_file_paths);
@@ -1726,7 +2198,7 @@ async fn delete_series(
             if !cloud_file_ids.is_empty() {
                 println!("[DELETE] Deleting {} cloud episode files from Google Drive", cloud_file_ids.len());
                 for cloud_file_id in cloud_file_ids {
-                    match state.gdrive_client.delete_file(&cloud_file_id).await {
+                    match state.gdrive_client.delete_file(cloud_account_for_file(&state, &cloud_file_id).as_deref(), &cloud_file_id).await {
                         Ok(_) => {
                             println!("[DELETE] Deleted cloud episode: {}", cloud_file_id);
                             total_deleted += 1;
@@ -1768,6 +2240,9 @@ async fn delete_series(
             let db = state.db.lock().map_err(|e| e.to_string())?;
             db.delete_media_entries(&episode_ids).map_err(|e| e.to_string())?;
         }
+        for &episode_id in &episode_ids {
+            trickplay::remove(episode_id);
+        }
     }
 
     // Clean up empty parent directories (only for local files)
@@ -1832,9 +2307,41 @@ async fn save_config(
     state: State<'_, AppState>,
     new_config: config::Config,
 ) -> Result<ApiResponse, String> {
+    if new_config.jellyfin_enabled && new_config.jellyfin_password.as_deref().unwrap_or("").is_empty() {
+        return Err("Set a password before enabling the Jellyfin API".to_string());
+    }
     let mut config = state.config.lock().map_err(|e| e.to_string())?;
+    let dlna_changed = dlna_settings(&config) != dlna_settings(&new_config);
+    let jellyfin_changed = jellyfin_settings(&config) != jellyfin_settings(&new_config);
+    // Changing the login signs out every Jellyfin app
+    let credentials_changed = config.jellyfin_username != new_config.jellyfin_username
+        || config.jellyfin_password != new_config.jellyfin_password;
     *config = new_config.clone();
     config::save_config(&new_config).map_err(|e| e.to_string())?;
+    downloads::set_max_concurrent(new_config.max_concurrent_downloads);
+    prefetch::set_cache_dir(prefetch_cache_dir(&new_config));
+    transcoder::set_max_sessions(new_config.max_transcode_sessions as usize);
+    // The config is already saved, so one server failing to start must not skip the rest
+    let mut errors = Vec::new();
+    if dlna_changed {
+        if let Err(e) = dlna::apply(dlna_settings(&new_config)) {
+            println!("[DLNA] {}", e);
+            errors.push(e);
+        }
+    }
+    if credentials_changed {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.clear_jellyfin_sessions().map_err(|e| e.to_string())?;
+    }
+    if jellyfin_changed {
+        if let Err(e) = jellyfin::apply(jellyfin_settings(&new_config)) {
+            println!("[JELLYFIN] {}", e);
+            errors.push(e);
+        }
+    }
+    if !errors.is_empty() {
+        return Err(format!("Configuration saved, but {}", errors.join("; ")));
+    }
     Ok(ApiResponse {
         message: "Configuration saved.".to_string(),
     })
@@ -1867,50 +2374,168 @@ async fn get_resume_info(
     db.get_resume_info(media_id).map_err(|e| e.to_string())
 }
 
-// Get media info by ID
-#[tauri::command]
-async fn get_media_info(
-    state: State<'_, AppState>,
-    media_id: i64,
-) -> Result<database::MediaItem, String> {
-    let db = state.db.lock().map_err(|e| e.to_string())?;
-    db.get_media_by_id(media_id).map_err(|e| e.to_string())
+// Media item together with its technical info
+#[derive(Serialize)]
+pub struct MediaDetails {
+    #[serde(flatten)]
+    pub media: database::MediaItem,
+    pub technical: Option<database::MediaTechInfo>,
 }
 
-// Get stream info for built-in player
-#[derive(Serialize)]
-pub struct StreamInfo {
-    pub stream_url: String,
-    pub file_path: String,
-    pub title: String,
-    pub poster: Option<String>,
-    pub duration_seconds: Option<f64>,
-    pub resume_position_seconds: Option<f64>,
-    // Cloud streaming fields
-    pub is_cloud: bool,
-    pub access_token: Option<String>,
+/// ffprobe from the config, falling back to the one installed next to ffmpeg
+fn ffprobe_path(config: &config::Config) -> Result<String, String> {
+    media_probe::find_ffprobe(config.ffprobe_path.as_deref(), config.ffmpeg_path.as_deref())
+        .ok_or_else(|| "FFprobe path not configured. Please set it in Settings > Player.".to_string())
 }
 
+// Get media info by ID. Local files that were indexed before probing existed are analysed here.
 #[tauri::command]
-async fn get_stream_info(
+async fn get_media_info(
     state: State<'_, AppState>,
     media_id: i64,
-) -> Result<StreamInfo, String> {
-    let media = {
+) -> Result<MediaDetails, String> {
+    let (media, technical) = {
         let db = state.db.lock().map_err(|e| e.to_string())?;
-        db.get_media_by_id(media_id).map_err(|e| e.to_string())?
+        let media = db.get_media_by_id(media_id).map_err(|e| e.to_string())?;
+        let technical = db.get_media_tech_info(media_id).map_err(|e| e.to_string())?;
+        (media, technical)
     };
 
-    let file_path = media.file_path.clone().unwrap_or_default();
-    let is_cloud = media.is_cloud.unwrap_or(false);
+    let local_file = media.file_path.clone()
+        .filter(|p| !media.is_cloud.unwrap_or(false) && std::path::Path::new(p).is_file());
+    let ffprobe = {
+        let config = state.config.lock().map_err(|e| e.to_string())?;
+        ffprobe_path(&config).ok()
+    };
 
-    // Handle cloud media
+    let technical = match (technical, local_file, ffprobe) {
+        (None, Some(file_path), Some(ffprobe)) => tokio::task::spawn_blocking(move || {
+            let db = database::Database::new(&database::get_database_path()).map_err(|e| e.to_string())?;
+            media_probe::analyze(&db, media_id, &ffprobe, &file_path)
+        })
+        .await
+        .map_err(|e| format!("Probe task failed: {}", e))?
+        .map_err(|e| println!("[PROBE] Media {}: {}", media_id, e))
+        .ok(),
+        (technical, _, _) => technical,
+    };
+
+    // The probe may have filled in the duration
+    let media = match technical.as_ref().and_then(|t| t.duration_seconds) {
+        Some(duration) if media.duration_seconds.map_or(true, |d| d <= 0.0) => database::MediaItem {
+            duration_seconds: Some(duration),
+            ..media
+        },
+        _ => media,
+    };
+
+    Ok(MediaDetails { media, technical })
+}
+
+/// Run ffprobe on a media item now. Drive files are read from an offline copy if there is
+/// one, otherwise through the stream proxy.
+#[tauri::command]
+async fn analyze_media(
+    state: State<'_, AppState>,
+    media_id: i64,
+) -> Result<database::MediaTechInfo, String> {
+    let ffprobe = {
+        let config = state.config.lock().map_err(|e| e.to_string())?;
+        ffprobe_path(&config)?
+    };
+    let media = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.get_media_by_id(media_id).map_err(|e| e.to_string())?
+    };
+
+    let source = if media.is_cloud.unwrap_or(false) {
+        match offline_copy(&state, media_id) {
+            Some(local_path) => local_path,
+            None => stream_proxy::media_url(media_id)?,
+        }
+    } else {
+        media.file_path.clone()
+            .filter(|p| std::path::Path::new(p).is_file())
+            .ok_or_else(|| "Video file not found".to_string())?
+    };
+
+    println!("[PROBE] Analysing media {}", media_id);
+    tokio::task::spawn_blocking(move || {
+        let db = database::Database::new(&database::get_database_path()).map_err(|e| e.to_string())?;
+        media_probe::analyze(&db, media_id, &ffprobe, &source)
+    })
+    .await
+    .map_err(|e| format!("Probe task failed: {}", e))?
+}
+
+// Get stream info for built-in player
+#[derive(Serialize)]
+pub struct StreamInfo {
+    pub stream_url: String,
+    pub file_path: String,
+    pub title: String,
+    pub poster: Option<String>,
+    pub duration_seconds: Option<f64>,
+    pub resume_position_seconds: Option<f64>,
+    // Cloud media streams through the local proxy, so no token is handed out
+    pub is_cloud: bool,
+    // External subtitles as WebVTT through the local proxy
+    pub subtitles: Vec<SubtitleTrack>,
+    // Embedded or manual chapters, on the stream's timeline
+    pub chapters: Vec<database::Chapter>,
+}
+
+#[derive(Serialize)]
+pub struct SubtitleTrack {
+    pub id: i64,
+    pub label: String,
+    pub language: Option<String>,
+    pub forced: bool,
+    pub url: String,
+}
+
+/// Local URL for handing a cloud file to any external player (no Google token in it)
+#[tauri::command]
+async fn get_external_stream_url(
+    state: State<'_, AppState>,
+    media_id: i64,
+) -> Result<String, String> {
+    let media = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.get_media_by_id(media_id).map_err(|e| e.to_string())?
+    };
+
+    if media.is_cloud.unwrap_or(false) {
+        match offline_copy(&state, media_id) {
+            Some(local_path) => Ok(local_path),
+            None => stream_proxy::media_url(media_id),
+        }
+    } else {
+        media.file_path.ok_or_else(|| "No file path".to_string())
+    }
+}
+
+#[tauri::command]
+async fn get_stream_info(
+    state: State<'_, AppState>,
+    media_id: i64,
+) -> Result<StreamInfo, String> {
+    let media = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.get_media_by_id(media_id).map_err(|e| e.to_string())?
+    };
+
+    let file_path = media.file_path.clone().unwrap_or_default();
+    let is_cloud = media.is_cloud.unwrap_or(false);
+
+    // Handle cloud media
     if is_cloud {
-        if let Some(ref cloud_file_id) = media.cloud_file_id {
-            // Get streaming URL and access token from Google Drive
-            let (stream_url, access_token) = state.gdrive_client
-                .get_stream_url(cloud_file_id)
-                .await?;
+        if media.cloud_file_id.is_some() {
+            // Offline copy if downloaded, else the local proxy URL (the proxy injects the token)
+            let stream_url = match offline_copy(&state, media_id) {
+                Some(local_path) => local_path,
+                None => stream_proxy::media_url(media_id)?,
+            };
 
             let poster = media.poster_path.as_ref().map(|p| {
                 let cache_dir = database::get_image_cache_dir();
@@ -1921,12 +2546,13 @@ async fn get_stream_info(
             return Ok(StreamInfo {
                 stream_url,
                 file_path,
+                subtitles: subtitle_tracks(&media_subtitles(&state, &media)?),
+                chapters: media_chapters(&state, media.id)?,
                 title: media.title,
                 poster,
                 duration_seconds: media.duration_seconds,
                 resume_position_seconds: media.resume_position_seconds,
                 is_cloud: true,
-                access_token: Some(access_token),
             });
         } else {
             return Err("Cloud file ID not found".to_string());
@@ -1949,12 +2575,13 @@ async fn get_stream_info(
     Ok(StreamInfo {
         stream_url,
         file_path,
+        subtitles: subtitle_tracks(&media_subtitles(&state, &media)?),
+        chapters: media_chapters(&state, media.id)?,
         title: media.title,
         poster,
         duration_seconds: media.duration_seconds,
         resume_position_seconds: media.resume_position_seconds,
         is_cloud: false,
-        access_token: None,
     })
 }
 
@@ -2052,12 +2679,13 @@ async fn play_with_mpv(
     let (playback_url, auth_header): (String, Option<String>) = if is_cloud {
         // Cloud file - get stream URL from Google Drive
         if let Some(ref cloud_file_id) = media.cloud_file_id {
-            println!("[MPV] Cloud file detected, getting stream URL for file ID: {}", cloud_file_id);
-            let (stream_url, access_token) = state.gdrive_client
-                .get_stream_url(cloud_file_id)
-                .await?;
-            println!("[MPV] Got cloud stream URL, token length: {}", access_token.len());
-            (stream_url, Some(format!("Authorization: Bearer {}", access_token)))
+            if let Some(local_path) = offline_copy(&state, media_id) {
+                println!("[MPV] Playing offline copy: {}", local_path);
+                (local_path, None)
+            } else {
+                println!("[MPV] Cloud file detected, streaming file ID {} through the local proxy", cloud_file_id);
+                (stream_proxy::media_url(media_id)?, None)
+            }
         } else {
             return Err("Cloud file ID not found".to_string());
         }
@@ -2087,7 +2715,8 @@ async fn play_with_mpv(
     let playback_url_clone = playback_url.clone();
 
     // Launch MPV with tracking (pass auth header and cache settings for cloud files)
-    let cache_settings = if is_cloud && config.cloud_cache_enabled {
+    // (offline copies are already on disk - nothing to record)
+    let cache_settings = if is_cloud && config.cloud_cache_enabled && playback_url.starts_with("http") {
         config.cloud_cache_dir.as_ref().map(|dir| mpv_ipc::CloudCacheSettings {
             enabled: true,
             cache_dir: dir.clone(),
@@ -2097,6 +2726,8 @@ async fn play_with_mpv(
         None
     };
 
+    let extra_args = mpv_extra_args(&state, &config, &media)?;
+
     let pid = mpv_ipc::launch_mpv_with_tracking(
         &mpv_path_clone,
         &playback_url_clone,
@@ -2104,6 +2735,7 @@ async fn play_with_mpv(
         start_position,
         auth_header.as_deref(),
         cache_settings.as_ref(),
+        &extra_args,
     )?;
     
     // Store the session
@@ -2117,15 +2749,54 @@ async fn play_with_mpv(
         });
     }
     
+    // Register the recording in the cache index so eviction sees it as just used
+    let cache_dir = cache_settings.map(|c| c.cache_dir);
+    let cache_quota_bytes = (config.cloud_cache_quota_mb as u64) * 1024 * 1024;
+    if let Some(ref dir) = cache_dir {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        if let Err(e) = cloud_cache::touch(&db, dir, media_id) {
+            println!("[CACHE] Failed to index cache entry: {}", e);
+        }
+    }
+
+    // Queue up the next cloud episode so it starts instantly on slow connections
+    let mut prefetch_job = next_episode_prefetch(&state, &config, &media);
+
     // Spawn a background thread to monitor MPV and save progress
     let db_path = database::get_database_path();
     let window_clone = window.clone();
+    let app_handle = window.app_handle();
     
     std::thread::spawn(move || {
         println!("[MPV] Starting progress monitor for media ID: {}", media_id);
         
         if let Ok(db) = database::Database::new(&db_path) {
-            let result = mpv_ipc::monitor_mpv_and_save_progress(&db, media_id, pid);
+            let result = mpv_ipc::monitor_mpv_and_save_progress(&db, media_id, pid, &mut |progress| {
+                if progress.position < prefetch::START_AFTER_SECONDS {
+                    return;
+                }
+                if let Some(mut job) = prefetch_job.take() {
+                    // Episodes of a show run about the same length - use this one's if needed
+                    job.duration_seconds = job.duration_seconds.or(Some(progress.duration));
+                    println!("[MPV] Prefetching next episode: {}", job.title);
+                    prefetch::start(job, drive_source_resolver(app_handle.clone()));
+                }
+            });
+            end_mpv_session(&app_handle.state::<AppState>(), media_id, pid);
+
+            // Keep the recording only if it holds the whole file, then refresh its size and
+            // trim the cache back under quota
+            if let Some(ref dir) = cache_dir {
+                mpv_ipc::finish_recording(dir, media_id, result.played_through && start_position <= 0.0);
+                // Keep this recording (even on its own over quota) and any other player's folder
+                let mut in_use = active_playback_ids(&app_handle.state::<AppState>());
+                in_use.push(media_id);
+                let trimmed = cloud_cache::touch(&db, dir, media_id)
+                    .and_then(|_| cloud_cache::enforce_quota(&db, dir, cache_quota_bytes, &in_use));
+                if let Err(e) = trimmed {
+                    println!("[CACHE] Failed to enforce cache quota: {}", e);
+                }
+            }
             
             // Emit event to frontend when MPV exits
             let _ = window_clone.emit("mpv-playback-ended", serde_json::json!({
@@ -2146,9 +2817,152 @@ async fn play_with_mpv(
     })
 }
 
+/// Forget an mpv session once its process has exited, unless the item was started again meanwhile
+fn end_mpv_session(state: &AppState, media_id: i64, pid: u32) {
+    if let Ok(mut sessions) = state.active_mpv_sessions.lock() {
+        if sessions.get(&media_id).is_some_and(|session| session.pid == pid) {
+            sessions.remove(&media_id);
+        }
+    }
+}
+
+/// Media playing in mpv or VLC right now, whose cache folders must not be evicted
+fn active_playback_ids(state: &AppState) -> Vec<i64> {
+    let mut ids: Vec<i64> = state.active_mpv_sessions.lock().map(|s| s.keys().copied().collect()).unwrap_or_default();
+    if let Ok(sessions) = state.active_vlc_sessions.lock() {
+        ids.extend(sessions.keys().copied());
+    }
+    ids
+}
+
+/// Stream cache folder the proxy should look in for prefetched data
+fn prefetch_cache_dir(config: &config::Config) -> Option<String> {
+    if config.cloud_cache_enabled {
+        config.cloud_cache_dir.clone().filter(|dir| !dir.is_empty())
+    } else {
+        None
+    }
+}
+
+/// DLNA server settings, or None when it's disabled
+fn dlna_settings(config: &config::Config) -> Option<dlna::DlnaSettings> {
+    if !config.dlna_enabled {
+        return None;
+    }
+    Some(dlna::DlnaSettings {
+        friendly_name: config.dlna_name.clone(),
+        port: config.dlna_port,
+        ffmpeg_path: lan_ffmpeg_path(config),
+        profile: lan_transcode_profile(config),
+    })
+}
+
+/// Jellyfin API settings, or None when it's disabled or has no password yet
+fn jellyfin_settings(config: &config::Config) -> Option<jellyfin::JellyfinSettings> {
+    if !config.jellyfin_enabled {
+        return None;
+    }
+    let password = config.jellyfin_password.clone().filter(|password| !password.is_empty())?;
+    Some(jellyfin::JellyfinSettings {
+        port: config.jellyfin_port,
+        username: config.jellyfin_username.clone(),
+        password,
+        ffmpeg_path: lan_ffmpeg_path(config),
+        profile: lan_transcode_profile(config),
+    })
+}
+
+/// FFmpeg for the LAN servers, if it's actually installed
+fn lan_ffmpeg_path(config: &config::Config) -> Option<String> {
+    config.ffmpeg_path.clone().filter(|path| std::path::Path::new(path).exists())
+}
+
+/// Profile for streams the LAN servers transcode. A remux can't fix a codec the
+/// client doesn't support, so always encode.
+fn lan_transcode_profile(config: &config::Config) -> transcoder::QualityProfile {
+    config
+        .transcode_profile(None)
+        .ok()
+        .filter(|profile| !profile.is_remux())
+        .unwrap_or_default()
+}
+
+/// Save VLC's progress until it exits, then drop the session and notify the frontend
+fn spawn_vlc_monitor(window: Window, media_id: i64, title: String, process: vlc_ipc::VlcProcess) {
+    let db_path = database::get_database_path();
+    let app_handle = window.app_handle();
+
+    std::thread::spawn(move || {
+        if let Ok(db) = database::Database::new(&db_path) {
+            let result = vlc_ipc::monitor_vlc_and_save_progress(&db, media_id, process, &mut |pid| {
+                let state: State<'_, AppState> = app_handle.state();
+                let mut sessions = match state.active_vlc_sessions.lock() {
+                    Ok(sessions) => sessions,
+                    Err(_) => return,
+                };
+                if let Some(session) = sessions.get_mut(&media_id) {
+                    session.pid = pid;
+                }
+            });
+
+            let state: State<'_, AppState> = app_handle.state();
+            if let Ok(mut sessions) = state.active_vlc_sessions.lock() {
+                sessions.remove(&media_id);
+            }
+
+            // Same event as MPV so the frontend refreshes progress whichever player was used
+            let _ = window.emit("mpv-playback-ended", serde_json::json!({
+                "media_id": media_id,
+                "title": title,
+                "final_position": result.final_position,
+                "final_duration": result.final_duration,
+                "completed": result.completed,
+                "player": "vlc",
+            }));
+
+            println!("[VLC] Playback ended for media ID: {}. Completed: {}",
+                media_id, result.completed);
+        }
+    });
+}
+
+/// Prefetch job for the episode after `media`, if it's on Drive and not already local
+fn next_episode_prefetch(state: &AppState, config: &config::Config, media: &database::MediaItem) -> Option<prefetch::PrefetchJob> {
+    if !config.prefetch_next_episode || !config.cloud_cache_enabled {
+        return None;
+    }
+    let cache_dir = config.cloud_cache_dir.clone().filter(|dir| !dir.is_empty())?;
+
+    let next = state.db.lock().ok()?.get_next_episode(media.id).ok().flatten()?;
+    if !next.is_cloud.unwrap_or(false) || offline_copy(state, next.id).is_some() {
+        return None;
+    }
+
+    let extension = next
+        .file_path
+        .as_deref()
+        .and_then(|p| std::path::Path::new(p).extension())
+        .and_then(|e| e.to_str())
+        .unwrap_or("mp4")
+        .to_lowercase();
+
+    Some(prefetch::PrefetchJob {
+        media_id: next.id,
+        title: next.episode_title.clone().unwrap_or_else(|| next.title.clone()),
+        cache_dir,
+        minutes: config.prefetch_minutes,
+        duration_seconds: next.duration_seconds.filter(|d| *d > 0.0),
+        max_kbps: config.prefetch_max_kbps,
+        extension,
+        keep: vec![media.id],
+        quota_bytes: (config.cloud_cache_quota_mb as u64) * 1024 * 1024,
+    })
+}
+
 // Play media with VLC (external player)
 #[tauri::command]
 async fn play_with_vlc(
+    window: Window,
     state: State<'_, AppState>,
     media_id: i64,
     resume: bool,
@@ -2186,39 +3000,201 @@ async fn play_with_vlc(
     let is_cloud = media.is_cloud.unwrap_or(false);
     let title = media.title.clone();
 
-    // Build VLC command
-    let mut command = std::process::Command::new(vlc_path);
-
-    if is_cloud {
-        // VLC doesn't support authenticated Google Drive streams properly
-        // The Google Drive API requires Authorization headers, which VLC can't pass
-        return Err("VLC doesn't support authenticated cloud streaming. Please use MPV or the built-in player for cloud files.".to_string());
+    // Pick the source: a verified offline copy or the tokenless proxy URL for cloud files
+    // (VLC can't send Authorization headers), the file itself for local media
+    let source = if is_cloud {
+        match offline_copy(&state, media_id) {
+            Some(local_path) => local_path,
+            None => stream_proxy::media_url(media_id)?,
+        }
     } else {
-        // Local file
         let file_path = media.file_path.clone().ok_or_else(|| "No file path".to_string())?;
 
         if !std::path::Path::new(&file_path).exists() {
             return Err(format!("File not found: {}", file_path));
         }
 
-        // Add the file path
-        command.arg(&file_path);
+        file_path
+    };
+
+    // Launch VLC with its HTTP interface so progress can be tracked
+    let sub_files = subtitle_sources(&media_subtitles(&state, &media)?);
+    let process = vlc_ipc::launch_vlc_with_tracking(vlc_path, &source, start_position, sub_files.first().map(String::as_str))?;
+    let pid = process.pid();
+
+    // Store the session
+    {
+        let mut sessions = state.active_vlc_sessions.lock().map_err(|e| e.to_string())?;
+        sessions.insert(media_id, MpvSession {
+            media_id,
+            pid,
+            title: title.clone(),
+            start_time: chrono::Utc::now().timestamp(),
+        });
+    }
+
+    println!("[VLC] Playback started for: {}", title);
+    spawn_vlc_monitor(window, media_id, title, process);
+
+    Ok(ApiResponse {
+        message: format!("VLC playback started: {}", media.title),
+    })
+}
+
+/// Player profile plus whether its executable can be found
+#[derive(Serialize)]
+struct PlayerProfileInfo {
+    #[serde(flatten)]
+    profile: players::PlayerProfile,
+    available: bool,
+}
+
+// List built-in and user player profiles
+#[tauri::command]
+async fn get_player_profiles(state: State<'_, AppState>) -> Result<Vec<PlayerProfileInfo>, String> {
+    let config = state.config.lock().map_err(|e| e.to_string())?.clone();
+    Ok(players::all_profiles(&config)
+        .into_iter()
+        .map(|profile| PlayerProfileInfo { available: players::is_available(&profile), profile })
+        .collect())
+}
+
+// Play media with any player profile
+#[tauri::command]
+async fn play_with_profile(
+    window: Window,
+    state: State<'_, AppState>,
+    profile_id: String,
+    media_id: i64,
+    resume: bool,
+) -> Result<ApiResponse, String> {
+    let config = state.config.lock().map_err(|e| e.to_string())?.clone();
+    let profile = players::find_profile(&config, &profile_id)
+        .ok_or_else(|| format!("Unknown player profile: {}", profile_id))?;
+
+    // The stock mpv/VLC profiles keep their dedicated launchers (disk cache, prefetch)
+    if profile.builtin && profile.id == "mpv" {
+        return play_with_mpv(window, state, media_id, resume).await;
+    }
+    if profile.builtin && profile.id == "vlc" {
+        return play_with_vlc(window, state, media_id, resume).await;
+    }
+
+    let (media, resume_info) = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let media = db.get_media_by_id(media_id).map_err(|e| e.to_string())?;
+        let resume_info = db.get_resume_info(media_id).map_err(|e| e.to_string())?;
+
+        // Update last_watched
+        db.update_last_watched(media_id).map_err(|e| e.to_string())?;
+
+        (media, resume_info)
+    };
+
+    let mut args = players::PlayerArgs {
+        start: if profile.capabilities.resume && resume && resume_info.has_progress {
+            Some(resume_info.position)
+        } else {
+            None
+        },
+        title: Some(media.episode_title.clone().unwrap_or_else(|| media.title.clone())),
+        ..Default::default()
+    };
 
-        // Add start time if resuming (as input option after the file)
-        if start_position > 0.0 {
-            command.arg(format!(":start-time={:.0}", start_position));
+    if media.is_cloud.unwrap_or(false) {
+        let cloud_file_id = media.cloud_file_id.clone().ok_or_else(|| "Cloud file ID not found".to_string())?;
+        if let Some(local_path) = offline_copy(&state, media_id) {
+            args.source = local_path;
+        } else if profile.capabilities.auth_headers {
+            // Player can authenticate itself - stream straight from Drive
+            let account_id = cloud_account_for_file(&state, &cloud_file_id);
+            let (url, access_token) = state.gdrive_client.get_stream_url(account_id.as_deref(), &cloud_file_id).await?;
+            args.source = url;
+            args.headers = Some(format!("Authorization: Bearer {}", access_token));
+        } else {
+            args.source = stream_proxy::media_url(media_id)?;
+        }
+    } else {
+        let file_path = media.file_path.clone().ok_or_else(|| "No file path".to_string())?;
+        if !std::path::Path::new(&file_path).exists() {
+            return Err(format!("File not found: {}", file_path));
         }
+        args.source = file_path;
+    }
+    args.subtitle = subtitle_sources(&media_subtitles(&state, &media)?).into_iter().next();
+
+    let tracking = profile.capabilities.progress_tracking;
+    if tracking && profile.kind == "vlc" {
+        // Built per launch so VLC can be started again if its HTTP port gets taken
+        let executable = profile.executable.clone();
+        let player_args = players::render_args(&profile, &args)?;
+        println!("[PLAYER] Launching {} for: {}", profile.name, media.title);
+        let process = vlc_ipc::VlcProcess::spawn(Box::new(move |interface| {
+            let mut command = std::process::Command::new(&executable);
+            command.args(interface.args()).args(&player_args);
+            command
+        }))?;
+
+        state.active_vlc_sessions.lock().map_err(|e| e.to_string())?.insert(media_id, MpvSession {
+            media_id,
+            pid: process.pid(),
+            title: media.title.clone(),
+            start_time: chrono::Utc::now().timestamp(),
+        });
+        spawn_vlc_monitor(window, media_id, media.title.clone(), process);
+        return Ok(ApiResponse {
+            message: format!("{} playback started: {}", profile.name, media.title),
+        });
     }
 
-    // Launch VLC
-    println!("[VLC] Launching with args: {:?}", command);
-    command.spawn()
-        .map_err(|e| format!("Failed to launch VLC: {}", e))?;
+    let mut command = std::process::Command::new(&profile.executable);
+    if tracking && profile.kind == "mpv" {
+        let script_path = mpv_ipc::create_lua_script(media_id)?;
+        command.arg(format!("--script={}", script_path.to_string_lossy()));
+        command.args(skip_marker_args(&state, media_id));
+    }
+    if profile.kind == "mpv" {
+        command.args(remembered_track_args(&state, &config, &media));
+    }
+    command.args(players::render_args(&profile, &args)?);
 
-    println!("[VLC] Playback started for: {}", title);
+    println!("[PLAYER] Launching {} for: {}", profile.name, media.title);
+    let child = command.spawn()
+        .map_err(|e| format!("Failed to launch {}: {}", profile.name, e))?;
+
+    let session = MpvSession {
+        media_id,
+        pid: child.id(),
+        title: media.title.clone(),
+        start_time: chrono::Utc::now().timestamp(),
+    };
+
+    if tracking && profile.kind == "mpv" {
+        let pid = child.id();
+        state.active_mpv_sessions.lock().map_err(|e| e.to_string())?.insert(media_id, session);
+
+        let db_path = database::get_database_path();
+        let title = media.title.clone();
+        let app_handle = window.app_handle();
+        std::thread::spawn(move || {
+            if let Ok(db) = database::Database::new(&db_path) {
+                let result = mpv_ipc::monitor_mpv_and_save_progress(&db, media_id, pid, &mut |_| {});
+
+                end_mpv_session(&app_handle.state::<AppState>(), media_id, pid);
+
+                let _ = window.emit("mpv-playback-ended", serde_json::json!({
+                    "media_id": media_id,
+                    "title": title,
+                    "final_position": result.final_position,
+                    "final_duration": result.final_duration,
+                    "completed": result.completed,
+                }));
+            }
+        });
+    }
 
     Ok(ApiResponse {
-        message: format!("VLC playback started: {}", title),
+        message: format!("{} playback started: {}", profile.name, media.title),
     })
 }
 
@@ -2284,6 +3260,15 @@ async fn get_active_mpv_sessions(
     Ok(sessions.values().cloned().collect())
 }
 
+// Get all active VLC sessions
+#[tauri::command]
+async fn get_active_vlc_sessions(
+    state: State<'_, AppState>,
+) -> Result<Vec<MpvSession>, String> {
+    let sessions = state.active_vlc_sessions.lock().map_err(|e| e.to_string())?;
+    Ok(sessions.values().cloned().collect())
+}
+
 // Get image from cache (returns the file path for asset protocol)
 #[tauri::command]
 async fn get_cached_image(image_name: String) -> Result<String, String> {
@@ -3019,6 +4004,12 @@ async fn save_videasy_progress(
 struct TranscodeResponse {
     session_id: u64,
     stream_url: String,
+    // Relative to the start of the transcoded stream
+    chapters: Vec<database::Chapter>,
+    // The selected text subtitle stream, extracted to WebVTT (image ones are burned in)
+    subtitle: Option<SubtitleTrack>,
+    // stream_url is an HLS master playlist rather than a progressive MP4
+    is_hls: bool,
 }
 
 /// Check if a file needs transcoding for HTML5 playback
@@ -3027,46 +4018,251 @@ async fn check_needs_transcode(file_path: String) -> Result<bool, String> {
     Ok(transcoder::needs_transcoding(&file_path))
 }
 
-/// Start transcoding a video file
+/// Start transcoding a video file, or a media item by id (Drive items are read from Drive)
 #[tauri::command]
 async fn start_transcode_stream(
-    state: State<'_, AppState>,
-    file_path: String,
+    app_handle: AppHandle,
+    file_path: Option<String>,
     start_time: Option<f64>,
-) -> Result<TranscodeResponse, String> {
+    media_id: Option<i64>,
+    subtitle_stream: Option<i64>,
+    audio: Option<transcoder::AudioOptions>,
+    quality: Option<String>,
+) -> Result<TranscodeResponse, transcoder::TranscodeError> {
+    let state: State<'_, AppState> = app_handle.state();
     let ffmpeg_path = {
         let config = state.config.lock().map_err(|e| e.to_string())?;
         config.ffmpeg_path.clone()
             .ok_or_else(|| "FFmpeg path not configured. Please set it in Settings > Player.".to_string())?
     };
+    let (profile, hls_variants) = transcode_quality(&state, media_id, quality.as_deref())?;
 
     if ffmpeg_path.is_empty() || !std::path::Path::new(&ffmpeg_path).exists() {
-        return Err("FFmpeg path not set or invalid. Please configure it in Settings > Player.".to_string());
+        return Err("FFmpeg path not set or invalid. Please configure it in Settings > Player.".to_string().into());
+    }
+
+    let (resolve_input, file_path) = transcode_input(&app_handle, file_path, media_id).await?;
+
+    let chapters = match media_id {
+        Some(id) => {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            db.get_chapters(id).map_err(|e| e.to_string())?
+        }
+        None => Vec::new(),
+    };
+
+    let mut options = transcoder::TranscodeOptions {
+        profile,
+        hls_variants,
+        audio: audio.unwrap_or_default(),
+        ..Default::default()
+    };
+    let mut subtitle = None;
+    if let Some(index) = subtitle_stream {
+        let stream = embedded_subtitle_stream(&state, media_id, &file_path, index).await?;
+        if subtitles::is_image_codec(&stream.codec) {
+            options.burn_subtitle = Some(index);
+        } else if subtitles::is_text_codec(&stream.codec) {
+            let media_id = media_id.ok_or_else(|| "A media id is needed to extract subtitles".to_string())?;
+            let path = embedded_subtitle_path(media_id, index);
+            if !path.exists() {
+                println!("[SUBTITLES] Extracting stream {} of media {} ({})", index, media_id, stream.codec);
+                let (ffmpeg_path, file_path, path) = (ffmpeg_path.clone(), file_path.clone(), path.clone());
+                tokio::task::spawn_blocking(move || {
+                    if let Some(dir) = path.parent() {
+                        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create subtitle folder: {}", e))?;
+                    }
+                    transcoder::extract_subtitle(&ffmpeg_path, &file_path, index, &path)
+                })
+                .await
+                .map_err(|e| format!("Subtitle task failed: {}", e))??;
+            }
+            let extracted = register_embedded_subtitle(&state, media_id, &stream, &path)?;
+            subtitle = subtitle_tracks_from(&[extracted], start_time.unwrap_or(0.0)).pop();
+        } else {
+            return Err(format!("Unsupported subtitle codec: {}", stream.codec).into());
+        }
     }
 
-    let (session_id, stream_url) = transcoder::start_transcode(&ffmpeg_path, &file_path, start_time)?;
+    options.audio_channels = match media_id {
+        Some(id) => transcode_audio_channels(&state, id, &options)?,
+        None => None,
+    };
+
+    let (session_id, stream_url) = run_start_transcode(ffmpeg_path, resolve_input, start_time, options.clone()).await?;
 
     Ok(TranscodeResponse {
         session_id,
         stream_url,
+        chapters: transcoder::stream_chapters(&chapters, start_time),
+        subtitle,
+        is_hls: !options.hls_variants.is_empty(),
     })
 }
 
-/// Stop a transcoding session
-#[tauri::command]
-async fn stop_transcode_stream(session_id: u64) -> Result<ApiResponse, String> {
-    transcoder::stop_transcode(session_id)?;
-    Ok(ApiResponse {
-        message: "Transcoding stopped".to_string(),
-    })
-}
+/// What a transcode reads. Drive items without an offline copy go straight to Drive's
+/// download URL, re-resolved whenever FFmpeg restarts so the token stays fresh; the
+/// returned path (a local file, or the proxy URL) is what ffprobe and subtitle
+/// extraction read.
+async fn transcode_input(
+    app_handle: &AppHandle,
+    file_path: Option<String>,
+    media_id: Option<i64>,
+) -> Result<(transcoder::InputResolver, String), transcoder::TranscodeError> {
+    let state: State<'_, AppState> = app_handle.state();
+    let media = match media_id {
+        Some(id) => {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            Some(db.get_media_by_id(id).map_err(|e| e.to_string())?)
+        }
+        None => None,
+    };
 
-/// Get stream info with transcoding support
-#[tauri::command]
-async fn get_stream_info_with_transcode(
-    state: State<'_, AppState>,
+    let local_path = match media {
+        Some(ref media) if media.is_cloud.unwrap_or(false) => {
+            let cloud_file_id = media.cloud_file_id.clone()
+                .ok_or_else(|| "Cloud file ID not found".to_string())?;
+            match offline_copy(&state, media.id) {
+                Some(path) => path,
+                None => {
+                    // Fail early with a typed error if Drive won't serve the file
+                    let account_id = cloud_account_for_file(&state, &cloud_file_id);
+                    state.gdrive_client.check_download(account_id.as_deref(), &cloud_file_id).await?;
+
+                    let media_id = media.id;
+                    let source = drive_source_resolver(app_handle.clone());
+                    let resolve_input: transcoder::InputResolver = Arc::new(move || {
+                        let upstream = source(media_id, false)?;
+                        Ok(transcoder::TranscodeInput {
+                            location: upstream.url,
+                            headers: Some(format!("Authorization: Bearer {}", upstream.access_token)),
+                        })
+                    });
+                    return Ok((resolve_input, stream_proxy::media_url(media_id)?));
+                }
+            }
+        }
+        Some(ref media) => file_path.or_else(|| media.file_path.clone()).unwrap_or_default(),
+        None => file_path.ok_or_else(|| "A file path or media id is needed to transcode".to_string())?,
+    };
+
+    let input = transcoder::TranscodeInput::file(&local_path);
+    Ok((Arc::new(move || Ok(input.clone())), local_path))
+}
+
+/// Start a transcode off the async runtime: it waits for the server, and resolving a
+/// Drive input blocks on a token refresh
+async fn run_start_transcode(
+    ffmpeg_path: String,
+    resolve_input: transcoder::InputResolver,
+    start_time: Option<f64>,
+    options: transcoder::TranscodeOptions,
+) -> Result<(u64, String), String> {
+    tokio::task::spawn_blocking(move || transcoder::start_transcode(&ffmpeg_path, resolve_input, start_time, &options))
+        .await
+        .map_err(|e| format!("Transcode task failed: {}", e))?
+}
+
+/// Quality for a transcode: a profile id from the config (default profile if None), or
+/// "auto" for an adaptive HLS ladder of every encoding profile up to the source height
+fn transcode_quality(
+    state: &AppState,
+    media_id: Option<i64>,
+    quality: Option<&str>,
+) -> Result<(transcoder::QualityProfile, Vec<transcoder::QualityProfile>), String> {
+    let config = state.config.lock().map_err(|e| e.to_string())?.clone();
+    if quality != Some("auto") {
+        return Ok((config.transcode_profile(quality)?, Vec::new()));
+    }
+
+    let source_height = match media_id {
+        Some(id) => {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            db.get_media_tech_info(id).map_err(|e| e.to_string())?.and_then(|info| info.height)
+        }
+        None => None,
+    };
+
+    let mut ladder: Vec<transcoder::QualityProfile> = config
+        .transcode_profiles
+        .iter()
+        .filter(|p| !p.is_remux())
+        .cloned()
+        .collect();
+    ladder.sort_by(|a, b| b.video_kbps.cmp(&a.video_kbps));
+    // Upscaled variants would only waste bandwidth, but keep the smallest rung that covers
+    // the source so a 1920x800 film still gets its 1080p variant
+    if let Some(height) = source_height {
+        let covering = ladder
+            .iter()
+            .filter_map(|p| p.max_height)
+            .filter(|h| *h as i64 >= height)
+            .min();
+        let smallest = ladder.pop();
+        ladder.retain(|p| p.max_height.is_none_or(|h| h as i64 <= height || Some(h) == covering));
+        ladder.extend(smallest);
+    }
+    if ladder.is_empty() {
+        return Err("Adaptive streaming needs at least one quality profile that re-encodes the video".to_string());
+    }
+    Ok((config.transcode_profile(None)?, ladder))
+}
+
+/// Channel count of the audio stream a transcode will play, from stored ffprobe results.
+/// Without a chosen stream ffmpeg takes the first one when burning in subtitles, and
+/// otherwise prefers the one with the most channels.
+fn transcode_audio_channels(state: &AppState, media_id: i64, options: &transcoder::TranscodeOptions) -> Result<Option<i64>, String> {
+    let info = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.get_media_tech_info(media_id).map_err(|e| e.to_string())?
+    };
+    let tracks = match info {
+        Some(info) => info.audio_tracks,
+        None => return Ok(None),
+    };
+
+    let channels = match (options.audio.stream_index, options.burn_subtitle) {
+        (Some(index), _) => tracks.iter().find(|t| t.index == index).and_then(|t| t.channels),
+        (None, Some(_)) => tracks.first().and_then(|t| t.channels),
+        (None, None) => tracks.iter().filter_map(|t| t.channels).max(),
+    };
+    Ok(channels)
+}
+
+/// Stop a transcoding session
+#[tauri::command]
+async fn stop_transcode_stream(session_id: u64) -> Result<ApiResponse, String> {
+    transcoder::stop_transcode(session_id)?;
+    Ok(ApiResponse {
+        message: "Transcoding stopped".to_string(),
+    })
+}
+
+/// Progress and recent FFmpeg log of a transcoding session (also keeps it alive while paused)
+#[tauri::command]
+async fn get_transcode_status(session_id: u64) -> Result<transcoder::TranscodeStatus, String> {
+    transcoder::session_status(session_id)
+}
+
+/// All running transcoding sessions
+#[tauri::command]
+async fn list_transcode_sessions() -> Result<Vec<transcoder::TranscodeStatus>, String> {
+    transcoder::list_sessions()
+}
+
+/// Whether the DLNA server is running and where TVs can find it
+#[tauri::command]
+async fn get_dlna_status() -> Result<dlna::DlnaStatus, String> {
+    Ok(dlna::status())
+}
+
+/// Get stream info with transcoding support
+#[tauri::command]
+async fn get_stream_info_with_transcode(
+    app_handle: AppHandle,
     media_id: i64,
 ) -> Result<StreamInfo, String> {
+    let state: State<'_, AppState> = app_handle.state();
     let media = {
         let db = state.db.lock().map_err(|e| e.to_string())?;
         db.get_media_by_id(media_id).map_err(|e| e.to_string())?
@@ -3075,49 +4271,29 @@ async fn get_stream_info_with_transcode(
     let file_path = media.file_path.clone().unwrap_or_default();
     let is_cloud = media.is_cloud.unwrap_or(false);
 
-    // Handle cloud media - same as get_stream_info
-    if is_cloud {
-        if let Some(ref cloud_file_id) = media.cloud_file_id {
-            let (stream_url, access_token) = state.gdrive_client
-                .get_stream_url(cloud_file_id)
-                .await?;
-
-            let poster = media.poster_path.as_ref().map(|p| {
-                let cache_dir = database::get_image_cache_dir();
-                let full_path = std::path::Path::new(&cache_dir).join(p.replace("image_cache/", ""));
-                format!("asset://localhost/{}", full_path.to_string_lossy().replace("\\", "/").replace(":", ""))
-            });
-
-            return Ok(StreamInfo {
-                stream_url,
-                file_path,
-                title: media.title,
-                poster,
-                duration_seconds: media.duration_seconds,
-                resume_position_seconds: media.resume_position_seconds,
-                is_cloud: true,
-                access_token: Some(access_token),
-            });
-        } else {
-            return Err("Cloud file ID not found".to_string());
-        }
+    if is_cloud && media.cloud_file_id.is_none() {
+        return Err("Cloud file ID not found".to_string());
     }
 
-    // Check if local file needs transcoding
+    // Check if the file needs transcoding (cloud items by their file name)
     let needs_transcode = transcoder::needs_transcoding(&file_path);
 
     if needs_transcode {
         // Check if FFmpeg is configured
-        let ffmpeg_path = {
+        let (ffmpeg_path, profile) = {
             let config = state.config.lock().map_err(|e| e.to_string())?;
-            config.ffmpeg_path.clone()
+            (config.ffmpeg_path.clone(), config.transcode_profile(None)?)
         };
 
         if let Some(ref path) = ffmpeg_path {
             if !path.is_empty() && std::path::Path::new(path).exists() {
                 // Start transcoding
                 let start_time = media.resume_position_seconds;
-                let (_, stream_url) = transcoder::start_transcode(path, &file_path, start_time)?;
+                let options = transcoder::TranscodeOptions { profile, ..Default::default() };
+                let (resolve_input, _) = transcode_input(&app_handle, None, Some(media_id))
+                    .await
+                    .map_err(|e| e.to_string())?;
+                let (_, stream_url) = run_start_transcode(path.clone(), resolve_input, start_time, options).await?;
 
                 let poster = media.poster_path.as_ref().map(|p| {
                     let cache_dir = database::get_image_cache_dir();
@@ -3128,20 +4304,49 @@ async fn get_stream_info_with_transcode(
                 return Ok(StreamInfo {
                     stream_url,
                     file_path,
+                    subtitles: subtitle_tracks_from(&media_subtitles(&state, &media)?, start_time.unwrap_or(0.0)),
+                    chapters: transcoder::stream_chapters(&media_chapters(&state, media.id)?, start_time),
                     title: media.title,
                     poster,
                     duration_seconds: media.duration_seconds,
                     resume_position_seconds: Some(0.0), // Already seeked in transcode
-                    is_cloud: false,
-                    access_token: None,
+                    is_cloud,
                 });
             }
         }
 
         // FFmpeg not configured, return error with helpful message
-        return Err(format!(
-            "This video format requires transcoding. Please configure FFmpeg in Settings > Player, or use MPV/VLC player instead."
-        ));
+        if !is_cloud {
+            return Err(format!(
+                "This video format requires transcoding. Please configure FFmpeg in Settings > Player, or use MPV/VLC player instead."
+            ));
+        }
+    }
+
+    // Cloud media that plays as is (or with no FFmpeg to transcode it) - same as get_stream_info
+    if is_cloud {
+        let stream_url = match offline_copy(&state, media_id) {
+            Some(local_path) => local_path,
+            None => stream_proxy::media_url(media_id)?,
+        };
+
+        let poster = media.poster_path.as_ref().map(|p| {
+            let cache_dir = database::get_image_cache_dir();
+            let full_path = std::path::Path::new(&cache_dir).join(p.replace("image_cache/", ""));
+            format!("asset://localhost/{}", full_path.to_string_lossy().replace("\\", "/").replace(":", ""))
+        });
+
+        return Ok(StreamInfo {
+            stream_url,
+            file_path,
+            subtitles: subtitle_tracks(&media_subtitles(&state, &media)?),
+            chapters: media_chapters(&state, media.id)?,
+            title: media.title,
+            poster,
+            duration_seconds: media.duration_seconds,
+            resume_position_seconds: media.resume_position_seconds,
+            is_cloud: true,
+        });
     }
 
     // No transcoding needed - return local file path
@@ -3155,18 +4360,621 @@ async fn get_stream_info_with_transcode(
         return Ok(StreamInfo {
             stream_url: file_path.clone(),
             file_path,
+            subtitles: subtitle_tracks(&media_subtitles(&state, &media)?),
+            chapters: media_chapters(&state, media.id)?,
             title: media.title,
             poster,
             duration_seconds: media.duration_seconds,
             resume_position_seconds: media.resume_position_seconds,
             is_cloud: false,
-            access_token: None,
         });
     }
 
     Err("File not found".to_string())
 }
 
+// ==================== INTRO DETECTION ====================
+
+/// Progress of skip marker detection for a season
+#[derive(Clone, Serialize)]
+struct SkipDetectionProgress {
+    series_id: i64,
+    season: i32,
+    analysed: usize,
+    total: usize,
+}
+
+/// Detect intros and end credits for a series (or one season of it) by comparing
+/// episodes' audio. Returns how many episodes got markers.
+#[tauri::command]
+async fn detect_skip_markers(
+    window: Window,
+    state: State<'_, AppState>,
+    series_id: i64,
+    season: Option<i32>,
+) -> Result<usize, String> {
+    let ffmpeg_path = {
+        let config = state.config.lock().map_err(|e| e.to_string())?;
+        config.ffmpeg_path.clone()
+            .ok_or_else(|| "FFmpeg path not configured. Please set it in Settings > Player.".to_string())?
+    };
+    if ffmpeg_path.is_empty() || !std::path::Path::new(&ffmpeg_path).exists() {
+        return Err("FFmpeg path not set or invalid. Please configure it in Settings > Player.".to_string());
+    }
+
+    let episodes = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.get_episodes(series_id).map_err(|e| e.to_string())?
+    };
+
+    // Group by season, reading cloud episodes from an offline copy or through the proxy
+    let mut seasons: Vec<(i32, Vec<intro_detect::EpisodeSource>)> = Vec::new();
+    for episode in episodes {
+        let season_number = episode.season_number.unwrap_or(0);
+        if season.map_or(false, |s| s != season_number) {
+            continue;
+        }
+        let source = if episode.is_cloud.unwrap_or(false) {
+            match offline_copy(&state, episode.id) {
+                Some(local_path) => local_path,
+                None => stream_proxy::media_url(episode.id)?,
+            }
+        } else {
+            match episode.file_path.clone().filter(|p| std::path::Path::new(p).exists()) {
+                Some(path) => path,
+                None => continue,
+            }
+        };
+
+        let entry = intro_detect::EpisodeSource {
+            media_id: episode.id,
+            source,
+            duration: episode.duration_seconds.filter(|d| *d > 0.0),
+        };
+        match seasons.iter_mut().find(|(n, _)| *n == season_number) {
+            Some((_, list)) => list.push(entry),
+            None => seasons.push((season_number, vec![entry])),
+        }
+    }
+
+    let db_path = database::get_database_path();
+    tokio::task::spawn_blocking(move || {
+        let db = database::Database::new(&db_path).map_err(|e| format!("Failed to open database: {}", e))?;
+
+        let mut detected = 0;
+        for (season_number, sources) in seasons {
+            println!("[INTRO] Analysing season {} of series {} ({} episodes)", season_number, series_id, sources.len());
+            let results = intro_detect::detect_season(&ffmpeg_path, &sources, &mut |analysed, total| {
+                let _ = window.emit("skip-detection-progress", SkipDetectionProgress {
+                    series_id,
+                    season: season_number,
+                    analysed,
+                    total,
+                });
+            });
+
+            for (media_id, markers) in results {
+                if markers == database::SkipMarkers::default() {
+                    continue;
+                }
+                println!("[INTRO] Media {}: {:?}", media_id, markers);
+                db.set_skip_markers(media_id, &markers).map_err(|e| e.to_string())?;
+                detected += 1;
+            }
+        }
+
+        println!("[INTRO] Found markers for {} episode(s) of series {}", detected, series_id);
+        Ok(detected)
+    })
+    .await
+    .map_err(|e| format!("Detection task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn get_skip_markers(state: State<'_, AppState>, media_id: i64) -> Result<database::SkipMarkers, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_skip_markers(media_id).map_err(|e| e.to_string())
+}
+
+// ==================== TRICKPLAY ====================
+
+/// Seek preview thumbnails for a media item. Returns them if they exist, otherwise queues
+/// generation (reported through the 'trickplay-ready' event) and returns None.
+#[tauri::command]
+async fn get_trickplay(state: State<'_, AppState>, media_id: i64) -> Result<Option<trickplay::TrickplayInfo>, String> {
+    if let Some(info) = trickplay::info(media_id) {
+        return Ok(Some(info));
+    }
+    if trickplay::is_pending(media_id) {
+        return Ok(None);
+    }
+
+    let (ffmpeg_path, cache_dir) = {
+        let config = state.config.lock().map_err(|e| e.to_string())?;
+        let ffmpeg_path = config.ffmpeg_path.clone()
+            .ok_or_else(|| "FFmpeg path not configured. Please set it in Settings > Player.".to_string())?;
+        (ffmpeg_path, config.cloud_cache_dir.clone().filter(|dir| !dir.is_empty()))
+    };
+    if ffmpeg_path.is_empty() || !std::path::Path::new(&ffmpeg_path).exists() {
+        return Err("FFmpeg path not set or invalid. Please configure it in Settings > Player.".to_string());
+    }
+
+    let media = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.get_media_by_id(media_id).map_err(|e| e.to_string())?
+    };
+
+    // Cloud items need a local copy - reading a whole film through Drive for thumbnails is too slow
+    let source = if media.is_cloud.unwrap_or(false) {
+        offline_copy(&state, media_id)
+            .or_else(|| cache_dir.and_then(|dir| mpv_ipc::get_cached_video_path(&dir, media_id)))
+            .ok_or_else(|| "Thumbnails need a downloaded or cached copy of this cloud file".to_string())?
+    } else {
+        media.file_path.clone()
+            .filter(|p| std::path::Path::new(p).exists())
+            .ok_or_else(|| "Video file not found".to_string())?
+    };
+
+    trickplay::queue(trickplay::TrickplayJob {
+        media_id,
+        ffmpeg_path,
+        source,
+        duration: media.duration_seconds,
+    })?;
+    Ok(None)
+}
+
+// ==================== CHAPTERS ====================
+
+/// Chapters of a media item: embedded ones from ffprobe, or the user's own markers
+#[tauri::command]
+async fn get_chapters(state: State<'_, AppState>, media_id: i64) -> Result<Vec<database::Chapter>, String> {
+    media_chapters(&state, media_id)
+}
+
+/// Add a chapter marker at a position
+#[tauri::command]
+async fn add_chapter(
+    state: State<'_, AppState>,
+    media_id: i64,
+    start_seconds: f64,
+    title: Option<String>,
+) -> Result<Vec<database::Chapter>, String> {
+    if start_seconds < 0.0 {
+        return Err("Chapter position can't be negative".to_string());
+    }
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.add_chapter(media_id, start_seconds, title.as_deref().filter(|t| !t.trim().is_empty()))
+        .map_err(|e| e.to_string())?;
+    db.get_chapters(media_id).map_err(|e| e.to_string())
+}
+
+/// Remove a chapter marker added with add_chapter
+#[tauri::command]
+async fn delete_chapter(state: State<'_, AppState>, media_id: i64, chapter_id: i64) -> Result<Vec<database::Chapter>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.delete_chapter(media_id, chapter_id).map_err(|e| e.to_string())?;
+    db.get_chapters(media_id).map_err(|e| e.to_string())
+}
+
+// ==================== SUBTITLES ====================
+
+/// External subtitles for a media item, as linked when its folder was scanned (local
+/// files at indexing or import, Drive files during cloud indexing)
+fn media_subtitles(state: &AppState, media: &database::MediaItem) -> Result<Vec<database::Subtitle>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_subtitles(media.id).map_err(|e| e.to_string())
+}
+
+/// Chapters for the built-in player
+fn media_chapters(state: &AppState, media_id: i64) -> Result<Vec<database::Chapter>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_chapters(media_id).map_err(|e| e.to_string())
+}
+
+/// What to hand an external player for each subtitle: the file itself, or a proxy URL for Drive
+fn subtitle_sources(subtitles: &[database::Subtitle]) -> Vec<String> {
+    subtitles
+        .iter()
+        // Players read streams inside the file themselves
+        .filter(|sub| sub.source != subtitles::SOURCE_EMBEDDED)
+        .filter_map(|sub| {
+            if sub.is_cloud {
+                stream_proxy::subtitle_url(sub.id, false).ok()
+            } else {
+                Some(sub.location.clone())
+            }
+        })
+        .collect()
+}
+
+/// Remembered audio/subtitle choices for the episode's series, or the configured defaults
+fn remembered_track_args(state: &AppState, config: &config::Config, media: &database::MediaItem) -> Vec<String> {
+    let prefs = media.parent_id.and_then(|series_id| {
+        state.db.lock().ok()?.get_track_preferences(series_id).ok().flatten()
+    });
+    mpv_ipc::track_args(prefs.as_ref(), &config.track_audio_languages, &config.track_subtitle_languages)
+}
+
+/// Detected intro/credits positions for the tracking script
+fn skip_marker_args(state: &AppState, media_id: i64) -> Vec<String> {
+    state
+        .db
+        .lock()
+        .ok()
+        .and_then(|db| db.get_skip_markers(media_id).ok())
+        .map(|markers| mpv_ipc::skip_marker_args(&markers))
+        .unwrap_or_default()
+}
+
+/// mpv options for external subtitles, track selection and skip markers
+fn mpv_extra_args(state: &AppState, config: &config::Config, media: &database::MediaItem) -> Result<Vec<String>, String> {
+    let mut args: Vec<String> = subtitle_sources(&media_subtitles(state, media)?)
+        .into_iter()
+        .map(|source| format!("--sub-file={}", source))
+        .collect();
+    args.extend(remembered_track_args(state, config, media));
+    args.extend(skip_marker_args(state, media.id));
+    Ok(args)
+}
+
+/// WebVTT tracks for the built-in player
+fn subtitle_tracks(subtitles: &[database::Subtitle]) -> Vec<SubtitleTrack> {
+    subtitle_tracks_from(subtitles, 0.0)
+}
+
+/// WebVTT tracks for a stream that starts `offset` seconds into the video (a transcode)
+fn subtitle_tracks_from(subtitles: &[database::Subtitle], offset: f64) -> Vec<SubtitleTrack> {
+    subtitles
+        .iter()
+        .filter_map(|sub| {
+            Some(SubtitleTrack {
+                id: sub.id,
+                label: sub.label.clone(),
+                language: sub.language.clone(),
+                forced: sub.forced,
+                url: stream_proxy::shifted_subtitle_url(sub.id, offset).ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Where an embedded text subtitle stream is extracted to
+fn embedded_subtitle_path(media_id: i64, stream_index: i64) -> std::path::PathBuf {
+    database::get_app_data_dir()
+        .join("subtitles")
+        .join("embedded")
+        .join(format!("media_{}", media_id))
+        .join(format!("stream_{}.vtt", stream_index))
+}
+
+/// Add an extracted stream to the item's subtitles so the proxy serves it like any other
+fn register_embedded_subtitle(
+    state: &AppState,
+    media_id: i64,
+    stream: &database::MediaStream,
+    path: &std::path::Path,
+) -> Result<database::Subtitle, String> {
+    let mut suffix = stream.language.clone().unwrap_or_default();
+    if stream.forced {
+        suffix.push_str(".forced");
+    }
+    let mut info = subtitles::parse_suffix(&suffix, "vtt");
+    if let Some(ref title) = stream.title {
+        info.label = format!("{} - {}", info.label, title);
+    }
+
+    let file_name = format!("Stream {} ({})", stream.index, stream.codec);
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.add_subtitle(media_id, subtitles::SOURCE_EMBEDDED, &file_name, &path.to_string_lossy(), &info)
+        .map_err(|e| e.to_string())
+}
+
+/// A subtitle stream inside the file, from the stored ffprobe results or a fresh probe
+async fn embedded_subtitle_stream(
+    state: &AppState,
+    media_id: Option<i64>,
+    file_path: &str,
+    stream_index: i64,
+) -> Result<database::MediaStream, String> {
+    let stored = match media_id {
+        Some(id) => {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            db.get_media_tech_info(id).map_err(|e| e.to_string())?
+        }
+        None => None,
+    };
+
+    let info = match stored {
+        Some(info) => info,
+        None => {
+            let ffprobe = {
+                let config = state.config.lock().map_err(|e| e.to_string())?;
+                ffprobe_path(&config)?
+            };
+            let source = file_path.to_string();
+            tokio::task::spawn_blocking(move || match media_id {
+                Some(id) => {
+                    let db = database::Database::new(&database::get_database_path()).map_err(|e| e.to_string())?;
+                    media_probe::analyze(&db, id, &ffprobe, &source)
+                }
+                None => media_probe::probe(&ffprobe, &source),
+            })
+            .await
+            .map_err(|e| format!("Probe task failed: {}", e))??
+        }
+    };
+
+    info.subtitle_tracks
+        .into_iter()
+        .find(|s| s.index == stream_index)
+        .ok_or_else(|| format!("No subtitle stream with index {}", stream_index))
+}
+
+#[tauri::command]
+async fn get_subtitles(state: State<'_, AppState>, media_id: i64) -> Result<Vec<database::Subtitle>, String> {
+    let media = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.get_media_by_id(media_id).map_err(|e| e.to_string())?
+    };
+    media_subtitles(&state, &media)
+}
+
+/// Download the best OpenSubtitles match for each preferred language.
+/// Replaces earlier downloads for the item; found and Drive subtitles are kept.
+#[tauri::command]
+async fn download_subtitles(state: State<'_, AppState>, media_id: i64) -> Result<Vec<database::Subtitle>, String> {
+    let config = state.config.lock().map_err(|e| e.to_string())?.clone();
+    let provider = opensubtitles::OpenSubtitlesClient::new(&config)?;
+
+    let (media, series_tmdb_id) = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let media = db.get_media_by_id(media_id).map_err(|e| e.to_string())?;
+        let series_tmdb_id = match media.parent_id {
+            Some(parent_id) => db.get_media_by_id(parent_id).ok().and_then(|series| series.tmdb_id),
+            None => None,
+        };
+        (media, series_tmdb_id)
+    };
+
+    // Hash whatever copy is at hand - offline copies and local files from disk, Drive by range
+    let local_path = if media.is_cloud.unwrap_or(false) { offline_copy(&state, media_id) } else { media.file_path.clone() };
+    let moviehash = match (local_path, media.cloud_file_id.as_deref()) {
+        (Some(path), _) => opensubtitles::local_file_hash(&path),
+        (None, Some(cloud_file_id)) => {
+            let account_id = cloud_account_for_file(&state, cloud_file_id);
+            opensubtitles::drive_file_hash(&state.gdrive_client, account_id.as_deref(), cloud_file_id).await
+        }
+        (None, None) => Ok(None),
+    }
+    .unwrap_or_else(|e| {
+        println!("[SUBTITLES] Could not hash media {}: {}", media_id, e);
+        None
+    });
+
+    let is_episode = media.media_type == "tvepisode";
+    let query = opensubtitles::SubtitleQuery {
+        moviehash,
+        tmdb_id: if is_episode { series_tmdb_id } else { media.tmdb_id.clone() },
+        season: if is_episode { media.season_number } else { None },
+        episode: if is_episode { media.episode_number } else { None },
+        languages: config.subtitle_languages.clone(),
+    };
+    if query.moviehash.is_none() && query.tmdb_id.is_none() {
+        return Err("Nothing to search by - the file can't be hashed and has no TMDB match".to_string());
+    }
+
+    println!("[SUBTITLES] Searching subtitles for: {}", media.title);
+    let fetched = opensubtitles::fetch(&provider, &query).await?;
+    if fetched.is_empty() {
+        return Err(format!("No subtitles found in {}", config.subtitle_languages.join(", ")));
+    }
+
+    // Downloads live in the app data folder, one file per language
+    let dir = database::get_app_data_dir().join("subtitles").join(format!("media_{}", media_id));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).map_err(|e| format!("Failed to clear old subtitles: {}", e))?;
+    }
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create subtitle folder: {}", e))?;
+
+    let mut stored = Vec::new();
+    for subtitle in fetched {
+        let format = std::path::Path::new(&subtitle.file_name)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .filter(|e| subtitles::SUBTITLE_EXTENSIONS.contains(&e.as_str()))
+            .unwrap_or_else(|| "srt".to_string());
+
+        let candidate = &subtitle.candidate;
+        let mut suffix = candidate.language.clone();
+        if candidate.forced {
+            suffix.push_str(".forced");
+        }
+        if candidate.sdh {
+            suffix.push_str(".sdh");
+        }
+        let path = dir.join(format!("{}.{}", suffix, format));
+        std::fs::write(&path, &subtitle.data).map_err(|e| format!("Failed to save subtitle: {}", e))?;
+
+        stored.push((subtitle.file_name, path.to_string_lossy().to_string(), subtitles::parse_suffix(&suffix, &format)));
+    }
+
+    {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.replace_subtitles(media_id, opensubtitles::SOURCE, false, &stored).map_err(|e| e.to_string())?;
+    }
+    println!("[SUBTITLES] Downloaded {} subtitle(s) for: {}", stored.len(), media.title);
+
+    media_subtitles(&state, &media)
+}
+
+/// Audio/subtitle tracks remembered for a series
+#[tauri::command]
+async fn get_track_preferences(state: State<'_, AppState>, series_id: i64) -> Result<Option<database::TrackPreferences>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_track_preferences(series_id).map_err(|e| e.to_string())
+}
+
+/// Forget a series' track choices so the global defaults apply again
+#[tauri::command]
+async fn clear_track_preferences(state: State<'_, AppState>, series_id: i64) -> Result<ApiResponse, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.clear_track_preferences(series_id).map_err(|e| e.to_string())?;
+    Ok(ApiResponse {
+        message: "Track preferences cleared.".to_string(),
+    })
+}
+
+// ==================== OFFLINE DOWNLOADS ====================
+
+/// Verified offline copy of a cloud item, if one has finished downloading
+fn offline_copy(state: &AppState, media_id: i64) -> Option<String> {
+    let record = state.db.lock().ok()?.get_download(media_id).ok().flatten()?;
+    downloads::verified_path(&record)
+}
+
+/// Look up size/checksum on Drive and add each cloud item to the download queue
+async fn queue_media_downloads(state: &AppState, media_ids: &[i64]) -> Result<usize, String> {
+    let download_dir = {
+        let config = state.config.lock().map_err(|e| e.to_string())?;
+        config.download_dir()
+    };
+
+    let mut queued = 0;
+    for &media_id in media_ids {
+        let media = {
+            let db = state.db.lock().map_err(|e| e.to_string())?;
+            db.get_media_by_id(media_id).map_err(|e| e.to_string())?
+        };
+        let cloud_file_id = match media.cloud_file_id {
+            Some(ref id) if media.is_cloud.unwrap_or(false) => id.clone(),
+            _ => {
+                println!("[DOWNLOAD] Skipping {} (not a cloud file)", media.title);
+                continue;
+            }
+        };
+
+        let account_id = cloud_account_for_file(state, &cloud_file_id);
+        let metadata = state.gdrive_client.get_file_metadata(account_id.as_deref(), &cloud_file_id).await?;
+        let file_name: String = metadata
+            .name
+            .chars()
+            .map(|c| if "<>:\"/\\|?*".contains(c) { '_' } else { c })
+            .collect();
+        let dest_path = std::path::Path::new(&download_dir)
+            .join(format!("media_{}", media_id))
+            .join(file_name);
+        let total_bytes = metadata.size.as_deref().and_then(|s| s.parse::<i64>().ok());
+
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.queue_download(
+            media_id,
+            &cloud_file_id,
+            &dest_path.to_string_lossy(),
+            total_bytes,
+            metadata.md5_checksum.as_deref(),
+        )
+        .map_err(|e| e.to_string())?;
+        queued += 1;
+    }
+
+    if queued > 0 {
+        tokio::task::spawn_blocking(downloads::pump).await.map_err(|e| e.to_string())?;
+    }
+    Ok(queued)
+}
+
+/// Queue cloud movies/episodes for offline playback
+#[tauri::command]
+async fn queue_downloads(
+    state: State<'_, AppState>,
+    media_ids: Vec<i64>,
+) -> Result<ApiResponse, String> {
+    let queued = queue_media_downloads(&state, &media_ids).await?;
+    Ok(ApiResponse {
+        message: format!("Queued {} download(s)", queued),
+    })
+}
+
+/// Queue every episode of a season for offline playback
+#[tauri::command]
+async fn queue_season_download(
+    state: State<'_, AppState>,
+    series_id: i64,
+    season_number: i32,
+) -> Result<ApiResponse, String> {
+    let episode_ids: Vec<i64> = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.get_episodes(series_id)
+            .map_err(|e| e.to_string())?
+            .into_iter()
+            .filter(|ep| ep.season_number == Some(season_number))
+            .map(|ep| ep.id)
+            .collect()
+    };
+
+    let queued = queue_media_downloads(&state, &episode_ids).await?;
+    Ok(ApiResponse {
+        message: format!("Queued {} episode(s) from season {}", queued, season_number),
+    })
+}
+
+/// All offline downloads with their progress
+#[tauri::command]
+async fn get_downloads(state: State<'_, AppState>) -> Result<Vec<database::DownloadRecord>, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    db.get_downloads().map_err(|e| e.to_string())
+}
+
+/// Pause a download (partial data is kept)
+#[tauri::command]
+async fn pause_download(state: State<'_, AppState>, media_id: i64) -> Result<ApiResponse, String> {
+    {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.set_download_status(media_id, "paused", None).map_err(|e| e.to_string())?;
+    }
+    downloads::stop(media_id);
+    Ok(ApiResponse {
+        message: "Download paused".to_string(),
+    })
+}
+
+/// Resume a paused or failed download from where it left off
+#[tauri::command]
+async fn resume_download(state: State<'_, AppState>, media_id: i64) -> Result<ApiResponse, String> {
+    {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        db.set_download_status(media_id, "queued", None).map_err(|e| e.to_string())?;
+    }
+    tokio::task::spawn_blocking(downloads::pump).await.map_err(|e| e.to_string())?;
+    Ok(ApiResponse {
+        message: "Download resumed".to_string(),
+    })
+}
+
+/// Cancel a download or delete a finished one
+#[tauri::command]
+async fn remove_download(state: State<'_, AppState>, media_id: i64) -> Result<ApiResponse, String> {
+    let record = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        let record = db.get_download(media_id).map_err(|e| e.to_string())?;
+        db.delete_download(media_id).map_err(|e| e.to_string())?;
+        record
+    };
+
+    if let Some(record) = record {
+        if downloads::stop(media_id) {
+            // Give the worker a moment to let go of the .part file
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        downloads::remove_files(&record);
+    }
+
+    Ok(ApiResponse {
+        message: "Download removed".to_string(),
+    })
+}
+
 // ==================== CLOUD CACHE MANAGEMENT ====================
 
 /// Cache info response
@@ -3178,13 +4986,24 @@ struct CloudCacheInfo {
     total_size_mb: f64,
     file_count: usize,
     max_size_mb: u32,
+    quota_mb: u32,
     expiry_hours: u32,
+    /// Indexed entries, least recently used first
+    entries: Vec<CloudCacheEntryInfo>,
+}
+
+#[derive(serde::Serialize)]
+struct CloudCacheEntryInfo {
+    #[serde(flatten)]
+    entry: database::CacheEntry,
+    /// Pinned, in a pinned series or partly watched - never evicted
+    protected: bool,
 }
 
 /// Get cloud cache info and statistics
 #[tauri::command]
 async fn get_cloud_cache_info(state: State<'_, AppState>) -> Result<CloudCacheInfo, String> {
-    let config = state.config.lock().map_err(|e| e.to_string())?;
+    let config = state.config.lock().map_err(|e| e.to_string())?.clone();
 
     if !config.cloud_cache_enabled || config.cloud_cache_dir.is_none() {
         return Ok(CloudCacheInfo {
@@ -3194,13 +5013,21 @@ async fn get_cloud_cache_info(state: State<'_, AppState>) -> Result<CloudCacheIn
             total_size_mb: 0.0,
             file_count: 0,
             max_size_mb: config.cloud_cache_max_mb,
+            quota_mb: config.cloud_cache_quota_mb,
             expiry_hours: config.cloud_cache_expiry_hours,
+            entries: Vec::new(),
         });
     }
 
     let cache_dir = config.cloud_cache_dir.clone().unwrap();
     let (total_size, file_count) = calculate_cache_size(&cache_dir);
 
+    let entries = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        cloud_cache::reconcile(&db, &cache_dir)?;
+        db.get_cache_entries().map_err(|e| e.to_string())?
+    };
+
     Ok(CloudCacheInfo {
         enabled: true,
         cache_dir: Some(cache_dir),
@@ -3208,7 +5035,33 @@ async fn get_cloud_cache_info(state: State<'_, AppState>) -> Result<CloudCacheIn
         total_size_mb: total_size as f64 / (1024.0 * 1024.0),
         file_count,
         max_size_mb: config.cloud_cache_max_mb,
+        quota_mb: config.cloud_cache_quota_mb,
         expiry_hours: config.cloud_cache_expiry_hours,
+        entries: entries
+            .into_iter()
+            .map(|entry| CloudCacheEntryInfo { protected: cloud_cache::is_protected(&entry), entry })
+            .collect(),
+    })
+}
+
+/// Pin or unpin a cached item so eviction skips it. Pinning a TV show covers all its episodes.
+#[tauri::command]
+async fn set_cache_pinned(
+    state: State<'_, AppState>,
+    media_id: i64,
+    pinned: bool,
+) -> Result<ApiResponse, String> {
+    let db = state.db.lock().map_err(|e| e.to_string())?;
+    let media = db.get_media_by_id(media_id).map_err(|e| e.to_string())?;
+
+    if media.media_type == "tvshow" {
+        db.set_series_cache_pinned(media_id, pinned).map_err(|e| e.to_string())?;
+    } else {
+        db.set_cache_entry_pinned(media_id, pinned).map_err(|e| e.to_string())?;
+    }
+
+    Ok(ApiResponse {
+        message: format!("{} {}", media.title, if pinned { "pinned in cache" } else { "unpinned" }),
     })
 }
 
@@ -3247,12 +5100,24 @@ async fn cleanup_cloud_cache(state: State<'_, AppState>) -> Result<ApiResponse,
 
     let cache_dir = config.cloud_cache_dir.clone().unwrap();
     let expiry_hours = config.cloud_cache_expiry_hours;
+    let quota_bytes = (config.cloud_cache_quota_mb as u64) * 1024 * 1024;
+    drop(config);
 
     let (deleted_count, freed_bytes) = cleanup_expired_cache(&cache_dir, expiry_hours);
-    let freed_mb = freed_bytes as f64 / (1024.0 * 1024.0);
+
+    // Then trim least recently used entries down to the quota (skipping anything playing)
+    let in_use = active_playback_ids(&state);
+    let (evicted_count, evicted_bytes) = {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        cloud_cache::enforce_quota(&db, &cache_dir, quota_bytes, &in_use)?
+    };
+    let freed_mb = (freed_bytes + evicted_bytes) as f64 / (1024.0 * 1024.0);
 
     Ok(ApiResponse {
-        message: format!("Cleaned up {} files, freed {:.1} MB", deleted_count, freed_mb),
+        message: format!(
+            "Cleaned up {} expired files and evicted {} cached items, freed {:.1} MB",
+            deleted_count, evicted_count, freed_mb
+        ),
     })
 }
 
@@ -3353,6 +5218,13 @@ async fn clear_cloud_cache(state: State<'_, AppState>) -> Result<ApiResponse, St
     // Recreate empty directory
     std::fs::create_dir_all(path).ok();
 
+    // Drop the index entries for everything that was removed
+    drop(config);
+    {
+        let db = state.db.lock().map_err(|e| e.to_string())?;
+        cloud_cache::reconcile(&db, &cache_dir)?;
+    }
+
     let freed_mb = total_size as f64 / (1024.0 * 1024.0);
     Ok(ApiResponse {
         message: format!("Cleared {} files, freed {:.1} MB", file_count, freed_mb),
@@ -3374,51 +5246,64 @@ fn create_main_window(app: &AppHandle) -> Result<tauri::Window, tauri::Error> {
 }
 
 /// Background cloud change detection polling
-/// Runs independently of the window to detect new files even when minimized to tray
+/// Runs independently of the window to detect new files even when minimized to tray.
+/// Polls every `cloud_scan_interval_minutes`, doubling the wait (up to 8x) while nothing changes.
 async fn background_cloud_poll(app_handle: AppHandle) {
     use std::time::Duration;
 
+    const MAX_IDLE_BACKOFF: u32 = 3; // 2^3 = 8x the configured interval
+
     // Initial delay to let app fully initialize (same as frontend)
     tokio::time::sleep(Duration::from_secs(3)).await;
 
-    println!("[CLOUD BG] Background cloud polling started (5-second interval)");
+    println!("[CLOUD BG] Background cloud polling started");
+
+    let mut idle_polls: u32 = 0;
 
     loop {
         // Get state from app handle
         let state: tauri::State<'_, AppState> = app_handle.state();
 
+        let base_interval = {
+            let minutes = state.config.lock().map(|c| c.cloud_scan_interval_minutes).unwrap_or(5);
+            Duration::from_secs(u64::from(minutes.max(1)) * 60)
+        };
+
         // Check if authenticated
         if !state.gdrive_client.is_authenticated() {
             // Not connected - wait and retry (silent, don't spam logs)
-            tokio::time::sleep(Duration::from_secs(5)).await;
+            tokio::time::sleep(base_interval).await;
             continue;
         }
 
-        // Cloud-only mode: No folder check needed - we monitor entire Drive
-        println!("[CLOUD BG] Polling for changes...");
+        println!("[CLOUD BG] Polling for changes in tracked folders...");
 
         // Perform the actual check
         match background_check_cloud_changes(&app_handle).await {
             Ok(result) => {
-                if result.indexed_count > 0 {
-                    println!("[CLOUD BG] ✓ Indexed {} new items ({} movies, {} TV)",
-                        result.indexed_count, result.movies_count, result.tv_count);
+                if result.indexed_count > 0 || result.removed_count > 0 {
+                    println!("[CLOUD BG] ✓ Indexed {} new items ({} movies, {} TV), removed {}",
+                        result.indexed_count, result.movies_count, result.tv_count, result.removed_count);
 
                     // Emit event to window if it exists
                     if let Some(window) = app_handle.get_window("main") {
                         window.emit("library-updated", ()).ok();
                     }
+                    idle_polls = 0;
                 } else {
                     println!("[CLOUD BG] No new files detected");
+                    idle_polls = (idle_polls + 1).min(MAX_IDLE_BACKOFF);
                 }
             }
             Err(e) => {
                 println!("[CLOUD BG] Poll error: {}", e);
+                idle_polls = (idle_polls + 1).min(MAX_IDLE_BACKOFF);
             }
         }
 
-        // Wait 5 seconds before next poll (same as frontend)
-        tokio::time::sleep(Duration::from_secs(5)).await;
+        let wait = base_interval * 2u32.pow(idle_polls);
+        println!("[CLOUD BG] Next poll in {} minute(s)", wait.as_secs() / 60);
+        tokio::time::sleep(wait).await;
     }
 }
 
@@ -3440,59 +5325,21 @@ async fn background_check_cloud_changes(app_handle: &AppHandle) -> Result<CloudI
             skipped_count: 0,
             movies_count: 0,
             tv_count: 0,
+            removed_count: 0,
             message: "Not connected to Google Drive".to_string(),
         });
     }
 
-    // Get or initialize the changes token
-    let current_token = {
-        let db = state.db.lock().map_err(|e| e.to_string())?;
-        db.get_gdrive_changes_token().map_err(|e| e.to_string())?
-    };
-
-    let page_token = match current_token {
-        Some(token) => {
-            println!("[CLOUD BG] Using existing token: {}...", &token[..token.len().min(20)]);
-            token
-        },
-        None => {
-            // First time - get the start token
-            println!("[CLOUD BG] No token found - initializing changes tracking...");
-            let start_token = state.gdrive_client.get_changes_start_token().await?;
-            println!("[CLOUD BG] Got start token: {}...", &start_token[..start_token.len().min(20)]);
-            let db = state.db.lock().map_err(|e| e.to_string())?;
-            db.set_gdrive_changes_token(&start_token).map_err(|e| e.to_string())?;
-            println!("[CLOUD BG] Token saved - will detect changes on next poll");
-            println!("[CLOUD BG] ══════════════════════════════════════════");
-            return Ok(CloudIndexResult {
-                success: true,
-                indexed_count: 0,
-                skipped_count: 0,
-                movies_count: 0,
-                tv_count: 0,
-                message: "Changes tracking initialized".to_string(),
-            });
-        }
-    };
-
-    // Note: We no longer filter by tracked folders - index all video files in Drive
-    println!("[CLOUD BG] Monitoring entire Google Drive for changes");
-
-    // Get changes since last check
+    // Get changes since last check (every account's My Drive plus tracked Shared Drives)
     let api_start = std::time::Instant::now();
-    let (changed_files, new_token) = state.gdrive_client.get_video_changes(&page_token).await?;
+    let mut changes = poll_change_feeds(&state, "[CLOUD BG]").await?;
+    let subtitle_folders = std::mem::take(&mut changes.subtitle_folders);
     let api_duration = api_start.elapsed();
     println!("[CLOUD BG] Changes API call took {:?}", api_duration);
 
-    // Save the new token immediately
-    {
-        let db = state.db.lock().map_err(|e| e.to_string())?;
-        db.set_gdrive_changes_token(&new_token).map_err(|e| e.to_string())?;
-    }
-
-    if changed_files.is_empty() {
+    if changes.is_empty() {
         let total_duration = start_time.elapsed();
-        println!("[CLOUD BG] No changes detected (total: {:?})", total_duration);
+        println!("[CLOUD BG] No changes in tracked folders (total: {:?})", total_duration);
         println!("[CLOUD BG] ══════════════════════════════════════════");
         return Ok(CloudIndexResult {
             success: true,
@@ -3500,23 +5347,31 @@ async fn background_check_cloud_changes(app_handle: &AppHandle) -> Result<CloudI
             skipped_count: 0,
             movies_count: 0,
             tv_count: 0,
+            removed_count: 0,
             message: "No new files detected".to_string(),
         });
     }
 
-    println!("[CLOUD BG] Detected {} changed video file(s)", changed_files.len());
+    let removed_count = remove_untracked_cloud_files(&state, &changes.removed, "[CLOUD BG]")?;
+    if removed_count > 0 {
+        if let Some(window) = app_handle.get_window("main") {
+            window.emit("library-updated", ()).ok();
+        }
+    }
 
-    // Index all detected video files (no folder filtering)
-    let files_to_index = changed_files;
+    let files_to_index = changes.added;
+    println!("[CLOUD BG] Detected {} new video file(s), removed {}", files_to_index.len(), removed_count);
 
     if files_to_index.is_empty() {
+        index_changed_subtitles(&state, &subtitle_folders, "[CLOUD BG]").await;
         return Ok(CloudIndexResult {
             success: true,
             indexed_count: 0,
             skipped_count: 0,
             movies_count: 0,
             tv_count: 0,
-            message: "No new files detected".to_string(),
+            removed_count,
+            message: format!("Removed {} files", removed_count),
         });
     }
 
@@ -3533,8 +5388,8 @@ async fn background_check_cloud_changes(app_handle: &AppHandle) -> Result<CloudI
     // PHASE 1: Add files immediately without metadata
     let phase1_result = {
         let db_path_clone = db_path.clone();
-        let files_to_index_clone: Vec<_> = files_to_index.iter().map(|f| {
-            (f.id.clone(), f.name.clone(), f.parents.clone())
+        let files_to_index_clone: Vec<_> = files_to_index.iter().map(|(f, account_id)| {
+            (f.id.clone(), f.name.clone(), f.parents.clone(), account_id.clone())
         }).collect();
 
         tokio::task::spawn_blocking(move || {
@@ -3549,7 +5404,7 @@ async fn background_check_cloud_changes(app_handle: &AppHandle) -> Result<CloudI
             let mut tv_count = 0;
             let mut tv_show_cache: std::collections::HashMap<String, i64> = std::collections::HashMap::new();
 
-            for (file_id, file_name, parents) in files_to_index_clone {
+            for (file_id, file_name, parents, account_id) in files_to_index_clone {
                 if db.cloud_file_exists(&file_id) {
                     skipped_count += 1;
                     continue;
@@ -3594,7 +5449,7 @@ async fn background_check_cloud_changes(app_handle: &AppHandle) -> Result<CloudI
                     };
 
                     match db.insert_cloud_episode(&show_title, &file_name, db_show_id, season, episode,
-                        &file_id, &folder_id, None, None, None) {
+                        &file_id, &folder_id, None, None, None, Some(&account_id)) {
                         Ok(ep_id) => {
                             indexed_items.push((ep_id, show_title, file_id, true, Some(season), Some(episode), folder_id));
                             tv_count += 1;
@@ -3603,7 +5458,7 @@ async fn background_check_cloud_changes(app_handle: &AppHandle) -> Result<CloudI
                     }
                 } else {
                     match db.insert_cloud_movie(&parsed.title, parsed.year, None, None,
-                        &file_name, &file_id, &folder_id, None) {
+                        &file_name, &file_id, &folder_id, None, Some(&account_id)) {
                         Ok(movie_id) => {
                             indexed_items.push((movie_id, parsed.title, file_id, false, None, None, folder_id));
                             movies_count += 1;
@@ -3728,6 +5583,8 @@ async fn background_check_cloud_changes(app_handle: &AppHandle) -> Result<CloudI
         });
     }
 
+    index_changed_subtitles(&state, &subtitle_folders, "[CLOUD BG]").await;
+
     let total_duration = start_time.elapsed();
     println!("[CLOUD BG] Poll complete: {} indexed, {} skipped ({:?})", indexed_count, skipped_count, total_duration);
 
@@ -3737,6 +5594,7 @@ async fn background_check_cloud_changes(app_handle: &AppHandle) -> Result<CloudI
         skipped_count,
         movies_count,
         tv_count,
+        removed_count,
         message: format!("Indexed {} new files", indexed_count),
     })
 }
@@ -3932,6 +5790,9 @@ async fn install_update(installer_path: String) -> Result<(), String> {
 
     // Exit the app to allow installer to run
     println!("[UPDATE] Exiting app for update installation...");
+    let _ = transcoder::stop_all_transcodes();
+    dlna::stop();
+    jellyfin::stop();
     std::process::exit(0);
 }
 
@@ -3973,7 +5834,9 @@ fn main() {
         config: Mutex::new(config.clone()),
         is_scanning: Arc::new(AtomicBool::new(false)),
         active_mpv_sessions: Mutex::new(HashMap::new()),
+        active_vlc_sessions: Mutex::new(HashMap::new()),
         gdrive_client: gdrive::GoogleDriveClient::new(),
+        drive_ancestry: tokio::sync::Mutex::new(drive_resolver::FolderAncestry::new()),
     };
 
     // Create system tray menu
@@ -4036,6 +5899,10 @@ fn main() {
                             }
                         }
                         "quit" => {
+                            // Exiting skips destructors - stop FFmpeg explicitly
+                            let _ = transcoder::stop_all_transcodes();
+                            dlna::stop();
+                            jellyfin::stop();
                             std::process::exit(0);
                         }
                         _ => {}
@@ -4094,9 +5961,70 @@ fn main() {
                         println!("[STARTUP] Cleaned up {} expired cache files ({:.1} MB)",
                             deleted, freed as f64 / (1024.0 * 1024.0));
                     }
+
+                    if let Ok(cache_db) = database::Database::new(&db_path) {
+                        let quota_bytes = (config.cloud_cache_quota_mb as u64) * 1024 * 1024;
+                        match cloud_cache::enforce_quota(&cache_db, cache_dir, quota_bytes, &[]) {
+                            Ok((evicted, freed)) if evicted > 0 => println!(
+                                "[STARTUP] Evicted {} cached items to stay under quota ({:.1} MB)",
+                                evicted, freed as f64 / (1024.0 * 1024.0)
+                            ),
+                            Ok(_) => {}
+                            Err(e) => println!("[STARTUP] Warning: Failed to enforce cache quota: {}", e),
+                        }
+                    }
                 }
             }
 
+            prefetch::set_cache_dir(prefetch_cache_dir(&config));
+            transcoder::set_max_sessions(config.max_transcode_sessions as usize);
+
+            // Local proxy so players without header support can stream Drive files
+            let source_resolver = drive_source_resolver(app.handle());
+            if let Err(e) = stream_proxy::start(source_resolver.clone(), subtitle_file_resolver(app.handle())) {
+                println!("[STARTUP] Warning: {}", e);
+            }
+
+            // DLNA server for TVs on the LAN, when enabled
+            if let Err(e) = dlna::apply(dlna_settings(&config)) {
+                println!("[STARTUP] Warning: {}", e);
+            }
+
+            // Jellyfin-compatible API for Jellyfin apps, when enabled
+            if config.jellyfin_enabled && jellyfin_settings(&config).is_none() {
+                println!("[STARTUP] Jellyfin API is enabled but has no password (secrets store locked?)");
+            }
+            if let Err(e) = jellyfin::apply(jellyfin_settings(&config)) {
+                println!("[STARTUP] Warning: {}", e);
+            }
+
+            // Resume offline downloads left queued from the last run
+            let progress_handle = app.handle();
+            let max_downloads = config.max_concurrent_downloads;
+            std::thread::spawn(move || {
+                downloads::init(
+                    source_resolver,
+                    Arc::new(move |progress| {
+                        progress_handle.emit_all("download-progress", progress.clone()).ok();
+                    }),
+                    max_downloads,
+                );
+            });
+
+            // Clean up after a previous run's transcodes and reap idle sessions
+            transcoder::init();
+
+            // Seek preview thumbnails are generated one at a time in the background
+            let trickplay_handle = app.handle();
+            trickplay::init(Arc::new(move |media_id, result| {
+                let payload = serde_json::json!({
+                    "media_id": media_id,
+                    "info": result.as_ref().ok(),
+                    "error": result.as_ref().err(),
+                });
+                trickplay_handle.emit_all("trickplay-ready", payload).ok();
+            }));
+
             // Start background cloud polling (runs independently of window)
             let app_handle_for_polling = app.handle();
             tauri::async_runtime::spawn(async move {
@@ -4205,6 +6133,16 @@ fn main() {
             clear_all_app_data,
             cleanup_missing_metadata,
             repair_file_paths,
+            // Backup & export commands
+            create_backup,
+            restore_backup,
+            export_library,
+            import_library,
+            // Secrets store commands
+            get_secrets_status,
+            unlock_secrets,
+            rotate_secrets,
+            wipe_secrets,
             // Other commands
             delete_media_files,
             delete_series,
@@ -4215,14 +6153,19 @@ fn main() {
             get_scan_status,
             get_resume_info,
             get_media_info,
+            analyze_media,
             get_stream_info,
+            get_external_stream_url,
             update_progress,
             clear_progress,
             fix_match,
             play_with_mpv,
             play_with_vlc,
+            get_player_profiles,
+            play_with_profile,
             get_mpv_status,
             get_active_mpv_sessions,
+            get_active_vlc_sessions,
             get_cached_image,
             get_cached_image_path,
             read_video_chunk,
@@ -4231,7 +6174,20 @@ fn main() {
             check_needs_transcode,
             start_transcode_stream,
             stop_transcode_stream,
+            get_transcode_status,
+            list_transcode_sessions,
+            get_dlna_status,
             get_stream_info_with_transcode,
+            get_subtitles,
+            download_subtitles,
+            get_track_preferences,
+            clear_track_preferences,
+            detect_skip_markers,
+            get_skip_markers,
+            get_trickplay,
+            get_chapters,
+            add_chapter,
+            delete_chapter,
             search_tmdb,
             get_tv_details,
             get_tv_season_episodes,
@@ -4243,6 +6199,8 @@ fn main() {
             // Google Drive commands
             gdrive_is_connected,
             gdrive_get_account_info,
+            gdrive_list_accounts,
+            gdrive_list_shared_drives,
             gdrive_start_auth,
             gdrive_complete_auth,
             gdrive_auth_with_code,
@@ -4260,8 +6218,16 @@ fn main() {
             get_cloud_folders,
             scan_all_cloud_folders,
             check_cloud_changes,
+            // Offline download commands
+            queue_downloads,
+            queue_season_download,
+            get_downloads,
+            pause_download,
+            resume_download,
+            remove_download,
             // Cloud cache commands
             get_cloud_cache_info,
+            set_cache_pinned,
             cleanup_cloud_cache,
             clear_cloud_cache,
             // Auto-update commands
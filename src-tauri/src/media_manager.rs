@@ -8,6 +8,8 @@ use rayon::prelude::*;
 
 use crate::config::Config;
 use crate::database::Database;
+use crate::media_probe;
+use crate::subtitles;
 use crate::tmdb;
 
 const VIDEO_EXTENSIONS: &[&str] = &[".mkv", ".mp4", ".avi", ".mov", ".webm", ".m4v", ".wmv", ".flv", ".ts", ".m2ts"];
@@ -241,6 +243,7 @@ pub fn process_movie(
     api_key: &str,
     image_cache_dir: &str,
     duration: f64,
+    ffprobe_path: Option<&str>,
 ) {
     let mut title = parsed.title.clone();
     let mut year = parsed.year;
@@ -274,7 +277,11 @@ pub fn process_movie(
         duration,
         tmdb_id.as_deref(),
     ) {
-        Ok(_) => println!("Indexed Movie: {}", title),
+        Ok(media_id) => {
+            println!("Indexed Movie: {}", title);
+            probe_indexed_file(db, media_id, ffprobe_path, file_path);
+            link_local_subtitles(db, media_id, file_path);
+        }
         Err(e) => println!("Error indexing movie {}: {}", title, e),
     }
 }
@@ -286,6 +293,7 @@ pub fn process_tv_episode(
     api_key: &str,
     image_cache_dir: &str,
     duration: f64,
+    ffprobe_path: Option<&str>,
 ) {
     println!("[TV] Processing episode: {} S{:02}E{:02} from file: {}",
              parsed.title, parsed.season.unwrap_or(0), parsed.episode.unwrap_or(0), file_path);
@@ -488,11 +496,43 @@ pub fn process_tv_episode(
         episode_overview.as_deref(),
         episode_still.as_deref(),
     ) {
-        Ok(_) => println!("[TV] Indexed Episode: {} - {} (series_id: {})", series_title, ep_title, final_series_id),
+        Ok(media_id) => {
+            println!("[TV] Indexed Episode: {} - {} (series_id: {})", series_title, ep_title, final_series_id);
+            probe_indexed_file(db, media_id, ffprobe_path, file_path);
+            link_local_subtitles(db, media_id, file_path);
+        }
         Err(e) => println!("[TV] Error indexing episode {}: {}", ep_title, e),
     }
 }
 
+/// Read codecs, tracks and chapters of a newly indexed file
+#[allow(dead_code)]
+fn probe_indexed_file(db: &Database, media_id: i64, ffprobe_path: Option<&str>, file_path: &str) {
+    if let Some(ffprobe) = ffprobe_path {
+        if let Err(e) = media_probe::analyze(db, media_id, ffprobe, file_path) {
+            println!("[PROBE] Failed to analyse {}: {}", file_path, e);
+        }
+    }
+}
+
+/// Record the subtitle files beside a newly indexed local video
+pub fn link_local_subtitles(db: &Database, media_id: i64, file_path: &str) -> usize {
+    let found: Vec<(String, String, subtitles::SubtitleInfo)> = subtitles::discover_local(file_path)
+        .into_iter()
+        .map(|(path, info)| {
+            let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            (file_name, path.to_string_lossy().to_string(), info)
+        })
+        .collect();
+    match db.replace_subtitles(media_id, subtitles::SOURCE_LOCAL, false, &found) {
+        Ok(()) => found.len(),
+        Err(e) => {
+            println!("[SUBTITLES] Failed to link subtitles for {}: {}", file_path, e);
+            0
+        }
+    }
+}
+
 /// Fetch metadata for a single episode from TMDB
 fn fetch_single_episode_metadata(
     api_key: &str,
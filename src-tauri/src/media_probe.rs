@@ -0,0 +1,221 @@
+// Media Probe Module
+// Reads container, codecs, tracks and chapters with ffprobe. Local files are probed when
+// they're indexed; Drive files on demand through the stream proxy, which serves ranged
+// reads so ffprobe only pulls the headers it needs.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+
+use crate::database::{Chapter, Database, MediaStream, MediaTechInfo, CHAPTER_EMBEDDED};
+
+/// ffprobe to use: the configured one, or the one next to ffmpeg
+pub fn find_ffprobe(ffprobe_path: Option<&str>, ffmpeg_path: Option<&str>) -> Option<String> {
+    if let Some(path) = ffprobe_path.filter(|p| !p.is_empty() && Path::new(p).exists()) {
+        return Some(path.to_string());
+    }
+
+    let ffmpeg = Path::new(ffmpeg_path.filter(|p| !p.is_empty())?);
+    let name = if cfg!(windows) { "ffprobe.exe" } else { "ffprobe" };
+    let sibling = ffmpeg.parent()?.join(name);
+    sibling.exists().then(|| sibling.to_string_lossy().to_string())
+}
+
+/// Run ffprobe on a file path or URL
+pub fn probe(ffprobe_path: &str, source: &str) -> Result<MediaTechInfo, String> {
+    let mut command = Command::new(ffprobe_path);
+    command
+        .args(["-v", "error", "-print_format", "json"])
+        .args(["-show_format", "-show_streams", "-show_chapters"])
+        .arg(source)
+        .stdin(Stdio::null());
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(0x08000000); // CREATE_NO_WINDOW
+    }
+
+    let output = command.output().map_err(|e| format!("Failed to run ffprobe: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("ffprobe failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    parse(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Probe a media item's file (or proxy URL) and store the results
+pub fn analyze(db: &Database, media_id: i64, ffprobe_path: &str, source: &str) -> Result<MediaTechInfo, String> {
+    let info = probe(ffprobe_path, source)?;
+    db.save_media_tech_info(media_id, &info).map_err(|e| e.to_string())?;
+    println!(
+        "[PROBE] Media {}: {} {} {}x{}, {} audio, {} subtitle, {} chapter(s)",
+        media_id,
+        info.container.as_deref().unwrap_or("?"),
+        info.video_codec.as_deref().unwrap_or("?"),
+        info.width.unwrap_or(0),
+        info.height.unwrap_or(0),
+        info.audio_tracks.len(),
+        info.subtitle_tracks.len(),
+        info.chapters.len()
+    );
+    Ok(info)
+}
+
+/// ffprobe reports numbers as strings ("duration": "2712.345000")
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::String(s) => s.parse().ok(),
+        other => other.as_f64(),
+    }
+}
+
+fn tag(value: &Value, name: &str) -> Option<String> {
+    value["tags"][name]
+        .as_str()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty() && s != "und")
+}
+
+/// ffprobe's format_name lists every alias ("mov,mp4,m4a,3gp,3g2,mj2")
+fn container_name(format_name: &str) -> String {
+    let first = format_name.split(',').next().unwrap_or(format_name);
+    match first {
+        "mov" => "mp4".to_string(),
+        "matroska" => "mkv".to_string(),
+        "mpegts" => "ts".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn hdr_format(stream: &Value) -> Option<String> {
+    let dolby_vision = stream["side_data_list"]
+        .as_array()
+        .is_some_and(|list| list.iter().any(|d| d["side_data_type"].as_str() == Some("DOVI configuration record")));
+    if dolby_vision {
+        return Some("Dolby Vision".to_string());
+    }
+    match stream["color_transfer"].as_str() {
+        Some("smpte2084") => Some("HDR10".to_string()),
+        Some("arib-std-b67") => Some("HLG".to_string()),
+        _ => None,
+    }
+}
+
+fn media_stream(stream: &Value) -> MediaStream {
+    MediaStream {
+        index: stream["index"].as_i64().unwrap_or(0),
+        codec: stream["codec_name"].as_str().unwrap_or("unknown").to_string(),
+        language: tag(stream, "language"),
+        title: tag(stream, "title"),
+        channels: stream["channels"].as_i64(),
+        channel_layout: stream["channel_layout"].as_str().map(|s| s.to_string()),
+        is_default: stream["disposition"]["default"].as_i64() == Some(1),
+        forced: stream["disposition"]["forced"].as_i64() == Some(1),
+    }
+}
+
+/// Parse `ffprobe -print_format json -show_format -show_streams -show_chapters` output
+pub fn parse(json: &str) -> Result<MediaTechInfo, String> {
+    let root: Value = serde_json::from_str(json).map_err(|e| format!("Invalid ffprobe output: {}", e))?;
+    let format = &root["format"];
+    let streams = root["streams"].as_array().cloned().unwrap_or_default();
+
+    let mut info = MediaTechInfo {
+        container: format["format_name"].as_str().map(container_name),
+        duration_seconds: number(&format["duration"]).filter(|d| *d > 0.0),
+        bitrate: number(&format["bit_rate"]).map(|b| b as i64),
+        ..Default::default()
+    };
+
+    // Cover art is stored as a video stream too - skip attached pictures
+    let video = streams.iter().find(|s| {
+        s["codec_type"].as_str() == Some("video") && s["disposition"]["attached_pic"].as_i64() != Some(1)
+    });
+    if let Some(video) = video {
+        info.video_codec = video["codec_name"].as_str().map(|s| s.to_string());
+        info.width = video["width"].as_i64();
+        info.height = video["height"].as_i64();
+        info.hdr = hdr_format(video);
+    }
+
+    for stream in &streams {
+        match stream["codec_type"].as_str() {
+            Some("audio") => info.audio_tracks.push(media_stream(stream)),
+            Some("subtitle") => info.subtitle_tracks.push(media_stream(stream)),
+            _ => {}
+        }
+    }
+
+    info.chapters = root["chapters"]
+        .as_array()
+        .map(|chapters| {
+            chapters
+                .iter()
+                .filter_map(|chapter| {
+                    Some(Chapter {
+                        id: 0,
+                        start_seconds: number(&chapter["start_time"])?,
+                        end_seconds: number(&chapter["end_time"])?,
+                        title: tag(chapter, "title"),
+                        source: CHAPTER_EMBEDDED.to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ffprobe_output() {
+        let json = r#"{
+            "streams": [
+                {"index": 0, "codec_name": "hevc", "codec_type": "video", "width": 3840, "height": 2160,
+                 "color_transfer": "smpte2084", "disposition": {"default": 1, "attached_pic": 0}},
+                {"index": 1, "codec_name": "eac3", "codec_type": "audio", "channels": 6, "channel_layout": "5.1(side)",
+                 "disposition": {"default": 1, "forced": 0}, "tags": {"language": "eng", "title": "Surround"}},
+                {"index": 2, "codec_name": "aac", "codec_type": "audio", "channels": 2,
+                 "disposition": {"default": 0, "forced": 0}, "tags": {"language": "und"}},
+                {"index": 3, "codec_name": "subrip", "codec_type": "subtitle",
+                 "disposition": {"default": 0, "forced": 1}, "tags": {"language": "fre"}},
+                {"index": 4, "codec_name": "mjpeg", "codec_type": "video", "width": 600, "height": 900,
+                 "disposition": {"attached_pic": 1}}
+            ],
+            "chapters": [
+                {"id": 0, "start_time": "0.000000", "end_time": "95.500000", "tags": {"title": "Opening"}},
+                {"id": 1, "start_time": "95.500000", "end_time": "2712.345000"}
+            ],
+            "format": {"format_name": "matroska,webm", "duration": "2712.345000", "bit_rate": "18500000"}
+        }"#;
+
+        let info = parse(json).unwrap();
+        assert_eq!(info.container.as_deref(), Some("mkv"));
+        assert_eq!(info.duration_seconds, Some(2712.345));
+        assert_eq!(info.bitrate, Some(18_500_000));
+        assert_eq!(info.video_codec.as_deref(), Some("hevc"));
+        assert_eq!((info.width, info.height), (Some(3840), Some(2160)));
+        assert_eq!(info.hdr.as_deref(), Some("HDR10"));
+
+        assert_eq!(info.audio_tracks.len(), 2);
+        assert_eq!(info.audio_tracks[0].language.as_deref(), Some("eng"));
+        assert_eq!(info.audio_tracks[0].channels, Some(6));
+        assert!(info.audio_tracks[0].is_default);
+        assert_eq!(info.audio_tracks[1].language, None);
+
+        assert_eq!(info.subtitle_tracks.len(), 1);
+        assert_eq!(info.subtitle_tracks[0].index, 3);
+        assert!(info.subtitle_tracks[0].forced);
+
+        assert_eq!(info.chapters.len(), 2);
+        assert_eq!(info.chapters[0].title.as_deref(), Some("Opening"));
+        assert_eq!(info.chapters[1].start_seconds, 95.5);
+        assert_eq!(info.chapters[1].title, None);
+    }
+}
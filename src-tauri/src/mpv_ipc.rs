@@ -1,5 +1,7 @@
 // MPV Progress Tracking Module
-// Uses a watch-later style approach with a temp file that MPV updates via script
+// Uses a watch-later style approach with a temp file that MPV updates via script.
+// The script also reports audio/subtitle tracks the user switches to, which are
+// remembered per series and passed back as --alang/--slang/--aid/--sid next time.
 
 use std::fs;
 use std::io::Write;
@@ -15,6 +17,26 @@ pub struct MpvProgressInfo {
     pub paused: bool,
     pub eof_reached: bool,
     pub quit_time: Option<i64>,
+    /// The user seeked at some point, so a stream recording has gaps
+    #[serde(default)]
+    pub seeked: bool,
+    /// Only set once the user has changed tracks during playback
+    #[serde(default)]
+    pub tracks: Option<MpvTrackSelection>,
+}
+
+/// Audio/subtitle tracks selected in mpv
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct MpvTrackSelection {
+    pub audio_lang: Option<String>,
+    pub audio_id: Option<i64>,
+    pub sub_lang: Option<String>,
+    pub sub_id: Option<i64>,
+    /// Selected subtitle is an external file (its id depends on which files were passed in)
+    #[serde(default)]
+    pub sub_external: bool,
+    #[serde(default)]
+    pub subs_off: bool,
 }
 
 /// Get the path to the progress tracking directory
@@ -43,6 +65,40 @@ local save_interval = 2 -- seconds
 local last_duration = 0
 local last_position = 0
 
+-- Tracks are only reported once the user changes them, not mpv's initial pick
+local tracks_ready = false
+local tracks_json = "null"
+
+-- Any seek leaves a gap in a --stream-record recording
+local seeked = false
+
+local function json_value(value)
+    if value == nil then return "null" end
+    if type(value) == "number" then return tostring(value) end
+    return '"' .. (tostring(value):gsub('[%c"\\]', '')) .. '"'
+end
+
+local function selected_track(kind)
+    for _, track in ipairs(mp.get_property_native("track-list") or {{}}) do
+        if track.type == kind and track.selected then return track end
+    end
+    return nil
+end
+
+local function record_tracks()
+    local audio = selected_track("audio") or {{}}
+    local sub = selected_track("sub")
+    tracks_json = string.format(
+        '{{"audio_lang":%s,"audio_id":%s,"sub_lang":%s,"sub_id":%s,"sub_external":%s,"subs_off":%s}}',
+        json_value(audio.lang),
+        json_value(audio.id),
+        json_value(sub and sub.lang),
+        json_value(sub and sub.id),
+        (sub and sub.external) and "true" or "false",
+        sub and "false" or "true"
+    )
+end
+
 local function get_progress_data()
     local pos = mp.get_property_number("time-pos")
     local duration = mp.get_property_number("duration")
@@ -72,12 +128,14 @@ local function get_progress_data()
     end
     
     return string.format(
-        '{{"position":%.3f,"duration":%.3f,"paused":%s,"eof_reached":%s,"quit_time":%d}}',
+        '{{"position":%.3f,"duration":%.3f,"paused":%s,"eof_reached":%s,"quit_time":%d,"seeked":%s,"tracks":%s}}',
         p_to_save,
         d_to_save,
         paused and "true" or "false",
         eof and "true" or "false",
-        os.time()
+        os.time(),
+        seeked and "true" or "false",
+        tracks_json
     )
 end
 
@@ -105,7 +163,10 @@ mp.observe_property("pause", "bool", function(name, value)
 end)
 
 -- Save on seek
-mp.register_event("seek", save_progress)
+mp.register_event("seek", function()
+    seeked = true
+    save_progress()
+end)
 
 -- Save on quit - most important!
 mp.register_event("shutdown", function()
@@ -119,10 +180,71 @@ mp.register_event("end-file", function(event)
     save_progress()
 end)
 
+-- Intro/credits detected by StreamVault, passed in as script options
+local intro_start = tonumber(mp.get_opt("streamvault-intro_start") or "")
+local intro_end = tonumber(mp.get_opt("streamvault-intro_end") or "")
+local credits_start = tonumber(mp.get_opt("streamvault-credits_start") or "")
+
+local function add_marker_chapters()
+    local chapters = mp.get_property_native("chapter-list") or {{}}
+    if intro_start and intro_end then
+        table.insert(chapters, {{title = "Intro", time = intro_start}})
+        table.insert(chapters, {{title = "Episode", time = intro_end}})
+    end
+    if credits_start then
+        table.insert(chapters, {{title = "Credits", time = credits_start}})
+    end
+    table.sort(chapters, function(a, b) return a.time < b.time end)
+    mp.set_property_native("chapter-list", chapters)
+end
+
+if intro_start and intro_end then
+    local function in_intro()
+        local pos = mp.get_property_number("time-pos")
+        return pos and pos >= intro_start and pos < intro_end - 1
+    end
+
+    mp.add_key_binding("n", "skip-intro", function()
+        if in_intro() then
+            mp.set_property_number("time-pos", intro_end)
+            mp.osd_message("Skipped intro")
+        end
+    end)
+
+    -- Offer the skip once each time playback enters the intro
+    local offered = false
+    mp.observe_property("time-pos", "number", function()
+        if in_intro() then
+            if not offered then
+                mp.osd_message("Press n to skip intro", 5)
+                offered = true
+            end
+        else
+            offered = false
+        end
+    end)
+end
+
+-- Record track switches made by the user
+local function on_track_change()
+    if tracks_ready then
+        record_tracks()
+        save_progress()
+    end
+end
+mp.observe_property("aid", "native", on_track_change)
+mp.observe_property("sid", "native", on_track_change)
+
 -- Initial save
 mp.register_event("file-loaded", function()
+    if intro_start or credits_start then
+        add_marker_chapters()
+    end
     -- Wait a bit for duration to be available
-    mp.add_timeout(1, save_progress)
+    mp.add_timeout(1, function()
+        tracks_ready = true
+        save_progress()
+    end)
 end)
 
 mp.msg.info("StreamVault progress tracker loaded.")
@@ -130,7 +252,7 @@ mp.msg.info("StreamVault progress tracker loaded.")
 }
 
 /// Create the Lua script file for MPV
-fn create_lua_script(media_id: i64) -> Result<PathBuf, String> {
+pub fn create_lua_script(media_id: i64) -> Result<PathBuf, String> {
     let progress_dir = get_progress_dir();
     fs::create_dir_all(&progress_dir).map_err(|e| format!("Failed to create progress dir: {}", e))?;
     
@@ -176,6 +298,8 @@ pub struct MpvLaunchResult {
     pub final_position: Option<f64>,
     pub final_duration: Option<f64>,
     pub completed: bool,
+    /// Played to the end without seeking, so a stream recording holds the whole file
+    pub played_through: bool,
 }
 
 /// Cloud cache settings for MPV disk caching
@@ -198,7 +322,8 @@ pub fn get_cached_video_path(cache_dir: &str, media_id: i64) -> Option<String> {
     if let Ok(entries) = std::fs::read_dir(&media_cache_dir) {
         for entry in entries.filter_map(|e| e.ok()) {
             let path = entry.path();
-            if path.is_file() {
+            // A partial prefetch of the next episode or an unfinished recording isn't playable on its own
+            if path.is_file() && !crate::prefetch::is_prefetch_file(&path) && !is_recording_file(&path) {
                 // Check if it's a video file (has reasonable size)
                 if let Ok(metadata) = path.metadata() {
                     // Consider files > 1MB as valid cached videos
@@ -213,9 +338,38 @@ pub fn get_cached_video_path(cache_dir: &str, media_id: i64) -> Option<String> {
     None
 }
 
+// mpv's --stream-record target; only a recording that played through becomes the cached video
+const RECORDING_FILE: &str = "recording.mp4";
+const CACHED_VIDEO_FILE: &str = "video.mp4";
+
+/// Whether a file in a media cache folder is a stream recording that hasn't been kept yet
+pub fn is_recording_file(path: &std::path::Path) -> bool {
+    path.file_name().is_some_and(|n| n == RECORDING_FILE)
+}
+
+/// Keep a finished recording as the cached video, or drop it if parts are missing
+/// (playback started mid-file, stopped early or seeked past what was recorded)
+pub fn finish_recording(cache_dir: &str, media_id: i64, complete: bool) {
+    let media_cache_dir = std::path::Path::new(cache_dir).join(format!("media_{}", media_id));
+    let recording = media_cache_dir.join(RECORDING_FILE);
+    if !recording.exists() {
+        return;
+    }
+    if complete && get_cached_video_path(cache_dir, media_id).is_none() {
+        match fs::rename(&recording, media_cache_dir.join(CACHED_VIDEO_FILE)) {
+            Ok(()) => println!("[MPV] Kept complete recording for media {}", media_id),
+            Err(e) => println!("[MPV] Failed to keep recording for media {}: {}", media_id, e),
+        }
+    } else {
+        let _ = fs::remove_file(&recording);
+        println!("[MPV] Discarded partial recording for media {}", media_id);
+    }
+}
+
 /// Launch MPV with progress tracking
 /// `auth_header` is optional and used for cloud files (e.g., "Authorization: Bearer xxx")
 /// `cache_settings` is optional and enables disk-based caching for cloud streams
+/// `extra_args` are passed through (external subtitles, track selection)
 pub fn launch_mpv_with_tracking(
     mpv_path: &str,
     file_or_url: &str,
@@ -223,6 +377,7 @@ pub fn launch_mpv_with_tracking(
     start_position: f64,
     auth_header: Option<&str>,
     cache_settings: Option<&CloudCacheSettings>,
+    extra_args: &[String],
 ) -> Result<u32, String> {
     println!("[MPV] ========== LAUNCHING MPV ==========");
     println!("[MPV] Media ID: {}", media_id);
@@ -286,6 +441,9 @@ pub fn launch_mpv_with_tracking(
     // Add the file/URL to play
     cmd.arg(&actual_source);
 
+    // External subtitles and remembered track choices
+    cmd.args(extra_args);
+
     // Options
     cmd.arg("--save-position-on-quit=no");
     cmd.arg("--keep-open=no");
@@ -302,15 +460,11 @@ pub fn launch_mpv_with_tracking(
                 if let Err(e) = std::fs::create_dir_all(&media_cache_dir) {
                     println!("[MPV] Warning: Failed to create cache dir: {}", e);
                 } else {
-                    // Use stream-record to save the video to disk as it plays
-                    // This creates a persistent cache file that survives MPV exit
-                    let cache_file = media_cache_dir.join("video.mp4");
-
-                    // Only record if we don't already have a cache file
-                    if !cache_file.exists() {
-                        cmd.arg(format!("--stream-record={}", cache_file.to_string_lossy()));
-                        println!("[MPV] Recording stream to: {}", cache_file.display());
-                    }
+                    // Use stream-record to save the video to disk as it plays.
+                    // finish_recording keeps it once playback is over if nothing is missing.
+                    let cache_file = media_cache_dir.join(RECORDING_FILE);
+                    cmd.arg(format!("--stream-record={}", cache_file.to_string_lossy()));
+                    println!("[MPV] Recording stream to: {}", cache_file.display());
 
                     // Also enable memory cache for smooth playback while recording
                     cmd.arg("--cache=yes");
@@ -335,8 +489,13 @@ pub fn launch_mpv_with_tracking(
         }
     }
 
-    // Print full command for debugging
-    println!("[MPV] Command: {:?}", cmd);
+    // Print full command for debugging, minus the Drive access token
+    let logged_args: Vec<String> = cmd
+        .get_args()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .map(|arg| if arg.starts_with("--http-header-fields=") { "--http-header-fields=<redacted>".to_string() } else { arg })
+        .collect();
+    println!("[MPV] Command: {:?} {:?}", cmd.get_program(), logged_args);
 
     // Hide console window on Windows - but keep stderr/stdout for debugging
     #[cfg(windows)]
@@ -390,10 +549,12 @@ pub fn is_mpv_running(pid: u32) -> bool {
 
 /// Monitor MPV playback and update database when it exits
 /// This should be called in a background thread after launching MPV
+/// `on_progress` sees each progress update while MPV is running
 pub fn monitor_mpv_and_save_progress(
     db: &crate::database::Database,
     media_id: i64,
     pid: u32,
+    on_progress: &mut dyn FnMut(&MpvProgressInfo),
 ) -> MpvLaunchResult {
     println!("[MPV] Monitoring MPV process {} for media {}", pid, media_id);
     
@@ -406,6 +567,7 @@ pub fn monitor_mpv_and_save_progress(
             if progress.duration > 0.0 {
                 // Save to database silently
                 let _ = db.update_progress(media_id, progress.position, progress.duration);
+                on_progress(&progress);
             }
         }
     }
@@ -428,11 +590,19 @@ pub fn monitor_mpv_and_save_progress(
             println!("[MPV] Warning: Invalid duration (0.0), skipping final DB update to preserve existing data");
         }
         
+        // Reaching the end credits counts as finished
+        let credits_start = db.get_skip_markers(media_id).ok().and_then(|m| m.credits_start);
         let completed = if progress.duration > 0.0 {
-            (progress.position / progress.duration) >= 0.95 || progress.eof_reached
+            (progress.position / progress.duration) >= 0.95
+                || progress.eof_reached
+                || credits_start.is_some_and(|credits| progress.position >= credits)
         } else {
             false
         };
+
+        if let Some(ref tracks) = progress.tracks {
+            save_track_preferences(db, media_id, tracks);
+        }
         
         MpvLaunchResult {
             success: true,
@@ -440,6 +610,7 @@ pub fn monitor_mpv_and_save_progress(
             final_position: Some(progress.position),
             final_duration: Some(progress.duration),
             completed,
+            played_through: progress.eof_reached && !progress.seeked,
         }
     } else {
         println!("[MPV] No progress data found after MPV exit");
@@ -449,6 +620,7 @@ pub fn monitor_mpv_and_save_progress(
             final_position: None,
             final_duration: None,
             completed: false,
+            played_through: false,
         }
     };
     
@@ -459,6 +631,66 @@ pub fn monitor_mpv_and_save_progress(
     result
 }
 
+/// Remember the user's track choice for the episode's series
+fn save_track_preferences(db: &crate::database::Database, media_id: i64, tracks: &MpvTrackSelection) {
+    let series_id = match db.get_media_by_id(media_id).ok().and_then(|m| m.parent_id) {
+        Some(id) => id,
+        None => return, // Movies have nothing to carry over
+    };
+
+    // Track numbers only matter when there's no language to go by
+    let prefs = crate::database::TrackPreferences {
+        series_id,
+        audio_track: if tracks.audio_lang.is_none() { tracks.audio_id } else { None },
+        audio_lang: tracks.audio_lang.clone(),
+        sub_track: if tracks.sub_lang.is_none() && !tracks.sub_external { tracks.sub_id } else { None },
+        sub_lang: tracks.sub_lang.clone(),
+        subs_off: tracks.subs_off,
+    };
+    match db.set_track_preferences(&prefs) {
+        Ok(_) => println!("[MPV] Saved track preferences for series {}: {:?}", series_id, tracks),
+        Err(e) => println!("[MPV] Failed to save track preferences: {}", e),
+    }
+}
+
+/// Script options that hand detected intro/credits positions to the tracking script
+pub fn skip_marker_args(markers: &crate::database::SkipMarkers) -> Vec<String> {
+    [
+        ("intro_start", markers.intro_start),
+        ("intro_end", markers.intro_end),
+        ("credits_start", markers.credits_start),
+    ]
+    .iter()
+    .filter_map(|(name, value)| value.map(|v| format!("--script-opts-append=streamvault-{}={:.3}", name, v)))
+    .collect()
+}
+
+/// mpv options for a series' remembered tracks, falling back to the global language defaults
+pub fn track_args(
+    prefs: Option<&crate::database::TrackPreferences>,
+    audio_languages: &[String],
+    subtitle_languages: &[String],
+) -> Vec<String> {
+    let audio = prefs
+        .and_then(|p| match (&p.audio_lang, p.audio_track) {
+            (Some(lang), _) => Some(format!("--alang={}", lang)),
+            (None, Some(track)) => Some(format!("--aid={}", track)),
+            (None, None) => None,
+        })
+        .or_else(|| (!audio_languages.is_empty()).then(|| format!("--alang={}", audio_languages.join(","))));
+
+    let subtitles = prefs
+        .and_then(|p| match (p.subs_off, &p.sub_lang, p.sub_track) {
+            (true, _, _) => Some("--sid=no".to_string()),
+            (false, Some(lang), _) => Some(format!("--slang={}", lang)),
+            (false, None, Some(track)) => Some(format!("--sid={}", track)),
+            (false, None, None) => None,
+        })
+        .or_else(|| (!subtitle_languages.is_empty()).then(|| format!("--slang={}", subtitle_languages.join(","))));
+
+    audio.into_iter().chain(subtitles).collect()
+}
+
 /// Poll for MPV progress (for real-time updates if needed)
 pub fn poll_mpv_progress(media_id: i64) -> Option<MpvProgressInfo> {
     read_mpv_progress(media_id)
@@ -0,0 +1,423 @@
+// OpenSubtitles Module
+// Finds subtitles on an OpenSubtitles-compatible REST API and downloads the best match for
+// each preferred language. Lookups go by the OpenSubtitles movie hash first (an exact
+// release match) and fall back to the TMDB id plus season/episode. Providers sit behind
+// the SubtitleProvider trait so the search and selection logic can run against a mock.
+
+use std::fs::File;
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom};
+
+use serde::Deserialize;
+
+use crate::config::Config;
+use crate::gdrive::GoogleDriveClient;
+
+/// Source tag for downloaded subtitles in the subtitle store
+pub const SOURCE: &str = "opensubtitles";
+
+const DEFAULT_API_URL: &str = "https://api.opensubtitles.com/api/v1";
+
+// The hash covers the file size plus this much from each end of the file
+const HASH_CHUNK_SIZE: u64 = 65536;
+
+/// OpenSubtitles movie hash: the file size plus the first and last 64 KiB summed as
+/// little-endian u64 words, wrapping on overflow
+pub fn movie_hash(size: u64, head: &[u8], tail: &[u8]) -> String {
+    let mut hash = size;
+    for chunk in [head, tail] {
+        for word in chunk.chunks_exact(8) {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(word);
+            hash = hash.wrapping_add(u64::from_le_bytes(bytes));
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// Movie hash of a local file (None if it's too small to hash)
+pub fn local_file_hash(path: &str) -> Result<Option<String>, String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let size = file.metadata().map_err(|e| e.to_string())?.len();
+    if size < HASH_CHUNK_SIZE {
+        return Ok(None);
+    }
+
+    let mut head = vec![0u8; HASH_CHUNK_SIZE as usize];
+    let mut tail = vec![0u8; HASH_CHUNK_SIZE as usize];
+    file.read_exact(&mut head).map_err(|e| e.to_string())?;
+    file.seek(SeekFrom::Start(size - HASH_CHUNK_SIZE)).map_err(|e| e.to_string())?;
+    file.read_exact(&mut tail).map_err(|e| e.to_string())?;
+
+    Ok(Some(movie_hash(size, &head, &tail)))
+}
+
+/// Movie hash of a Drive file from two ranged reads, without downloading the rest
+pub async fn drive_file_hash(client: &GoogleDriveClient, account_id: Option<&str>, file_id: &str) -> Result<Option<String>, String> {
+    let size = client
+        .get_file_metadata(account_id, file_id)
+        .await?
+        .size
+        .and_then(|s| s.parse::<u64>().ok())
+        .ok_or_else(|| "Drive did not report the file size".to_string())?;
+    if size < HASH_CHUNK_SIZE {
+        return Ok(None);
+    }
+
+    let head = client.download_range(account_id, file_id, 0, HASH_CHUNK_SIZE - 1).await?;
+    let tail = client.download_range(account_id, file_id, size - HASH_CHUNK_SIZE, size - 1).await?;
+    if head.len() as u64 != HASH_CHUNK_SIZE || tail.len() as u64 != HASH_CHUNK_SIZE {
+        return Err("Drive returned a short range while hashing".to_string());
+    }
+
+    Ok(Some(movie_hash(size, &head, &tail)))
+}
+
+/// What to search for. Episodes put the series' TMDB id in `tmdb_id` and set season/episode.
+#[derive(Debug, Clone, Default)]
+pub struct SubtitleQuery {
+    pub moviehash: Option<String>,
+    pub tmdb_id: Option<String>,
+    pub season: Option<i32>,
+    pub episode: Option<i32>,
+    /// ISO 639-1 codes, most preferred first
+    pub languages: Vec<String>,
+}
+
+/// A downloadable subtitle file from a search
+#[derive(Debug, Clone)]
+pub struct SubtitleCandidate {
+    pub file_id: i64,
+    pub file_name: Option<String>,
+    pub language: String,
+    /// Found by movie hash, so it is synced to this exact release
+    pub hash_match: bool,
+    pub download_count: i64,
+    pub forced: bool,
+    pub sdh: bool,
+}
+
+/// A subtitle search and download API
+pub trait SubtitleProvider {
+    fn search(&self, query: &SubtitleQuery) -> impl Future<Output = Result<Vec<SubtitleCandidate>, String>> + Send;
+
+    /// Fetch a candidate's file, returning its name and contents
+    fn download(&self, candidate: &SubtitleCandidate) -> impl Future<Output = Result<(String, Vec<u8>), String>> + Send;
+}
+
+/// A downloaded subtitle ready for the subtitle store
+#[derive(Debug, Clone)]
+pub struct FetchedSubtitle {
+    pub candidate: SubtitleCandidate,
+    pub file_name: String,
+    pub data: Vec<u8>,
+}
+
+/// Search by hash, falling back to the TMDB id when the hash finds nothing
+pub async fn search(provider: &impl SubtitleProvider, query: &SubtitleQuery) -> Result<Vec<SubtitleCandidate>, String> {
+    if query.moviehash.is_some() {
+        let by_hash = SubtitleQuery { tmdb_id: None, season: None, episode: None, ..query.clone() };
+        let found = provider.search(&by_hash).await?;
+        if !found.is_empty() {
+            println!("[SUBTITLES] {} result(s) by movie hash", found.len());
+            return Ok(found);
+        }
+    }
+
+    if query.tmdb_id.is_some() {
+        let by_id = SubtitleQuery { moviehash: None, ..query.clone() };
+        let found = provider.search(&by_id).await?;
+        println!("[SUBTITLES] {} result(s) by TMDB id", found.len());
+        return Ok(found);
+    }
+
+    Ok(Vec::new())
+}
+
+fn language_matches(candidate: &str, wanted: &str) -> bool {
+    let base = candidate.split('-').next().unwrap_or(candidate);
+    candidate.eq_ignore_ascii_case(wanted) || base.eq_ignore_ascii_case(wanted)
+}
+
+/// Best candidate per preferred language: hash matches first, then full tracks over
+/// forced-only ones, then the most downloaded
+pub fn best_matches<'a>(candidates: &'a [SubtitleCandidate], languages: &[String]) -> Vec<&'a SubtitleCandidate> {
+    languages
+        .iter()
+        .filter_map(|language| {
+            candidates
+                .iter()
+                .filter(|c| language_matches(&c.language, language))
+                .max_by_key(|c| (c.hash_match, !c.forced, c.download_count))
+        })
+        .collect()
+}
+
+/// Search and download the best subtitle for each preferred language
+pub async fn fetch(provider: &impl SubtitleProvider, query: &SubtitleQuery) -> Result<Vec<FetchedSubtitle>, String> {
+    let candidates = search(provider, query).await?;
+
+    let mut fetched = Vec::new();
+    for candidate in best_matches(&candidates, &query.languages) {
+        match provider.download(candidate).await {
+            Ok((file_name, data)) => fetched.push(FetchedSubtitle { candidate: candidate.clone(), file_name, data }),
+            Err(e) => println!("[SUBTITLES] Failed to download {} subtitle {}: {}", candidate.language, candidate.file_id, e),
+        }
+    }
+    Ok(fetched)
+}
+
+/// Client for the OpenSubtitles REST API (or a compatible server)
+pub struct OpenSubtitlesClient {
+    http_client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    data: Vec<SearchResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResult {
+    attributes: SearchAttributes,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchAttributes {
+    #[serde(default)]
+    language: Option<String>,
+    #[serde(default)]
+    download_count: i64,
+    #[serde(default)]
+    hearing_impaired: bool,
+    #[serde(default)]
+    foreign_parts_only: bool,
+    #[serde(default)]
+    moviehash_match: bool,
+    #[serde(default)]
+    files: Vec<SearchFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchFile {
+    file_id: i64,
+    #[serde(default)]
+    file_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DownloadResponse {
+    link: String,
+    #[serde(default)]
+    file_name: Option<String>,
+}
+
+impl OpenSubtitlesClient {
+    pub fn new(config: &Config) -> Result<Self, String> {
+        let api_key = config
+            .opensubtitles_api_key
+            .clone()
+            .filter(|k| !k.is_empty())
+            .ok_or_else(|| "OpenSubtitles API key not set. Please configure it in Settings > Subtitles.".to_string())?;
+        let base_url = config
+            .opensubtitles_url
+            .clone()
+            .filter(|u| !u.is_empty())
+            .unwrap_or_else(|| DEFAULT_API_URL.to_string());
+
+        Ok(Self {
+            http_client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        })
+    }
+
+    fn request(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        builder
+            .header("Api-Key", &self.api_key)
+            .header("User-Agent", format!("StreamVault v{}", env!("CARGO_PKG_VERSION")))
+            .header("Accept", "application/json")
+    }
+
+    async fn search_api(&self, query: &SubtitleQuery) -> Result<Vec<SubtitleCandidate>, String> {
+        // The API asks for sorted, lowercase parameters to avoid redirects
+        let mut languages: Vec<String> = query.languages.iter().map(|l| l.to_lowercase()).collect();
+        languages.sort();
+
+        let mut params: Vec<(&str, String)> = vec![("languages", languages.join(","))];
+        if let Some(ref hash) = query.moviehash {
+            params.push(("moviehash", hash.clone()));
+        }
+        if let Some(ref tmdb_id) = query.tmdb_id {
+            match (query.season, query.episode) {
+                (Some(season), Some(episode)) => {
+                    params.push(("episode_number", episode.to_string()));
+                    params.push(("parent_tmdb_id", tmdb_id.clone()));
+                    params.push(("season_number", season.to_string()));
+                }
+                _ => params.push(("tmdb_id", tmdb_id.clone())),
+            }
+        }
+        params.sort();
+
+        let response = self
+            .request(self.http_client.get(format!("{}/subtitles", self.base_url)))
+            .query(&params)
+            .send()
+            .await
+            .map_err(|e| format!("Subtitle search failed: {}", e))?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(format!("OpenSubtitles error: {}", error_text));
+        }
+
+        let results: SearchResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse subtitle search: {}", e))?;
+
+        Ok(results
+            .data
+            .into_iter()
+            .flat_map(|result| {
+                let attributes = result.attributes;
+                attributes.files.into_iter().map(move |file| SubtitleCandidate {
+                    file_id: file.file_id,
+                    file_name: file.file_name,
+                    language: attributes.language.clone().unwrap_or_default(),
+                    hash_match: attributes.moviehash_match,
+                    download_count: attributes.download_count,
+                    forced: attributes.foreign_parts_only,
+                    sdh: attributes.hearing_impaired,
+                })
+            })
+            .collect())
+    }
+
+    async fn download_api(&self, candidate: &SubtitleCandidate) -> Result<(String, Vec<u8>), String> {
+        let response = self
+            .request(self.http_client.post(format!("{}/download", self.base_url)))
+            .json(&serde_json::json!({ "file_id": candidate.file_id }))
+            .send()
+            .await
+            .map_err(|e| format!("Subtitle download request failed: {}", e))?;
+
+        if !response.status().is_success() {
+            // Download quota errors come back here
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(format!("OpenSubtitles error: {}", error_text));
+        }
+
+        let download: DownloadResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse download response: {}", e))?;
+
+        let data = self
+            .http_client
+            .get(&download.link)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Failed to download subtitle: {}", e))?
+            .bytes()
+            .await
+            .map_err(|e| format!("Failed to read subtitle: {}", e))?
+            .to_vec();
+
+        let file_name = download
+            .file_name
+            .or_else(|| candidate.file_name.clone())
+            .unwrap_or_else(|| format!("{}.srt", candidate.file_id));
+        Ok((file_name, data))
+    }
+}
+
+impl SubtitleProvider for OpenSubtitlesClient {
+    async fn search(&self, query: &SubtitleQuery) -> Result<Vec<SubtitleCandidate>, String> {
+        self.search_api(query).await
+    }
+
+    async fn download(&self, candidate: &SubtitleCandidate) -> Result<(String, Vec<u8>), String> {
+        self.download_api(candidate).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn candidate(file_id: i64, language: &str, hash_match: bool, download_count: i64) -> SubtitleCandidate {
+        SubtitleCandidate {
+            file_id,
+            file_name: None,
+            language: language.to_string(),
+            hash_match,
+            download_count,
+            forced: false,
+            sdh: false,
+        }
+    }
+
+    /// Answers hash searches with `by_hash` and everything else with `by_id`
+    struct MockProvider {
+        by_hash: Vec<SubtitleCandidate>,
+        by_id: Vec<SubtitleCandidate>,
+        queries: Mutex<Vec<SubtitleQuery>>,
+    }
+
+    impl SubtitleProvider for MockProvider {
+        async fn search(&self, query: &SubtitleQuery) -> Result<Vec<SubtitleCandidate>, String> {
+            self.queries.lock().unwrap().push(query.clone());
+            Ok(if query.moviehash.is_some() { self.by_hash.clone() } else { self.by_id.clone() })
+        }
+
+        async fn download(&self, candidate: &SubtitleCandidate) -> Result<(String, Vec<u8>), String> {
+            Ok((format!("{}.srt", candidate.file_id), b"1\n00:00:01,000 --> 00:00:02,000\nHi\n".to_vec()))
+        }
+    }
+
+    #[test]
+    fn hashes_head_and_tail_with_the_file_size() {
+        // Each 8-byte word of 0x01 bytes adds 0x0101010101010101
+        let head = vec![1u8; 16];
+        let tail = vec![0u8; 16];
+        assert_eq!(movie_hash(0x10000, &head, &tail), "0202020202030202");
+    }
+
+    #[test]
+    fn fetch_falls_back_to_tmdb_and_picks_per_language() {
+        let provider = MockProvider {
+            by_hash: Vec::new(),
+            by_id: vec![
+                candidate(1, "en", false, 10),
+                candidate(2, "en", false, 500),
+                candidate(3, "pt-BR", false, 5),
+                candidate(4, "de", false, 900),
+            ],
+            queries: Mutex::new(Vec::new()),
+        };
+        let query = SubtitleQuery {
+            moviehash: Some("0123456789abcdef".to_string()),
+            tmdb_id: Some("1396".to_string()),
+            season: Some(1),
+            episode: Some(2),
+            languages: vec!["en".to_string(), "pt".to_string()],
+        };
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let fetched = runtime.block_on(fetch(&provider, &query)).unwrap();
+        let ids: Vec<i64> = fetched.iter().map(|f| f.candidate.file_id).collect();
+        assert_eq!(ids, vec![2, 3]);
+
+        let queries = provider.queries.lock().unwrap();
+        assert_eq!(queries.len(), 2);
+        assert!(queries[0].tmdb_id.is_none());
+        assert!(queries[1].moviehash.is_none());
+    }
+}
@@ -0,0 +1,316 @@
+// Player Profiles Module
+// External players are described by profiles: an executable plus an argument template.
+// Templates are split on whitespace ("..." keeps spaces together) and support placeholders:
+//   {source}   file path or stream URL
+//   {start}    resume position in whole seconds
+//   {title}    display title
+//   {subtitle} external subtitle file
+//   {headers}  HTTP header line for direct Drive streaming ("Authorization: Bearer ...")
+// Wrap arguments in [ ] to make them optional - the whole group is dropped when any
+// placeholder inside it has no value (e.g. "[-ss {start}]" when not resuming).
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// What a player can do beyond opening a file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlayerCapabilities {
+    /// Can send HTTP headers, so Drive can be streamed directly instead of via the local proxy
+    #[serde(default)]
+    pub auth_headers: bool,
+    /// Can start at a given position
+    #[serde(default)]
+    pub resume: bool,
+    /// Reports playback progress back (needs `kind` "mpv" or "vlc")
+    #[serde(default)]
+    pub progress_tracking: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerProfile {
+    pub id: String,
+    pub name: String,
+    pub executable: String,
+    pub args: String,
+    /// "mpv" or "vlc" for players that speak those tracking protocols, otherwise "generic"
+    #[serde(default = "default_kind")]
+    pub kind: String,
+    #[serde(default)]
+    pub capabilities: PlayerCapabilities,
+    /// Shipped with StreamVault rather than added by the user
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+fn default_kind() -> String {
+    "generic".to_string()
+}
+
+/// Values substituted into an argument template
+#[derive(Debug, Default)]
+pub struct PlayerArgs {
+    pub source: String,
+    pub start: Option<f64>,
+    pub title: Option<String>,
+    pub subtitle: Option<String>,
+    pub headers: Option<String>,
+}
+
+/// Built-in profiles, using the configured mpv/VLC/ffmpeg paths where there are any
+pub fn builtin_profiles(config: &Config) -> Vec<PlayerProfile> {
+    let configured = |path: &Option<String>, fallback: &str| {
+        path.clone().filter(|p| !p.is_empty()).unwrap_or_else(|| fallback.to_string())
+    };
+
+    // ffplay ships alongside ffmpeg
+    let ffplay = config
+        .ffmpeg_path
+        .as_deref()
+        .filter(|p| !p.is_empty())
+        .and_then(|p| std::path::Path::new(p).parent())
+        .map(|dir| dir.join(if cfg!(windows) { "ffplay.exe" } else { "ffplay" }).to_string_lossy().to_string())
+        .unwrap_or_else(|| "ffplay".to_string());
+
+    let iina = if cfg!(target_os = "macos") {
+        "/Applications/IINA.app/Contents/MacOS/iina-cli"
+    } else {
+        "iina-cli"
+    };
+
+    vec![
+        PlayerProfile {
+            id: "mpv".to_string(),
+            name: "mpv".to_string(),
+            executable: configured(&config.mpv_path, "mpv"),
+            args: "{source} [--start={start}] [--force-media-title={title}] [--sub-file={subtitle}] [--http-header-fields={headers}]".to_string(),
+            kind: "mpv".to_string(),
+            capabilities: PlayerCapabilities { auth_headers: true, resume: true, progress_tracking: true },
+            builtin: true,
+        },
+        PlayerProfile {
+            id: "vlc".to_string(),
+            name: "VLC".to_string(),
+            executable: configured(&config.vlc_path, "vlc"),
+            args: "{source} [:start-time={start}] [--meta-title={title}] [--sub-file={subtitle}]".to_string(),
+            kind: "vlc".to_string(),
+            capabilities: PlayerCapabilities { auth_headers: false, resume: true, progress_tracking: true },
+            builtin: true,
+        },
+        PlayerProfile {
+            id: "iina".to_string(),
+            name: "IINA".to_string(),
+            executable: iina.to_string(),
+            args: "{source} [--mpv-start={start}] [--mpv-force-media-title={title}] [--mpv-sub-file={subtitle}] [--mpv-http-header-fields={headers}]".to_string(),
+            kind: default_kind(),
+            capabilities: PlayerCapabilities { auth_headers: true, resume: true, progress_tracking: false },
+            builtin: true,
+        },
+        PlayerProfile {
+            id: "ffplay".to_string(),
+            name: "ffplay".to_string(),
+            executable: ffplay,
+            args: "[-ss {start}] [-window_title {title}] [-headers {headers}] {source}".to_string(),
+            kind: default_kind(),
+            capabilities: PlayerCapabilities { auth_headers: true, resume: true, progress_tracking: false },
+            builtin: true,
+        },
+    ]
+}
+
+/// Built-ins followed by the user's own profiles (a user profile with a built-in id replaces it)
+pub fn all_profiles(config: &Config) -> Vec<PlayerProfile> {
+    let mut profiles: Vec<PlayerProfile> = builtin_profiles(config)
+        .into_iter()
+        .filter(|b| !config.player_profiles.iter().any(|p| p.id == b.id))
+        .collect();
+    profiles.extend(config.player_profiles.iter().cloned().map(|mut p| {
+        p.builtin = false;
+        p
+    }));
+    profiles
+}
+
+pub fn find_profile(config: &Config, id: &str) -> Option<PlayerProfile> {
+    all_profiles(config).into_iter().find(|p| p.id == id)
+}
+
+/// Whether the profile's executable exists (as a path or on PATH)
+pub fn is_available(profile: &PlayerProfile) -> bool {
+    let path = std::path::Path::new(&profile.executable);
+    if path.components().count() > 1 {
+        return path.exists();
+    }
+
+    std::env::var_os("PATH")
+        .map(|paths| {
+            std::env::split_paths(&paths).any(|dir| {
+                dir.join(&profile.executable).exists()
+                    || (cfg!(windows) && dir.join(format!("{}.exe", profile.executable)).exists())
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// Split a template into argument groups. Bracketed groups are optional.
+fn parse_template(template: &str) -> Result<Vec<(bool, Vec<String>)>, String> {
+    let mut groups: Vec<(bool, Vec<String>)> = Vec::new();
+    let mut current = String::new();
+    let mut has_token = false;
+    let mut in_quotes = false;
+    let mut optional: Option<Vec<String>> = None;
+
+    fn finish_token(current: &mut String, has_token: &mut bool, optional: &mut Option<Vec<String>>, groups: &mut Vec<(bool, Vec<String>)>) {
+        if *has_token {
+            let token = std::mem::take(current);
+            match optional {
+                Some(group) => group.push(token),
+                None => groups.push((false, vec![token])),
+            }
+            *has_token = false;
+        }
+    }
+
+    for c in template.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_token = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                finish_token(&mut current, &mut has_token, &mut optional, &mut groups);
+            }
+            '[' if !in_quotes => {
+                finish_token(&mut current, &mut has_token, &mut optional, &mut groups);
+                if optional.is_some() {
+                    return Err("Nested [ ] groups are not supported".to_string());
+                }
+                optional = Some(Vec::new());
+            }
+            ']' if !in_quotes => {
+                finish_token(&mut current, &mut has_token, &mut optional, &mut groups);
+                let group = optional.take().ok_or_else(|| "Unmatched ] in player arguments".to_string())?;
+                groups.push((true, group));
+            }
+            c => {
+                current.push(c);
+                has_token = true;
+            }
+        }
+    }
+
+    if in_quotes {
+        return Err("Unclosed quote in player arguments".to_string());
+    }
+    if optional.is_some() {
+        return Err("Unclosed [ in player arguments".to_string());
+    }
+    finish_token(&mut current, &mut has_token, &mut optional, &mut groups);
+    Ok(groups)
+}
+
+/// Substitute placeholders. Returns None if a placeholder used here has no value.
+fn substitute(arg: &str, values: &[(&str, Option<String>)]) -> Option<String> {
+    let mut result = arg.to_string();
+    for (name, value) in values {
+        let placeholder = format!("{{{}}}", name);
+        if result.contains(&placeholder) {
+            result = result.replace(&placeholder, value.as_deref()?);
+        }
+    }
+    Some(result)
+}
+
+/// Build the command line arguments for a profile
+pub fn render_args(profile: &PlayerProfile, args: &PlayerArgs) -> Result<Vec<String>, String> {
+    let values = [
+        ("source", Some(args.source.clone())),
+        ("start", args.start.filter(|s| *s > 0.0).map(|s| format!("{:.0}", s))),
+        ("title", args.title.clone()),
+        ("subtitle", args.subtitle.clone()),
+        ("headers", args.headers.clone()),
+    ];
+
+    let mut rendered = Vec::new();
+    for (optional, group) in parse_template(&profile.args)? {
+        let substituted: Option<Vec<String>> = group.iter().map(|arg| substitute(arg, &values)).collect();
+        match substituted {
+            Some(group) => rendered.extend(group),
+            None if optional => {}
+            None => return Err(format!("{} needs a value that isn't available: {}", profile.name, group.join(" "))),
+        }
+    }
+
+    if !profile.args.contains("{source}") {
+        rendered.push(args.source.clone());
+    }
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(args: &str) -> PlayerProfile {
+        PlayerProfile {
+            id: "custom".to_string(),
+            name: "Custom".to_string(),
+            executable: "player".to_string(),
+            args: args.to_string(),
+            kind: default_kind(),
+            capabilities: PlayerCapabilities::default(),
+            builtin: false,
+        }
+    }
+
+    fn args(start: Option<f64>) -> PlayerArgs {
+        PlayerArgs {
+            source: "/media/My Movie.mkv".to_string(),
+            start,
+            title: Some("My Movie".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parses_quotes_and_optional_groups() {
+        let groups = parse_template(r#"--fs  --title="{title}" "" [-ss {start} --pause] "[not a group]""#).unwrap();
+        let expected: Vec<(bool, Vec<String>)> = vec![
+            (false, vec!["--fs".into()]),
+            (false, vec!["--title={title}".into()]),
+            (false, vec!["".into()]),
+            (true, vec!["-ss".into(), "{start}".into(), "--pause".into()]),
+            (false, vec!["[not a group]".into()]),
+        ];
+        assert_eq!(groups, expected);
+        assert_eq!(parse_template("   ").unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn rejects_malformed_templates() {
+        assert!(parse_template("[a [b]]").is_err());
+        assert!(parse_template("a ]").is_err());
+        assert!(parse_template("[a {start}").is_err());
+        assert!(parse_template("--title \"{title}").is_err());
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        let template = profile("--title {title} [--start={start}] [--sub-file={subtitle}] {source}");
+        assert_eq!(
+            render_args(&template, &args(Some(61.6))).unwrap(),
+            vec!["--title", "My Movie", "--start=62", "/media/My Movie.mkv"]
+        );
+        // Optional groups drop out without a value, and a zero start isn't a resume
+        assert_eq!(
+            render_args(&template, &args(Some(0.0))).unwrap(),
+            vec!["--title", "My Movie", "/media/My Movie.mkv"]
+        );
+    }
+
+    #[test]
+    fn appends_the_source_and_requires_mandatory_values() {
+        assert_eq!(render_args(&profile("--fs"), &args(None)).unwrap(), vec!["--fs", "/media/My Movie.mkv"]);
+        assert!(render_args(&profile("{source} --sub-file={subtitle}"), &args(None)).is_err());
+    }
+}
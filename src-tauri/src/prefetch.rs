@@ -0,0 +1,407 @@
+// Next Episode Prefetch Module
+// While a cloud episode plays, the start (or all) of the next episode is fetched into the
+// stream cache so switching to it doesn't wait on Drive. A partial fetch stays in
+// media_{id}/prefetch.bin and the stream proxy serves those bytes from disk before falling back
+// to Drive for the rest; a whole-file fetch is moved into place as the cached video that
+// `get_cached_video_path` hands straight to mpv.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cloud_cache;
+use crate::database;
+use crate::mpv_ipc;
+use crate::stream_proxy::{self, SourceResolver};
+
+const HEAD_FILE: &str = "prefetch.bin";
+const INFO_FILE: &str = "prefetch.json";
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Let the current episode fill its own buffer before competing with it for bandwidth
+pub const START_AFTER_SECONDS: f64 = 60.0;
+
+// Used to turn minutes into bytes when neither episode's duration is known yet
+const ASSUMED_EPISODE_SECONDS: f64 = 45.0 * 60.0;
+
+/// What to fetch for the next episode
+pub struct PrefetchJob {
+    pub media_id: i64,
+    pub title: String,
+    pub cache_dir: String,
+    /// Minutes of playback to fetch, 0 for the whole file
+    pub minutes: u32,
+    /// Episode length used to size a partial fetch
+    pub duration_seconds: Option<f64>,
+    /// Bandwidth cap in KB/s, 0 for unlimited
+    pub max_kbps: u32,
+    /// Extension for the cached video once the whole file is on disk
+    pub extension: String,
+    /// Media that must survive the quota check afterwards (what's playing now)
+    pub keep: Vec<i64>,
+    pub quota_bytes: u64,
+}
+
+/// Sidecar describing the full file the prefetched head belongs to
+#[derive(Serialize, Deserialize)]
+struct PrefetchInfo {
+    total_bytes: u64,
+    content_type: Option<String>,
+}
+
+/// Prefetched leading bytes of a media item, as served by the stream proxy
+pub struct LocalHead {
+    pub path: PathBuf,
+    pub available_bytes: u64,
+    pub total_bytes: u64,
+    pub content_type: Option<String>,
+}
+
+lazy_static::lazy_static! {
+    static ref CACHE_DIR: Mutex<Option<String>> = Mutex::new(None);
+    // Stop flags for prefetches that are currently running, by media id
+    static ref ACTIVE: Mutex<HashMap<i64, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+}
+
+/// Point the proxy lookup at the current stream cache (None when the cache is off)
+pub fn set_cache_dir(cache_dir: Option<String>) {
+    if let Ok(mut dir) = CACHE_DIR.lock() {
+        *dir = cache_dir;
+    }
+}
+
+/// Whether a file in a media cache folder belongs to an (unfinished) prefetch
+pub fn is_prefetch_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n == HEAD_FILE || n == INFO_FILE)
+}
+
+/// Prefetched bytes available for a media item, if any
+pub fn local_head(media_id: i64) -> Option<LocalHead> {
+    let cache_dir = CACHE_DIR.lock().ok()?.clone()?;
+    let dir = cloud_cache::media_cache_dir(&cache_dir, media_id);
+    let info = read_info(&dir)?;
+    let path = dir.join(HEAD_FILE);
+    let available_bytes = fs::metadata(&path).ok()?.len().min(info.total_bytes);
+    if available_bytes == 0 {
+        return None;
+    }
+
+    Some(LocalHead {
+        path,
+        available_bytes,
+        total_bytes: info.total_bytes,
+        content_type: info.content_type,
+    })
+}
+
+/// Start prefetching in the background. Returns false if it's already running.
+pub fn start(job: PrefetchJob, resolver: SourceResolver) -> bool {
+    let stop = Arc::new(AtomicBool::new(false));
+    match ACTIVE.lock() {
+        Ok(mut active) => {
+            if active.contains_key(&job.media_id) {
+                return false;
+            }
+            active.insert(job.media_id, stop.clone());
+        }
+        Err(_) => return false,
+    }
+
+    std::thread::spawn(move || {
+        let media_id = job.media_id;
+        match run(&job, &resolver, &stop) {
+            Ok(fetched) if fetched > 0 => {
+                println!("[PREFETCH] ✓ {} ready ({:.1} MB fetched)", job.title, fetched as f64 / (1024.0 * 1024.0));
+                update_cache_index(&job);
+            }
+            Ok(_) => {}
+            Err(e) => println!("[PREFETCH] ✗ {}: {}", job.title, e),
+        }
+        if let Ok(mut active) = ACTIVE.lock() {
+            active.remove(&media_id);
+        }
+    });
+    true
+}
+
+/// Stop a running prefetch (e.g. the episode is about to play and the proxy takes over)
+pub fn cancel(media_id: i64) {
+    if let Ok(active) = ACTIVE.lock() {
+        if let Some(flag) = active.get(&media_id) {
+            flag.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+fn read_info(dir: &Path) -> Option<PrefetchInfo> {
+    let content = fs::read_to_string(dir.join(INFO_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Fetch up to the target into prefetch.bin. Returns the number of new bytes written.
+fn run(job: &PrefetchJob, resolver: &SourceResolver, stop: &AtomicBool) -> Result<u64, String> {
+    if mpv_ipc::get_cached_video_path(&job.cache_dir, job.media_id).is_some() {
+        return Ok(0); // Already fully cached
+    }
+
+    let dir = cloud_cache::media_cache_dir(&job.cache_dir, job.media_id);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create cache folder: {}", e))?;
+    let head = dir.join(HEAD_FILE);
+
+    let mut offset = fs::metadata(&head).map(|m| m.len()).unwrap_or(0);
+    if let Some(info) = read_info(&dir) {
+        if offset >= target_bytes(job, info.total_bytes) {
+            return Ok(0);
+        }
+    }
+
+    let client = reqwest::blocking::Client::builder()
+        .timeout(None)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let range = format!("bytes={}-", offset);
+    let mut response = stream_proxy::send_upstream(&client, resolver, job.media_id, Some(&range))?;
+
+    let total = match response.status().as_u16() {
+        206 => response
+            .headers()
+            .get("Content-Range")
+            .and_then(|v| v.to_str().ok())
+            .and_then(stream_proxy::parse_content_range)
+            .map(|(_, end)| end + 1)
+            .ok_or_else(|| "Drive sent no Content-Range".to_string())?,
+        200 => {
+            // Range ignored - the body starts from byte 0
+            offset = 0;
+            fs::File::create(&head).map_err(|e| e.to_string())?;
+            response.content_length().ok_or_else(|| "Drive sent no Content-Length".to_string())?
+        }
+        416 => offset, // Nothing left to fetch
+        status => return Err(format!("Drive returned {}", status)),
+    };
+
+    let info = PrefetchInfo {
+        total_bytes: total,
+        content_type: response
+            .headers()
+            .get("Content-Type")
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string()),
+    };
+    let info_json = serde_json::to_string(&info).map_err(|e| e.to_string())?;
+    fs::write(dir.join(INFO_FILE), info_json).map_err(|e| e.to_string())?;
+
+    let target = target_bytes(job, total);
+    println!(
+        "[PREFETCH] Fetching {} ({:.1} of {:.1} MB)",
+        job.title,
+        target as f64 / (1024.0 * 1024.0),
+        total as f64 / (1024.0 * 1024.0)
+    );
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&head)
+        .map_err(|e| format!("Failed to open {}: {}", head.display(), e))?;
+
+    let started = Instant::now();
+    let start_offset = offset;
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    while offset < target {
+        if stop.load(Ordering::SeqCst) {
+            println!("[PREFETCH] Stopped {} at {} bytes", job.title, offset);
+            break;
+        }
+
+        let wanted = (target - offset).min(CHUNK_SIZE as u64) as usize;
+        let n = response.read(&mut buffer[..wanted]).map_err(|e| e.to_string())?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buffer[..n]).map_err(|e| e.to_string())?;
+        offset += n as u64;
+
+        throttle(offset - start_offset, started, job.max_kbps);
+    }
+    file.flush().map_err(|e| e.to_string())?;
+    drop(file);
+
+    // The whole file is here - promote it to the regular cached video
+    if offset >= total && !stop.load(Ordering::SeqCst) {
+        let video = dir.join(format!("video.{}", job.extension));
+        fs::rename(&head, &video).map_err(|e| format!("Failed to move prefetched file into place: {}", e))?;
+        fs::remove_file(dir.join(INFO_FILE)).ok();
+    }
+
+    Ok(offset - start_offset)
+}
+
+/// Bytes covering the requested minutes of playback (or the whole file)
+fn target_bytes(job: &PrefetchJob, total: u64) -> u64 {
+    if job.minutes == 0 {
+        return total;
+    }
+    let duration = job.duration_seconds.filter(|d| *d > 0.0).unwrap_or(ASSUMED_EPISODE_SECONDS);
+    let fraction = (job.minutes as f64 * 60.0 / duration).min(1.0);
+    ((total as f64) * fraction).ceil() as u64
+}
+
+/// Sleep long enough to keep the average rate under the cap
+fn throttle(fetched: u64, started: Instant, max_kbps: u32) {
+    if max_kbps == 0 {
+        return;
+    }
+    let expected = Duration::from_secs_f64(fetched as f64 / (max_kbps as f64 * 1024.0));
+    let elapsed = started.elapsed();
+    if expected > elapsed {
+        std::thread::sleep(expected - elapsed);
+    }
+}
+
+/// Record the new cache data and trim the cache back under quota
+fn update_cache_index(job: &PrefetchJob) {
+    let db = match database::Database::new(&database::get_database_path()) {
+        Ok(db) => db,
+        Err(e) => {
+            println!("[PREFETCH] Failed to open database: {}", e);
+            return;
+        }
+    };
+
+    let mut keep = job.keep.clone();
+    keep.push(job.media_id);
+    let result = cloud_cache::touch(&db, &job.cache_dir, job.media_id)
+        .and_then(|_| cloud_cache::enforce_quota(&db, &job.cache_dir, job.quota_bytes, &keep));
+    if let Err(e) = result {
+        println!("[PREFETCH] Failed to update cache index: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_proxy::UpstreamSource;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("streamvault-prefetch-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn job(cache_dir: &Path, minutes: u32, duration_seconds: Option<f64>) -> PrefetchJob {
+        PrefetchJob {
+            media_id: 7,
+            title: "S01E02".to_string(),
+            cache_dir: cache_dir.to_string_lossy().to_string(),
+            minutes,
+            duration_seconds,
+            max_kbps: 0,
+            extension: "mkv".to_string(),
+            keep: Vec::new(),
+            quota_bytes: 0,
+        }
+    }
+
+    /// Serve `data` once, as Drive does (206 for `bytes=N-`) or ignoring the Range (200),
+    /// and hand back the Range it was asked for
+    fn serve_once(data: Vec<u8>, honour_range: bool) -> (SourceResolver, std::thread::JoinHandle<Option<String>>) {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file", server.server_addr());
+        let handle = std::thread::spawn(move || {
+            let request = server.recv().unwrap();
+            let range = request
+                .headers()
+                .iter()
+                .find(|h| h.field.equiv("Range"))
+                .map(|h| h.value.as_str().to_string());
+            let start = range
+                .as_deref()
+                .and_then(|r| r.strip_prefix("bytes="))
+                .and_then(|r| r.trim_end_matches('-').parse::<usize>().ok())
+                .filter(|_| honour_range);
+            let response = match start {
+                Some(start) => {
+                    let content_range = format!("bytes {}-{}/{}", start, data.len() - 1, data.len());
+                    tiny_http::Response::from_data(data[start..].to_vec())
+                        .with_status_code(206)
+                        .with_header(tiny_http::Header::from_bytes("Content-Range", content_range).unwrap())
+                }
+                None => tiny_http::Response::from_data(data),
+            };
+            // Drive sends a Content-Length; tiny_http would switch large bodies to chunked
+            request.respond(response.with_chunked_threshold(usize::MAX)).unwrap();
+            range
+        });
+        let resolver: SourceResolver = Arc::new(move |_, _| {
+            Ok(UpstreamSource { url: url.clone(), access_token: "token".to_string() })
+        });
+        (resolver, handle)
+    }
+
+    fn episode_bytes() -> Vec<u8> {
+        (0..300_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    #[test]
+    fn sizes_the_fetch_from_the_episode_length() {
+        let dir = Path::new("/cache");
+        assert_eq!(target_bytes(&job(dir, 0, Some(2400.0)), 1_000_000), 1_000_000);
+        assert_eq!(target_bytes(&job(dir, 10, Some(2400.0)), 1_000_000), 250_000);
+        // Unknown length: assume a 45 minute episode
+        assert_eq!(target_bytes(&job(dir, 9, None), 1_000_000), 200_000);
+        assert_eq!(target_bytes(&job(dir, 60, Some(2400.0)), 1_000_000), 1_000_000);
+    }
+
+    #[test]
+    fn fetches_the_head_then_promotes_the_whole_file() {
+        let cache_dir = test_dir("head");
+        let data = episode_bytes();
+        let media_dir = cloud_cache::media_cache_dir(&cache_dir.to_string_lossy(), 7);
+
+        // First 10 of 40 minutes
+        let (resolver, server) = serve_once(data.clone(), true);
+        let fetched = run(&job(&cache_dir, 10, Some(2400.0)), &resolver, &AtomicBool::new(false));
+        assert_eq!(server.join().unwrap().as_deref(), Some("bytes=0-"));
+        assert_eq!(fetched, Ok(75_000));
+        assert_eq!(fs::read(media_dir.join(HEAD_FILE)).unwrap(), &data[..75_000]);
+        assert_eq!(read_info(&media_dir).map(|info| info.total_bytes), Some(300_000));
+
+        // The rest picks up where the head ends and becomes the cached video
+        let (resolver, server) = serve_once(data.clone(), true);
+        let fetched = run(&job(&cache_dir, 0, Some(2400.0)), &resolver, &AtomicBool::new(false));
+        assert_eq!(server.join().unwrap().as_deref(), Some("bytes=75000-"));
+        assert_eq!(fetched, Ok(225_000));
+        assert_eq!(fs::read(media_dir.join("video.mkv")).unwrap(), data);
+        assert!(!media_dir.join(HEAD_FILE).exists());
+        assert!(!media_dir.join(INFO_FILE).exists());
+        fs::remove_dir_all(&cache_dir).ok();
+    }
+
+    #[test]
+    fn starts_over_when_the_range_is_ignored() {
+        let cache_dir = test_dir("restart");
+        let data = episode_bytes();
+        let media_dir = cloud_cache::media_cache_dir(&cache_dir.to_string_lossy(), 7);
+        fs::create_dir_all(&media_dir).unwrap();
+        fs::write(media_dir.join(HEAD_FILE), vec![0xAAu8; 50_000]).unwrap();
+        fs::write(media_dir.join(INFO_FILE), r#"{"total_bytes":300000,"content_type":null}"#).unwrap();
+
+        let (resolver, server) = serve_once(data.clone(), false);
+        let fetched = run(&job(&cache_dir, 0, None), &resolver, &AtomicBool::new(false));
+        assert_eq!(server.join().unwrap().as_deref(), Some("bytes=50000-"));
+        // A 200 body starts at byte 0, so the old head is replaced rather than appended to
+        assert_eq!(fetched, Ok(300_000));
+        assert_eq!(fs::read(media_dir.join("video.mkv")).unwrap(), data);
+        fs::remove_dir_all(&cache_dir).ok();
+    }
+}
@@ -0,0 +1,522 @@
+//! Secrets store
+//! Keeps OAuth tokens and API keys out of plaintext files. Uses the OS keyring when one is
+//! available, otherwise an AES-256-GCM encrypted file whose key is derived (PBKDF2-SHA256)
+//! from a user passphrase or the machine id.
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::database::get_app_data_dir;
+
+const KEYRING_SERVICE: &str = "StreamVault";
+const PBKDF2_ROUNDS: u32 = 210_000;
+const FILE_FORMAT_VERSION: u32 = 1;
+
+lazy_static::lazy_static! {
+    static ref STORE: Mutex<Option<SecretStore>> = Mutex::new(None);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Backend {
+    Keyring,
+    EncryptedFile,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum KeySource {
+    MachineId,
+    Passphrase,
+}
+
+/// On-disk layout of the encrypted fallback file
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    version: u32,
+    key_source: KeySource,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+struct SecretStore {
+    backend: Backend,
+    /// Derived key for the encrypted file backend (None while a passphrase store is locked)
+    file_key: Option<[u8; 32]>,
+    file_salt: Vec<u8>,
+    key_source: KeySource,
+}
+
+/// Summary of the secrets store for the settings UI
+#[derive(Debug, Clone, Serialize)]
+pub struct SecretsStatus {
+    pub backend: String,
+    pub passphrase_protected: bool,
+    pub locked: bool,
+    pub secret_count: usize,
+}
+
+fn secrets_file_path() -> PathBuf {
+    get_app_data_dir().join("secrets.enc")
+}
+
+/// Names stored in the keyring (the keyring can't enumerate its own entries)
+fn keyring_index_path() -> PathBuf {
+    get_app_data_dir().join("secrets_index.json")
+}
+
+fn b64() -> base64::engine::GeneralPurpose {
+    base64::engine::general_purpose::STANDARD
+}
+
+fn derive_key(secret: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(secret.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    key
+}
+
+fn machine_secret() -> Result<String, String> {
+    machine_uid::get().map_err(|e| format!("Could not read machine id, set a passphrase instead: {}", e))
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+fn keyring_available() -> bool {
+    match keyring::Entry::new(KEYRING_SERVICE, "__probe__").and_then(|e| e.get_password()) {
+        Ok(_) | Err(keyring::Error::NoEntry) => true,
+        Err(e) => {
+            println!("[SECRETS] OS keyring unavailable ({}), using encrypted file", e);
+            false
+        }
+    }
+}
+
+fn read_encrypted_file() -> Result<Option<EncryptedFile>, String> {
+    let path = secrets_file_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json = fs::read_to_string(&path).map_err(|e| format!("Failed to read secrets file: {}", e))?;
+    serde_json::from_str(&json)
+        .map(Some)
+        .map_err(|e| format!("Corrupt secrets file: {}", e))
+}
+
+/// Encrypt secrets into the on-disk layout
+fn seal(
+    key: &[u8; 32],
+    key_source: KeySource,
+    salt: &[u8],
+    secrets: &HashMap<String, String>,
+) -> Result<EncryptedFile, String> {
+    let plaintext = serde_json::to_vec(secrets).map_err(|e| e.to_string())?;
+    let nonce = random_bytes(12);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+        .map_err(|_| "Failed to encrypt secrets".to_string())?;
+    Ok(EncryptedFile {
+        version: FILE_FORMAT_VERSION,
+        key_source,
+        salt: b64().encode(salt),
+        nonce: b64().encode(&nonce),
+        ciphertext: b64().encode(&ciphertext),
+    })
+}
+
+/// Decrypt the on-disk layout; fails on a wrong key
+fn unseal(key: &[u8; 32], file: &EncryptedFile) -> Result<HashMap<String, String>, String> {
+    let nonce = b64().decode(&file.nonce).map_err(|e| e.to_string())?;
+    let ciphertext = b64().decode(&file.ciphertext).map_err(|e| e.to_string())?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| "Failed to decrypt secrets (wrong passphrase or different machine)".to_string())?;
+    serde_json::from_slice(&plaintext).map_err(|e| format!("Corrupt secrets payload: {}", e))
+}
+
+impl SecretStore {
+    /// Pick a backend: an existing passphrase file always wins (the user opted into it),
+    /// then the OS keyring, then a machine-id encrypted file.
+    fn open() -> Result<Self, String> {
+        if let Some(file) = read_encrypted_file()? {
+            let salt = b64().decode(&file.salt).map_err(|e| e.to_string())?;
+            let file_key = match file.key_source {
+                KeySource::MachineId => Some(derive_key(&machine_secret()?, &salt)),
+                KeySource::Passphrase => None,
+            };
+            if file.key_source == KeySource::Passphrase || !keyring_available() {
+                return Ok(Self {
+                    backend: Backend::EncryptedFile,
+                    file_key,
+                    file_salt: salt,
+                    key_source: file.key_source,
+                });
+            }
+            // A machine-id file left over from a time without a keyring: move it over
+            let mut legacy = Self {
+                backend: Backend::EncryptedFile,
+                file_key,
+                file_salt: salt,
+                key_source: KeySource::MachineId,
+            };
+            let secrets = legacy.load_all()?;
+            let mut store = Self::keyring();
+            store.store_all(&secrets)?;
+            legacy.clear()?;
+            println!("[SECRETS] Moved {} secret(s) from encrypted file to OS keyring", secrets.len());
+            return Ok(store);
+        }
+
+        if keyring_available() {
+            Ok(Self::keyring())
+        } else {
+            Self::machine_file()
+        }
+    }
+
+    fn keyring() -> Self {
+        Self {
+            backend: Backend::Keyring,
+            file_key: None,
+            file_salt: Vec::new(),
+            key_source: KeySource::MachineId,
+        }
+    }
+
+    fn machine_file() -> Result<Self, String> {
+        let salt = random_bytes(16);
+        Ok(Self {
+            backend: Backend::EncryptedFile,
+            file_key: Some(derive_key(&machine_secret()?, &salt)),
+            file_salt: salt,
+            key_source: KeySource::MachineId,
+        })
+    }
+
+    fn passphrase_file(passphrase: &str) -> Self {
+        let salt = random_bytes(16);
+        Self {
+            backend: Backend::EncryptedFile,
+            file_key: Some(derive_key(passphrase, &salt)),
+            file_salt: salt,
+            key_source: KeySource::Passphrase,
+        }
+    }
+
+    fn key(&self) -> Result<&[u8; 32], String> {
+        self.file_key
+            .as_ref()
+            .ok_or_else(|| "Secrets store is locked, enter your passphrase to unlock it".to_string())
+    }
+
+    // ---- Keyring backend ----
+
+    fn keyring_index(&self) -> Vec<String> {
+        fs::read_to_string(keyring_index_path())
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_keyring_index(&self, names: &[String]) -> Result<(), String> {
+        if names.is_empty() {
+            let _ = fs::remove_file(keyring_index_path());
+            return Ok(());
+        }
+        let json = serde_json::to_string(names).map_err(|e| e.to_string())?;
+        fs::write(keyring_index_path(), json).map_err(|e| format!("Failed to write secrets index: {}", e))
+    }
+
+    fn keyring_entry(name: &str) -> Result<keyring::Entry, String> {
+        keyring::Entry::new(KEYRING_SERVICE, name).map_err(|e| format!("Keyring error: {}", e))
+    }
+
+    // ---- Encrypted file backend ----
+
+    fn decrypt_file(&self) -> Result<HashMap<String, String>, String> {
+        match read_encrypted_file()? {
+            Some(file) => unseal(self.key()?, &file),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    fn encrypt_file(&self, secrets: &HashMap<String, String>) -> Result<(), String> {
+        let path = secrets_file_path();
+        if secrets.is_empty() {
+            let _ = fs::remove_file(&path);
+            return Ok(());
+        }
+        let file = seal(self.key()?, self.key_source, &self.file_salt, secrets)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).ok();
+        }
+        // Write next to the file and rename over it, so a failed write leaves the old one intact
+        let json = serde_json::to_string_pretty(&file).map_err(|e| e.to_string())?;
+        let temp_path = path.with_extension("enc.tmp");
+        fs::write(&temp_path, json).map_err(|e| format!("Failed to write secrets file: {}", e))?;
+        fs::rename(&temp_path, &path).map_err(|e| format!("Failed to write secrets file: {}", e))
+    }
+
+    // ---- Common operations ----
+
+    fn get(&self, name: &str) -> Result<Option<String>, String> {
+        match self.backend {
+            Backend::Keyring => match Self::keyring_entry(name)?.get_password() {
+                Ok(value) => Ok(Some(value)),
+                Err(keyring::Error::NoEntry) => Ok(None),
+                Err(e) => Err(format!("Keyring error: {}", e)),
+            },
+            Backend::EncryptedFile => Ok(self.decrypt_file()?.remove(name)),
+        }
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match self.backend {
+            Backend::Keyring => {
+                Self::keyring_entry(name)?
+                    .set_password(value)
+                    .map_err(|e| format!("Keyring error: {}", e))?;
+                let mut index = self.keyring_index();
+                if !index.iter().any(|n| n == name) {
+                    index.push(name.to_string());
+                    self.save_keyring_index(&index)?;
+                }
+                Ok(())
+            }
+            Backend::EncryptedFile => {
+                let mut secrets = self.decrypt_file()?;
+                secrets.insert(name.to_string(), value.to_string());
+                self.encrypt_file(&secrets)
+            }
+        }
+    }
+
+    fn delete(&mut self, name: &str) -> Result<(), String> {
+        match self.backend {
+            Backend::Keyring => {
+                match Self::keyring_entry(name)?.delete_password() {
+                    Ok(()) | Err(keyring::Error::NoEntry) => {}
+                    Err(e) => return Err(format!("Keyring error: {}", e)),
+                }
+                let index: Vec<String> = self.keyring_index().into_iter().filter(|n| n != name).collect();
+                self.save_keyring_index(&index)
+            }
+            Backend::EncryptedFile => {
+                let mut secrets = self.decrypt_file()?;
+                if secrets.remove(name).is_some() {
+                    self.encrypt_file(&secrets)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn load_all(&self) -> Result<HashMap<String, String>, String> {
+        match self.backend {
+            Backend::Keyring => {
+                let mut secrets = HashMap::new();
+                for name in self.keyring_index() {
+                    if let Some(value) = self.get(&name)? {
+                        secrets.insert(name, value);
+                    }
+                }
+                Ok(secrets)
+            }
+            Backend::EncryptedFile => self.decrypt_file(),
+        }
+    }
+
+    fn store_all(&mut self, secrets: &HashMap<String, String>) -> Result<(), String> {
+        match self.backend {
+            Backend::Keyring => {
+                for (name, value) in secrets {
+                    self.set(name, value)?;
+                }
+                Ok(())
+            }
+            Backend::EncryptedFile => self.encrypt_file(secrets),
+        }
+    }
+
+    /// Remove every secret this backend holds
+    fn clear(&mut self) -> Result<(), String> {
+        match self.backend {
+            Backend::Keyring => {
+                for name in self.keyring_index() {
+                    self.delete(&name)?;
+                }
+                self.save_keyring_index(&[])
+            }
+            Backend::EncryptedFile => {
+                let path = secrets_file_path();
+                if path.exists() {
+                    fs::remove_file(path).map_err(|e| format!("Failed to remove secrets file: {}", e))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn status(&self) -> SecretsStatus {
+        let locked = self.backend == Backend::EncryptedFile && self.file_key.is_none();
+        SecretsStatus {
+            backend: match self.backend {
+                Backend::Keyring => "keyring".to_string(),
+                Backend::EncryptedFile => "encrypted_file".to_string(),
+            },
+            passphrase_protected: self.key_source == KeySource::Passphrase,
+            locked,
+            secret_count: if locked { 0 } else { self.load_all().map(|s| s.len()).unwrap_or(0) },
+        }
+    }
+}
+
+/// Run `f` against the process-wide store, opening it on first use
+fn with_store<T>(f: impl FnOnce(&mut SecretStore) -> Result<T, String>) -> Result<T, String> {
+    let mut guard = STORE.lock().map_err(|e| e.to_string())?;
+    if guard.is_none() {
+        *guard = Some(SecretStore::open()?);
+    }
+    f(guard.as_mut().unwrap())
+}
+
+/// Read a secret by name
+pub fn get_secret(name: &str) -> Result<Option<String>, String> {
+    with_store(|store| store.get(name))
+}
+
+/// Store (or overwrite) a secret
+pub fn set_secret(name: &str, value: &str) -> Result<(), String> {
+    with_store(|store| store.set(name, value))
+}
+
+/// Remove a secret if present
+pub fn delete_secret(name: &str) -> Result<(), String> {
+    with_store(|store| store.delete(name))
+}
+
+/// Unlock a passphrase-protected store for this session
+pub fn unlock(passphrase: &str) -> Result<SecretsStatus, String> {
+    with_store(|store| {
+        if store.key_source != KeySource::Passphrase {
+            return Ok(store.status());
+        }
+        // Without the file there is nothing to check the passphrase against
+        let file = read_encrypted_file()?
+            .ok_or_else(|| "Secrets file is missing, the passphrase cannot be checked".to_string())?;
+        let key = derive_key(passphrase, &store.file_salt);
+        unseal(&key, &file)?;
+        store.file_key = Some(key);
+        Ok(store.status())
+    })
+}
+
+/// Re-encrypt every secret under a fresh key. With a passphrase the secrets move into a
+/// passphrase-protected file; without one they go back to the keyring (or a machine-id file).
+pub fn rotate(new_passphrase: Option<&str>) -> Result<SecretsStatus, String> {
+    with_store(|store| {
+        let secrets = store.load_all()?;
+        let mut next = match new_passphrase.filter(|p| !p.is_empty()) {
+            Some(passphrase) => SecretStore::passphrase_file(passphrase),
+            None if keyring_available() => SecretStore::keyring(),
+            None => SecretStore::machine_file()?,
+        };
+        let same_backend = next.backend == store.backend;
+
+        // Write and read back the new store before touching the old one, so a locked
+        // keyring or failed write can't lose anything
+        match next.store_all(&secrets).and_then(|_| next.load_all()) {
+            Ok(stored) if stored == secrets => {}
+            result => {
+                if same_backend {
+                    // Same file or keyring entries: put the old copy back
+                    store.store_all(&secrets)?;
+                } else {
+                    let _ = next.clear();
+                }
+                return Err(result
+                    .err()
+                    .unwrap_or_else(|| "Secrets did not read back correctly from the new store".to_string()));
+            }
+        }
+
+        // With the same backend the write above already replaced the old copy
+        let mut previous = std::mem::replace(store, next);
+        if !same_backend {
+            if let Err(e) = previous.clear() {
+                println!("[SECRETS] Warning: failed to clear the previous store: {}", e);
+            }
+        }
+        println!("[SECRETS] Rotated {} secret(s) into {}", secrets.len(), store.status().backend);
+        Ok(store.status())
+    })
+}
+
+/// Delete every stored secret from the keyring and the encrypted file
+pub fn wipe() -> Result<(), String> {
+    with_store(|store| {
+        // A locked store can't be read, but its file can still be deleted
+        if store.backend == Backend::Keyring || store.file_key.is_some() {
+            store.clear()?;
+        }
+        let path = secrets_file_path();
+        if path.exists() {
+            fs::remove_file(path).map_err(|e| format!("Failed to remove secrets file: {}", e))?;
+        }
+        *store = if keyring_available() { SecretStore::keyring() } else { SecretStore::machine_file()? };
+        println!("[SECRETS] All secrets wiped");
+        Ok(())
+    })
+}
+
+/// Current backend and lock state
+pub fn status() -> Result<SecretsStatus, String> {
+    with_store(|store| Ok(store.status()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> HashMap<String, String> {
+        HashMap::from([
+            ("tmdb_api_key".to_string(), "abc123".to_string()),
+            ("gdrive_tokens:me@example.com".to_string(), "{\"access_token\":\"ya29\"}".to_string()),
+        ])
+    }
+
+    #[test]
+    fn sealed_secrets_round_trip() {
+        let salt = random_bytes(16);
+        let key = derive_key("correct horse", &salt);
+        let file = seal(&key, KeySource::Passphrase, &salt, &sample()).unwrap();
+        assert!(!file.ciphertext.contains("abc123"));
+
+        // Through the on-disk JSON, as read_encrypted_file sees it
+        let json = serde_json::to_string(&file).unwrap();
+        let file: EncryptedFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(file.key_source, KeySource::Passphrase);
+        let salt = b64().decode(&file.salt).unwrap();
+        assert_eq!(unseal(&derive_key("correct horse", &salt), &file).unwrap(), sample());
+    }
+
+    #[test]
+    fn wrong_passphrase_is_rejected() {
+        let salt = random_bytes(16);
+        let file = seal(&derive_key("correct horse", &salt), KeySource::Passphrase, &salt, &sample()).unwrap();
+        let error = unseal(&derive_key("battery staple", &salt), &file).unwrap_err();
+        assert!(error.contains("wrong passphrase"));
+    }
+}
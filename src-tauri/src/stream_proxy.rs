@@ -0,0 +1,506 @@
+// Local Streaming Proxy Module
+// Serves cloud media to players that can't send an Authorization header (VLC, the webview,
+// external players). `GET /media/{id}?key=...` on 127.0.0.1 is forwarded to Drive's
+// alt=media URL with the bearer token injected. Range requests pass straight through, and a
+// stream that drops part way (e.g. the token expired) is reopened from the current offset.
+// Bytes already prefetched for the next episode are served from disk first.
+// `GET /subtitle/{id}?key=...` serves external subtitle files, converted to WebVTT with
+// `&format=vtt` for the built-in player, with `&offset=SECONDS` shifting the cues for a
+// transcoded stream that starts part way into the video.
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+use rand::Rng;
+use tiny_http::{Header, Request, Response, Server, StatusCode};
+
+use crate::prefetch::{self, LocalHead};
+use crate::subtitles;
+
+// How many times a dropped upstream read is reopened before giving up on the client
+const MAX_RESUME_ATTEMPTS: u32 = 3;
+
+// Upstream headers worth handing back to the player
+const FORWARDED_HEADERS: [&str; 4] = ["Content-Type", "Content-Range", "Accept-Ranges", "Last-Modified"];
+
+/// Where to fetch a media item from
+pub struct UpstreamSource {
+    pub url: String,
+    pub access_token: String,
+}
+
+/// Looks up the Drive URL and a valid token for a media id.
+/// The flag asks for a freshly refreshed token (after Drive answered 401).
+pub type SourceResolver = Arc<dyn Fn(i64, bool) -> Result<UpstreamSource, String> + Send + Sync>;
+
+/// Loads a subtitle file by id, returning its bytes and format ("srt", "ass", ...)
+pub type SubtitleResolver = Arc<dyn Fn(i64) -> Result<(Vec<u8>, String), String> + Send + Sync>;
+
+enum ProxyRequest {
+    Media(i64),
+    Subtitle { id: i64, vtt: bool, offset: f64 },
+}
+
+struct ProxyHandle {
+    port: u16,
+    // Random per-run key so other local processes can't pull media through the proxy
+    key: String,
+}
+
+lazy_static::lazy_static! {
+    static ref PROXY: Mutex<Option<ProxyHandle>> = Mutex::new(None);
+}
+
+/// Start the proxy (once per process) and return its port
+pub fn start(resolver: SourceResolver, subtitle_resolver: SubtitleResolver) -> Result<u16, String> {
+    let mut proxy = PROXY.lock().map_err(|e| e.to_string())?;
+    if let Some(ref handle) = *proxy {
+        return Ok(handle.port);
+    }
+
+    let server = Server::http("127.0.0.1:0")
+        .map_err(|e| format!("Failed to start stream proxy: {}", e))?;
+    let port = server
+        .server_addr()
+        .to_ip()
+        .map(|addr| addr.port())
+        .ok_or_else(|| "Stream proxy is not bound to a TCP port".to_string())?;
+
+    let key: String = rand::thread_rng()
+        .gen::<[u8; 16]>()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    let request_key = key.clone();
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            // Players open several ranged connections at once (probing, seeking)
+            let resolver = resolver.clone();
+            let subtitle_resolver = subtitle_resolver.clone();
+            let key = request_key.clone();
+            std::thread::spawn(move || handle_request(request, resolver, subtitle_resolver, &key));
+        }
+        println!("[PROXY] Stream proxy stopped");
+    });
+
+    println!("[PROXY] Stream proxy listening on 127.0.0.1:{}", port);
+    *proxy = Some(ProxyHandle { port, key });
+    Ok(port)
+}
+
+/// Tokenless local URL for a cloud media item
+pub fn media_url(media_id: i64) -> Result<String, String> {
+    let proxy = PROXY.lock().map_err(|e| e.to_string())?;
+    let handle = proxy.as_ref().ok_or_else(|| "Stream proxy is not running".to_string())?;
+    Ok(format!("http://127.0.0.1:{}/media/{}?key={}", handle.port, media_id, handle.key))
+}
+
+/// Local URL for an external subtitle file, optionally converted to WebVTT
+pub fn subtitle_url(subtitle_id: i64, vtt: bool) -> Result<String, String> {
+    let proxy = PROXY.lock().map_err(|e| e.to_string())?;
+    let handle = proxy.as_ref().ok_or_else(|| "Stream proxy is not running".to_string())?;
+    let format = if vtt { "&format=vtt" } else { "" };
+    Ok(format!("http://127.0.0.1:{}/subtitle/{}?key={}{}", handle.port, subtitle_id, handle.key, format))
+}
+
+/// WebVTT URL with cues moved `offset` seconds earlier (for a stream starting at `offset`)
+pub fn shifted_subtitle_url(subtitle_id: i64, offset: f64) -> Result<String, String> {
+    let url = subtitle_url(subtitle_id, true)?;
+    if offset > 0.0 {
+        Ok(format!("{}&offset={:.3}", url, offset))
+    } else {
+        Ok(url)
+    }
+}
+
+/// Parse `/media/{id}?key=...` or `/subtitle/{id}?key=...`, if the key matches
+fn parse_request(url: &str, key: &str) -> Option<ProxyRequest> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let params: Vec<(&str, &str)> = query.split('&').filter_map(|pair| pair.split_once('=')).collect();
+    if !params.iter().any(|(name, value)| *name == "key" && *value == key) {
+        return None;
+    }
+
+    if let Some(id) = path.strip_prefix("/media/") {
+        return id.parse::<i64>().ok().map(ProxyRequest::Media);
+    }
+    let id = path.strip_prefix("/subtitle/")?.parse::<i64>().ok()?;
+    let vtt = params.iter().any(|(name, value)| *name == "format" && *value == "vtt");
+    let offset = params
+        .iter()
+        .find(|(name, _)| *name == "offset")
+        .and_then(|(_, value)| value.parse::<f64>().ok())
+        .unwrap_or(0.0);
+    Some(ProxyRequest::Subtitle { id, vtt, offset })
+}
+
+fn handle_request(request: Request, resolver: SourceResolver, subtitle_resolver: SubtitleResolver, key: &str) {
+    let media_id = match parse_request(request.url(), key) {
+        Some(ProxyRequest::Media(id)) => id,
+        Some(ProxyRequest::Subtitle { id, vtt, offset }) => {
+            handle_subtitle_request(request, subtitle_resolver, id, vtt, offset);
+            return;
+        }
+        None => {
+            let _ = request.respond(Response::from_string("Not found").with_status_code(404));
+            return;
+        }
+    };
+
+    // A player has taken over - stop prefetching this item so it gets the bandwidth
+    prefetch::cancel(media_id);
+
+    let range = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Range"))
+        .map(|h| h.value.as_str().to_string());
+
+    // Serve the prefetched start of the file from disk and only go to Drive for the rest
+    if let Some(head) = prefetch::local_head(media_id) {
+        if let Some((start, end)) = local_range(range.as_deref(), &head) {
+            match HeadStream::open(head, resolver.clone(), media_id, start, end) {
+                Ok(stream) => {
+                    let (status, headers, length) = stream.response_parts(range.is_some());
+                    if let Err(e) = request.respond(Response::new(status, headers, stream, Some(length), None)) {
+                        println!("[PROXY] Client stopped reading media {}: {}", media_id, e);
+                    }
+                    return;
+                }
+                Err(e) => println!("[PROXY] Prefetched data for media {} unusable: {}", media_id, e),
+            }
+        }
+    }
+
+    let stream = match UpstreamStream::open(resolver, media_id, range.as_deref()) {
+        Ok(stream) => stream,
+        Err(e) => {
+            println!("[PROXY] Failed to open media {}: {}", media_id, e);
+            let _ = request.respond(Response::from_string(e).with_status_code(502));
+            return;
+        }
+    };
+
+    let status = StatusCode(stream.status);
+    let headers = stream.headers.clone();
+    let length = stream.content_length.map(|l| l as usize);
+    if let Err(e) = request.respond(Response::new(status, headers, stream, length, None)) {
+        // Players routinely drop connections when seeking
+        println!("[PROXY] Client stopped reading media {}: {}", media_id, e);
+    }
+}
+
+fn handle_subtitle_request(request: Request, subtitle_resolver: SubtitleResolver, subtitle_id: i64, vtt: bool, offset: f64) {
+    let (bytes, format) = match subtitle_resolver(subtitle_id) {
+        Ok(subtitle) => subtitle,
+        Err(e) => {
+            println!("[PROXY] Failed to load subtitle {}: {}", subtitle_id, e);
+            let _ = request.respond(Response::from_string(e).with_status_code(502));
+            return;
+        }
+    };
+
+    let (body, content_type) = if vtt {
+        match subtitles::to_webvtt(&bytes, &format) {
+            Ok(text) if offset > 0.0 => (subtitles::shift_webvtt(&text, offset).into_bytes(), "text/vtt; charset=utf-8"),
+            Ok(text) => (text.into_bytes(), "text/vtt; charset=utf-8"),
+            Err(e) => {
+                println!("[PROXY] Failed to convert subtitle {}: {}", subtitle_id, e);
+                let _ = request.respond(Response::from_string(e).with_status_code(415));
+                return;
+            }
+        }
+    } else {
+        (bytes, "application/octet-stream")
+    };
+
+    let mut response = Response::from_data(body);
+    if let Ok(header) = Header::from_bytes("Content-Type", content_type) {
+        response.add_header(header);
+    }
+    // The webview loads tracks cross-origin from the app's own origin
+    if let Ok(header) = Header::from_bytes("Access-Control-Allow-Origin", "*") {
+        response.add_header(header);
+    }
+    let _ = request.respond(response);
+}
+
+/// Upstream body that reopens itself with a Range request if Drive drops the connection
+struct UpstreamStream {
+    resolver: SourceResolver,
+    media_id: i64,
+    client: reqwest::blocking::Client,
+    response: reqwest::blocking::Response,
+    status: u16,
+    headers: Vec<Header>,
+    content_length: Option<u64>,
+    // Absolute byte offset of the next byte, and the last byte we promised the player
+    position: u64,
+    end: Option<u64>,
+    resumable: bool,
+}
+
+impl UpstreamStream {
+    fn open(resolver: SourceResolver, media_id: i64, range: Option<&str>) -> Result<Self, String> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(None)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let response = send_upstream(&client, &resolver, media_id, range)?;
+        let status = response.status().as_u16();
+
+        let headers: Vec<Header> = FORWARDED_HEADERS
+            .iter()
+            .filter_map(|name| {
+                let value = response.headers().get(*name)?;
+                Header::from_bytes(name.as_bytes(), value.as_bytes()).ok()
+            })
+            .collect();
+        let content_length = response.content_length();
+
+        // Work out which absolute bytes this response covers so a resume asks for the rest
+        let (position, end, resumable) = match status {
+            200 => (0, content_length.map(|l| l.saturating_sub(1)), true),
+            206 => match response
+                .headers()
+                .get("Content-Range")
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_content_range)
+            {
+                Some((start, end)) => (start, Some(end), true),
+                None => (0, None, false),
+            },
+            _ => (0, None, false),
+        };
+
+        Ok(Self {
+            resolver,
+            media_id,
+            client,
+            response,
+            status,
+            headers,
+            content_length,
+            position,
+            end,
+            resumable,
+        })
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        let range = match self.end {
+            Some(end) => format!("bytes={}-{}", self.position, end),
+            None => format!("bytes={}-", self.position),
+        };
+        let response = send_upstream(&self.client, &self.resolver, self.media_id, Some(&range))
+            .map_err(io::Error::other)?;
+        if response.status().as_u16() != 206 {
+            return Err(io::Error::other(format!(
+                "Drive returned {} when resuming",
+                response.status()
+            )));
+        }
+        self.response = response;
+        Ok(())
+    }
+
+    fn finished(&self) -> bool {
+        match self.end {
+            Some(end) => self.position > end,
+            None => true,
+        }
+    }
+}
+
+impl Read for UpstreamStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut attempts = 0;
+        loop {
+            let error = match self.response.read(buf) {
+                Ok(0) if self.finished() => return Ok(0),
+                Ok(0) => io::Error::new(io::ErrorKind::UnexpectedEof, "upstream closed early"),
+                Ok(n) => {
+                    self.position += n as u64;
+                    return Ok(n);
+                }
+                Err(e) => e,
+            };
+
+            attempts += 1;
+            if !self.resumable || attempts > MAX_RESUME_ATTEMPTS {
+                return Err(error);
+            }
+            println!(
+                "[PROXY] Media {} dropped at byte {} ({}), reopening",
+                self.media_id, self.position, error
+            );
+            self.reopen()?;
+        }
+    }
+}
+
+/// Byte range (inclusive) to serve when it starts inside the prefetched data.
+/// Suffix ranges (`bytes=-N`) and anything past the prefetched bytes go straight to Drive.
+fn local_range(range: Option<&str>, head: &LocalHead) -> Option<(u64, u64)> {
+    let last = head.total_bytes.checked_sub(1)?;
+    let (start, end) = match range {
+        None => (0, last),
+        Some(value) => {
+            let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+            let start: u64 = start.trim().parse().ok()?;
+            let end = match end.trim() {
+                "" => last,
+                end => end.parse::<u64>().ok()?.min(last),
+            };
+            (start, end)
+        }
+    };
+    if start < head.available_bytes && start <= end { Some((start, end)) } else { None }
+}
+
+/// Body that reads the prefetched bytes from disk, then continues from Drive
+struct HeadStream {
+    head: LocalHead,
+    file: fs::File,
+    resolver: SourceResolver,
+    media_id: i64,
+    // Absolute offset of the next byte and the last byte to send
+    position: u64,
+    start: u64,
+    end: u64,
+    upstream: Option<UpstreamStream>,
+}
+
+impl HeadStream {
+    fn open(head: LocalHead, resolver: SourceResolver, media_id: i64, start: u64, end: u64) -> io::Result<Self> {
+        let mut file = fs::File::open(&head.path)?;
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Self { head, file, resolver, media_id, position: start, start, end, upstream: None })
+    }
+
+    fn response_parts(&self, ranged: bool) -> (StatusCode, Vec<Header>, usize) {
+        let content_type = self.head.content_type.as_deref().unwrap_or("application/octet-stream");
+        let mut headers: Vec<Header> = vec![
+            Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()),
+            Header::from_bytes(&b"Accept-Ranges"[..], &b"bytes"[..]),
+        ]
+        .into_iter()
+        .filter_map(|h| h.ok())
+        .collect();
+
+        let status = if ranged {
+            let content_range = format!("bytes {}-{}/{}", self.start, self.end, self.head.total_bytes);
+            headers.extend(Header::from_bytes(&b"Content-Range"[..], content_range.as_bytes()).ok());
+            StatusCode(206)
+        } else {
+            StatusCode(200)
+        };
+        (status, headers, (self.end - self.start + 1) as usize)
+    }
+}
+
+impl Read for HeadStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position > self.end {
+            return Ok(0);
+        }
+
+        let local_end = self.head.available_bytes.min(self.end + 1);
+        if self.position < local_end {
+            let wanted = buf.len().min((local_end - self.position) as usize);
+            let n = self.file.read(&mut buf[..wanted])?;
+            if n > 0 {
+                self.position += n as u64;
+                return Ok(n);
+            }
+        }
+
+        if self.upstream.is_none() {
+            let range = format!("bytes={}-{}", self.position, self.end);
+            let stream = UpstreamStream::open(self.resolver.clone(), self.media_id, Some(&range))
+                .map_err(io::Error::other)?;
+            if stream.status != 206 {
+                return Err(io::Error::other(format!(
+                    "Drive returned {} after the prefetched data",
+                    stream.status
+                )));
+            }
+            self.upstream = Some(stream);
+        }
+
+        let n = self.upstream.as_mut().map_or(Ok(0), |upstream| upstream.read(buf))?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+/// Request the media from Drive, retrying once with a refreshed token on 401
+pub fn send_upstream(
+    client: &reqwest::blocking::Client,
+    resolver: &SourceResolver,
+    media_id: i64,
+    range: Option<&str>,
+) -> Result<reqwest::blocking::Response, String> {
+    let mut force_refresh = false;
+    loop {
+        let source = resolver(media_id, force_refresh)?;
+        let mut request = client.get(&source.url).bearer_auth(&source.access_token);
+        if let Some(range) = range {
+            request = request.header("Range", range);
+        }
+
+        let response = request
+            .send()
+            .map_err(|e| format!("Failed to reach Google Drive: {}", e))?;
+        if response.status().as_u16() == 401 && !force_refresh {
+            println!("[PROXY] Token rejected for media {}, refreshing", media_id);
+            force_refresh = true;
+            continue;
+        }
+        return Ok(response);
+    }
+}
+
+/// Parse `bytes START-END/TOTAL` into (start, end)
+pub fn parse_content_range(value: &str) -> Option<(u64, u64)> {
+    let range = value.strip_prefix("bytes ")?.split('/').next()?;
+    let (start, end) = range.split_once('-')?;
+    Some((start.trim().parse().ok()?, end.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_content_range() {
+        assert_eq!(parse_content_range("bytes 0-1023/4096"), Some((0, 1023)));
+        assert_eq!(parse_content_range("bytes 100-199/*"), Some((100, 199)));
+        assert_eq!(parse_content_range("bytes */4096"), None);
+        assert_eq!(parse_content_range("items 0-1/2"), None);
+        assert_eq!(parse_content_range("bytes 5-x/10"), None);
+    }
+
+    #[test]
+    fn parses_proxy_requests() {
+        assert!(matches!(parse_request("/media/42?key=abc", "abc"), Some(ProxyRequest::Media(42))));
+        assert!(matches!(
+            parse_request("/subtitle/7?key=abc&format=vtt&offset=12.5", "abc"),
+            Some(ProxyRequest::Subtitle { id: 7, vtt: true, offset }) if offset == 12.5
+        ));
+        assert!(matches!(
+            parse_request("/subtitle/7?key=abc", "abc"),
+            Some(ProxyRequest::Subtitle { id: 7, vtt: false, offset }) if offset == 0.0
+        ));
+
+        // Wrong, missing or partial key
+        assert!(parse_request("/media/42?key=abd", "abc").is_none());
+        assert!(parse_request("/media/42", "abc").is_none());
+        assert!(parse_request("/media/42?key=ab", "abc").is_none());
+        // Unknown paths and bad ids
+        assert!(parse_request("/other/42?key=abc", "abc").is_none());
+        assert!(parse_request("/media/x?key=abc", "abc").is_none());
+        assert!(parse_request("/media/../42?key=abc", "abc").is_none());
+    }
+}
@@ -0,0 +1,489 @@
+// Subtitle Discovery Module
+// Finds external subtitle files for videos and converts them to WebVTT for the built-in
+// player. Matching works on paths relative to a scanned folder, so local folders and Drive
+// folders share the same rules:
+//   Movie.2010.mkv  <-  Movie.2010.srt, Movie.2010.en.srt, Movie.2010.English.forced.srt
+//   Movie.2010.mkv  <-  Subs/English.srt (only video in the folder), Subs/Movie.2010.en.srt
+//   Show.S01E01.mkv <-  Subs/Show.S01E01/2_English.srt
+
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+pub const SUBTITLE_EXTENSIONS: [&str; 4] = ["srt", "ass", "ssa", "vtt"];
+
+// Subtitle store source tags (downloaders use their own, e.g. opensubtitles::SOURCE)
+pub const SOURCE_LOCAL: &str = "local";
+pub const SOURCE_DRIVE: &str = "drive";
+// Text streams extracted from inside the video file
+pub const SOURCE_EMBEDDED: &str = "embedded";
+const VIDEO_EXTENSIONS: [&str; 10] = ["mkv", "mp4", "avi", "mov", "wmv", "m4v", "webm", "flv", "ts", "m2ts"];
+
+// (ISO 639-1 code, names and ISO 639-2 codes that mean it)
+const LANGUAGES: [(&str, &[&str]); 24] = [
+    ("en", &["english", "eng"]),
+    ("fr", &["french", "francais", "fre", "fra"]),
+    ("es", &["spanish", "espanol", "spa", "esp"]),
+    ("de", &["german", "deutsch", "ger", "deu"]),
+    ("it", &["italian", "italiano", "ita"]),
+    ("pt", &["portuguese", "portugues", "por", "pob", "ptbr", "pt-br"]),
+    ("nl", &["dutch", "nederlands", "dut", "nld"]),
+    ("sv", &["swedish", "svenska", "swe"]),
+    ("no", &["norwegian", "norsk", "nor"]),
+    ("da", &["danish", "dansk", "dan"]),
+    ("fi", &["finnish", "suomi", "fin"]),
+    ("pl", &["polish", "polski", "pol"]),
+    ("cs", &["czech", "cze", "ces"]),
+    ("hu", &["hungarian", "hun"]),
+    ("ro", &["romanian", "rum", "ron"]),
+    ("el", &["greek", "gre", "ell"]),
+    ("tr", &["turkish", "tur"]),
+    ("ru", &["russian", "rus"]),
+    ("uk", &["ukrainian", "ukr"]),
+    ("ar", &["arabic", "ara"]),
+    ("he", &["hebrew", "heb"]),
+    ("ja", &["japanese", "jpn"]),
+    ("ko", &["korean", "kor"]),
+    ("zh", &["chinese", "chi", "zho", "chs", "cht"]),
+];
+
+/// A file found while walking a folder: its folders relative to the walk root, its name
+/// and whatever identifies it (a local path or a Drive file id)
+#[derive(Debug, Clone)]
+pub struct FoundFile<K> {
+    pub dirs: Vec<String>,
+    pub name: String,
+    pub key: K,
+}
+
+/// What a subtitle's file name says about it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SubtitleInfo {
+    /// ISO 639-1 code when recognised
+    pub language: Option<String>,
+    /// Human readable label, e.g. "English (Forced)"
+    pub label: String,
+    /// srt, ass, ssa or vtt
+    pub format: String,
+    pub forced: bool,
+    pub sdh: bool,
+}
+
+pub fn is_subtitle_file(name: &str) -> bool {
+    extension(name).is_some_and(|ext| SUBTITLE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+fn is_video_file(name: &str) -> bool {
+    extension(name).is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.as_str()))
+}
+
+fn extension(name: &str) -> Option<String> {
+    Path::new(name).extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase())
+}
+
+fn stem(name: &str) -> String {
+    Path::new(name).file_stem().and_then(|s| s.to_str()).unwrap_or(name).to_string()
+}
+
+/// Folder names that hold a release's subtitles
+pub fn is_subs_folder(name: &str) -> bool {
+    matches!(name.to_lowercase().as_str(), "subs" | "sub" | "subtitles" | "subtitle")
+}
+
+fn language_code(token: &str) -> Option<&'static str> {
+    let token = token.to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(code, names)| *code == token || names.contains(&token.as_str()))
+        .map(|(code, _)| *code)
+}
+
+/// Read language and flags from what follows the video name ("en.forced", "2_English", "SDH")
+pub fn parse_suffix(suffix: &str, format: &str) -> SubtitleInfo {
+    let mut info = SubtitleInfo { format: format.to_string(), ..Default::default() };
+    let mut unknown = Vec::new();
+
+    for token in suffix.split(['.', '_', '-', ' ']).filter(|t| !t.is_empty()) {
+        let lower = token.to_lowercase();
+        if lower.chars().all(|c| c.is_ascii_digit()) {
+            continue; // Track numbers like "2_English"
+        }
+        match lower.as_str() {
+            "forced" => info.forced = true,
+            "sdh" | "cc" | "hi" => info.sdh = true,
+            "default" | "full" => {}
+            _ => match language_code(&lower) {
+                Some(code) if info.language.is_none() => info.language = Some(code.to_string()),
+                _ => unknown.push(token.to_string()),
+            },
+        }
+    }
+
+    let mut label = match info.language.as_deref() {
+        Some(code) => LANGUAGES
+            .iter()
+            .find(|(c, _)| *c == code)
+            .map(|(_, names)| capitalize(names[0]))
+            .unwrap_or_else(|| code.to_string()),
+        None if !unknown.is_empty() => unknown.join(" "),
+        None => "Unknown".to_string(),
+    };
+    if info.forced {
+        label.push_str(" (Forced)");
+    }
+    if info.sdh {
+        label.push_str(" (SDH)");
+    }
+    info.label = label;
+    info
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+/// Suffix after the video's name, if the subtitle name starts with it
+fn suffix_after<'a>(sub_stem: &'a str, video_stem: &str) -> Option<&'a str> {
+    // get() rather than indexing: the cut may land inside a multi-byte character
+    match sub_stem.get(..video_stem.len()) {
+        Some(head) if head.eq_ignore_ascii_case(video_stem) => {}
+        _ => return None,
+    }
+    let rest = &sub_stem[video_stem.len()..];
+    match rest.chars().next() {
+        None => Some(""),
+        Some('.' | '_' | '-' | ' ') => Some(&rest[1..]),
+        _ => None, // "Show.S01E1" must not claim "Show.S01E10.srt"
+    }
+}
+
+/// Keep the candidate video with the longest name
+fn consider<'a, K>(best: &mut Option<(&'a FoundFile<K>, String)>, video: &'a FoundFile<K>, suffix: &str) {
+    if best.as_ref().is_none_or(|(b, _)| stem(&b.name).len() < stem(&video.name).len()) {
+        *best = Some((video, suffix.to_string()));
+    }
+}
+
+/// Pair subtitles with videos. Returns (video key, subtitle key, info) for every match.
+pub fn match_subtitles<K: Clone>(files: &[FoundFile<K>]) -> Vec<(K, K, SubtitleInfo)> {
+    let videos: Vec<&FoundFile<K>> = files.iter().filter(|f| is_video_file(&f.name)).collect();
+    let mut matches = Vec::new();
+
+    for sub in files.iter().filter(|f| is_subtitle_file(&f.name)) {
+        let format = extension(&sub.name).unwrap_or_default();
+        let sub_stem = stem(&sub.name);
+
+        // Longest video name wins so "Movie" doesn't take "Movie 2"'s subtitles
+        let mut best: Option<(&FoundFile<K>, String)> = None;
+
+        for video in &videos {
+            let video_stem = stem(&video.name);
+            let depth = video.dirs.len();
+            let same_dir = sub.dirs == video.dirs;
+            let in_subs = sub.dirs.len() == depth + 1
+                && sub.dirs[..depth] == video.dirs[..]
+                && is_subs_folder(&sub.dirs[depth]);
+            let in_episode_subs = sub.dirs.len() == depth + 2
+                && sub.dirs[..depth] == video.dirs[..]
+                && is_subs_folder(&sub.dirs[depth])
+                && sub.dirs[depth + 1].eq_ignore_ascii_case(&video_stem);
+
+            if in_episode_subs {
+                consider(&mut best, video, &sub_stem);
+            } else if same_dir || in_subs {
+                if let Some(suffix) = suffix_after(&sub_stem, &video_stem) {
+                    consider(&mut best, video, suffix);
+                }
+            }
+        }
+
+        // Subs/English.srt next to a single video belongs to it
+        if best.is_none() && sub.dirs.last().is_some_and(|d| is_subs_folder(d)) {
+            let parent = &sub.dirs[..sub.dirs.len() - 1];
+            let mut in_parent = videos.iter().filter(|v| v.dirs == parent);
+            if let (Some(video), None) = (in_parent.next(), in_parent.next()) {
+                best = Some((video, sub_stem.clone()));
+            }
+        }
+
+        if let Some((video, suffix)) = best {
+            matches.push((video.key.clone(), sub.key.clone(), parse_suffix(&suffix, &format)));
+        }
+    }
+
+    matches
+}
+
+/// Subtitles for a local video: its folder, a Subs/ folder beside it and Subs/<video name>/
+pub fn discover_local(video_path: &str) -> Vec<(PathBuf, SubtitleInfo)> {
+    let video = Path::new(video_path);
+    let dir = match video.parent() {
+        Some(dir) => dir,
+        None => return Vec::new(),
+    };
+    let video_name = video.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_string();
+
+    let mut files = vec![FoundFile { dirs: Vec::new(), name: video_name, key: video.to_path_buf() }];
+    let walker = walkdir::WalkDir::new(dir).min_depth(1).max_depth(3).into_iter().filter_map(|e| e.ok());
+    for entry in walker.filter(|e| e.file_type().is_file()) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !is_subtitle_file(&name) {
+            continue;
+        }
+        let dirs: Vec<String> = entry
+            .path()
+            .strip_prefix(dir)
+            .ok()
+            .and_then(|rel| rel.parent())
+            .map(|rel| rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect())
+            .unwrap_or_default();
+        files.push(FoundFile { dirs, name, key: entry.path().to_path_buf() });
+    }
+
+    match_subtitles(&files).into_iter().map(|(_, path, info)| (path, info)).collect()
+}
+
+/// Decode subtitle bytes: UTF-8 (BOM stripped) or, failing that, Latin-1
+pub fn decode(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+/// Convert a subtitle file to WebVTT for the built-in player
+pub fn to_webvtt(bytes: &[u8], format: &str) -> Result<String, String> {
+    let text = decode(bytes).replace("\r\n", "\n").replace('\r', "\n");
+    match format {
+        "vtt" => Ok(text),
+        "srt" => Ok(srt_to_vtt(&text)),
+        "ass" | "ssa" => ass_to_vtt(&text),
+        other => Err(format!("Unsupported subtitle format: {}", other)),
+    }
+}
+
+fn srt_to_vtt(text: &str) -> String {
+    let timing = Regex::new(r"(\d{1,2}:\d{2}:\d{2}),(\d{3})").unwrap();
+    // ASS-style position tags some SRT files carry ({\an8})
+    let tags = Regex::new(r"\{\\[^}]*\}").unwrap();
+
+    let mut vtt = String::from("WEBVTT\n\n");
+    for line in text.lines() {
+        if line.contains("-->") {
+            vtt.push_str(&timing.replace_all(line, "$1.$2"));
+        } else {
+            vtt.push_str(&tags.replace_all(line, ""));
+        }
+        vtt.push('\n');
+    }
+    vtt
+}
+
+// Embedded subtitle codecs (ffprobe codec_name): text ones convert to WebVTT,
+// image ones can only be burned into the picture
+const TEXT_CODECS: [&str; 7] = ["subrip", "srt", "ass", "ssa", "mov_text", "webvtt", "text"];
+const IMAGE_CODECS: [&str; 4] = ["hdmv_pgs_subtitle", "dvd_subtitle", "dvb_subtitle", "xsub"];
+
+pub fn is_text_codec(codec: &str) -> bool {
+    TEXT_CODECS.contains(&codec)
+}
+
+pub fn is_image_codec(codec: &str) -> bool {
+    IMAGE_CODECS.contains(&codec)
+}
+
+/// VTT time (HH:MM:SS.mmm or MM:SS.mmm) in seconds
+fn vtt_seconds(value: &str) -> Option<f64> {
+    let mut total = 0.0;
+    for part in value.trim().split(':') {
+        total = total * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(total)
+}
+
+fn vtt_time(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!("{:02}:{:02}:{:02}.{:03}", millis / 3_600_000, (millis / 60_000) % 60, (millis / 1000) % 60, millis % 1000)
+}
+
+/// Move every cue `offset` seconds earlier, for a stream that starts part way into the
+/// video. Cues that end before the new start are dropped.
+pub fn shift_webvtt(vtt: &str, offset: f64) -> String {
+    let mut blocks = vtt.split("\n\n");
+    let mut shifted = blocks.next().unwrap_or("WEBVTT").to_string();
+
+    for block in blocks.filter(|b| !b.trim().is_empty()) {
+        let mut lines: Vec<String> = block.lines().map(|l| l.to_string()).collect();
+        let timing = lines.iter().position(|l| l.contains("-->"));
+        if let Some(i) = timing {
+            let (start, rest) = lines[i].split_once("-->").unwrap_or_default();
+            let rest = rest.trim_start();
+            let (end, settings) = rest.split_once(' ').unwrap_or((rest, ""));
+            match (vtt_seconds(start), vtt_seconds(end)) {
+                (Some(start), Some(end)) if end - offset > 0.0 => {
+                    let line = format!("{} --> {} {}", vtt_time((start - offset).max(0.0)), vtt_time(end - offset), settings);
+                    lines[i] = line.trim_end().to_string();
+                }
+                (Some(_), Some(_)) => continue,
+                _ => {}
+            }
+        }
+        shifted.push_str("\n\n");
+        shifted.push_str(&lines.join("\n"));
+    }
+    shifted.push('\n');
+    shifted
+}
+
+/// ASS time (H:MM:SS.cc) to VTT time (HH:MM:SS.mmm)
+fn ass_time(value: &str) -> Option<String> {
+    let mut parts = value.trim().split(':');
+    let hours: u32 = parts.next()?.parse().ok()?;
+    let minutes: u32 = parts.next()?.parse().ok()?;
+    let (seconds, centis) = parts.next()?.split_once('.')?;
+    let seconds: u32 = seconds.parse().ok()?;
+    let centis: u32 = centis.parse().ok()?;
+    Some(format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, seconds, centis * 10))
+}
+
+fn ass_to_vtt(text: &str) -> Result<String, String> {
+    let tags = Regex::new(r"\{[^}]*\}").unwrap();
+    let mut in_events = false;
+    let mut fields: Vec<String> = Vec::new();
+    let mut cues: Vec<(String, String, String)> = Vec::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_events = line.eq_ignore_ascii_case("[events]");
+            continue;
+        }
+        if !in_events {
+            continue;
+        }
+        if let Some(format) = line.strip_prefix("Format:") {
+            fields = format.split(',').map(|f| f.trim().to_lowercase()).collect();
+        } else if let Some(dialogue) = line.strip_prefix("Dialogue:") {
+            if fields.is_empty() {
+                return Err("ASS subtitle has no Format line before its events".to_string());
+            }
+            // Text is the last field and may itself contain commas
+            let values: Vec<&str> = dialogue.splitn(fields.len(), ',').collect();
+            let field = |name: &str| fields.iter().position(|f| f == name).and_then(|i| values.get(i)).copied();
+            let (start, end, body) = match (field("start").and_then(ass_time), field("end").and_then(ass_time), field("text")) {
+                (Some(start), Some(end), Some(body)) => (start, end, body),
+                _ => continue,
+            };
+            let body = tags
+                .replace_all(body, "")
+                .replace("\\N", "\n")
+                .replace("\\n", "\n")
+                .replace("\\h", " ");
+            if !body.trim().is_empty() {
+                cues.push((start, end, body.trim().to_string()));
+            }
+        }
+    }
+
+    // Zero-padded timestamps sort correctly as strings
+    cues.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut vtt = String::from("WEBVTT\n\n");
+    for (start, end, body) in cues {
+        vtt.push_str(&format!("{} --> {}\n{}\n\n", start, end, body));
+    }
+    Ok(vtt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str) -> FoundFile<String> {
+        let mut parts: Vec<String> = path.split('/').map(String::from).collect();
+        let name = parts.pop().unwrap();
+        FoundFile { dirs: parts, name, key: path.to_string() }
+    }
+
+    fn matches(paths: &[&str]) -> Vec<(String, String, SubtitleInfo)> {
+        let files: Vec<_> = paths.iter().map(|p| file(p)).collect();
+        let mut found = match_subtitles(&files);
+        found.sort_by(|a, b| a.1.cmp(&b.1));
+        found
+    }
+
+    #[test]
+    fn matches_subtitles_by_stem() {
+        let found = matches(&[
+            "Movie.2010.mkv",
+            "Movie.2010.en.srt",
+            "Movie.2010.English.forced.srt",
+            "Movie.2010.Part.2.mkv",
+            "Movie.2010.Part.2.fr.srt",
+        ]);
+        assert_eq!(found.len(), 3);
+        assert_eq!((found[0].0.as_str(), found[0].2.label.as_str()), ("Movie.2010.mkv", "English (Forced)"));
+        assert_eq!((found[1].0.as_str(), found[1].2.language.as_deref()), ("Movie.2010.Part.2.mkv", Some("fr")));
+        assert_eq!((found[2].0.as_str(), found[2].2.language.as_deref()), ("Movie.2010.mkv", Some("en")));
+
+        // Episode 1 must not claim episode 10's subtitles
+        let found = matches(&["Show.S01E1.mkv", "Show.S01E10.mkv", "Show.S01E10.en.srt"]);
+        assert_eq!(found[0].0, "Show.S01E10.mkv");
+
+        // Subs/<episode>/2_English.srt and a lone Subs/English.srt
+        let found = matches(&["Show.S01E01.mkv", "Subs/Show.S01E01/2_English.srt"]);
+        assert_eq!(found[0].2.language.as_deref(), Some("en"));
+        let found = matches(&["Movie/Film.mkv", "Movie/Subs/Spanish.srt"]);
+        assert_eq!(found[0].0, "Movie/Film.mkv");
+    }
+
+    #[test]
+    fn stem_compare_handles_multibyte_names() {
+        assert_eq!(suffix_after("Show 第1話", "Show 01"), None);
+        assert_eq!(suffix_after("第1話.ja", "第1話"), Some("ja"));
+        assert!(matches(&["Show 01.mkv", "Show 第1話.srt"]).is_empty());
+    }
+
+    #[test]
+    fn rescans_keep_subtitle_ids() {
+        let db = crate::database::Database::new(":memory:").unwrap();
+        let media_id = db.insert_movie("Movie", None, None, None, "/m/Movie.mkv", 0.0, None).unwrap();
+        let entry = |name: &str, suffix: &str| (name.to_string(), format!("/m/{}", name), parse_suffix(suffix, "srt"));
+
+        db.replace_subtitles(media_id, SOURCE_LOCAL, false, &[entry("Movie.en.srt", "en"), entry("Movie.fr.srt", "fr")]).unwrap();
+        let first = db.get_subtitles(media_id).unwrap();
+
+        db.replace_subtitles(media_id, SOURCE_LOCAL, false, &[entry("Movie.en.srt", "en")]).unwrap();
+        let second = db.get_subtitles(media_id).unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].id, first.iter().find(|s| s.location == "/m/Movie.en.srt").unwrap().id);
+    }
+
+    #[test]
+    fn converts_srt_to_webvtt() {
+        let srt = "1\r\n00:00:01,500 --> 00:00:03,000\r\n{\\an8}Hello\r\n\r\n2\r\n00:01:02,000 --> 00:01:04,250\r\nWorld\r\n";
+        let vtt = to_webvtt(srt.as_bytes(), "srt").unwrap();
+        assert!(vtt.starts_with("WEBVTT\n\n"));
+        assert!(vtt.contains("00:00:01.500 --> 00:00:03.000\nHello\n"));
+        assert!(vtt.contains("00:01:02.000 --> 00:01:04.250\nWorld"));
+    }
+
+    #[test]
+    fn converts_ass_to_webvtt() {
+        let ass = "[Script Info]\nTitle: Test\n\n[Events]\n\
+                   Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                   Dialogue: 0,0:00:05.00,0:00:07.50,Default,,0,0,0,,Second, with a comma\n\
+                   Dialogue: 0,0:00:01.20,0:00:02.00,Default,,0,0,0,,{\\i1}First{\\i0}\\Nline two\n";
+        let vtt = to_webvtt(ass.as_bytes(), "ass").unwrap();
+        let expected = "WEBVTT\n\n00:00:01.200 --> 00:00:02.000\nFirst\nline two\n\n\
+                        00:00:05.000 --> 00:00:07.500\nSecond, with a comma\n\n";
+        assert_eq!(vtt, expected);
+
+        assert!(to_webvtt(b"[Events]\nDialogue: 0,0:00:01.00,0:00:02.00,,,,,,,Hi\n", "ass").is_err());
+    }
+}
@@ -1,30 +1,321 @@
-use std::process::{Child, Command, Stdio};
+use std::process::{Child, ChildStderr, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use std::collections::HashMap;
-use std::io::BufReader;
-use std::net::TcpListener;
-use tiny_http::{Server, Response, Header};
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use tiny_http::{Server, Request, Response, Header};
+
+// HLS segment length; keyframes are forced on this grid so every variant switches cleanly
+const HLS_SEGMENT_SECONDS: u32 = 6;
+// How long a request for a playlist or segment waits for FFmpeg to write it
+const HLS_FILE_WAIT: Duration = Duration::from_secs(30);
+
+// Sessions nobody has read from or asked about for this long are stopped. The player
+// polls the session status while it's open, which keeps a paused stream alive.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+const REAP_INTERVAL: Duration = Duration::from_secs(15);
+// FFmpeg log lines kept per session for diagnostics
+const LOG_LINES: usize = 50;
+// PIDs of running FFmpeg processes, so ones left by a crash are killed on the next start
+const PID_FILE: &str = "transcode_pids.txt";
 
 // Store active transcoding sessions
 lazy_static::lazy_static! {
-    static ref TRANSCODE_SESSIONS: Arc<Mutex<HashMap<u64, TranscodeSession>>> = Arc::new(Mutex::new(HashMap::new()));
-    static ref SESSION_COUNTER: Arc<Mutex<u64>> = Arc::new(Mutex::new(0));
+    static ref TRANSCODE_SESSIONS: Mutex<HashMap<u64, TranscodeSession>> = Mutex::new(HashMap::new());
+}
+
+static SESSION_COUNTER: AtomicU64 = AtomicU64::new(0);
+// Concurrent sessions allowed (each FFmpeg encode can take most of the CPU)
+static MAX_SESSIONS: AtomicUsize = AtomicUsize::new(2);
+// Sessions that passed the limit check but aren't in TRANSCODE_SESSIONS yet
+static PENDING_SESSIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Live numbers from FFmpeg's -progress output
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TranscodeProgress {
+    pub frame: u64,
+    pub fps: f64,
+    /// Encoding speed relative to playback (2.0 = twice real time)
+    pub speed: Option<f64>,
+    /// How far into the source FFmpeg has got, in seconds
+    pub position_seconds: f64,
 }
 
-pub struct TranscodeSession {
-    pub ffmpeg_process: Option<Child>,
-    pub server_port: u16,
-    pub file_path: String,
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionState {
+    Running,
+    /// FFmpeg reached the end of the input
+    Finished,
+    /// FFmpeg exited with an error (see the log)
+    Failed,
+}
+
+/// A session as the UI sees it
+#[derive(Debug, Clone, Serialize)]
+pub struct TranscodeStatus {
+    pub session_id: u64,
+    pub stream_url: String,
+    pub is_hls: bool,
+    pub source: String,
+    pub state: SessionState,
+    pub progress: TranscodeProgress,
+    pub uptime_seconds: u64,
+    pub idle_seconds: u64,
+    /// Recent FFmpeg warnings and errors
+    pub log: Vec<String>,
+}
+
+/// Shared between a session, its FFmpeg stderr reader and its HTTP server
+struct SessionMonitor {
+    progress: TranscodeProgress,
+    log: VecDeque<String>,
+    last_activity: Instant,
+    // Source position the stream starts at (ffmpeg -ss)
+    offset: f64,
+}
+
+type SharedMonitor = Arc<Mutex<SessionMonitor>>;
+
+fn new_monitor(start_time: Option<f64>) -> SharedMonitor {
+    Arc::new(Mutex::new(SessionMonitor {
+        progress: TranscodeProgress::default(),
+        log: VecDeque::new(),
+        last_activity: Instant::now(),
+        offset: start_time.filter(|t| *t > 0.0).unwrap_or(0.0),
+    }))
+}
+
+fn touch(monitor: &SharedMonitor) {
+    if let Ok(mut monitor) = monitor.lock() {
+        monitor.last_activity = Instant::now();
+    }
+}
+
+/// One transcode: a single FFmpeg process and the server handing out its output
+struct TranscodeSession {
+    ffmpeg: Child,
+    // Progressive sessions: FFmpeg's output, until the player's request picks it up
+    stdout: Option<ChildStdout>,
+    stream_url: String,
+    is_hls: bool,
+    source: String,
+    started_at: Instant,
+    monitor: SharedMonitor,
+}
+
+impl TranscodeSession {
+    fn state(&mut self) -> SessionState {
+        match self.ffmpeg.try_wait() {
+            Ok(None) => SessionState::Running,
+            Ok(Some(status)) if status.success() => SessionState::Finished,
+            _ => SessionState::Failed,
+        }
+    }
+
+    fn idle_time(&self) -> Duration {
+        self.monitor.lock().map(|m| m.last_activity.elapsed()).unwrap_or_default()
+    }
+
+    fn status(&mut self, session_id: u64) -> TranscodeStatus {
+        let state = self.state();
+        let (progress, log) = match self.monitor.lock() {
+            Ok(monitor) => (monitor.progress.clone(), monitor.log.iter().cloned().collect()),
+            Err(_) => (TranscodeProgress::default(), Vec::new()),
+        };
+        TranscodeStatus {
+            session_id,
+            stream_url: self.stream_url.clone(),
+            is_hls: self.is_hls,
+            source: self.source.clone(),
+            state,
+            progress,
+            uptime_seconds: self.started_at.elapsed().as_secs(),
+            idle_seconds: self.idle_time().as_secs(),
+            log,
+        }
+    }
 }
 
 impl Drop for TranscodeSession {
     fn drop(&mut self) {
-        if let Some(ref mut process) = self.ffmpeg_process {
-            let _ = process.kill();
+        let _ = self.ffmpeg.kill();
+        // Reap it so no zombie is left behind
+        let _ = self.ffmpeg.wait();
+    }
+}
+
+// Night mode downmix from 5.1/7.1 (FL FR FC LFE BL BR ...): centre channel (dialogue) at
+// full level, the rest of the mix turned down
+const NIGHT_DOWNMIX: &str = "pan=stereo|c0=c2+0.30*c0+0.30*c4|c1=c2+0.30*c1+0.30*c5";
+const NIGHT_COMPRESSOR: &str = "acompressor=threshold=-24dB:ratio=4:attack=5:release=200:makeup=2";
+const LOUDNORM: &str = "loudnorm=I=-16:TP=-1.5:LRA=11";
+const NIGHT_LOUDNORM: &str = "loudnorm=I=-16:TP=-1.5:LRA=7";
+
+/// Codec for keeping surround sound, when the client can decode it
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SurroundCodec {
+    Aac,
+    Ac3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioNormalization {
+    /// Even out loudness between and within titles
+    Loudnorm,
+    /// Compress the dynamic range and lift dialogue, for quiet listening
+    Night,
+}
+
+/// How the player wants the audio
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AudioOptions {
+    /// Audio stream to play, by ffmpeg stream index (default: ffmpeg's own pick)
+    pub stream_index: Option<i64>,
+    /// Keep 5.1 instead of downmixing to stereo AAC
+    pub surround: Option<SurroundCodec>,
+    pub normalize: Option<AudioNormalization>,
+}
+
+/// What FFmpeg reads: a local file, or a URL fetched with extra request headers
+/// (Drive's alt=media URL with a bearer token)
+#[derive(Debug, Clone)]
+pub struct TranscodeInput {
+    pub location: String,
+    pub headers: Option<String>,
+}
+
+impl TranscodeInput {
+    pub fn file(path: &str) -> Self {
+        TranscodeInput { location: path.to_string(), headers: None }
+    }
+
+    pub fn is_remote(&self) -> bool {
+        self.location.starts_with("http://") || self.location.starts_with("https://")
+    }
+}
+
+/// Produces the input each time FFmpeg starts, so a restarted encode gets a fresh token
+pub type InputResolver = Arc<dyn Fn() -> Result<TranscodeInput, String> + Send + Sync>;
+
+/// Why a transcode couldn't start. Drive refusals are told apart so the player can
+/// explain them (`{"kind": "drive_quota_exceeded", "message": "..."}`).
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", content = "message", rename_all = "snake_case")]
+pub enum TranscodeError {
+    DriveQuotaExceeded(String),
+    DriveRateLimited(String),
+    DriveForbidden(String),
+    Failed(String),
+}
+
+impl std::fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TranscodeError::DriveQuotaExceeded(m)
+            | TranscodeError::DriveRateLimited(m)
+            | TranscodeError::DriveForbidden(m)
+            | TranscodeError::Failed(m) => write!(f, "{}", m),
         }
     }
 }
 
+impl From<String> for TranscodeError {
+    fn from(message: String) -> Self {
+        TranscodeError::Failed(message)
+    }
+}
+
+impl From<crate::gdrive::DriveError> for TranscodeError {
+    fn from(error: crate::gdrive::DriveError) -> Self {
+        use crate::gdrive::DriveError;
+        let message = error.to_string();
+        match error {
+            DriveError::QuotaExceeded(_) => TranscodeError::DriveQuotaExceeded(message),
+            DriveError::RateLimited(_) => TranscodeError::DriveRateLimited(message),
+            DriveError::Forbidden(_) => TranscodeError::DriveForbidden(message),
+            DriveError::Other(_) => TranscodeError::Failed(message),
+        }
+    }
+}
+
+/// Named output quality. Without a bitrate the video stream is copied as is (remux).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QualityProfile {
+    pub id: String,
+    pub name: String,
+    /// Output height cap (None = source resolution)
+    #[serde(default)]
+    pub max_height: Option<u32>,
+    /// Peak video bitrate in kbps (None = copy the video stream)
+    #[serde(default)]
+    pub video_kbps: Option<u32>,
+    /// x264 constant quality; the bitrate above caps it for complex scenes
+    #[serde(default = "default_crf")]
+    pub crf: u32,
+}
+
+fn default_crf() -> u32 {
+    21
+}
+
+impl QualityProfile {
+    fn new(id: &str, name: &str, max_height: Option<u32>, video_kbps: Option<u32>) -> Self {
+        QualityProfile {
+            id: id.to_string(),
+            name: name.to_string(),
+            max_height,
+            video_kbps,
+            crf: default_crf(),
+        }
+    }
+
+    pub fn is_remux(&self) -> bool {
+        self.video_kbps.is_none()
+    }
+}
+
+impl Default for QualityProfile {
+    /// 1080p at up to 8 Mbps
+    fn default() -> Self {
+        QualityProfile::new("1080p", "1080p 8 Mbps", Some(1080), Some(8000))
+    }
+}
+
+/// The built-in quality ladder
+pub fn default_profiles() -> Vec<QualityProfile> {
+    vec![
+        QualityProfile::new("original", "Original (remux)", None, None),
+        QualityProfile::default(),
+        QualityProfile::new("720p", "720p 4 Mbps", Some(720), Some(4000)),
+        QualityProfile::new("480p", "480p 1.5 Mbps", Some(480), Some(1500)),
+    ]
+}
+
+/// Per-session choices passed in by the player
+#[derive(Debug, Clone, Default)]
+pub struct TranscodeOptions {
+    /// Quality of a single progressive MP4 stream
+    pub profile: QualityProfile,
+    /// Variants of an HLS adaptive stream, best first (empty = progressive MP4)
+    pub hls_variants: Vec<QualityProfile>,
+    /// Image-based subtitle stream (PGS/VobSub) to burn into the picture, by ffmpeg stream index
+    pub burn_subtitle: Option<i64>,
+    pub audio: AudioOptions,
+    /// Channel count of the audio stream being played, when known from ffprobe
+    pub audio_channels: Option<i64>,
+}
+
+/// Limit how many transcodes can run at once
+pub fn set_max_sessions(max: usize) {
+    MAX_SESSIONS.store(max.max(1), Ordering::SeqCst);
+}
+
 /// Check if a video file needs transcoding for HTML5 playback
 pub fn needs_transcoding(file_path: &str) -> bool {
     let ext = file_path.split('.').last().unwrap_or("").to_lowercase();
@@ -35,221 +326,733 @@ pub fn needs_transcoding(file_path: &str) -> bool {
     )
 }
 
-/// Find an available port for the transcoding server
-fn find_available_port() -> Option<u16> {
-    // Try ports in range 9000-9100
-    for port in 9000..9100 {
-        if TcpListener::bind(format!("127.0.0.1:{}", port)).is_ok() {
-            return Some(port);
+/// Chapters on the transcoded stream's timeline. The stream starts at `start_time`
+/// (ffmpeg -ss), so its position 0 is that point in the file.
+pub fn stream_chapters(chapters: &[crate::database::Chapter], start_time: Option<f64>) -> Vec<crate::database::Chapter> {
+    let offset = start_time.filter(|t| *t > 0.0).unwrap_or(0.0);
+    chapters
+        .iter()
+        .filter(|c| c.end_seconds > offset)
+        .map(|c| crate::database::Chapter {
+            start_seconds: (c.start_seconds - offset).max(0.0),
+            end_seconds: c.end_seconds - offset,
+            ..c.clone()
+        })
+        .collect()
+}
+
+/// Kill FFmpeg processes left behind by a previous run, clear its HLS output and start
+/// the idle session reaper
+pub fn init() {
+    cleanup_orphans();
+    std::thread::spawn(|| loop {
+        std::thread::sleep(REAP_INTERVAL);
+        reap_idle_sessions();
+    });
+}
+
+fn reap_idle_sessions() {
+    let mut sessions = match TRANSCODE_SESSIONS.lock() {
+        Ok(sessions) => sessions,
+        Err(_) => return,
+    };
+    let idle: Vec<u64> = sessions
+        .iter()
+        .filter(|(_, session)| session.idle_time() >= IDLE_TIMEOUT)
+        .map(|(id, _)| *id)
+        .collect();
+    for id in &idle {
+        sessions.remove(id);
+        println!("[TRANSCODE] Stopped idle session {}", id);
+    }
+    if !idle.is_empty() {
+        save_pids(&sessions);
+    }
+}
+
+fn hls_root() -> PathBuf {
+    std::env::temp_dir().join("streamvault-transcode")
+}
+
+fn pid_file() -> PathBuf {
+    crate::database::get_app_data_dir().join(PID_FILE)
+}
+
+fn save_pids(sessions: &HashMap<u64, TranscodeSession>) {
+    let pids: Vec<String> = sessions.values().map(|s| s.ffmpeg.id().to_string()).collect();
+    if let Err(e) = std::fs::write(pid_file(), pids.join("\n")) {
+        println!("[TRANSCODE] Failed to record FFmpeg processes: {}", e);
+    }
+}
+
+fn cleanup_orphans() {
+    if let Ok(contents) = std::fs::read_to_string(pid_file()) {
+        for pid in contents.lines().filter_map(|line| line.trim().parse::<u32>().ok()) {
+            // The PID may belong to something else by now - only kill it if it's still FFmpeg
+            match process_name(pid) {
+                Some(name) if name.to_lowercase().contains("ffmpeg") => {
+                    println!("[TRANSCODE] Killing orphaned FFmpeg (PID {})", pid);
+                    kill_process(pid);
+                }
+                _ => {}
+            }
         }
+        let _ = std::fs::remove_file(pid_file());
+    }
+
+    let root = hls_root();
+    if root.exists() {
+        if let Err(e) = std::fs::remove_dir_all(&root) {
+            println!("[TRANSCODE] Failed to remove old HLS output: {}", e);
+        }
+    }
+}
+
+/// A command that doesn't flash a console window on Windows
+fn background_command(program: &str) -> Command {
+    #[allow(unused_mut)]
+    let mut command = Command::new(program);
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(0x08000000); // CREATE_NO_WINDOW
+    }
+    command
+}
+
+/// Executable name of a running process (None if nothing runs under that PID)
+fn process_name(pid: u32) -> Option<String> {
+    #[cfg(windows)]
+    let output = background_command("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/FO", "CSV", "/NH"])
+        .output()
+        .ok()?;
+    #[cfg(not(windows))]
+    let output = background_command("ps")
+        .args(["-p", &pid.to_string(), "-o", "comm="])
+        .output()
+        .ok()?;
+
+    // tasklist prints "ffmpeg.exe","1234",...; ps prints the command path
+    let text = String::from_utf8_lossy(&output.stdout);
+    let name = text.lines().next()?.split(',').next()?.trim().trim_matches('"');
+    (output.status.success() && !name.is_empty()).then(|| name.to_string())
+}
+
+fn kill_process(pid: u32) {
+    #[cfg(windows)]
+    let result = background_command("taskkill").args(["/PID", &pid.to_string(), "/F"]).output();
+    #[cfg(not(windows))]
+    let result = background_command("kill").args(["-9", &pid.to_string()]).output();
+    if let Err(e) = result {
+        println!("[TRANSCODE] Failed to kill process {}: {}", pid, e);
+    }
+}
+
+/// Start FFmpeg, with its stderr (progress and log) read into `monitor`
+fn spawn_ffmpeg(ffmpeg_path: &str, args: &[String], pipe_output: bool, monitor: &SharedMonitor) -> Result<Child, String> {
+    let mut child = background_command(ffmpeg_path)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(if pipe_output { Stdio::piped() } else { Stdio::null() })
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+
+    if let Some(stderr) = child.stderr.take() {
+        let monitor = monitor.clone();
+        std::thread::spawn(move || read_ffmpeg_stderr(stderr, monitor));
     }
-    None
+    Ok(child)
+}
+
+/// `-progress` writes `key=value` lines (frame=120, out_time_us=5005000, speed=1.98x)
+fn progress_field(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once('=')?;
+    let is_key = !key.is_empty() && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    is_key.then(|| (key, value.trim()))
+}
+
+/// Sort FFmpeg's stderr into progress updates and log lines until it exits
+fn read_ffmpeg_stderr(stderr: ChildStderr, monitor: SharedMonitor) {
+    for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut monitor = match monitor.lock() {
+            Ok(monitor) => monitor,
+            Err(_) => return,
+        };
+
+        match progress_field(line) {
+            Some(("frame", value)) => monitor.progress.frame = value.parse().unwrap_or(monitor.progress.frame),
+            Some(("fps", value)) => monitor.progress.fps = value.parse().unwrap_or(monitor.progress.fps),
+            // "N/A" until FFmpeg has timed a few frames
+            Some(("speed", value)) => monitor.progress.speed = value.trim_end_matches('x').parse().ok(),
+            Some(("out_time_us", value)) => {
+                if let Ok(us) = value.parse::<i64>() {
+                    monitor.progress.position_seconds = monitor.offset + us.max(0) as f64 / 1_000_000.0;
+                }
+            }
+            Some(_) => {}
+            None => {
+                if monitor.log.len() >= LOG_LINES {
+                    monitor.log.pop_front();
+                }
+                monitor.log.push_back(line.to_string());
+            }
+        }
+    }
+}
+
+fn insert_session(session_id: u64, session: TranscodeSession) -> Result<(), String> {
+    let mut sessions = TRANSCODE_SESSIONS.lock().map_err(|e| e.to_string())?;
+    sessions.insert(session_id, session);
+    save_pids(&sessions);
+    Ok(())
+}
+
+/// A place under the session limit, held while a transcode starts up. DLNA and Jellyfin
+/// start transcodes from their own threads, so the check and the insert are far apart.
+struct SessionSlot;
+
+impl SessionSlot {
+    fn reserve() -> Result<Self, String> {
+        let sessions = TRANSCODE_SESSIONS.lock().map_err(|e| e.to_string())?;
+        let max = MAX_SESSIONS.load(Ordering::SeqCst);
+        if sessions.len() + PENDING_SESSIONS.load(Ordering::SeqCst) >= max {
+            return Err(format!(
+                "{} transcodes are already running. Stop one, or raise the limit in Settings > Player.",
+                max
+            ));
+        }
+        PENDING_SESSIONS.fetch_add(1, Ordering::SeqCst);
+        Ok(SessionSlot)
+    }
+}
+
+impl Drop for SessionSlot {
+    fn drop(&mut self) {
+        PENDING_SESSIONS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn is_active(session_id: u64) -> bool {
+    TRANSCODE_SESSIONS.lock().map(|s| s.contains_key(&session_id)).unwrap_or(false)
 }
 
 /// Start transcoding a video file and return a local HTTP URL
 pub fn start_transcode(
     ffmpeg_path: &str,
-    file_path: &str,
+    resolve_input: InputResolver,
     start_time: Option<f64>,
+    options: &TranscodeOptions,
 ) -> Result<(u64, String), String> {
     if !std::path::Path::new(ffmpeg_path).exists() {
         return Err("FFmpeg not found. Please configure FFmpeg path in Settings.".to_string());
     }
 
-    if !std::path::Path::new(file_path).exists() {
-        return Err(format!("Video file not found: {}", file_path));
+    let input = resolve_input()?;
+    if !input.is_remote() && !std::path::Path::new(&input.location).exists() {
+        return Err(format!("Video file not found: {}", input.location));
+    }
+
+    // Held until the session is inserted (or startup fails)
+    let _slot = SessionSlot::reserve()?;
+
+    // Port 0: the OS hands out a free port, so there's no probe-then-bind race
+    let server = Server::http("127.0.0.1:0")
+        .map_err(|e| format!("Failed to start transcode server: {}", e))?;
+    let port = server
+        .server_addr()
+        .to_ip()
+        .map(|addr| addr.port())
+        .ok_or_else(|| "Transcode server is not bound to a TCP port".to_string())?;
+
+    let session_id = SESSION_COUNTER.fetch_add(1, Ordering::SeqCst) + 1;
+
+    if !options.hls_variants.is_empty() {
+        return start_hls(ffmpeg_path, &input, start_time, options, session_id, server);
+    }
+
+    let args = ffmpeg_args(&input, start_time, options)?;
+    println!("[TRANSCODE] Starting FFmpeg with args: {:?}", loggable_args(&args));
+
+    let monitor = new_monitor(start_time);
+    let mut ffmpeg = spawn_ffmpeg(ffmpeg_path, &args, true, &monitor)?;
+    let stdout = ffmpeg.stdout.take();
+    let stream_url = format!("http://127.0.0.1:{}/stream.mp4", port);
+    insert_session(session_id, TranscodeSession {
+        ffmpeg,
+        stdout,
+        stream_url: stream_url.clone(),
+        is_hls: false,
+        source: input.location.clone(),
+        started_at: Instant::now(),
+        monitor,
+    })?;
+
+    let ffmpeg_path = ffmpeg_path.to_string();
+    let options = options.clone();
+    std::thread::spawn(move || run_transcode_server(server, session_id, ffmpeg_path, resolve_input, start_time, options));
+
+    println!("[TRANSCODE] Started session {} at {}", session_id, stream_url);
+    Ok((session_id, stream_url))
+}
+
+/// Serve a progressive session's MP4 until the session is stopped. The first request
+/// gets the FFmpeg started with the session; a player that reconnects gets FFmpeg
+/// restarted from the session's start position.
+fn run_transcode_server(
+    server: Server,
+    session_id: u64,
+    ffmpeg_path: String,
+    resolve_input: InputResolver,
+    start_time: Option<f64>,
+    options: TranscodeOptions,
+) {
+    while is_active(session_id) {
+        let request = match server.recv_timeout(Duration::from_secs(1)) {
+            Ok(Some(request)) => request,
+            Ok(None) => continue,
+            Err(_) => break,
+        };
+        println!("[TRANSCODE] Session {} request: {} {}", session_id, request.method(), request.url());
+
+        if !request.url().starts_with("/stream") {
+            let _ = request.respond(Response::from_string("Not found").with_status_code(404));
+            continue;
+        }
+
+        match session_output(session_id, &ffmpeg_path, &resolve_input, start_time, &options) {
+            // Streamed on its own thread so a reconnect can take over
+            Ok((stdout, monitor)) => {
+                std::thread::spawn(move || serve_stream(request, stdout, monitor));
+            }
+            Err(e) => {
+                println!("[TRANSCODE] Session {}: {}", session_id, e);
+                let _ = request.respond(Response::from_string(e).with_status_code(502));
+            }
+        }
     }
 
-    let port = find_available_port()
-        .ok_or_else(|| "No available port for transcoding server".to_string())?;
+    println!("[TRANSCODE] Session {} server closed", session_id);
+}
 
-    // Create session ID
-    let session_id = {
-        let mut counter = SESSION_COUNTER.lock().map_err(|e| e.to_string())?;
-        *counter += 1;
-        *counter
+/// FFmpeg's output for a player request: the session's first FFmpeg if nobody has read
+/// it yet, otherwise a replacement FFmpeg (the old one is killed)
+fn session_output(
+    session_id: u64,
+    ffmpeg_path: &str,
+    resolve_input: &InputResolver,
+    start_time: Option<f64>,
+    options: &TranscodeOptions,
+) -> Result<(ChildStdout, SharedMonitor), String> {
+    let monitor = {
+        let mut sessions = TRANSCODE_SESSIONS.lock().map_err(|e| e.to_string())?;
+        let session = sessions.get_mut(&session_id).ok_or_else(|| "Session stopped".to_string())?;
+        if let Some(stdout) = session.stdout.take() {
+            return Ok((stdout, session.monitor.clone()));
+        }
+        session.monitor.clone()
     };
 
-    // Build FFmpeg command for HLS output (most compatible for streaming)
-    // We'll use fragmented MP4 for better seeking support
-    let mut args = vec![
-        "-hide_banner".to_string(),
-        "-loglevel".to_string(), "warning".to_string(),
-    ];
+    // Resolve the input again: a Drive token may have expired since the session started
+    let args = resolve_input().and_then(|input| ffmpeg_args(&input, start_time, options))?;
+    if let Ok(mut monitor) = monitor.lock() {
+        monitor.progress = TranscodeProgress::default();
+    }
+    let mut ffmpeg = spawn_ffmpeg(ffmpeg_path, &args, true, &monitor)?;
+    let stdout = ffmpeg.stdout.take().ok_or_else(|| "FFmpeg output is not piped".to_string())?;
+
+    let mut sessions = TRANSCODE_SESSIONS.lock().map_err(|e| e.to_string())?;
+    match sessions.get_mut(&session_id) {
+        Some(session) => {
+            println!("[TRANSCODE] Restarted FFmpeg for session {}", session_id);
+            let mut old = std::mem::replace(&mut session.ffmpeg, ffmpeg);
+            let _ = old.kill();
+            let _ = old.wait();
+            save_pids(&sessions);
+            Ok((stdout, monitor))
+        }
+        None => {
+            let _ = ffmpeg.kill();
+            let _ = ffmpeg.wait();
+            Err("Session stopped".to_string())
+        }
+    }
+}
+
+fn serve_stream(request: Request, stdout: ChildStdout, monitor: SharedMonitor) {
+    let content_type = Header::from_bytes(&b"Content-Type"[..], &b"video/mp4"[..]).unwrap();
+    let reader = ActivityReader { inner: BufReader::new(stdout), monitor };
+    let response = Response::new(tiny_http::StatusCode(200), vec![content_type], reader, None, None);
+
+    if let Err(e) = request.respond(response) {
+        println!("[TRANSCODE] Failed to send response: {}", e);
+    }
+}
+
+/// Counts the player reading the stream as session activity
+struct ActivityReader<R> {
+    inner: R,
+    monitor: SharedMonitor,
+}
+
+impl<R: Read> Read for ActivityReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf);
+        touch(&self.monitor);
+        read
+    }
+}
+
+/// Status of a session. Asking counts as activity, so a player that polls while paused
+/// keeps its session.
+pub fn session_status(session_id: u64) -> Result<TranscodeStatus, String> {
+    let mut sessions = TRANSCODE_SESSIONS.lock().map_err(|e| e.to_string())?;
+    let session = sessions
+        .get_mut(&session_id)
+        .ok_or_else(|| format!("Transcode session {} is not running", session_id))?;
+    touch(&session.monitor);
+    Ok(session.status(session_id))
+}
+
+/// Status of every running session
+pub fn list_sessions() -> Result<Vec<TranscodeStatus>, String> {
+    let mut sessions = TRANSCODE_SESSIONS.lock().map_err(|e| e.to_string())?;
+    let mut list: Vec<TranscodeStatus> = sessions.iter_mut().map(|(id, s)| s.status(*id)).collect();
+    list.sort_by_key(|s| s.session_id);
+    Ok(list)
+}
+
+/// FFmpeg arguments safe to log: request headers carry the Drive access token
+fn loggable_args(args: &[String]) -> Vec<&str> {
+    let mut after_headers = false;
+    args.iter()
+        .map(|arg| {
+            let shown = if after_headers { "<redacted>" } else { arg.as_str() };
+            after_headers = arg == "-headers";
+            shown
+        })
+        .collect()
+}
+
+/// Start of every FFmpeg command line: input and resume position
+fn input_args(input: &TranscodeInput, start_time: Option<f64>) -> Vec<String> {
+    let mut args: Vec<String> = vec!["-hide_banner".into(), "-nostdin".into(), "-loglevel".into(), "warning".into()];
+    // Machine-readable progress on stderr, next to the log
+    args.extend(["-progress", "pipe:2", "-nostats"].map(String::from));
 
     // Add start time if resuming
-    if let Some(time) = start_time {
-        if time > 0.0 {
-            args.push("-ss".to_string());
-            args.push(format!("{:.2}", time));
+    if let Some(time) = start_time.filter(|t| *t > 0.0) {
+        args.push("-ss".into());
+        args.push(format!("{:.2}", time));
+    }
+    if let Some(ref headers) = input.headers {
+        args.push("-headers".into());
+        args.push(format!("{}\r\n", headers));
+    }
+    if input.is_remote() {
+        // Drive drops long reads now and then; pick up from the same offset
+        args.extend(["-reconnect", "1", "-reconnect_on_network_error", "1", "-reconnect_delay_max", "10"].map(String::from));
+    }
+    args.push("-i".into());
+    args.push(input.location.clone());
+    args
+}
+
+fn scale_filter(profile: &QualityProfile) -> Option<String> {
+    // -2 keeps the width even, which x264 needs; min() never upscales
+    profile.max_height.map(|height| format!("scale=-2:'min({},ih)'", height))
+}
+
+/// Video encoder arguments for one output stream (`spec` is ":v" or ":v:N")
+fn video_codec_args(profile: &QualityProfile, spec: &str) -> Vec<String> {
+    let kbps = match profile.video_kbps {
+        Some(kbps) => kbps,
+        None => return vec![format!("-c{}", spec), "copy".to_string()],
+    };
+    vec![
+        format!("-c{}", spec), "libx264".to_string(),
+        format!("-preset{}", spec), "veryfast".to_string(),
+        format!("-crf{}", spec), profile.crf.to_string(),
+        format!("-maxrate{}", spec), format!("{}k", kbps),
+        format!("-bufsize{}", spec), format!("{}k", kbps * 2),
+        format!("-profile{}", spec), "high".to_string(),
+        format!("-pix_fmt{}", spec), "yuv420p".to_string(),
+    ]
+}
+
+fn audio_map(options: &TranscodeOptions) -> String {
+    match options.audio.stream_index {
+        Some(index) => format!("0:{}", index),
+        None => "0:a:0?".to_string(),
+    }
+}
+
+/// FFmpeg arguments for a fragmented MP4 stream of `input` on stdout
+fn ffmpeg_args(input: &TranscodeInput, start_time: Option<f64>, options: &TranscodeOptions) -> Result<Vec<String>, String> {
+    let mut args = input_args(input, start_time);
+    let profile = &options.profile;
+
+    match options.burn_subtitle {
+        Some(_) if profile.is_remux() => {
+            return Err("Burning in subtitles needs a quality profile that re-encodes the video".to_string());
+        }
+        // Image subtitles are drawn onto the video before scaling, so they scale with it
+        Some(index) => {
+            let mut graph = format!("[0:v:0][0:{}]overlay", index);
+            if let Some(scale) = scale_filter(profile) {
+                graph = format!("{},{}", graph, scale);
+            }
+            args.extend([
+                "-filter_complex".to_string(), format!("{}[v]", graph),
+                "-map".to_string(), "[v]".to_string(),
+                "-map".to_string(), audio_map(options),
+            ]);
+        }
+        None => {
+            if let Some(scale) = scale_filter(profile).filter(|_| !profile.is_remux()) {
+                args.extend(["-vf".to_string(), scale]);
+            }
+            if options.audio.stream_index.is_some() {
+                args.extend(["-map".to_string(), "0:v:0".to_string(), "-map".to_string(), audio_map(options)]);
+            }
         }
     }
 
-    args.extend(vec![
-        "-i".to_string(), file_path.to_string(),
-        // Video: transcode to H.264 baseline for maximum compatibility
-        "-c:v".to_string(), "libx264".to_string(),
-        "-preset".to_string(), "ultrafast".to_string(),
-        "-tune".to_string(), "zerolatency".to_string(),
-        "-profile:v".to_string(), "baseline".to_string(),
-        "-level".to_string(), "3.0".to_string(),
-        "-pix_fmt".to_string(), "yuv420p".to_string(),
-        // Scale down if too large (max 1080p)
-        "-vf".to_string(), "scale='min(1920,iw)':'min(1080,ih)':force_original_aspect_ratio=decrease".to_string(),
-        // Audio: transcode to AAC stereo
-        "-c:a".to_string(), "aac".to_string(),
-        "-ac".to_string(), "2".to_string(),
-        "-b:a".to_string(), "192k".to_string(),
+    args.extend(video_codec_args(profile, ":v"));
+    args.extend(audio_args(options));
+    args.extend([
+        // Subtitles reach the player as WebVTT tracks or burned in, never in the stream
+        "-sn",
         // Output format: fragmented MP4 for streaming
-        "-f".to_string(), "mp4".to_string(),
-        "-movflags".to_string(), "frag_keyframe+empty_moov+faststart".to_string(),
+        "-f", "mp4",
+        "-movflags", "frag_keyframe+empty_moov+faststart",
         // Output to pipe
-        "pipe:1".to_string(),
-    ]);
-
-    println!("[TRANSCODE] Starting FFmpeg with args: {:?}", args);
+        "pipe:1",
+    ].map(String::from));
+    Ok(args)
+}
 
-    let ffmpeg_process = Command::new(ffmpeg_path)
-        .args(&args)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("Failed to start FFmpeg: {}", e))?;
+/// FFmpeg arguments for an HLS ladder written to `dir`: master.m3u8 plus v{N}/index.m3u8
+/// and segments for each variant, all cut from one decode of the source
+fn hls_args(input: &TranscodeInput, start_time: Option<f64>, options: &TranscodeOptions, dir: &Path) -> Vec<String> {
+    let mut args = input_args(input, start_time);
+    let variants = &options.hls_variants;
 
-    let session = TranscodeSession {
-        ffmpeg_process: Some(ffmpeg_process),
-        server_port: port,
-        file_path: file_path.to_string(),
+    let mut graph = match options.burn_subtitle {
+        Some(index) => format!("[0:v:0][0:{}]overlay,", index),
+        None => "[0:v:0]".to_string(),
     };
+    graph.push_str(&format!("split={}", variants.len()));
+    for i in 0..variants.len() {
+        graph.push_str(&format!("[s{}]", i));
+    }
+    for (i, variant) in variants.iter().enumerate() {
+        let scale = scale_filter(variant).unwrap_or_else(|| "null".to_string());
+        graph.push_str(&format!(";[s{}]{}[v{}]", i, scale, i));
+    }
+    args.extend(["-filter_complex".to_string(), graph]);
 
-    // Store session
-    {
-        let mut sessions = TRANSCODE_SESSIONS.lock().map_err(|e| e.to_string())?;
-        sessions.insert(session_id, session);
+    for i in 0..variants.len() {
+        args.extend(["-map".to_string(), format!("[v{}]", i)]);
     }
+    // Every variant carries its own copy of the audio
+    for _ in variants {
+        args.extend(["-map".to_string(), audio_map(options)]);
+    }
+    for (i, variant) in variants.iter().enumerate() {
+        args.extend(video_codec_args(variant, &format!(":v:{}", i)));
+    }
+    args.extend([
+        "-force_key_frames".to_string(),
+        format!("expr:gte(t,n_forced*{})", HLS_SEGMENT_SECONDS),
+    ]);
+    args.extend(audio_args(options));
 
-    // Start HTTP server in background thread
-    let file_path_clone = file_path.to_string();
-    let ffmpeg_path_clone = ffmpeg_path.to_string();
-    let start_time_clone = start_time;
+    let stream_map: Vec<String> = (0..variants.len()).map(|i| format!("v:{},a:{}", i, i)).collect();
+    args.extend([
+        "-sn".to_string(),
+        "-f".to_string(), "hls".to_string(),
+        "-hls_time".to_string(), HLS_SEGMENT_SECONDS.to_string(),
+        "-hls_playlist_type".to_string(), "event".to_string(),
+        "-hls_flags".to_string(), "independent_segments+temp_file".to_string(),
+        "-hls_segment_filename".to_string(), dir.join("v%v").join("seg_%05d.ts").to_string_lossy().to_string(),
+        "-master_pl_name".to_string(), "master.m3u8".to_string(),
+        "-var_stream_map".to_string(), stream_map.join(" "),
+        dir.join("v%v").join("index.m3u8").to_string_lossy().to_string(),
+    ]);
+    args
+}
 
-    std::thread::spawn(move || {
-        run_transcode_server(port, &ffmpeg_path_clone, &file_path_clone, start_time_clone);
-    });
+/// Start an HLS session: FFmpeg writes the ladder into a temp folder and a small server
+/// hands out the playlists and segments as they appear
+fn start_hls(
+    ffmpeg_path: &str,
+    input: &TranscodeInput,
+    start_time: Option<f64>,
+    options: &TranscodeOptions,
+    session_id: u64,
+    server: Server,
+) -> Result<(u64, String), String> {
+    if options.hls_variants.iter().any(|v| v.is_remux()) {
+        return Err("Adaptive streaming needs quality profiles that re-encode the video".to_string());
+    }
+    let port = server
+        .server_addr()
+        .to_ip()
+        .map(|addr| addr.port())
+        .ok_or_else(|| "Transcode server is not bound to a TCP port".to_string())?;
+
+    let dir = hls_root().join(format!("session_{}", session_id));
+    for i in 0..options.hls_variants.len() {
+        std::fs::create_dir_all(dir.join(format!("v{}", i)))
+            .map_err(|e| format!("Failed to create transcode folder: {}", e))?;
+    }
 
-    // Small delay to let server start
-    std::thread::sleep(std::time::Duration::from_millis(500));
+    let args = hls_args(input, start_time, options, &dir);
+    println!("[TRANSCODE] Starting HLS FFmpeg with args: {:?}", loggable_args(&args));
 
-    let url = format!("http://127.0.0.1:{}/stream.mp4", port);
-    println!("[TRANSCODE] Started session {} at {}", session_id, url);
+    let monitor = new_monitor(start_time);
+    let ffmpeg = spawn_ffmpeg(ffmpeg_path, &args, false, &monitor)?;
+    let stream_url = format!("http://127.0.0.1:{}/master.m3u8", port);
+    insert_session(session_id, TranscodeSession {
+        ffmpeg,
+        stdout: None,
+        stream_url: stream_url.clone(),
+        is_hls: true,
+        source: input.location.clone(),
+        started_at: Instant::now(),
+        monitor: monitor.clone(),
+    })?;
 
-    Ok((session_id, url))
+    std::thread::spawn(move || run_hls_server(server, session_id, dir, monitor));
+
+    println!("[TRANSCODE] Started HLS session {} at {}", session_id, stream_url);
+    Ok((session_id, stream_url))
 }
 
-/// Run the transcoding HTTP server
-fn run_transcode_server(port: u16, ffmpeg_path: &str, file_path: &str, start_time: Option<f64>) {
-    let server = match Server::http(format!("127.0.0.1:{}", port)) {
-        Ok(s) => s,
-        Err(e) => {
-            println!("[TRANSCODE] Failed to start server: {}", e);
+/// Serve a session's HLS folder until the session is stopped, then delete it
+fn run_hls_server(server: Server, session_id: u64, dir: PathBuf, monitor: SharedMonitor) {
+    while is_active(session_id) {
+        let request = match server.recv_timeout(Duration::from_secs(1)) {
+            Ok(Some(request)) => request,
+            Ok(None) => continue,
+            Err(_) => break,
+        };
+        touch(&monitor);
+
+        // A request can wait on FFmpeg for a while, so variants must not queue behind it
+        let dir = dir.clone();
+        std::thread::spawn(move || serve_hls_file(request, session_id, &dir));
+    }
+
+    if let Err(e) = std::fs::remove_dir_all(&dir) {
+        println!("[TRANSCODE] Failed to remove {}: {}", dir.display(), e);
+    }
+    println!("[TRANSCODE] HLS session {} closed", session_id);
+}
+
+/// Answer one playlist or segment request from the session folder
+fn serve_hls_file(request: Request, session_id: u64, dir: &Path) {
+    let path = request.url().split('?').next().unwrap_or("").trim_start_matches('/').to_string();
+    // Only names FFmpeg writes: master.m3u8, v0/index.m3u8, v0/seg_00001.ts
+    let valid = !path.is_empty()
+        && path.split('/').all(|part| !part.is_empty() && part != ".." && !part.contains('\\'));
+    let file = dir.join(&path);
+
+    // Playlists and segments show up as FFmpeg gets to them
+    let deadline = Instant::now() + HLS_FILE_WAIT;
+    while valid && !file.exists() && Instant::now() < deadline && is_active(session_id) {
+        std::thread::sleep(Duration::from_millis(200));
+    }
+
+    let content_type = if path.ends_with(".m3u8") { "application/vnd.apple.mpegurl" } else { "video/mp2t" };
+    let mut response = match std::fs::read(&file) {
+        Ok(data) if valid => Response::from_data(data),
+        _ => {
+            let _ = request.respond(Response::from_string("Not found").with_status_code(404));
             return;
         }
     };
+    if let Ok(header) = Header::from_bytes("Content-Type", content_type) {
+        response.add_header(header);
+    }
+    // hls.js in the webview fetches cross-origin
+    if let Ok(header) = Header::from_bytes("Access-Control-Allow-Origin", "*") {
+        response.add_header(header);
+    }
+    let _ = request.respond(response);
+}
 
-    println!("[TRANSCODE] Server listening on port {}", port);
-
-    for request in server.incoming_requests() {
-        let url = request.url();
-        println!("[TRANSCODE] Request: {} {}", request.method(), url);
-
-        if url.starts_with("/stream") {
-            // Start FFmpeg and stream output
-            let mut args = vec![
-                "-hide_banner",
-                "-loglevel", "warning",
-            ];
-
-            let start_str;
-            if let Some(time) = start_time {
-                if time > 0.0 {
-                    start_str = format!("{:.2}", time);
-                    args.push("-ss");
-                    args.push(&start_str);
-                }
-            }
-
-            args.extend(vec![
-                "-i", file_path,
-                "-c:v", "libx264",
-                "-preset", "ultrafast",
-                "-tune", "zerolatency",
-                "-profile:v", "baseline",
-                "-level", "3.0",
-                "-pix_fmt", "yuv420p",
-                "-vf", "scale='min(1920,iw)':'min(1080,ih)':force_original_aspect_ratio=decrease",
-                "-c:a", "aac",
-                "-ac", "2",
-                "-b:a", "192k",
-                "-f", "mp4",
-                "-movflags", "frag_keyframe+empty_moov+faststart",
-                "pipe:1",
-            ]);
+/// Audio encoder and filter arguments: stereo AAC by default, 5.1 AAC/AC3 when the
+/// client can play it, with optional loudness normalization or night mode
+fn audio_args(options: &TranscodeOptions) -> Vec<String> {
+    let channels = options.audio_channels;
+    // Stereo sources stay stereo whatever the client supports
+    let surround = options.audio.surround.filter(|_| channels.is_none_or(|c| c > 2));
 
-            match Command::new(ffmpeg_path)
-                .args(&args)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::null())
-                .spawn()
-            {
-                Ok(mut child) => {
-                    if let Some(stdout) = child.stdout.take() {
-                        let content_type = Header::from_bytes(
-                            &b"Content-Type"[..],
-                            &b"video/mp4"[..]
-                        ).unwrap();
-
-                        let reader = BufReader::new(stdout);
-                        let response = Response::new(
-                            tiny_http::StatusCode(200),
-                            vec![content_type],
-                            reader,
-                            None,
-                            None,
-                        );
-
-                        if let Err(e) = request.respond(response) {
-                            println!("[TRANSCODE] Failed to send response: {}", e);
-                        }
-                    }
-                    let _ = child.wait();
-                }
-                Err(e) => {
-                    println!("[TRANSCODE] Failed to start FFmpeg: {}", e);
-                    let response = Response::from_string(format!("FFmpeg error: {}", e))
-                        .with_status_code(500);
-                    let _ = request.respond(response);
-                }
+    let mut filters: Vec<&str> = Vec::new();
+    match options.audio.normalize {
+        Some(AudioNormalization::Loudnorm) => filters.push(LOUDNORM),
+        Some(AudioNormalization::Night) => {
+            if surround.is_none() && channels.is_some_and(|c| c >= 6) {
+                filters.push(NIGHT_DOWNMIX);
             }
-
-            // Only handle one request then exit
-            break;
-        } else {
-            let response = Response::from_string("Not found").with_status_code(404);
-            let _ = request.respond(response);
+            filters.push(NIGHT_COMPRESSOR);
+            filters.push(NIGHT_LOUDNORM);
         }
+        None => {}
     }
 
-    println!("[TRANSCODE] Server on port {} shutting down", port);
+    let mut args: Vec<String> = match surround {
+        Some(SurroundCodec::Aac) => vec!["-c:a".into(), "aac".into(), "-b:a".into(), "384k".into()],
+        Some(SurroundCodec::Ac3) => vec!["-c:a".into(), "ac3".into(), "-b:a".into(), "448k".into()],
+        None => vec!["-c:a".into(), "aac".into(), "-b:a".into(), "192k".into()],
+    };
+    match (surround, channels) {
+        // 7.1 and up is folded into 5.1 (AC3 can't carry more)
+        (Some(_), Some(c)) => args.extend(["-ac".to_string(), c.min(6).to_string()]),
+        (Some(_), None) => {}
+        (None, _) => args.extend(["-ac".to_string(), "2".to_string()]),
+    }
+
+    if !filters.is_empty() {
+        args.extend(["-af".to_string(), filters.join(",")]);
+        // loudnorm resamples to 192 kHz internally
+        args.extend(["-ar".to_string(), "48000".to_string()]);
+    }
+    args
+}
+
+/// Convert a text subtitle stream inside a video to a WebVTT file
+pub fn extract_subtitle(ffmpeg_path: &str, source: &str, stream_index: i64, dest: &std::path::Path) -> Result<(), String> {
+    let output = Command::new(ffmpeg_path)
+        .args(["-nostdin", "-hide_banner", "-loglevel", "error", "-y"])
+        .args(["-i", source])
+        .args(["-map", &format!("0:{}", stream_index), "-c:s", "webvtt", "-f", "webvtt"])
+        .arg(dest)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run FFmpeg: {}", e))?;
+
+    if !output.status.success() {
+        let _ = std::fs::remove_file(dest);
+        return Err(format!("Subtitle extraction failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
 }
 
 /// Stop a transcoding session
 pub fn stop_transcode(session_id: u64) -> Result<(), String> {
     let mut sessions = TRANSCODE_SESSIONS.lock().map_err(|e| e.to_string())?;
 
-    if let Some(mut session) = sessions.remove(&session_id) {
-        if let Some(ref mut process) = session.ffmpeg_process {
-            let _ = process.kill();
-        }
+    // Dropping the session kills its FFmpeg; the server notices and shuts down
+    if sessions.remove(&session_id).is_some() {
+        save_pids(&sessions);
         println!("[TRANSCODE] Stopped session {}", session_id);
     }
 
@@ -260,12 +1063,168 @@ pub fn stop_transcode(session_id: u64) -> Result<(), String> {
 pub fn stop_all_transcodes() -> Result<(), String> {
     let mut sessions = TRANSCODE_SESSIONS.lock().map_err(|e| e.to_string())?;
 
-    for (id, mut session) in sessions.drain() {
-        if let Some(ref mut process) = session.ffmpeg_process {
-            let _ = process.kill();
-        }
+    for (id, _session) in sessions.drain() {
         println!("[TRANSCODE] Stopped session {}", id);
     }
+    save_pids(&sessions);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Chapter;
+
+    /// The value after the first `flag`
+    fn arg<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+        args.iter().position(|a| a == flag).and_then(|i| args.get(i + 1)).map(String::as_str)
+    }
+
+    /// The value after every `flag`
+    fn arg_values<'a>(args: &'a [String], flag: &str) -> Vec<&'a str> {
+        args.windows(2).filter(|pair| pair[0] == flag).map(|pair| pair[1].as_str()).collect()
+    }
+
+    fn profile(id: &str) -> QualityProfile {
+        default_profiles().into_iter().find(|p| p.id == id).unwrap()
+    }
+
+    fn audio(surround: Option<SurroundCodec>, normalize: Option<AudioNormalization>, channels: Option<i64>) -> TranscodeOptions {
+        TranscodeOptions {
+            audio: AudioOptions { stream_index: None, surround, normalize },
+            audio_channels: channels,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn burns_in_subtitles_before_scaling() {
+        let options = TranscodeOptions {
+            profile: profile("720p"),
+            burn_subtitle: Some(3),
+            audio: AudioOptions { stream_index: Some(2), ..Default::default() },
+            ..Default::default()
+        };
+        let args = ffmpeg_args(&TranscodeInput::file("/media/Arrival.mkv"), None, &options).unwrap();
+        assert_eq!(arg(&args, "-filter_complex"), Some("[0:v:0][0:3]overlay,scale=-2:'min(720,ih)'[v]"));
+        assert_eq!(arg_values(&args, "-map"), ["[v]", "0:2"]);
+        assert_eq!(arg(&args, "-c:v"), Some("libx264"));
+        assert_eq!(arg(&args, "-maxrate:v"), Some("4000k"));
+        assert_eq!(arg(&args, "-bufsize:v"), Some("8000k"));
+        assert!(!args.contains(&"-vf".to_string()));
+
+        // A remux has no picture to draw on
+        let options = TranscodeOptions { profile: profile("original"), ..options };
+        assert!(ffmpeg_args(&TranscodeInput::file("/media/Arrival.mkv"), None, &options).is_err());
+    }
+
+    #[test]
+    fn remux_copies_the_video() {
+        let options = TranscodeOptions { profile: profile("original"), ..Default::default() };
+        let args = ffmpeg_args(&TranscodeInput::file("/media/Arrival.mkv"), Some(90.5), &options).unwrap();
+        assert_eq!(arg(&args, "-ss"), Some("90.50"));
+        assert_eq!(arg(&args, "-i"), Some("/media/Arrival.mkv"));
+        assert_eq!(arg(&args, "-c:v"), Some("copy"));
+        assert!(!args.contains(&"-vf".to_string()));
+        assert!(!args.contains(&"-map".to_string()));
+        assert_eq!(arg(&args, "-f"), Some("mp4"));
+        assert_eq!(args.last().map(String::as_str), Some("pipe:1"));
+
+        // Starting from the top needs no seek; a re-encode scales down
+        let options = TranscodeOptions { profile: profile("1080p"), ..Default::default() };
+        let args = ffmpeg_args(&TranscodeInput::file("/media/Arrival.mkv"), Some(0.0), &options).unwrap();
+        assert!(!args.contains(&"-ss".to_string()));
+        assert_eq!(arg(&args, "-vf"), Some("scale=-2:'min(1080,ih)'"));
+    }
+
+    #[test]
+    fn keeps_surround_only_for_surround_sources() {
+        assert_eq!(audio_args(&audio(None, None, Some(6))), ["-c:a", "aac", "-b:a", "192k", "-ac", "2"]);
+        // 7.1 is folded into 5.1
+        assert_eq!(audio_args(&audio(Some(SurroundCodec::Ac3), None, Some(8))), ["-c:a", "ac3", "-b:a", "448k", "-ac", "6"]);
+        assert_eq!(audio_args(&audio(Some(SurroundCodec::Aac), None, None)), ["-c:a", "aac", "-b:a", "384k"]);
+        // A stereo source stays stereo whatever the client supports
+        assert_eq!(audio_args(&audio(Some(SurroundCodec::Ac3), None, Some(2))), ["-c:a", "aac", "-b:a", "192k", "-ac", "2"]);
+    }
+
+    #[test]
+    fn night_mode_lifts_dialogue() {
+        let night = format!("{},{},{}", NIGHT_DOWNMIX, NIGHT_COMPRESSOR, NIGHT_LOUDNORM);
+        let args = audio_args(&audio(None, Some(AudioNormalization::Night), Some(6)));
+        assert_eq!(arg(&args, "-af"), Some(night.as_str()));
+        assert_eq!(arg(&args, "-ar"), Some("48000"));
+
+        // Kept surround (or a stereo source) has no centre channel to pull out
+        let args = audio_args(&audio(Some(SurroundCodec::Aac), Some(AudioNormalization::Night), Some(6)));
+        assert_eq!(arg(&args, "-af"), Some(format!("{},{}", NIGHT_COMPRESSOR, NIGHT_LOUDNORM).as_str()));
+        let args = audio_args(&audio(None, Some(AudioNormalization::Loudnorm), Some(2)));
+        assert_eq!(arg(&args, "-af"), Some(LOUDNORM));
+    }
+
+    #[test]
+    fn hls_ladder_maps_each_variant() {
+        let options = TranscodeOptions {
+            hls_variants: vec![profile("1080p"), profile("720p"), profile("480p")],
+            ..Default::default()
+        };
+        let dir = Path::new("/tmp/streamvault-transcode/session_1");
+        let args = hls_args(&TranscodeInput::file("/media/Arrival.mkv"), None, &options, dir);
+
+        assert_eq!(
+            arg(&args, "-filter_complex"),
+            Some("[0:v:0]split=3[s0][s1][s2];[s0]scale=-2:'min(1080,ih)'[v0];[s1]scale=-2:'min(720,ih)'[v1];[s2]scale=-2:'min(480,ih)'[v2]")
+        );
+        assert_eq!(arg_values(&args, "-map"), ["[v0]", "[v1]", "[v2]", "0:a:0?", "0:a:0?", "0:a:0?"]);
+        assert_eq!(arg(&args, "-maxrate:v:0"), Some("8000k"));
+        assert_eq!(arg(&args, "-maxrate:v:2"), Some("1500k"));
+        assert_eq!(arg(&args, "-var_stream_map"), Some("v:0,a:0 v:1,a:1 v:2,a:2"));
+        assert_eq!(arg(&args, "-force_key_frames"), Some("expr:gte(t,n_forced*6)"));
+        assert_eq!(arg(&args, "-master_pl_name"), Some("master.m3u8"));
+        assert_eq!(args.last().map(PathBuf::from), Some(dir.join("v%v").join("index.m3u8")));
+    }
+
+    #[test]
+    fn redacts_the_drive_token_from_logs() {
+        let input = TranscodeInput {
+            location: "https://www.googleapis.com/drive/v3/files/abc?alt=media".to_string(),
+            headers: Some("Authorization: Bearer ya29.secret".to_string()),
+        };
+        let args = ffmpeg_args(&input, None, &TranscodeOptions::default()).unwrap();
+        assert_eq!(arg(&args, "-headers"), Some("Authorization: Bearer ya29.secret\r\n"));
+        assert_eq!(arg(&args, "-reconnect"), Some("1"));
+
+        let logged = loggable_args(&args);
+        assert!(logged.iter().all(|arg| !arg.contains("ya29")));
+        assert!(logged.contains(&"<redacted>"));
+        assert!(logged.contains(&input.location.as_str()));
+    }
+
+    #[test]
+    fn shifts_chapters_to_the_stream_start() {
+        let chapter = |start: f64, end: f64, title: &str| Chapter {
+            id: 0,
+            start_seconds: start,
+            end_seconds: end,
+            title: Some(title.to_string()),
+            source: "file".to_string(),
+        };
+        let chapters = [chapter(0.0, 60.0, "Intro"), chapter(60.0, 600.0, "Arrival"), chapter(600.0, 1200.0, "Contact")];
+
+        let shifted = stream_chapters(&chapters, Some(300.0));
+        let spans: Vec<(f64, f64, Option<&str>)> =
+            shifted.iter().map(|c| (c.start_seconds, c.end_seconds, c.title.as_deref())).collect();
+        assert_eq!(spans, [(0.0, 300.0, Some("Arrival")), (300.0, 900.0, Some("Contact"))]);
+        assert_eq!(stream_chapters(&chapters, None).len(), 3);
+    }
+
+    #[test]
+    fn reserved_slots_count_toward_the_limit() {
+        let max = MAX_SESSIONS.load(Ordering::SeqCst);
+        let slots: Vec<SessionSlot> = (0..max).map(|_| SessionSlot::reserve().unwrap()).collect();
+        assert!(SessionSlot::reserve().is_err());
+
+        drop(slots);
+        assert!(SessionSlot::reserve().is_ok());
+    }
+}
@@ -0,0 +1,253 @@
+// Trickplay Module
+// Seek preview thumbnails: ffmpeg grabs a frame every few seconds and tiles them into JPEG
+// sprite sheets, and a WebVTT thumbnails track maps each time range to a tile
+// (sprite_001.jpg#xywh=x,y,w,h). Generated once per media item into
+// <app data>/trickplay/media_{id}/ by a background worker, one job at a time.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+const INTERVAL_SECONDS: f64 = 10.0;
+const THUMB_WIDTH: u32 = 320;
+const THUMB_HEIGHT: u32 = 180;
+const TILE_COLUMNS: u32 = 10;
+const TILE_ROWS: u32 = 10;
+
+const VTT_FILE: &str = "thumbnails.vtt";
+
+/// What to generate thumbnails from
+#[derive(Debug, Clone)]
+pub struct TrickplayJob {
+    pub media_id: i64,
+    pub ffmpeg_path: String,
+    /// Local file (a local video, offline copy or cached cloud file)
+    pub source: String,
+    pub duration: Option<f64>,
+}
+
+/// Generated thumbnails, as asset URLs for the webview
+#[derive(Debug, Clone, Serialize)]
+pub struct TrickplayInfo {
+    pub vtt_url: String,
+    pub sprite_urls: Vec<String>,
+    pub interval_seconds: f64,
+    pub thumb_width: u32,
+    pub thumb_height: u32,
+}
+
+lazy_static::lazy_static! {
+    // Media ids queued or being generated
+    static ref PENDING: Mutex<HashSet<i64>> = Mutex::new(HashSet::new());
+    static ref QUEUE: Mutex<Option<std::sync::mpsc::Sender<TrickplayJob>>> = Mutex::new(None);
+}
+
+pub fn trickplay_dir(media_id: i64) -> PathBuf {
+    crate::database::get_app_data_dir().join("trickplay").join(format!("media_{}", media_id))
+}
+
+fn asset_url(path: &Path) -> String {
+    format!("asset://localhost/{}", path.to_string_lossy().replace("\\", "/").replace(":", ""))
+}
+
+/// Thumbnails for a media item, if they have been generated
+pub fn info(media_id: i64) -> Option<TrickplayInfo> {
+    let dir = trickplay_dir(media_id);
+    let vtt = dir.join(VTT_FILE);
+    if !vtt.exists() {
+        return None;
+    }
+
+    let mut sprites: Vec<PathBuf> = fs::read_dir(&dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "jpg"))
+        .collect();
+    sprites.sort();
+
+    Some(TrickplayInfo {
+        vtt_url: asset_url(&vtt),
+        sprite_urls: sprites.iter().map(|p| asset_url(p)).collect(),
+        interval_seconds: INTERVAL_SECONDS,
+        thumb_width: THUMB_WIDTH,
+        thumb_height: THUMB_HEIGHT,
+    })
+}
+
+pub fn is_pending(media_id: i64) -> bool {
+    PENDING.lock().map(|p| p.contains(&media_id)).unwrap_or(false)
+}
+
+/// Delete generated thumbnails
+pub fn remove(media_id: i64) {
+    let dir = trickplay_dir(media_id);
+    if dir.exists() {
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            println!("[TRICKPLAY] Failed to remove thumbnails for media {}: {}", media_id, e);
+        }
+    }
+}
+
+/// Delete thumbnails for every media item
+pub fn remove_all() {
+    let root = crate::database::get_app_data_dir().join("trickplay");
+    if root.exists() {
+        if let Err(e) = fs::remove_dir_all(&root) {
+            println!("[TRICKPLAY] Failed to remove thumbnails: {}", e);
+        }
+    }
+}
+
+/// Start the worker. `on_done` is called with the media id and result of each job.
+pub fn init(on_done: std::sync::Arc<dyn Fn(i64, Result<TrickplayInfo, String>) + Send + Sync>) {
+    let (sender, receiver) = std::sync::mpsc::channel::<TrickplayJob>();
+    if let Ok(mut queue) = QUEUE.lock() {
+        *queue = Some(sender);
+    }
+
+    std::thread::spawn(move || {
+        for job in receiver {
+            let media_id = job.media_id;
+            println!("[TRICKPLAY] Generating thumbnails for media {}", media_id);
+            let result = generate(&job);
+            match result {
+                Ok(ref info) => println!("[TRICKPLAY] Media {}: {} sprite sheet(s)", media_id, info.sprite_urls.len()),
+                Err(ref e) => {
+                    println!("[TRICKPLAY] Media {} failed: {}", media_id, e);
+                    remove(media_id);
+                }
+            }
+            if let Ok(mut pending) = PENDING.lock() {
+                pending.remove(&media_id);
+            }
+            on_done(media_id, result);
+        }
+    });
+}
+
+/// Queue thumbnail generation (ignored if the item is already queued)
+pub fn queue(job: TrickplayJob) -> Result<(), String> {
+    {
+        let mut pending = PENDING.lock().map_err(|e| e.to_string())?;
+        if !pending.insert(job.media_id) {
+            return Ok(());
+        }
+    }
+
+    let media_id = job.media_id;
+    let sent = QUEUE
+        .lock()
+        .map_err(|e| e.to_string())?
+        .as_ref()
+        .map(|sender| sender.send(job).is_ok())
+        .unwrap_or(false);
+    if !sent {
+        if let Ok(mut pending) = PENDING.lock() {
+            pending.remove(&media_id);
+        }
+        return Err("Thumbnail worker is not running".to_string());
+    }
+    Ok(())
+}
+
+/// Read "Duration: 00:42:10.12" from ffmpeg's log
+fn parse_duration(log: &str) -> Option<f64> {
+    let value = log.split("Duration: ").nth(1)?.split(',').next()?;
+    let mut parts = value.trim().split(':').map(|p| p.parse::<f64>().ok());
+    let (hours, minutes, seconds) = (parts.next()??, parts.next()??, parts.next()??);
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// WebVTT track pointing each interval at its tile
+fn build_vtt(duration: f64, sheet_count: usize) -> String {
+    fn timestamp(seconds: f64) -> String {
+        let millis = (seconds * 1000.0).round() as u64;
+        format!("{:02}:{:02}:{:02}.{:03}", millis / 3_600_000, (millis / 60_000) % 60, (millis / 1000) % 60, millis % 1000)
+    }
+
+    let per_sheet = (TILE_COLUMNS * TILE_ROWS) as usize;
+    let thumbs = ((duration / INTERVAL_SECONDS).ceil() as usize).min(sheet_count * per_sheet);
+
+    let mut vtt = String::from("WEBVTT\n");
+    for index in 0..thumbs {
+        let start = index as f64 * INTERVAL_SECONDS;
+        let end = (start + INTERVAL_SECONDS).min(duration);
+        let tile = (index % per_sheet) as u32;
+        vtt.push_str(&format!(
+            "\n{} --> {}\nsprite_{:03}.jpg#xywh={},{},{},{}\n",
+            timestamp(start),
+            timestamp(end),
+            index / per_sheet + 1,
+            (tile % TILE_COLUMNS) * THUMB_WIDTH,
+            (tile / TILE_COLUMNS) * THUMB_HEIGHT,
+            THUMB_WIDTH,
+            THUMB_HEIGHT,
+        ));
+    }
+    vtt
+}
+
+/// Extract frames into sprite sheets and write the VTT track
+pub fn generate(job: &TrickplayJob) -> Result<TrickplayInfo, String> {
+    let dir = trickplay_dir(job.media_id);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).map_err(|e| format!("Failed to clear old thumbnails: {}", e))?;
+    }
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create thumbnail folder: {}", e))?;
+
+    // Letterbox every frame to the same size so tile offsets are fixed
+    let filter = format!(
+        "fps=1/{interval},scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,tile={cols}x{rows}",
+        interval = INTERVAL_SECONDS,
+        w = THUMB_WIDTH,
+        h = THUMB_HEIGHT,
+        cols = TILE_COLUMNS,
+        rows = TILE_ROWS,
+    );
+
+    let mut command = Command::new(&job.ffmpeg_path);
+    command
+        .args(["-nostdin", "-hide_banner"])
+        // Only decode keyframes - far faster, and close enough for previews
+        .args(["-skip_frame", "nokey"])
+        .args(["-i", &job.source])
+        .args(["-an", "-sn", "-vf", &filter, "-vsync", "vfr", "-q:v", "5"])
+        .arg(dir.join("sprite_%03d.jpg"))
+        .stdin(Stdio::null());
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(0x08000000); // CREATE_NO_WINDOW
+    }
+
+    let output = command.output().map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    let log = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(format!("ffmpeg failed: {}", log.lines().last().unwrap_or_default()));
+    }
+
+    let sheet_count = fs::read_dir(&dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "jpg"))
+        .count();
+    if sheet_count == 0 {
+        return Err("ffmpeg produced no thumbnails".to_string());
+    }
+
+    let duration = job
+        .duration
+        .filter(|d| *d > 0.0)
+        .or_else(|| parse_duration(&log))
+        .ok_or_else(|| "Could not determine the video duration".to_string())?;
+    fs::write(dir.join(VTT_FILE), build_vtt(duration, sheet_count))
+        .map_err(|e| format!("Failed to write thumbnail track: {}", e))?;
+
+    info(job.media_id).ok_or_else(|| "Thumbnails missing after generation".to_string())
+}
@@ -0,0 +1,234 @@
+// VLC Progress Tracking Module
+// VLC is launched with its HTTP interface on a random localhost port and password, and
+// requests/status.json is polled for time/length/state while it plays. The port is only known
+// to be free when it is picked, so VLC is relaunched on a new one if its interface never answers.
+
+use std::net::TcpListener;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+use rand::Rng;
+use serde::Deserialize;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// How long VLC gets to bring up its HTTP interface before it is relaunched on another port
+const INTERFACE_TIMEOUT: Duration = Duration::from_secs(20);
+const MAX_LAUNCH_ATTEMPTS: u32 = 3;
+
+/// Subset of VLC's requests/status.json
+#[derive(Debug, Clone, Deserialize)]
+pub struct VlcStatus {
+    #[serde(default)]
+    pub time: f64,
+    #[serde(default)]
+    pub length: f64,
+    #[serde(default)]
+    pub position: f64,
+    #[serde(default)]
+    pub state: String,
+}
+
+/// Random localhost endpoint for VLC's HTTP interface
+pub struct VlcHttpInterface {
+    pub port: u16,
+    pub password: String,
+}
+
+impl VlcHttpInterface {
+    pub fn new() -> Result<Self, String> {
+        // Ask the OS for a free localhost port
+        let listener = TcpListener::bind("127.0.0.1:0").map_err(|e| format!("No free port for VLC: {}", e))?;
+        let port = listener.local_addr().map(|a| a.port()).map_err(|e| e.to_string())?;
+        let password: String = rand::thread_rng()
+            .gen::<[u8; 16]>()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Ok(Self { port, password })
+    }
+
+    /// Command line options that enable the interface
+    pub fn args(&self) -> Vec<String> {
+        vec![
+            "--extraintf=http".to_string(),
+            "--http-host=127.0.0.1".to_string(),
+            format!("--http-port={}", self.port),
+            format!("--http-password={}", self.password),
+            // Exit at the end so the monitor sees the finished state instead of an idle playlist
+            "--play-and-exit".to_string(),
+        ]
+    }
+}
+
+/// Builds the VLC command line for an HTTP interface, so a launch can be repeated on another port
+pub type VlcCommand = Box<dyn Fn(&VlcHttpInterface) -> Command + Send>;
+
+/// A running VLC instance and how to reach its HTTP interface
+pub struct VlcProcess {
+    pub child: Child,
+    pub interface: VlcHttpInterface,
+    command: VlcCommand,
+}
+
+impl VlcProcess {
+    /// Start VLC with its HTTP interface on a fresh port
+    pub fn spawn(command: VlcCommand) -> Result<Self, String> {
+        let (child, interface) = Self::spawn_child(&command)?;
+        Ok(Self { child, interface, command })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    /// Wait for the HTTP interface to answer, relaunching VLC on a new port if something else
+    /// took the port first. `on_relaunch` gets the new process id.
+    fn ensure_interface(&mut self, client: &reqwest::blocking::Client, on_relaunch: &mut dyn FnMut(u32)) {
+        for attempt in 1..=MAX_LAUNCH_ATTEMPTS {
+            let deadline = Instant::now() + INTERFACE_TIMEOUT;
+            while Instant::now() < deadline {
+                // Closed already (or never started) - nothing to relaunch
+                if !matches!(self.child.try_wait(), Ok(None)) {
+                    return;
+                }
+                if poll_vlc_status(client, self.interface.port, &self.interface.password).is_some() {
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(250));
+            }
+
+            if attempt == MAX_LAUNCH_ATTEMPTS {
+                println!("[VLC] HTTP interface never came up - progress won't be tracked");
+                return;
+            }
+            println!("[VLC] HTTP interface on port {} didn't come up, relaunching", self.interface.port);
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+            match Self::spawn_child(&self.command) {
+                Ok((child, interface)) => {
+                    self.child = child;
+                    self.interface = interface;
+                    on_relaunch(self.child.id());
+                }
+                Err(e) => {
+                    println!("[VLC] {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    fn spawn_child(command: &VlcCommand) -> Result<(Child, VlcHttpInterface), String> {
+        let interface = VlcHttpInterface::new()?;
+        println!("[VLC] Launching with HTTP interface on 127.0.0.1:{}", interface.port);
+        let child = command(&interface).spawn().map_err(|e| format!("Failed to launch VLC: {}", e))?;
+        Ok((child, interface))
+    }
+}
+
+/// Result of a tracked VLC playback
+#[derive(Debug, Clone)]
+pub struct VlcPlaybackResult {
+    pub final_position: Option<f64>,
+    pub final_duration: Option<f64>,
+    pub completed: bool,
+}
+
+/// Launch VLC with the HTTP interface enabled so playback can be tracked
+pub fn launch_vlc_with_tracking(
+    vlc_path: &str,
+    file_or_url: &str,
+    start_position: f64,
+    sub_file: Option<&str>,
+) -> Result<VlcProcess, String> {
+    let vlc_path = vlc_path.to_string();
+    let file_or_url = file_or_url.to_string();
+    let sub_file = sub_file.map(str::to_string);
+
+    VlcProcess::spawn(Box::new(move |interface| {
+        let mut command = Command::new(&vlc_path);
+        command.args(interface.args()).arg(&file_or_url);
+
+        // Add start time if resuming (as input option after the file)
+        if start_position > 0.0 {
+            command.arg(format!(":start-time={:.0}", start_position));
+        }
+
+        // VLC takes a single external subtitle file
+        if let Some(ref sub_file) = sub_file {
+            command.arg(format!("--sub-file={}", sub_file));
+        }
+        command
+    }))
+}
+
+/// Read the current playback status from VLC's HTTP interface
+pub fn poll_vlc_status(client: &reqwest::blocking::Client, port: u16, password: &str) -> Option<VlcStatus> {
+    client
+        .get(format!("http://127.0.0.1:{}/requests/status.json", port))
+        .basic_auth("", Some(password))
+        .send()
+        .ok()?
+        .json::<VlcStatus>()
+        .ok()
+}
+
+/// Monitor VLC until it exits, saving progress to the database as it plays.
+/// This should be called in a background thread after launching VLC.
+/// `on_relaunch` gets the new process id if VLC had to be started again on another port.
+pub fn monitor_vlc_and_save_progress(
+    db: &crate::database::Database,
+    media_id: i64,
+    mut process: VlcProcess,
+    on_relaunch: &mut dyn FnMut(u32),
+) -> VlcPlaybackResult {
+    println!("[VLC] Monitoring VLC process {} for media {}", process.pid(), media_id);
+
+    let client = match reqwest::blocking::Client::builder().timeout(Duration::from_secs(2)).build() {
+        Ok(client) => client,
+        Err(e) => {
+            println!("[VLC] Failed to create HTTP client: {}", e);
+            process.child.wait().ok();
+            return VlcPlaybackResult { final_position: None, final_duration: None, completed: false };
+        }
+    };
+
+    process.ensure_interface(&client, on_relaunch);
+
+    // Last status that had a real duration (VLC reports 0 while opening and after stopping)
+    let mut last: Option<VlcStatus> = None;
+
+    while let Ok(None) = process.child.try_wait() {
+        std::thread::sleep(POLL_INTERVAL);
+
+        if let Some(status) = poll_vlc_status(&client, process.interface.port, &process.interface.password) {
+            if status.length > 0.0 && status.state != "stopped" {
+                let _ = db.update_progress(media_id, status.time, status.length);
+                last = Some(status);
+            }
+        }
+    }
+
+    match last {
+        Some(status) => {
+            // Polling is coarse, so treat the last seconds as the end too - as are the end credits
+            let credits_start = db.get_skip_markers(media_id).ok().and_then(|m| m.credits_start);
+            let completed = status.position >= 0.95
+                || status.time >= status.length - 2.0
+                || credits_start.is_some_and(|credits| status.time >= credits);
+            if completed {
+                let _ = db.update_progress(media_id, status.length, status.length);
+            }
+            println!("[VLC] Final progress: {:.0}s / {:.0}s (completed: {})", status.time, status.length, completed);
+
+            VlcPlaybackResult {
+                final_position: Some(status.time),
+                final_duration: Some(status.length),
+                completed,
+            }
+        }
+        None => {
+            println!("[VLC] No progress data received before VLC exited");
+            VlcPlaybackResult { final_position: None, final_duration: None, completed: false }
+        }
+    }
+}
@@ -64,6 +64,7 @@ interface MpvPlaybackEndedPayload {
   final_position?: number
   final_duration?: number
   completed: boolean
+  player?: 'vlc'
 }
 
 type ViewMode = 'grid' | 'list'
@@ -2,6 +2,7 @@ import { useState, useRef, useEffect, useCallback } from 'react'
 import { X, Play, Pause, Volume2, VolumeX, Maximize2, Minimize2, SkipBack, SkipForward, Settings, Loader2, AlertTriangle } from 'lucide-react'
 import { Slider } from '@/components/ui/slider'
 import { invoke } from '@tauri-apps/api/tauri'
+import { getTranscodeStatus, stopTranscodeStream, TranscodeStatus } from '@/services/api'
 
 interface VideoPlayerProps {
     src: string
@@ -12,12 +13,11 @@ interface VideoPlayerProps {
     initialTime?: number
     // Cloud streaming fields
     isCloud?: boolean
-    accessToken?: string
     // Media ID for transcoding fallback
     mediaId?: number
 }
 
-export function VideoPlayer({ src, title, poster, onClose, onProgress, initialTime = 0, isCloud = false, accessToken, mediaId }: VideoPlayerProps) {
+export function VideoPlayer({ src, title, poster, onClose, onProgress, initialTime = 0, isCloud = false, mediaId }: VideoPlayerProps) {
     const videoRef = useRef<HTMLVideoElement>(null)
     const containerRef = useRef<HTMLDivElement>(null)
     const progressReportRef = useRef<number>(0)
@@ -37,6 +37,8 @@ export function VideoPlayer({ src, title, poster, onClose, onProgress, initialTi
     // Transcoding state
     const [isTranscoding, setIsTranscoding] = useState(false)
     const [transcodeAttempted, setTranscodeAttempted] = useState(false)
+    const [transcodeSessionId, setTranscodeSessionId] = useState<number | null>(null)
+    const [transcodeStatus, setTranscodeStatus] = useState<TranscodeStatus | null>(null)
 
     const hideControlsTimeout = useRef<NodeJS.Timeout | null>(null)
 
@@ -62,6 +64,7 @@ export function VideoPlayer({ src, title, poster, onClose, onProgress, initialTi
             });
 
             console.log('[VideoPlayer] Transcoding started:', result);
+            setTranscodeSessionId(result.session_id);
             setVideoSrc(result.stream_url);
             setIsTranscoding(false);
             return true;
@@ -74,6 +77,35 @@ export function VideoPlayer({ src, title, poster, onClose, onProgress, initialTi
         }
     }, [transcodeAttempted, isCloud, initialTime]);
 
+    // Poll the transcode session while it's open: shows FFmpeg's progress, and keeps the
+    // session from being reaped as idle while playback is paused
+    useEffect(() => {
+        if (transcodeSessionId === null) return;
+        const sessionId = transcodeSessionId;
+
+        const poll = async () => {
+            try {
+                const status = await getTranscodeStatus(sessionId);
+                setTranscodeStatus(status);
+                if (status.state === 'failed') {
+                    const reason = status.log[status.log.length - 1];
+                    setError(`Transcoding stopped${reason ? `: ${reason}` : ''}. Try using MPV or VLC player instead.`);
+                    setIsLoading(false);
+                }
+            } catch (e) {
+                console.warn('[VideoPlayer] Transcode session ended:', e);
+                setTranscodeStatus(null);
+            }
+        };
+
+        poll();
+        const interval = setInterval(poll, 15000);
+        return () => {
+            clearInterval(interval);
+            stopTranscodeStream(sessionId);
+        };
+    }, [transcodeSessionId]);
+
     // Load video file as blob URL using Tauri commands
     useEffect(() => {
         let cancelled = false;
@@ -82,7 +114,6 @@ export function VideoPlayer({ src, title, poster, onClose, onProgress, initialTi
             console.log('[VideoPlayer] ========== LOADING VIDEO ==========');
             console.log('[VideoPlayer] Source:', src);
             console.log('[VideoPlayer] isCloud:', isCloud);
-            console.log('[VideoPlayer] accessToken:', accessToken ? 'present' : 'none');
             console.log('[VideoPlayer] mediaId:', mediaId);
             console.log('[VideoPlayer] initialTime:', initialTime);
 
@@ -196,7 +227,7 @@ export function VideoPlayer({ src, title, poster, onClose, onProgress, initialTi
                 blobUrlRef.current = null;
             }
         };
-    }, [src, isCloud, accessToken, attemptTranscode]);
+    }, [src, isCloud, attemptTranscode]);
 
     // Handle video playback error - try transcoding as fallback
     const handleVideoError = useCallback(async (e: React.SyntheticEvent<HTMLVideoElement>) => {
@@ -451,6 +482,13 @@ export function VideoPlayer({ src, title, poster, onClose, onProgress, initialTi
                 <div className="bg-gradient-to-b from-black/60 to-transparent p-6 flex items-start justify-between backdrop-blur-[2px]">
                     <div className="flex flex-col">
                         <h1 className="text-white text-xl font-bold tracking-tight drop-shadow-md">{title}</h1>
+                        {transcodeStatus && (
+                            <p className="text-white/60 text-xs mt-1">
+                                Transcoding
+                                {transcodeStatus.progress.speed ? ` · ${transcodeStatus.progress.speed.toFixed(1)}x` : ''}
+                                {transcodeStatus.state === 'finished' ? ' · done' : ''}
+                            </p>
+                        )}
                     </div>
                     <button
                         onClick={onClose}
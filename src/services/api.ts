@@ -24,19 +24,82 @@ export interface MediaItem {
     cloud_file_id?: string;
 }
 
+export interface PlayerCapabilities {
+    auth_headers: boolean;
+    resume: boolean;
+    progress_tracking: boolean;
+}
+
+// Placeholders: {source} {start} {title} {subtitle} {headers}; [ ... ] marks an optional group
+export interface PlayerProfile {
+    id: string;
+    name: string;
+    executable: string;
+    args: string;
+    kind: 'mpv' | 'vlc' | 'generic';
+    capabilities: PlayerCapabilities;
+    builtin?: boolean;
+}
+
+export interface PlayerProfileInfo extends PlayerProfile {
+    available: boolean;
+}
+
+// Transcode quality; without video_kbps the video is copied (remux)
+export interface QualityProfile {
+    id: string;
+    name: string;
+    max_height?: number;
+    video_kbps?: number;
+    crf?: number;
+}
+
 export interface Config {
     mpv_path?: string;
     vlc_path?: string;
+    player_profiles?: PlayerProfile[];
+    // Default mpv track languages, overridden by choices remembered per series
+    track_audio_languages?: string[];
+    track_subtitle_languages?: string[];
     ffprobe_path?: string;
     ffmpeg_path?: string;
+    // Transcode quality ladder, default profile id and concurrent session limit
+    transcode_profiles?: QualityProfile[];
+    default_transcode_profile?: string;
+    max_transcode_sessions?: number;
     tmdb_api_key?: string;
+    // Subtitle downloads (preferred ISO 639-1 languages, best first)
+    subtitle_languages?: string[];
+    opensubtitles_api_key?: string;
+    opensubtitles_url?: string;
     // Cloud cache settings
     cloud_cache_enabled?: boolean;
     cloud_cache_dir?: string;
     cloud_cache_max_mb?: number;
+    cloud_cache_quota_mb?: number;
     cloud_cache_expiry_hours?: number;
+    // Next-episode prefetch (minutes of playback, 0 = whole file; KB/s cap, 0 = unlimited)
+    prefetch_next_episode?: boolean;
+    prefetch_minutes?: number;
+    prefetch_max_kbps?: number;
     // Cloud auto-scan interval in minutes
     cloud_scan_interval_minutes?: number;
+    // Offline downloads
+    download_dir?: string;
+    max_concurrent_downloads?: number;
+    // Google Drive auth: 'hosted' (StreamVault auth server) or 'direct' (local OAuth + PKCE)
+    gdrive_auth_mode?: 'hosted' | 'direct';
+    gdrive_client_id?: string;
+    gdrive_client_secret?: string;
+    // DLNA/UPnP media server for TVs on the LAN
+    dlna_enabled?: boolean;
+    dlna_name?: string;
+    dlna_port?: number;
+    // Jellyfin-compatible API so Jellyfin apps can browse and play the library
+    jellyfin_enabled?: boolean;
+    jellyfin_port?: number;
+    jellyfin_username?: string;
+    jellyfin_password?: string;
 }
 
 export interface ResumeInfo {
@@ -45,6 +108,8 @@ export interface ResumeInfo {
     duration: number;
     time_str: string;
     progress_percent: number;
+    // Chapter containing the resume position, to offer resuming from its start
+    chapter?: Chapter;
 }
 
 export interface StreamInfo {
@@ -54,9 +119,107 @@ export interface StreamInfo {
     poster?: string;
     duration_seconds?: number;
     resume_position_seconds?: number;
-    // Cloud streaming fields
+    // Cloud media streams through the local proxy
     is_cloud?: boolean;
-    access_token?: string;
+    // External subtitles as WebVTT through the local proxy
+    subtitles?: SubtitleTrack[];
+    // Chapters on the stream's timeline
+    chapters?: Chapter[];
+}
+
+export interface SubtitleTrack {
+    id: number;
+    label: string;
+    language?: string;
+    forced: boolean;
+    url: string;
+}
+
+export interface MediaStream {
+    index: number;
+    codec: string;
+    language?: string;
+    title?: string;
+    channels?: number;
+    channel_layout?: string;
+    is_default: boolean;
+    forced: boolean;
+}
+
+export interface Chapter {
+    id: number;
+    start_seconds: number;
+    end_seconds: number;
+    title?: string;
+    source: 'embedded' | 'manual';
+}
+
+export interface MediaTechInfo {
+    container?: string;
+    duration_seconds?: number;
+    bitrate?: number;
+    video_codec?: string;
+    width?: number;
+    height?: number;
+    hdr?: string;
+    audio_tracks: MediaStream[];
+    subtitle_tracks: MediaStream[];
+    chapters: Chapter[];
+}
+
+export interface MediaDetails extends MediaItem {
+    technical?: MediaTechInfo;
+}
+
+export interface SkipMarkers {
+    intro_start?: number;
+    intro_end?: number;
+    credits_start?: number;
+}
+
+export interface SkipDetectionProgress {
+    series_id: number;
+    season: number;
+    analysed: number;
+    total: number;
+}
+
+export interface TrickplayInfo {
+    vtt_url: string;
+    sprite_urls: string[];
+    interval_seconds: number;
+    thumb_width: number;
+    thumb_height: number;
+}
+
+export interface TrickplayReady {
+    media_id: number;
+    info?: TrickplayInfo;
+    error?: string;
+}
+
+export interface TrackPreferences {
+    series_id: number;
+    audio_lang?: string;
+    audio_track?: number;
+    sub_lang?: string;
+    sub_track?: number;
+    subs_off: boolean;
+}
+
+export interface Subtitle {
+    id: number;
+    media_id: number;
+    file_name: string;
+    location: string;
+    is_cloud: boolean;
+    language?: string;
+    label: string;
+    format: string;
+    forced: boolean;
+    sdh: boolean;
+    // "local", "drive", "embedded" (extracted from the video) or "opensubtitles"
+    source: string;
 }
 
 // Get library items (movies or TV shows)
@@ -371,10 +534,10 @@ export const getResumeInfo = async (id: number): Promise<ResumeInfo> => {
     }
 };
 
-// Get media info by ID
-export const getMediaInfo = async (id: number): Promise<MediaItem> => {
+// Get media info by ID (with technical info once the file has been analysed)
+export const getMediaInfo = async (id: number): Promise<MediaDetails> => {
     try {
-        const media = await invoke<MediaItem>('get_media_info', { mediaId: id });
+        const media = await invoke<MediaDetails>('get_media_info', { mediaId: id });
         return media;
     } catch (error) {
         console.error('Failed to get media info:', error);
@@ -382,6 +545,16 @@ export const getMediaInfo = async (id: number): Promise<MediaItem> => {
     }
 };
 
+// Run ffprobe on a media item now (Drive files are read through the stream proxy)
+export const analyzeMedia = async (mediaId: number): Promise<MediaTechInfo> => {
+    try {
+        return await invoke<MediaTechInfo>('analyze_media', { mediaId });
+    } catch (error) {
+        console.error('Failed to analyse media:', error);
+        throw error;
+    }
+};
+
 // Get stream info for built-in player
 export const getStreamUrl = async (id: number): Promise<StreamInfo> => {
     try {
@@ -404,6 +577,102 @@ export const getStreamUrlWithTranscode = async (id: number): Promise<StreamInfo>
     }
 };
 
+// External subtitle files found for a media item
+export const getSubtitles = async (mediaId: number): Promise<Subtitle[]> => {
+    try {
+        return await invoke<Subtitle[]>('get_subtitles', { mediaId });
+    } catch (error) {
+        console.error('Failed to get subtitles:', error);
+        return [];
+    }
+};
+
+// Download the best OpenSubtitles match for each preferred language
+export const downloadSubtitles = async (mediaId: number): Promise<Subtitle[]> => {
+    try {
+        return await invoke<Subtitle[]>('download_subtitles', { mediaId });
+    } catch (error) {
+        console.error('Failed to download subtitles:', error);
+        throw error;
+    }
+};
+
+// Detect intros/credits by comparing episodes' audio (progress via 'skip-detection-progress')
+export const detectSkipMarkers = async (seriesId: number, season?: number): Promise<number> => {
+    try {
+        return await invoke<number>('detect_skip_markers', { seriesId, season });
+    } catch (error) {
+        console.error('Failed to detect skip markers:', error);
+        throw error;
+    }
+};
+
+export const getSkipMarkers = async (mediaId: number): Promise<SkipMarkers> => {
+    try {
+        return await invoke<SkipMarkers>('get_skip_markers', { mediaId });
+    } catch (error) {
+        console.error('Failed to get skip markers:', error);
+        return {};
+    }
+};
+
+// Seek preview thumbnails; null while they're being generated (completion via 'trickplay-ready')
+export const getTrickplay = async (mediaId: number): Promise<TrickplayInfo | null> => {
+    try {
+        return await invoke<TrickplayInfo | null>('get_trickplay', { mediaId });
+    } catch (error) {
+        console.error('Failed to get trickplay thumbnails:', error);
+        throw error;
+    }
+};
+
+// Chapters of a media item (manual markers replace embedded chapters)
+export const getChapters = async (mediaId: number): Promise<Chapter[]> => {
+    try {
+        return await invoke<Chapter[]>('get_chapters', { mediaId });
+    } catch (error) {
+        console.error('Failed to get chapters:', error);
+        return [];
+    }
+};
+
+export const addChapter = async (mediaId: number, startSeconds: number, title?: string): Promise<Chapter[]> => {
+    try {
+        return await invoke<Chapter[]>('add_chapter', { mediaId, startSeconds, title: title ?? null });
+    } catch (error) {
+        console.error('Failed to add chapter:', error);
+        throw error;
+    }
+};
+
+export const deleteChapter = async (mediaId: number, chapterId: number): Promise<Chapter[]> => {
+    try {
+        return await invoke<Chapter[]>('delete_chapter', { mediaId, chapterId });
+    } catch (error) {
+        console.error('Failed to delete chapter:', error);
+        throw error;
+    }
+};
+
+// Audio/subtitle tracks remembered for a series
+export const getTrackPreferences = async (seriesId: number): Promise<TrackPreferences | null> => {
+    try {
+        return await invoke<TrackPreferences | null>('get_track_preferences', { seriesId });
+    } catch (error) {
+        console.error('Failed to get track preferences:', error);
+        return null;
+    }
+};
+
+export const clearTrackPreferences = async (seriesId: number): Promise<{ message: string }> => {
+    try {
+        return await invoke<{ message: string }>('clear_track_preferences', { seriesId });
+    } catch (error) {
+        console.error('Failed to clear track preferences:', error);
+        throw error;
+    }
+};
+
 // Check if a file needs transcoding for HTML5 playback
 export const checkNeedsTranscode = async (filePath: string): Promise<boolean> => {
     try {
@@ -418,14 +687,51 @@ export const checkNeedsTranscode = async (filePath: string): Promise<boolean> =>
 export interface TranscodeResponse {
     session_id: number;
     stream_url: string;
+    // Relative to the start of the transcoded stream
+    chapters: Chapter[];
+    // Selected text subtitle stream as WebVTT (image subtitles are burned into the video)
+    subtitle?: SubtitleTrack;
+    // stream_url is an HLS master playlist (quality 'auto') rather than an MP4
+    is_hls: boolean;
+}
+
+// Audio choices for a transcode session
+export interface TranscodeAudioOptions {
+    // Audio stream index from MediaTechInfo.audio_tracks
+    stream_index?: number;
+    // Keep 5.1 when the client can decode it (default: stereo AAC)
+    surround?: 'aac' | 'ac3';
+    // 'night' compresses dynamics and lifts dialogue
+    normalize?: 'loudnorm' | 'night';
 }
 
-// Start transcoding a video file
-export const startTranscodeStream = async (filePath: string, startTime?: number): Promise<TranscodeResponse> => {
+// Why a transcode failed to start; Drive refusals get their own kinds
+export interface TranscodeError {
+    kind: 'drive_quota_exceeded' | 'drive_rate_limited' | 'drive_forbidden' | 'failed';
+    message: string;
+}
+
+// Start transcoding a video file, or a media item by id (pass mediaId to get its chapters;
+// Drive items are read straight from Drive, so filePath can be omitted).
+// Rejects with a TranscodeError.
+// subtitleStream is an embedded stream index from MediaTechInfo.subtitle_tracks.
+// quality is a QualityProfile id, or 'auto' for an adaptive HLS stream.
+export const startTranscodeStream = async (
+    filePath: string | null,
+    startTime?: number,
+    mediaId?: number,
+    subtitleStream?: number,
+    audio?: TranscodeAudioOptions,
+    quality?: string
+): Promise<TranscodeResponse> => {
     try {
         return await invoke<TranscodeResponse>('start_transcode_stream', {
-            filePath,
-            startTime: startTime || null
+            filePath: filePath || null,
+            startTime: startTime || null,
+            mediaId: mediaId ?? null,
+            subtitleStream: subtitleStream ?? null,
+            audio: audio ?? null,
+            quality: quality ?? null
         });
     } catch (error) {
         console.error('Failed to start transcode stream:', error);
@@ -442,6 +748,55 @@ export const stopTranscodeStream = async (sessionId: number): Promise<void> => {
     }
 };
 
+// Live numbers from FFmpeg for a transcoding session
+export interface TranscodeProgress {
+    frame: number;
+    fps: number;
+    // Encoding speed relative to playback (2 = twice real time)
+    speed?: number;
+    // How far into the source FFmpeg has got
+    position_seconds: number;
+}
+
+export interface TranscodeStatus {
+    session_id: number;
+    stream_url: string;
+    is_hls: boolean;
+    source: string;
+    state: 'running' | 'finished' | 'failed';
+    progress: TranscodeProgress;
+    uptime_seconds: number;
+    idle_seconds: number;
+    // Recent FFmpeg warnings and errors
+    log: string[];
+}
+
+// Session status; poll it while the player is open - idle sessions are stopped after two minutes
+export const getTranscodeStatus = async (sessionId: number): Promise<TranscodeStatus> => {
+    return await invoke<TranscodeStatus>('get_transcode_status', { sessionId });
+};
+
+export const listTranscodeSessions = async (): Promise<TranscodeStatus[]> => {
+    try {
+        return await invoke<TranscodeStatus[]>('list_transcode_sessions');
+    } catch (error) {
+        console.error('Failed to list transcode sessions:', error);
+        return [];
+    }
+};
+
+export interface DlnaStatus {
+    running: boolean;
+    friendly_name: string | null;
+    port: number | null;
+    // Device description URL on the LAN address
+    description_url: string | null;
+}
+
+export const getDlnaStatus = async (): Promise<DlnaStatus> => {
+    return await invoke<DlnaStatus>('get_dlna_status');
+};
+
 // Update watch progress
 export const updateWatchProgress = async (id: number, currentTime: number, duration: number): Promise<void> => {
     try {
@@ -485,6 +840,26 @@ export const playWithVlc = async (id: number, resume: boolean): Promise<void> =>
     }
 };
 
+// List built-in and user player profiles
+export const getPlayerProfiles = async (): Promise<PlayerProfileInfo[]> => {
+    try {
+        return await invoke<PlayerProfileInfo[]>('get_player_profiles');
+    } catch (error) {
+        console.error('Failed to get player profiles:', error);
+        return [];
+    }
+};
+
+// Play media with a player profile
+export const playWithProfile = async (profileId: string, id: number, resume: boolean): Promise<void> => {
+    try {
+        await invoke('play_with_profile', { profileId, mediaId: id, resume });
+    } catch (error) {
+        console.error('Failed to play with player profile:', error);
+        throw error;
+    }
+};
+
 // Fix match - update metadata from TMDB
 export const fixMatch = async (id: number, tmdbId: string, type: 'movie' | 'tv'): Promise<void> => {
     try {
@@ -576,6 +951,16 @@ export const getActiveMpvSessions = async (): Promise<MpvSession[]> => {
     }
 };
 
+// Get all active VLC sessions
+export const getActiveVlcSessions = async (): Promise<MpvSession[]> => {
+    try {
+        return await invoke<MpvSession[]>('get_active_vlc_sessions');
+    } catch (error) {
+        console.error('Failed to get active VLC sessions:', error);
+        return [];
+    }
+};
+
 // ==================== TMDB EPISODE METADATA ====================
 
 // Episode info from TMDB with rich metadata
@@ -832,7 +1217,22 @@ export interface CloudCacheInfo {
     total_size_mb: number;
     file_count: number;
     max_size_mb: number;
+    quota_mb: number;
     expiry_hours: number;
+    // Least recently used first
+    entries: CloudCacheEntry[];
+}
+
+export interface CloudCacheEntry {
+    media_id: number;
+    title: string;
+    path: string;
+    size_bytes: number;
+    last_access: number;
+    pinned: boolean;
+    series_pinned: boolean;
+    in_progress: boolean;
+    protected: boolean;
 }
 
 // Get cloud cache info and statistics
@@ -848,7 +1248,9 @@ export const getCloudCacheInfo = async (): Promise<CloudCacheInfo> => {
             total_size_mb: 0,
             file_count: 0,
             max_size_mb: 1024,
+            quota_mb: 10240,
             expiry_hours: 24,
+            entries: [],
         };
     }
 };
@@ -863,6 +1265,16 @@ export const cleanupCloudCache = async (): Promise<{ message: string }> => {
     }
 };
 
+// Pin or unpin a cached item (a TV show pins all of its episodes)
+export const setCachePinned = async (mediaId: number, pinned: boolean): Promise<{ message: string }> => {
+    try {
+        return await invoke<{ message: string }>('set_cache_pinned', { mediaId, pinned });
+    } catch (error) {
+        console.error('Failed to update cache pin:', error);
+        throw error;
+    }
+};
+
 // Clear all cloud cache
 export const clearCloudCache = async (): Promise<{ message: string }> => {
     try {
@@ -954,3 +1366,60 @@ export const getAppVersion = async (): Promise<string> => {
     }
 };
 
+
+// ==================== Offline Downloads ====================
+
+export interface DownloadRecord {
+    media_id: number;
+    title: string;
+    cloud_file_id: string;
+    dest_path: string;
+    total_bytes?: number;
+    downloaded_bytes: number;
+    md5_checksum?: string;
+    status: 'queued' | 'downloading' | 'paused' | 'completed' | 'failed';
+    error?: string;
+}
+
+// Payload of the 'download-progress' event
+export interface DownloadProgress {
+    media_id: number;
+    downloaded_bytes: number;
+    total_bytes?: number;
+    status: DownloadRecord['status'];
+    error?: string;
+}
+
+// Queue cloud movies/episodes for offline playback
+export const queueDownloads = async (mediaIds: number[]): Promise<string> => {
+    const result = await invoke<{ message: string }>('queue_downloads', { mediaIds });
+    return result.message;
+};
+
+// Queue every episode of a season
+export const queueSeasonDownload = async (seriesId: number, seasonNumber: number): Promise<string> => {
+    const result = await invoke<{ message: string }>('queue_season_download', { seriesId, seasonNumber });
+    return result.message;
+};
+
+export const getDownloads = async (): Promise<DownloadRecord[]> => {
+    try {
+        return await invoke<DownloadRecord[]>('get_downloads');
+    } catch (error) {
+        console.error('Failed to get downloads:', error);
+        return [];
+    }
+};
+
+export const pauseDownload = async (mediaId: number): Promise<void> => {
+    await invoke('pause_download', { mediaId });
+};
+
+export const resumeDownload = async (mediaId: number): Promise<void> => {
+    await invoke('resume_download', { mediaId });
+};
+
+// Cancel an in-progress download or delete a finished one
+export const removeDownload = async (mediaId: number): Promise<void> => {
+    await invoke('remove_download', { mediaId });
+};
@@ -25,6 +25,16 @@ export interface DriveListResponse {
     nextPageToken?: string;
 }
 
+export interface ConnectedAccount {
+    account_id: string;
+    is_primary: boolean;
+}
+
+export interface SharedDrive {
+    id: string;
+    name: string;
+}
+
 // ==================== Connection Status ====================
 
 /**
@@ -42,15 +52,39 @@ export const isGDriveConnected = async (): Promise<boolean> => {
 /**
  * Get Google Drive account info (email, name, storage)
  */
-export const getGDriveAccountInfo = async (): Promise<DriveAccountInfo | null> => {
+export const getGDriveAccountInfo = async (accountId?: string): Promise<DriveAccountInfo | null> => {
     try {
-        return await invoke<DriveAccountInfo>('gdrive_get_account_info');
+        return await invoke<DriveAccountInfo>('gdrive_get_account_info', { accountId });
     } catch (error) {
         console.error('[GDrive] Failed to get account info:', error);
         return null;
     }
 };
 
+/**
+ * List connected Google accounts (primary first)
+ */
+export const listGDriveAccounts = async (): Promise<ConnectedAccount[]> => {
+    try {
+        return await invoke<ConnectedAccount[]>('gdrive_list_accounts');
+    } catch (error) {
+        console.error('[GDrive] Failed to list accounts:', error);
+        return [];
+    }
+};
+
+/**
+ * List Shared Drives visible to an account
+ */
+export const listSharedDrives = async (accountId?: string): Promise<SharedDrive[]> => {
+    try {
+        return await invoke<SharedDrive[]>('gdrive_list_shared_drives', { accountId });
+    } catch (error) {
+        console.error('[GDrive] Failed to list shared drives:', error);
+        return [];
+    }
+};
+
 // ==================== Authentication ====================
 
 /**
@@ -97,10 +131,11 @@ export const completeGDriveAuthWithCode = async (code: string): Promise<DriveAcc
 
 /**
  * Disconnect from Google Drive (revoke access)
+ * Disconnects a single account when accountId is given, otherwise all accounts
  */
-export const disconnectGDrive = async (): Promise<void> => {
+export const disconnectGDrive = async (accountId?: string): Promise<void> => {
     try {
-        await invoke('gdrive_disconnect');
+        await invoke('gdrive_disconnect', { accountId });
     } catch (error) {
         console.error('[GDrive] Failed to disconnect:', error);
         throw error;
@@ -274,6 +309,7 @@ export interface CloudScanResult {
     skipped_count: number;
     movies_count: number;
     tv_count: number;
+    removed_count: number;
     message: string;
     // Aliases for convenience
     indexed?: number;